@@ -1,5 +1,7 @@
 use crate::Context;
-use atomic_lib::{errors::AtomicResult, Storelike};
+use atomic_lib::{
+    errors::AtomicResult, plugins::versioning::revert_commit_builder, urls, Storelike,
+};
 
 /// Apply a Commit using the Set method - create or update a value in a resource
 pub fn set(context: &Context, subject: &str, property: &str, value: &str) -> AtomicResult<()> {
@@ -48,3 +50,28 @@ pub fn destroy(context: &Context, subject: &str) -> AtomicResult<()> {
     resource.destroy(&context.store)?;
     Ok(())
 }
+
+/// Reverts a Resource to an earlier version. The server constructs the version, the Commit is signed locally.
+pub fn revert(context: &Context, commit_url: &str) -> AtomicResult<()> {
+    let config = context.read_config();
+    let commit = context.store.get_resource(commit_url)?;
+    let subject = commit.get(urls::SUBJECT)?.to_string();
+    let version_url = format!(
+        "{}/version?commit={}",
+        config.server.trim_end_matches('/'),
+        urlencoding::encode(commit_url)
+    );
+    let agent = context.store.get_default_agent()?;
+    let mut target =
+        atomic_lib::client::fetch_resource(&version_url, &context.store, Some(&agent))?;
+    target.set_subject(subject.clone());
+    // Destroyed Resources are created again
+    let current = context
+        .store
+        .get_resource(&subject)
+        .unwrap_or_else(|_| atomic_lib::Resource::new(subject.clone()));
+    let commit = revert_commit_builder(&current, &target).sign(&agent, &context.store, &current)?;
+    atomic_lib::client::post_commit(&commit, &context.store)?;
+    println!("Reverted {}", subject);
+    Ok(())
+}
@@ -87,12 +87,18 @@ enum Commands {
         #[arg(required = true)]
         property: String,
     },
-    /// Permanently removes a Resource.
+    /// Destroys a Resource. AtomicServer moves it to the trash.
     Destroy {
         /// Subject URL or bookmark of the resource to be destroyed
         #[arg(required = true)]
         subject: String,
     },
+    /// Reverts a Resource to the version created by a Commit, using a new Commit. Also restores destroyed Resources.
+    Revert {
+        /// URL of the Commit that created the version to return to
+        #[arg(required = true)]
+        commit: String,
+    },
     /// Full text search
     Search {
         /// The search query
@@ -103,7 +109,11 @@ enum Commands {
     List,
     /// Validates the store
     #[command(hide = true)]
-    Validate,
+    Validate {
+        /// Print the report as JSON-AD
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -257,11 +267,14 @@ fn exec_command(context: &mut Context) -> AtomicResult<()> {
         } => {
             commit::set(context, &subject, &property, &value)?;
         }
+        Commands::Revert { commit } => {
+            commit::revert(context, &commit)?;
+        }
         Commands::Search { query } => {
             search::search(context, query)?;
         }
-        Commands::Validate => {
-            validate(context);
+        Commands::Validate { json } => {
+            validate(context, json)?;
         }
     };
     Ok(())
@@ -281,9 +294,15 @@ fn list(context: &mut Context) {
 }
 
 /// Validates the store
-fn validate(context: &mut Context) {
-    let reportstring = context.store.validate().to_string();
-    println!("{}", reportstring);
+fn validate(context: &mut Context, json: bool) -> AtomicResult<()> {
+    let report = context.store.validate();
+    if json {
+        let subject = format!("{}/validate", context.store.get_server_url());
+        println!("{}", report.to_resource(&subject).to_json_ad()?);
+    } else {
+        println!("{}", report);
+    }
+    Ok(())
 }
 
 pub type CLIResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
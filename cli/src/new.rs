@@ -60,6 +60,8 @@ fn prompt_instance(
         &context.store,
     )?;
 
+    let class = class.with_inherited(&context.store)?;
+
     for prop_subject in &class.requires {
         let field = context.store.get_property(prop_subject)?;
         if field.subject == atomic_lib::urls::SHORTNAME && preferred_shortname.clone().is_some() {
@@ -274,6 +276,35 @@ fn prompt_field(
                 None => return Ok(None),
             }
         }
+        DataType::Decimal
+        | DataType::Json
+        | DataType::Uri
+        | DataType::Duration
+        | DataType::DateTime
+        | DataType::GeoPoint
+        | DataType::Array(_) => {
+            let example = match &property.data_type {
+                DataType::Decimal => "decimal, e.g. 12.50",
+                DataType::Json => "JSON, e.g. {\"a\": 1}",
+                DataType::Uri => "URI, e.g. mailto:alice@example.com",
+                DataType::Duration => "ISO 8601 duration, e.g. P1DT2H",
+                DataType::DateTime => "datetime with timezone, e.g. 2024-03-01T09:30:00+01:00",
+                DataType::Array(_) => "JSON array, e.g. [\"a\", \"b\"]",
+                _ => "latitude,longitude, e.g. 52.37,4.89",
+            };
+            let msg = format!("{}{}", example, msg_appendix);
+            let string: Option<String> = prompt_opt(msg)?;
+            match string {
+                Some(val) => {
+                    if let Err(e) = Value::new(&val, &property.data_type) {
+                        println!("{}", e);
+                        return Ok(None);
+                    }
+                    input = Some(val);
+                }
+                None => return Ok(None),
+            }
+        }
         DataType::Boolean => {
             let msg = format!("boolean{}", msg_appendix);
             let number: Option<bool> = prompt_opt(msg)?;
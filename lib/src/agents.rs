@@ -5,7 +5,10 @@
 use base64::{engine::general_purpose, Engine};
 use serde_json::from_slice;
 
-use crate::{errors::AtomicResult, urls, Resource, Storelike, Value};
+use crate::{
+    errors::AtomicResult, resources::PropVals, urls, values::SubResource, Resource, Storelike,
+    Value,
+};
 
 /// None represents no right checks will be performed, effectively SUDO mode.
 #[derive(Clone, Debug, PartialEq)]
@@ -18,6 +21,9 @@ pub enum ForAgent {
     /// Public Agent, most strict.
     /// See [urls::PUBLIC_AGENT]
     Public,
+    /// A key acting on behalf of an Agent, limited by a delegation token.
+    /// See [crate::delegation::Delegation]
+    Delegated(Box<crate::delegation::Delegation>),
 }
 
 impl std::fmt::Display for ForAgent {
@@ -26,6 +32,7 @@ impl std::fmt::Display for ForAgent {
             ForAgent::AgentSubject(subject) => write!(f, "{}", subject),
             ForAgent::Sudo => write!(f, "{}", urls::SUDO_AGENT),
             ForAgent::Public => write!(f, "{}", urls::PUBLIC_AGENT),
+            ForAgent::Delegated(delegation) => write!(f, "{}", delegation.agent),
         }
     }
 }
@@ -148,6 +155,401 @@ impl Agent {
     }
 }
 
+/// A public key that can sign on behalf of an Agent.
+/// Stored as a Nested Resource in the [urls::AGENT_KEYS] array of the Agent.
+/// The [urls::PUBLIC_KEY] of the Agent itself is always one of its keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgentKey {
+    /// Base64 encoded Ed25519 public key.
+    pub public_key: String,
+    /// Human readable label, e.g. the name of the device that holds the private key.
+    pub label: Option<String>,
+    /// Unix timestamp (ms) after which the key is no longer valid.
+    pub revoked_at: Option<i64>,
+    pub kind: KeyKind,
+}
+
+/// How an [AgentKey] creates signatures.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyKind {
+    /// Signs Commits and authentication headers directly.
+    Ed25519,
+    /// A WebAuthn credential (passkey), which can only be used to log in.
+    /// See [crate::authentication::login_with_passkey].
+    Passkey(PasskeyCredential),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasskeyCredential {
+    /// Base64url encoded credential ID, as returned by the authenticator.
+    /// [urls::PASSKEY_CREDENTIAL_ID]
+    pub credential_id: String,
+    /// COSE algorithm identifier, see [COSE_ES256] and [COSE_EDDSA].
+    /// [urls::PASSKEY_ALGORITHM]
+    pub algorithm: i64,
+    /// Signature counter of the authenticator at the last login, `0` if it does not count.
+    /// [urls::PASSKEY_SIGN_COUNT]
+    pub sign_count: u32,
+}
+
+/// COSE algorithm identifier for ECDSA with P-256 and SHA-256.
+pub const COSE_ES256: i64 = -7;
+/// COSE algorithm identifier for Ed25519.
+pub const COSE_EDDSA: i64 = -8;
+
+impl AgentKey {
+    pub fn new(public_key: &str, label: Option<&str>) -> AtomicResult<AgentKey> {
+        verify_public_key(public_key)?;
+        Ok(AgentKey {
+            public_key: public_key.into(),
+            label: label.map(|l| l.into()),
+            revoked_at: None,
+            kind: KeyKind::Ed25519,
+        })
+    }
+
+    /// A passkey. The `public_key` is the base64 encoded raw key:
+    /// the uncompressed point (65 bytes) for [COSE_ES256], or 32 bytes for [COSE_EDDSA].
+    pub fn new_passkey(
+        public_key: &str,
+        credential_id: &str,
+        algorithm: i64,
+        label: Option<&str>,
+    ) -> AtomicResult<AgentKey> {
+        let key_bytes = decode_base64(public_key)?;
+        let valid = match algorithm {
+            COSE_ES256 => key_bytes.len() == 65 && key_bytes[0] == 4,
+            COSE_EDDSA => key_bytes.len() == 32,
+            other => return Err(format!("Unsupported passkey algorithm {}", other).into()),
+        };
+        if !valid {
+            return Err(format!("Invalid public key for passkey algorithm {}", algorithm).into());
+        }
+        Ok(AgentKey {
+            public_key: public_key.into(),
+            label: label.map(|l| l.into()),
+            revoked_at: None,
+            kind: KeyKind::Passkey(PasskeyCredential {
+                credential_id: credential_id.into(),
+                algorithm,
+                sign_count: 0,
+            }),
+        })
+    }
+
+    pub fn from_propvals(propvals: &PropVals) -> AtomicResult<AgentKey> {
+        let public_key = propvals
+            .get(urls::PUBLIC_KEY)
+            .ok_or("Agent key has no publicKey")?
+            .to_string();
+        let label = propvals.get(urls::NAME).map(|v| v.to_string());
+        let revoked_at = match propvals.get(urls::REVOKED_AT) {
+            Some(val) => Some(val.to_int()?),
+            None => None,
+        };
+        let kind = match propvals.get(urls::PASSKEY_CREDENTIAL_ID) {
+            Some(credential_id) => KeyKind::Passkey(PasskeyCredential {
+                credential_id: credential_id.to_string(),
+                algorithm: propvals
+                    .get(urls::PASSKEY_ALGORITHM)
+                    .ok_or("Passkey has no algorithm")?
+                    .to_int()?,
+                sign_count: match propvals.get(urls::PASSKEY_SIGN_COUNT) {
+                    Some(count) => count
+                        .to_int()?
+                        .try_into()
+                        .map_err(|_e| "Invalid passkey sign count")?,
+                    None => 0,
+                },
+            }),
+            None => KeyKind::Ed25519,
+        };
+        Ok(AgentKey {
+            public_key,
+            label,
+            revoked_at,
+            kind,
+        })
+    }
+
+    pub fn to_propvals(&self) -> PropVals {
+        let mut propvals = PropVals::new();
+        propvals.insert(
+            urls::PUBLIC_KEY.into(),
+            Value::String(self.public_key.clone()),
+        );
+        if let Some(label) = &self.label {
+            propvals.insert(urls::NAME.into(), Value::String(label.clone()));
+        }
+        if let Some(revoked_at) = self.revoked_at {
+            propvals.insert(urls::REVOKED_AT.into(), Value::Timestamp(revoked_at));
+        }
+        if let KeyKind::Passkey(credential) = &self.kind {
+            propvals.insert(
+                urls::PASSKEY_CREDENTIAL_ID.into(),
+                Value::String(credential.credential_id.clone()),
+            );
+            propvals.insert(
+                urls::PASSKEY_ALGORITHM.into(),
+                Value::Integer(credential.algorithm),
+            );
+            if credential.sign_count > 0 {
+                propvals.insert(
+                    urls::PASSKEY_SIGN_COUNT.into(),
+                    Value::Integer(credential.sign_count.into()),
+                );
+            }
+        }
+        propvals
+    }
+
+    /// Whether this key may be used for something that happened at `timestamp`.
+    pub fn is_valid_at(&self, timestamp: i64) -> bool {
+        match self.revoked_at {
+            Some(revoked_at) => timestamp < revoked_at,
+            None => true,
+        }
+    }
+}
+
+/// Returns all keys of an Agent Resource, including its main [urls::PUBLIC_KEY].
+/// If the main key is also listed in [urls::AGENT_KEYS] (e.g. to revoke it), the listed entry is used.
+pub fn get_agent_keys(agent: &Resource) -> AtomicResult<Vec<AgentKey>> {
+    let mut keys = listed_agent_keys(agent)?;
+    if let Ok(main_key) = agent.get(urls::PUBLIC_KEY) {
+        let main_key = main_key.to_string();
+        if !keys.iter().any(|k| k.public_key == main_key) {
+            keys.insert(
+                0,
+                AgentKey {
+                    public_key: main_key,
+                    label: None,
+                    revoked_at: None,
+                    kind: KeyKind::Ed25519,
+                },
+            );
+        }
+    }
+    Ok(keys)
+}
+
+/// Adds a key to the [urls::AGENT_KEYS] of an Agent Resource. Does not save the resource.
+/// Save it with a Commit signed by one of the Agent's other valid keys.
+pub fn add_agent_key(
+    agent: &mut Resource,
+    key: AgentKey,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let mut keys = listed_agent_keys(agent)?;
+    if keys.iter().any(|k| k.public_key == key.public_key) {
+        return Err(format!("Key {} is already added to this Agent", key.public_key).into());
+    }
+    keys.push(key);
+    set_agent_keys(agent, keys, store)
+}
+
+/// Marks a key of an Agent Resource as revoked from `revoked_at` onwards. Does not save the resource.
+/// Also works for the main [urls::PUBLIC_KEY] of the Agent.
+pub fn revoke_agent_key(
+    agent: &mut Resource,
+    public_key: &str,
+    revoked_at: i64,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let mut keys = listed_agent_keys(agent)?;
+    match keys.iter_mut().find(|k| k.public_key == public_key) {
+        Some(key) => key.revoked_at = Some(revoked_at),
+        None => {
+            let is_main_key = agent
+                .get(urls::PUBLIC_KEY)
+                .map(|v| v.to_string() == public_key)
+                .unwrap_or(false);
+            if !is_main_key {
+                return Err(format!(
+                    "Key {} not found in Agent {}",
+                    public_key,
+                    agent.get_subject()
+                )
+                .into());
+            }
+            keys.push(AgentKey {
+                public_key: public_key.into(),
+                label: None,
+                revoked_at: Some(revoked_at),
+                kind: KeyKind::Ed25519,
+            });
+        }
+    }
+    set_agent_keys(agent, keys, store)
+}
+
+/// Adds a temporary Ed25519 key that is revoked at `expires_at`, e.g. after logging in with a passkey.
+/// Expired session keys with the same `label` that never signed a Commit are removed.
+/// The others are kept, so the Commits that were signed with them can still be verified. Does not save the resource.
+pub fn add_session_key(
+    agent: &mut Resource,
+    public_key: &str,
+    label: &str,
+    expires_at: i64,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let mut keys = listed_agent_keys(agent)?;
+    let unused = unused_session_keys(agent.get_subject(), &keys, label, store)?;
+    keys.retain(|k| !unused.contains(&k.public_key));
+    if keys.iter().any(|k| k.public_key == public_key) {
+        return Err(format!("Key {} is already added to this Agent", public_key).into());
+    }
+    let mut key = AgentKey::new(public_key, Some(label))?;
+    key.revoked_at = Some(expires_at);
+    keys.push(key);
+    set_agent_keys(agent, keys, store)
+}
+
+/// Stores the signature counter of a passkey after a login. Does not save the resource.
+pub fn set_passkey_sign_count(
+    agent: &mut Resource,
+    credential_id: &str,
+    sign_count: u32,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let mut keys = listed_agent_keys(agent)?;
+    let credential = keys
+        .iter_mut()
+        .find_map(|k| match &mut k.kind {
+            KeyKind::Passkey(credential) if credential.credential_id == credential_id => {
+                Some(credential)
+            }
+            _ => None,
+        })
+        .ok_or("This passkey does not belong to the agent")?;
+    credential.sign_count = sign_count;
+    set_agent_keys(agent, keys, store)
+}
+
+/// Removes a key from the [urls::AGENT_KEYS] of an Agent Resource. Does not save the resource.
+/// Note that Commits signed by a removed key can no longer be verified. Use [revoke_agent_key] to keep them valid.
+pub fn remove_agent_key(
+    agent: &mut Resource,
+    public_key: &str,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let mut keys = listed_agent_keys(agent)?;
+    let len = keys.len();
+    keys.retain(|k| k.public_key != public_key);
+    if keys.len() == len {
+        return Err(format!("Key {} is not listed in the agent's keys", public_key).into());
+    }
+    set_agent_keys(agent, keys, store)
+}
+
+/// Checks whether `public_key` belongs to the Agent and was not revoked at `timestamp`.
+pub fn check_agent_key(
+    store: &impl Storelike,
+    agent_subject: &str,
+    public_key: &str,
+    timestamp: i64,
+) -> AtomicResult<()> {
+    let agent = store.get_resource(agent_subject)?;
+    let key = get_agent_keys(&agent)?
+        .into_iter()
+        .find(|k| k.public_key == public_key)
+        .ok_or("The public key does not belong to this agent")?;
+    if key.kind != KeyKind::Ed25519 {
+        return Err("Passkeys can only be used to log in, not to sign requests".into());
+    }
+    if !key.is_valid_at(timestamp) {
+        return Err(format!(
+            "The public key {} of agent {} has been revoked",
+            public_key, agent_subject
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The expired session keys with `label` that did not sign any of the Commits of the Agent.
+fn unused_session_keys(
+    agent_subject: &str,
+    keys: &[AgentKey],
+    label: &str,
+    store: &impl Storelike,
+) -> AtomicResult<Vec<String>> {
+    let now = crate::utils::now();
+    let mut unused: Vec<&AgentKey> = keys
+        .iter()
+        .filter(|k| {
+            k.kind == KeyKind::Ed25519 && k.label.as_deref() == Some(label) && !k.is_valid_at(now)
+        })
+        .collect();
+    if unused.is_empty() {
+        return Ok(Vec::new());
+    }
+    let commits = store.query(&crate::storelike::Query::new_prop_val(
+        urls::SIGNER,
+        agent_subject,
+    ))?;
+    for resource in commits.resources {
+        let Ok(commit) = crate::Commit::from_resource(resource) else {
+            continue;
+        };
+        let mut signed = Vec::new();
+        for key in &unused {
+            if commit.is_signed_with(&key.public_key, store)? {
+                signed.push(key.public_key.clone());
+            }
+        }
+        unused.retain(|k| !signed.contains(&k.public_key));
+        if unused.is_empty() {
+            break;
+        }
+    }
+    Ok(unused.into_iter().map(|k| k.public_key.clone()).collect())
+}
+
+/// Only the keys that are explicitly listed in [urls::AGENT_KEYS].
+fn listed_agent_keys(agent: &Resource) -> AtomicResult<Vec<AgentKey>> {
+    let mut keys = Vec::new();
+    let items = match agent.get(urls::AGENT_KEYS) {
+        Ok(Value::ResourceArray(items)) => items,
+        Ok(other) => {
+            return Err(format!(
+                "{} should be a ResourceArray, got {}",
+                urls::AGENT_KEYS,
+                other
+            )
+            .into())
+        }
+        Err(_) => return Ok(keys),
+    };
+    for item in items {
+        match item {
+            SubResource::Nested(propvals) => keys.push(AgentKey::from_propvals(propvals)?),
+            SubResource::Resource(resource) => {
+                keys.push(AgentKey::from_propvals(resource.get_propvals())?)
+            }
+            SubResource::Subject(s) => {
+                return Err(
+                    format!("Agent key {} should be a nested resource, not a subject", s).into(),
+                )
+            }
+        }
+    }
+    Ok(keys)
+}
+
+fn set_agent_keys(
+    agent: &mut Resource,
+    keys: Vec<AgentKey>,
+    store: &impl Storelike,
+) -> AtomicResult<()> {
+    let items: Vec<SubResource> = keys
+        .iter()
+        .map(|k| SubResource::Nested(k.to_propvals()))
+        .collect();
+    agent.set(urls::AGENT_KEYS.into(), items.into(), store)?;
+    Ok(())
+}
+
 /// keypair, serialized using base64
 pub struct Pair {
     pub private: String,
@@ -155,7 +557,7 @@ pub struct Pair {
 }
 
 /// Returns a new random keypair.
-fn generate_keypair() -> AtomicResult<Pair> {
+pub fn generate_keypair() -> AtomicResult<Pair> {
     use ring::signature::KeyPair;
     let rng = ring::rand::SystemRandom::new();
     const SEED_LEN: usize = 32;
@@ -254,4 +656,107 @@ mod test {
             "http://localhost:9883/agents/RqPwpgHv+PK7Pnz/dVab8hmHjYnvTL1YrlVa6L9G9Zg="
         );
     }
+
+    #[test]
+    fn multiple_keys_and_revocation() {
+        use crate::commit::{CommitBuilder, CommitOpts};
+
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("multi_key")).unwrap();
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_links: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_previous_commit: false,
+            validate_rights: false,
+            validate_for_agent: None,
+            update_index: true,
+        };
+
+        // Add a second key, signed by the first one
+        let second_pair = generate_keypair().unwrap();
+        let mut agent_resource = store.get_resource(&agent.subject).unwrap();
+        add_agent_key(
+            &mut agent_resource,
+            AgentKey::new(&second_pair.public, Some("laptop")).unwrap(),
+            &store,
+        )
+        .unwrap();
+        let commit = agent_resource
+            .get_commit_builder()
+            .clone()
+            .sign(&agent, &store, &agent_resource)
+            .unwrap();
+        store.apply_commit(commit, &opts).unwrap();
+
+        let agent_resource = store.get_resource(&agent.subject).unwrap();
+        let keys = get_agent_keys(&agent_resource).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1].label.as_deref(), Some("laptop"));
+        check_agent_key(&store, &agent.subject, &second_pair.public, 0).unwrap();
+
+        // The second key can sign commits for the same Agent
+        let second_agent =
+            Agent::from_private_key_and_subject(&second_pair.private, &agent.subject).unwrap();
+        let subject = "https://localhost/signed_by_second_key";
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::NAME.into(), Value::String("first".into()));
+        let resource = Resource::new(subject.into());
+        let commit = builder.sign(&second_agent, &store, &resource).unwrap();
+        store.apply_commit(commit, &opts).unwrap();
+
+        // Revoke the main key with the second key
+        let mut agent_resource = store.get_resource(&agent.subject).unwrap();
+        revoke_agent_key(
+            &mut agent_resource,
+            &agent.public_key,
+            crate::utils::now() - 1,
+            &store,
+        )
+        .unwrap();
+        let commit = agent_resource
+            .get_commit_builder()
+            .clone()
+            .sign(&second_agent, &store, &agent_resource)
+            .unwrap();
+        store.apply_commit(commit, &opts).unwrap();
+        check_agent_key(
+            &store,
+            &agent.subject,
+            &agent.public_key,
+            crate::utils::now(),
+        )
+        .unwrap_err();
+
+        // Commits by the revoked key are rejected
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::NAME.into(), Value::String("second".into()));
+        let resource = store.get_resource(subject).unwrap();
+        let commit = builder.sign(&agent, &store, &resource).unwrap();
+        let err = store.apply_commit(commit.clone(), &opts).unwrap_err();
+        assert!(err.message.contains("revoked"), "{}", err);
+
+        // Also when the Commit claims to be created before the revocation
+        let mut backdated = commit;
+        backdated.created_at -= 60 * 1000;
+        backdated.signature = None;
+        let serialized = backdated
+            .serialize_deterministically_json_ad(&store)
+            .unwrap();
+        backdated.signature = Some(
+            crate::commit::sign_message(
+                &serialized,
+                agent.private_key.as_ref().unwrap(),
+                &agent.public_key,
+            )
+            .unwrap(),
+        );
+        backdated
+            .validate_signature(&store, backdated.created_at)
+            .unwrap();
+        let err = store.apply_commit(backdated, &opts).unwrap_err();
+        assert!(err.message.contains("revoked"), "{}", err);
+    }
 }
@@ -0,0 +1,404 @@
+//! Audit log of authorization failures and sensitive actions, such as failed authentication, denied reads and writes, `Sudo` usage, accepted Invites and destroyed Resources.
+//! Events are recorded using [Storelike::audit]. The [crate::Db] stores them in a dedicated tree.
+//! Every [AuditEntry] contains the hash of the previous one, so removing or changing an entry breaks the chain. See [verify_chain].
+//! The last entry is referenced by an [AuditHead] signed by the server's Agent, so removing entries from the end or rewriting the whole chain is detected as well.
+//! Events that anyone can cause, such as denied reads, are limited by an [AuditThrottle].
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::{decode_base64, encode_base64, Agent, ForAgent},
+    commit::sign_message,
+    errors::{AtomicErrorType, AtomicResult},
+    Storelike,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditEventKind {
+    /// Authentication headers or cookies were present, but invalid.
+    AuthenticationFailed,
+    /// An Agent tried to read a resource without the rights to do so.
+    ReadDenied,
+    /// An Agent tried to edit, create or upload without the rights to do so.
+    WriteDenied,
+    /// Rights checks were skipped using [ForAgent::Sudo].
+    Sudo,
+    /// An Agent accepted an Invite.
+    InviteAccepted,
+    /// A Resource was destroyed by a Commit.
+    Destroy,
+}
+
+impl std::str::FromStr for AuditEventKind {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.into()))
+            .map_err(|_| format!("Unknown audit event kind: {}", s).into())
+    }
+}
+
+/// Something that happened, which should be recorded in the audit log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub kind: AuditEventKind,
+    /// The Agent that performed (or attempted) the action
+    pub agent: Option<String>,
+    /// The Resource that was targeted
+    pub subject: Option<String>,
+    /// Explanation, for example the error that was returned
+    pub message: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(kind: AuditEventKind, agent: &ForAgent, subject: &str) -> Self {
+        AuditEvent {
+            kind,
+            agent: Some(agent.to_string()),
+            subject: Some(subject.into()),
+            message: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl std::fmt::Display) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+}
+
+/// A stored [AuditEvent], linked to the previous entry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Position in the log, starting at 0
+    pub sequence: u64,
+    /// Unix timestamp (ms)
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    /// [AuditEntry::hash] of the previous entry, empty for the first one
+    pub previous_hash: String,
+    /// Base64 encoded SHA-256 hash of all other fields
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// Creates the entry that follows `previous`, and calculates its hash.
+    pub fn new(event: AuditEvent, previous: Option<&AuditEntry>) -> AtomicResult<Self> {
+        let mut entry = AuditEntry {
+            sequence: previous.map(|p| p.sequence + 1).unwrap_or(0),
+            timestamp: crate::utils::now(),
+            event,
+            previous_hash: previous.map(|p| p.hash.clone()).unwrap_or_default(),
+            hash: String::new(),
+        };
+        entry.hash = entry.calculate_hash()?;
+        Ok(entry)
+    }
+
+    fn calculate_hash(&self) -> AtomicResult<String> {
+        let mut unhashed = self.clone();
+        unhashed.hash = String::new();
+        let serialized = serde_jcs::to_string(&unhashed)
+            .map_err(|e| format!("Failed to serialize audit entry. {}", e))?;
+        let digest = ring::digest::digest(&ring::digest::SHA256, serialized.as_bytes());
+        Ok(encode_base64(digest.as_ref()))
+    }
+
+    pub fn to_json(&self) -> AtomicResult<String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize audit entry. {}", e).into())
+    }
+}
+
+/// Checks whether the entries form an unbroken chain, starting at the first entry.
+/// Returns the amount of entries.
+pub fn verify_chain(entries: impl Iterator<Item = AtomicResult<AuditEntry>>) -> AtomicResult<u64> {
+    let mut previous: Option<AuditEntry> = None;
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry?;
+        let expected_sequence = previous.as_ref().map(|p| p.sequence + 1).unwrap_or(0);
+        let expected_previous_hash = previous.as_ref().map(|p| p.hash.as_str()).unwrap_or("");
+        if entry.sequence != expected_sequence || entry.previous_hash != expected_previous_hash {
+            return Err(format!(
+                "Audit log is broken at entry {}, expected entry {}",
+                entry.sequence, expected_sequence
+            )
+            .into());
+        }
+        if entry.calculate_hash()? != entry.hash {
+            return Err(format!("Audit log entry {} has been altered", entry.sequence).into());
+        }
+        count += 1;
+        previous = Some(entry);
+    }
+    Ok(count)
+}
+
+/// Points to the last [AuditEntry], signed by the server's Agent.
+/// Stored next to the log and replaced on every append.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditHead {
+    pub sequence: u64,
+    /// [AuditEntry::hash] of the last entry
+    pub hash: String,
+    /// Base64 encoded Ed25519 signature of [AuditHead::signed_message]
+    pub signature: Option<String>,
+}
+
+impl AuditHead {
+    /// Creates a head for `entry`, signed by `agent` if it has a private key.
+    pub fn new(entry: &AuditEntry, agent: Option<&Agent>) -> AtomicResult<Self> {
+        let mut head = AuditHead {
+            sequence: entry.sequence,
+            hash: entry.hash.clone(),
+            signature: None,
+        };
+        if let Some(Agent {
+            private_key: Some(private_key),
+            public_key,
+            ..
+        }) = agent
+        {
+            head.signature = Some(sign_message(
+                &head.signed_message(),
+                private_key,
+                public_key,
+            )?);
+        }
+        Ok(head)
+    }
+
+    fn signed_message(&self) -> String {
+        format!("atomic-audit-head:{}:{}", self.sequence, self.hash)
+    }
+
+    /// Checks whether the head is signed by `public_key` and points to `last`, the last entry in the log.
+    pub fn verify(&self, last: &AuditEntry, public_key: &str) -> AtomicResult<()> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or("Audit log head is not signed")?;
+        ring::signature::UnparsedPublicKey::new(
+            &ring::signature::ED25519,
+            decode_base64(public_key)?,
+        )
+        .verify(self.signed_message().as_bytes(), &decode_base64(signature)?)
+        .map_err(|_| "Incorrect signature for audit log head")?;
+        if self.sequence != last.sequence || self.hash != last.hash {
+            return Err(format!(
+                "Audit log head points to entry {}, but the last entry is {}",
+                self.sequence, last.sequence
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// How long an [AuditThrottle] window lasts (ms).
+const THROTTLE_WINDOW: i64 = 60_000;
+/// The amount of distinct throttled events of one kind that are recorded per window.
+const THROTTLE_MAX_PER_WINDOW: usize = 100;
+
+/// Limits how often [AuditEventKind::ReadDenied] and [AuditEventKind::AuthenticationFailed] are recorded, since anyone can cause them.
+/// Within a window, an event for the same Agent and subject is recorded once, and at most [THROTTLE_MAX_PER_WINDOW] events per kind are recorded.
+/// Skipped events are counted, and recorded as a single summary event when the next event of that kind arrives after the window.
+#[derive(Debug, Default)]
+pub struct AuditThrottle {
+    windows: HashMap<AuditEventKind, ThrottleWindow>,
+}
+
+#[derive(Debug)]
+struct ThrottleWindow {
+    started_at: i64,
+    seen: HashSet<(Option<String>, Option<String>)>,
+    skipped: u64,
+}
+
+impl AuditThrottle {
+    /// Returns the events that should be recorded for `event`, which happened at `now` (ms).
+    pub fn admit(&mut self, event: AuditEvent, now: i64) -> Vec<AuditEvent> {
+        if !matches!(
+            event.kind,
+            AuditEventKind::ReadDenied | AuditEventKind::AuthenticationFailed
+        ) {
+            return vec![event];
+        }
+        let mut events = Vec::new();
+        let window = self
+            .windows
+            .entry(event.kind)
+            .or_insert_with(|| ThrottleWindow {
+                started_at: now,
+                seen: HashSet::new(),
+                skipped: 0,
+            });
+        if now - window.started_at >= THROTTLE_WINDOW {
+            if window.skipped > 0 {
+                events.push(AuditEvent {
+                    kind: event.kind,
+                    agent: None,
+                    subject: None,
+                    message: Some(format!(
+                        "{} similar events since {} were not recorded individually",
+                        window.skipped, window.started_at
+                    )),
+                });
+            }
+            window.started_at = now;
+            window.seen.clear();
+            window.skipped = 0;
+        }
+        let key = (event.agent.clone(), event.subject.clone());
+        if window.seen.len() >= THROTTLE_MAX_PER_WINDOW || !window.seen.insert(key) {
+            window.skipped += 1;
+        } else {
+            events.push(event);
+        }
+        events
+    }
+}
+
+/// Filters for reading the audit log. Empty fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub kind: Option<AuditEventKind>,
+    pub agent: Option<String>,
+    pub subject: Option<String>,
+    /// Only entries at or after this timestamp (ms)
+    pub from: Option<i64>,
+    /// Only entries before this timestamp (ms)
+    pub until: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.kind.map(|k| k == entry.event.kind).unwrap_or(true)
+            && self
+                .agent
+                .as_ref()
+                .map(|a| entry.event.agent.as_ref() == Some(a))
+                .unwrap_or(true)
+            && self
+                .subject
+                .as_ref()
+                .map(|s| entry.event.subject.as_ref() == Some(s))
+                .unwrap_or(true)
+            && self.from.map(|f| entry.timestamp >= f).unwrap_or(true)
+            && self.until.map(|u| entry.timestamp < u).unwrap_or(true)
+    }
+}
+
+/// Records a [AuditEventKind::ReadDenied] event if `result` is an unauthorized error.
+/// Returns the result unchanged.
+pub fn audit_read<T>(
+    store: &impl Storelike,
+    subject: &str,
+    for_agent: &ForAgent,
+    result: AtomicResult<T>,
+) -> AtomicResult<T> {
+    audit_denied(
+        store,
+        AuditEventKind::ReadDenied,
+        subject,
+        for_agent,
+        result,
+    )
+}
+
+/// Records a [AuditEventKind::WriteDenied] event if `result` is an unauthorized error.
+/// Returns the result unchanged.
+pub fn audit_write<T>(
+    store: &impl Storelike,
+    subject: &str,
+    for_agent: &ForAgent,
+    result: AtomicResult<T>,
+) -> AtomicResult<T> {
+    audit_denied(
+        store,
+        AuditEventKind::WriteDenied,
+        subject,
+        for_agent,
+        result,
+    )
+}
+
+fn audit_denied<T>(
+    store: &impl Storelike,
+    kind: AuditEventKind,
+    subject: &str,
+    for_agent: &ForAgent,
+    result: AtomicResult<T>,
+) -> AtomicResult<T> {
+    if let Err(e) = &result {
+        if matches!(e.error_type, AtomicErrorType::UnauthorizedError) {
+            store.audit(AuditEvent::new(kind, for_agent, subject).with_message(&e.message));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chain_detects_tampering() {
+        let mut entries: Vec<AuditEntry> = Vec::new();
+        for subject in ["https://example.com/a", "https://example.com/b"] {
+            let event = AuditEvent::new(AuditEventKind::Destroy, &ForAgent::Public, subject);
+            entries.push(AuditEntry::new(event, entries.last()).unwrap());
+        }
+        assert_eq!(
+            verify_chain(entries.clone().into_iter().map(Ok)).unwrap(),
+            2
+        );
+
+        let mut altered = entries.clone();
+        altered[0].event.subject = Some("https://example.com/c".into());
+        verify_chain(altered.into_iter().map(Ok)).unwrap_err();
+
+        let removed = entries.clone().into_iter().skip(1);
+        verify_chain(removed.map(Ok)).unwrap_err();
+
+        // Rewriting the chain, or removing the last entry, does not match the signed head
+        let agent = Agent::new(None, &crate::Store::init().unwrap()).unwrap();
+        let head = AuditHead::new(&entries[1], Some(&agent)).unwrap();
+        head.verify(&entries[1], &agent.public_key).unwrap();
+        head.verify(&entries[0], &agent.public_key).unwrap_err();
+        let mut rewritten = entries[1].clone();
+        rewritten.event.subject = Some("https://example.com/c".into());
+        rewritten.hash = rewritten.calculate_hash().unwrap();
+        head.verify(&rewritten, &agent.public_key).unwrap_err();
+        let forged = AuditHead::new(&rewritten, Some(&agent)).unwrap();
+        let other = Agent::new(None, &crate::Store::init().unwrap()).unwrap();
+        forged.verify(&rewritten, &other.public_key).unwrap_err();
+    }
+
+    #[test]
+    fn throttle_aggregates_denied_reads() {
+        let mut throttle = AuditThrottle::default();
+        let denied =
+            |subject: &str| AuditEvent::new(AuditEventKind::ReadDenied, &ForAgent::Public, subject);
+        assert_eq!(throttle.admit(denied("https://example.com/a"), 0).len(), 1);
+        assert_eq!(throttle.admit(denied("https://example.com/a"), 10).len(), 0);
+        assert_eq!(throttle.admit(denied("https://example.com/b"), 20).len(), 1);
+        for i in 0..THROTTLE_MAX_PER_WINDOW {
+            throttle.admit(denied(&format!("https://example.com/{}", i)), 30);
+        }
+        let destroy = AuditEvent::new(AuditEventKind::Destroy, &ForAgent::Public, "x");
+        assert_eq!(throttle.admit(destroy.clone(), 40).len(), 1);
+        assert_eq!(throttle.admit(destroy, 40).len(), 1);
+
+        let next = throttle.admit(denied("https://example.com/a"), THROTTLE_WINDOW);
+        assert_eq!(next.len(), 2);
+        assert!(next[0].message.as_ref().unwrap().starts_with("3 similar"));
+    }
+}
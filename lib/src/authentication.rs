@@ -1,16 +1,23 @@
 //! Check signatures in authentication headers, find the correct agent. Authorization is done in Hierarchies
 
+use base64::{engine::general_purpose, Engine};
+
 use crate::{
-    agents::{decode_base64, ForAgent},
+    agents::{
+        add_session_key, check_agent_key, decode_base64, get_agent_keys, set_passkey_sign_count,
+        verify_public_key, Agent, ForAgent, KeyKind, COSE_EDDSA, COSE_ES256,
+    },
+    audit::{AuditEvent, AuditEventKind},
+    commit::sign_message,
+    delegation::Delegation,
     errors::AtomicResult,
-    urls,
     utils::check_timestamp_in_past,
-    Storelike,
+    Resource, Storelike,
 };
 
 /// Set of values extracted from the request.
 /// Most are coming from headers.
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct AuthValues {
     // x-atomic-public-key
     #[serde(rename = "https://atomicdata.dev/properties/auth/publicKey")]
@@ -26,6 +33,36 @@ pub struct AuthValues {
     pub requested_subject: String,
     #[serde(rename = "https://atomicdata.dev/properties/auth/agent")]
     pub agent_subject: String,
+    // x-atomic-delegation
+    // Base64 encoded [Delegation] token, if the public key is not one of the agent's own keys.
+    #[serde(
+        rename = "https://atomicdata.dev/properties/auth/delegation",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub delegation: Option<String>,
+}
+
+impl AuthValues {
+    /// Signs `requested_subject` at the current time.
+    /// Serialized as base64 JSON, this can be used as an `atomic_session` cookie or as a Bearer token.
+    pub fn new_signed(agent: &Agent, requested_subject: &str) -> AtomicResult<AuthValues> {
+        let timestamp = crate::utils::now();
+        let message = format!("{} {}", requested_subject, timestamp);
+        let private_key = agent
+            .private_key
+            .as_ref()
+            .ok_or("No private key in agent")?;
+        let signature = sign_message(&message, private_key, &agent.public_key)?;
+        Ok(AuthValues {
+            public_key: agent.public_key.clone(),
+            timestamp,
+            signature,
+            requested_subject: requested_subject.into(),
+            agent_subject: agent.subject.clone(),
+            delegation: None,
+        })
+    }
 }
 
 /// Checks if the signature is valid for this timestamp.
@@ -53,33 +90,254 @@ const ACCEPTABLE_TIME_DIFFERENCE: i64 = 10000;
 /// Get the Agent's subject from [AuthValues]
 /// Checks if the auth headers are correct, whether signature matches the public key, whether the timestamp is valid.
 /// by default, returns the public agent
+/// Failed checks are recorded in the audit log.
 #[tracing::instrument(skip_all)]
 pub fn get_agent_from_auth_values_and_check(
     auth_header_values: Option<AuthValues>,
     store: &impl Storelike,
 ) -> AtomicResult<ForAgent> {
     if let Some(auth_vals) = auth_header_values {
-        // If there are auth headers, check 'em, make sure they are valid.
-        check_auth_signature(&auth_vals.requested_subject, &auth_vals)
-            .map_err(|e| format!("Error checking authentication headers. {}", e))?;
-        // check if the timestamp is valid
-        check_timestamp_in_past(auth_vals.timestamp, ACCEPTABLE_TIME_DIFFERENCE)?;
-        // check if the public key belongs to the agent
-        let found_public_key = store.get_value(&auth_vals.agent_subject, urls::PUBLIC_KEY)?;
-        if found_public_key.to_string() != auth_vals.public_key {
-            Err(
-                "The public key in the auth headers does not match the public key in the agent"
-                    .to_string()
-                    .into(),
-            )
-        } else {
-            Ok(ForAgent::AgentSubject(auth_vals.agent_subject))
-        }
+        check_auth_values(&auth_vals, store).inspect_err(|e| {
+            store.audit(AuditEvent {
+                kind: AuditEventKind::AuthenticationFailed,
+                agent: Some(auth_vals.agent_subject.clone()),
+                subject: Some(auth_vals.requested_subject.clone()),
+                message: Some(e.message.clone()),
+            })
+        })
     } else {
         Ok(ForAgent::Public)
     }
 }
 
+fn check_auth_values(auth_vals: &AuthValues, store: &impl Storelike) -> AtomicResult<ForAgent> {
+    // If there are auth headers, check 'em, make sure they are valid.
+    check_auth_signature(&auth_vals.requested_subject, auth_vals)
+        .map_err(|e| format!("Error checking authentication headers. {}", e))?;
+    // check if the timestamp is valid
+    check_timestamp_in_past(auth_vals.timestamp, ACCEPTABLE_TIME_DIFFERENCE)?;
+    if let Some(encoded) = &auth_vals.delegation {
+        return get_delegated_agent(encoded, auth_vals, store);
+    }
+    // check if the public key is one of the (non-revoked) keys of the agent
+    check_agent_key(
+        store,
+        &auth_vals.agent_subject,
+        &auth_vals.public_key,
+        crate::utils::now(),
+    )
+    .map_err(|e| {
+        format!(
+            "The public key in the auth headers is not a valid key for the agent. {}",
+            e
+        )
+    })?;
+    Ok(ForAgent::AgentSubject(auth_vals.agent_subject.clone()))
+}
+
+/// Verifies the delegation token and checks whether it was issued to the key that signed the request.
+fn get_delegated_agent(
+    encoded: &str,
+    auth_vals: &AuthValues,
+    store: &impl Storelike,
+) -> AtomicResult<ForAgent> {
+    let delegation = Delegation::decode(encoded)?;
+    delegation
+        .verify(store, crate::utils::now())
+        .map_err(|e| format!("Invalid delegation token. {}", e))?;
+    if delegation.delegate_key != auth_vals.public_key {
+        return Err(
+            "The delegation token was not issued to the public key in the auth headers".into(),
+        );
+    }
+    if delegation.agent != auth_vals.agent_subject {
+        return Err("The delegation token was not issued by the agent in the auth headers".into());
+    }
+    Ok(ForAgent::Delegated(Box::new(delegation)))
+}
+
+/// A WebAuthn assertion, created by a passkey of the Agent, that asks the server to add a session key.
+/// The challenge that the authenticator signs is `{agent} {sessionPublicKey} {timestamp}`, base64url encoded.
+/// Because the challenge contains the session key that the client generated, the server does not need to keep track of challenges,
+/// and never sees the private key of the session.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyAssertion {
+    /// Subject of the Agent that owns the passkey
+    pub agent: String,
+    /// Base64url encoded credential ID of the passkey
+    pub credential_id: String,
+    /// Base64 encoded Ed25519 public key that will be added to the Agent
+    pub session_public_key: String,
+    /// Unix timestamp (ms) at which the challenge was created
+    pub timestamp: i64,
+    /// Base64url encoded, as returned by the authenticator
+    pub authenticator_data: String,
+    /// Base64url encoded, as returned by the authenticator
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    /// Base64url encoded, as returned by the authenticator
+    pub signature: String,
+}
+
+impl PasskeyAssertion {
+    /// The challenge that should be passed to `navigator.credentials.get`
+    pub fn challenge(agent: &str, session_public_key: &str, timestamp: i64) -> String {
+        general_purpose::URL_SAFE_NO_PAD
+            .encode(format!("{} {} {}", agent, session_public_key, timestamp))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// How long an assertion can be used to log in after it was created.
+const PASSKEY_ASSERTION_MAX_AGE: i64 = 5 * 60 * 1000;
+/// How long a session key created by [login_with_passkey] stays valid.
+pub const PASSKEY_SESSION_DURATION: i64 = 12 * 60 * 60 * 1000;
+/// Label of the session keys created by [login_with_passkey].
+pub const PASSKEY_SESSION_LABEL: &str = "Passkey session";
+
+/// Checks whether the assertion was signed by a valid passkey of the Agent, for this server.
+/// `server_url` is the origin the client is using, its host is the WebAuthn relying party ID.
+/// Returns the signature counter of the authenticator, which has to be stored for the next login.
+#[tracing::instrument(skip_all)]
+pub fn check_passkey_assertion(
+    store: &impl Storelike,
+    assertion: &PasskeyAssertion,
+    server_url: &str,
+) -> AtomicResult<u32> {
+    let now = crate::utils::now();
+    check_timestamp_in_past(assertion.timestamp, ACCEPTABLE_TIME_DIFFERENCE)?;
+    if assertion.timestamp < now - PASSKEY_ASSERTION_MAX_AGE {
+        return Err("Passkey assertion has expired, create a new one".into());
+    }
+    verify_public_key(&assertion.session_public_key)?;
+
+    let agent = store.get_resource(&assertion.agent)?;
+    let (key, credential) = get_agent_keys(&agent)?
+        .into_iter()
+        .find_map(|k| match &k.kind {
+            KeyKind::Passkey(credential) if credential.credential_id == assertion.credential_id => {
+                let credential = credential.clone();
+                Some((k, credential))
+            }
+            _ => None,
+        })
+        .ok_or("This passkey does not belong to the agent")?;
+    if !key.is_valid_at(now) {
+        return Err("This passkey has been revoked".into());
+    }
+
+    let server = url::Url::parse(server_url)?;
+    let client_data_bytes = decode_base64_url(&assertion.client_data_json)?;
+    let client_data: ClientData = serde_json::from_slice(&client_data_bytes)
+        .map_err(|e| format!("Invalid clientDataJSON. {}", e))?;
+    if client_data.kind != "webauthn.get" {
+        return Err(format!(
+            "Expected a webauthn.get assertion, got {}",
+            client_data.kind
+        )
+        .into());
+    }
+    let challenge = PasskeyAssertion::challenge(
+        &assertion.agent,
+        &assertion.session_public_key,
+        assertion.timestamp,
+    );
+    if client_data.challenge.trim_end_matches('=') != challenge {
+        return Err("The passkey signed a different challenge".into());
+    }
+    if client_data.origin != server.origin().ascii_serialization() {
+        return Err(format!(
+            "The passkey assertion was created for {}, not for this server",
+            client_data.origin
+        )
+        .into());
+    }
+
+    let authenticator_data = decode_base64_url(&assertion.authenticator_data)?;
+    // rpIdHash (32 bytes), flags (1 byte), signCount (4 bytes)
+    if authenticator_data.len() < 37 {
+        return Err("Authenticator data is too short".into());
+    }
+    let rp_id = server.host_str().ok_or("Server URL has no host")?;
+    let rp_id_hash = ring::digest::digest(&ring::digest::SHA256, rp_id.as_bytes());
+    if &authenticator_data[..32] != rp_id_hash.as_ref() {
+        return Err(format!("The passkey was not created for {}", rp_id).into());
+    }
+    const USER_PRESENT: u8 = 0x01;
+    if authenticator_data[32] & USER_PRESENT == 0 {
+        return Err("The authenticator did not confirm that the user is present".into());
+    }
+    let sign_count = u32::from_be_bytes([
+        authenticator_data[33],
+        authenticator_data[34],
+        authenticator_data[35],
+        authenticator_data[36],
+    ]);
+
+    let mut message = authenticator_data;
+    message.extend_from_slice(
+        ring::digest::digest(&ring::digest::SHA256, &client_data_bytes).as_ref(),
+    );
+    let algorithm: &dyn ring::signature::VerificationAlgorithm = match credential.algorithm {
+        COSE_ES256 => &ring::signature::ECDSA_P256_SHA256_ASN1,
+        COSE_EDDSA => &ring::signature::ED25519,
+        other => return Err(format!("Unsupported passkey algorithm {}", other).into()),
+    };
+    let public_key =
+        ring::signature::UnparsedPublicKey::new(algorithm, decode_base64(&key.public_key)?);
+    public_key
+        .verify(&message, &decode_base64_url(&assertion.signature)?)
+        .map_err(|_e| "Incorrect passkey signature")?;
+
+    // Authenticators that count increase the counter for every signature.
+    // A counter that did not increase means the assertion was replayed, or the passkey was cloned.
+    if (sign_count > 0 || credential.sign_count > 0) && sign_count <= credential.sign_count {
+        return Err(format!(
+            "The signature counter of the passkey did not increase ({} after {}). It may have been cloned.",
+            sign_count, credential.sign_count
+        )
+        .into());
+    }
+    Ok(sign_count)
+}
+
+/// Checks the assertion and adds its session key to the Agent, valid for [PASSKEY_SESSION_DURATION].
+/// Returns the updated Agent. The client can sign requests and Commits with the session key from now on.
+pub fn login_with_passkey(
+    store: &impl Storelike,
+    assertion: &PasskeyAssertion,
+) -> AtomicResult<Resource> {
+    let sign_count =
+        check_passkey_assertion(store, assertion, store.get_server_url()).map_err(|e| {
+            crate::errors::AtomicError::unauthorized(format!("Passkey login failed. {}", e))
+        })?;
+    let mut agent = store.get_resource(&assertion.agent)?;
+    set_passkey_sign_count(&mut agent, &assertion.credential_id, sign_count, store)?;
+    add_session_key(
+        &mut agent,
+        &assertion.session_public_key,
+        PASSKEY_SESSION_LABEL,
+        crate::utils::now() + PASSKEY_SESSION_DURATION,
+        store,
+    )?;
+    agent.save_locally(store)?;
+    Ok(agent)
+}
+
+fn decode_base64_url(string: &str) -> AtomicResult<Vec<u8>> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(string.trim_end_matches('='))
+        .map_err(|e| format!("Invalid base64url. {}", e).into())
+}
+
 // fn get_agent_from_value_index() {
 //     let map = store.get_prop_subject_map(&auth_vals.public_key)?;
 //     let agents = map.get(crate::urls::PUBLIC_KEY).ok_or(format!(
@@ -93,3 +351,179 @@ pub fn get_agent_from_auth_values_and_check(
 //         for_agent = Some(found.to_string());
 //     }
 // }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agents::{add_agent_key, encode_base64, generate_keypair, AgentKey};
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    /// Signs the challenge like a browser and authenticator would.
+    fn assert_with(
+        key_pair: &EcdsaKeyPair,
+        agent: &str,
+        session_public_key: &str,
+        origin: &str,
+        rp_id: &str,
+        sign_count: u32,
+    ) -> PasskeyAssertion {
+        let rng = ring::rand::SystemRandom::new();
+        let timestamp = crate::utils::now();
+        let client_data = format!(
+            r#"{{"type":"webauthn.get","challenge":"{}","origin":"{}","crossOrigin":false}}"#,
+            PasskeyAssertion::challenge(agent, session_public_key, timestamp),
+            origin
+        );
+        let mut authenticator_data = ring::digest::digest(&ring::digest::SHA256, rp_id.as_bytes())
+            .as_ref()
+            .to_vec();
+        authenticator_data.push(0x05);
+        authenticator_data.extend_from_slice(&sign_count.to_be_bytes());
+        let mut message = authenticator_data.clone();
+        message.extend_from_slice(
+            ring::digest::digest(&ring::digest::SHA256, client_data.as_bytes()).as_ref(),
+        );
+        let signature = key_pair.sign(&rng, &message).unwrap();
+        PasskeyAssertion {
+            agent: agent.into(),
+            credential_id: "credential-1".into(),
+            session_public_key: session_public_key.into(),
+            timestamp,
+            authenticator_data: general_purpose::URL_SAFE_NO_PAD.encode(authenticator_data),
+            client_data_json: general_purpose::URL_SAFE_NO_PAD.encode(client_data),
+            signature: general_purpose::URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }
+    }
+
+    #[cfg(feature = "db")]
+    #[test]
+    fn passkey_login() {
+        let store = crate::Db::init_temp("passkey_login").unwrap();
+        let agent = store.create_agent(Some("passkey")).unwrap();
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        let passkey = AgentKey::new_passkey(
+            &encode_base64(key_pair.public_key().as_ref()),
+            "credential-1",
+            COSE_ES256,
+            Some("phone"),
+        )
+        .unwrap();
+        let mut agent_resource = store.get_resource(&agent.subject).unwrap();
+        add_agent_key(&mut agent_resource, passkey.clone(), &store).unwrap();
+        agent_resource.save_locally(&store).unwrap();
+
+        // A passkey can not sign requests by itself
+        check_agent_key(&store, &agent.subject, &passkey.public_key, 0).unwrap_err();
+
+        let session = generate_keypair().unwrap();
+        let server_url = store.get_server_url().to_string();
+        let assert = |session: &str, rp_id: &str, sign_count: u32| {
+            assert_with(
+                &key_pair,
+                &agent.subject,
+                session,
+                &server_url,
+                rp_id,
+                sign_count,
+            )
+        };
+        let assertion = assert(&session.public, "localhost", 1);
+        assert_eq!(
+            check_passkey_assertion(&store, &assertion, &server_url).unwrap(),
+            1
+        );
+
+        // Bound to the server
+        check_passkey_assertion(&store, &assertion, "https://other.example").unwrap_err();
+        let wrong_rp = assert(&session.public, "evil.example", 1);
+        check_passkey_assertion(&store, &wrong_rp, &server_url).unwrap_err();
+
+        // Bound to the session key
+        let mut swapped = assert(&session.public, "localhost", 1);
+        swapped.session_public_key = generate_keypair().unwrap().public;
+        check_passkey_assertion(&store, &swapped, &server_url).unwrap_err();
+
+        // After logging in, the session key can sign requests
+        login_with_passkey(&store, &assertion).unwrap();
+        check_agent_key(&store, &agent.subject, &session.public, crate::utils::now()).unwrap();
+        let passkey_count = |store: &crate::Db| {
+            let agent_resource = store.get_resource(&agent.subject).unwrap();
+            get_agent_keys(&agent_resource)
+                .unwrap()
+                .into_iter()
+                .find_map(|k| match k.kind {
+                    KeyKind::Passkey(credential) => Some(credential.sign_count),
+                    KeyKind::Ed25519 => None,
+                })
+                .unwrap()
+        };
+        assert_eq!(passkey_count(&store), 1);
+
+        // The signature counter has to increase, so assertions can not be replayed
+        let replayed = assert(&generate_keypair().unwrap().public, "localhost", 1);
+        login_with_passkey(&store, &replayed).unwrap_err();
+
+        // The session key signs a Commit, and then expires
+        let session_agent =
+            crate::agents::Agent::from_private_key_and_subject(&session.private, &agent.subject)
+                .unwrap();
+        let subject = format!("{}/signed_in_session", store.get_server_url());
+        let mut builder = crate::commit::CommitBuilder::new(subject.clone());
+        builder.set(
+            crate::urls::NAME.into(),
+            crate::Value::String("session".into()),
+        );
+        let signed = builder
+            .sign(&session_agent, &store, &Resource::new(subject))
+            .unwrap();
+        store
+            .apply_commit(
+                signed.clone(),
+                &crate::commit::CommitOpts {
+                    update_index: true,
+                    ..crate::commit::CommitOpts::no_validations_no_index()
+                },
+            )
+            .unwrap();
+        let mut agent_resource = store.get_resource(&agent.subject).unwrap();
+        crate::agents::revoke_agent_key(
+            &mut agent_resource,
+            &session.public,
+            crate::utils::now() - 1,
+            &store,
+        )
+        .unwrap();
+        let unused = generate_keypair().unwrap();
+        add_session_key(
+            &mut agent_resource,
+            &unused.public,
+            PASSKEY_SESSION_LABEL,
+            crate::utils::now() - 1,
+            &store,
+        )
+        .unwrap();
+        agent_resource.save_locally(&store).unwrap();
+
+        // Expired session keys that signed Commits are kept, so their Commits can still be verified.
+        // The ones that never signed anything are removed.
+        let next = generate_keypair().unwrap();
+        login_with_passkey(&store, &assert(&next.public, "localhost", 2)).unwrap();
+        assert_eq!(passkey_count(&store), 2);
+        let agent_resource = store.get_resource(&agent.subject).unwrap();
+        let keys = get_agent_keys(&agent_resource).unwrap();
+        for public_key in [&session.public, &next.public] {
+            assert!(keys.iter().any(|k| &k.public_key == public_key));
+        }
+        assert!(!keys.iter().any(|k| k.public_key == unused.public));
+        signed
+            .validate_signature(&store, signed.created_at)
+            .unwrap();
+        check_agent_key(&store, &agent.subject, &session.public, crate::utils::now()).unwrap_err();
+        check_agent_key(&store, &agent.subject, &next.public, crate::utils::now()).unwrap();
+    }
+}
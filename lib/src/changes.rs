@@ -0,0 +1,91 @@
+/*!
+An ordered, resumable feed of all Commits that a server applied, for replication consumers such as ETL pipelines.
+Every applied Commit gets a [ChangeEntry] with a monotonic `sequence`, which consumers use as the cursor for their next request.
+The [crate::Db] stores the entries in an append-only tree when a Commit is applied.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{commit::CommitResponse, errors::AtomicResult, urls, Storelike};
+
+/// An applied Commit, at a position in the changefeed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEntry {
+    /// Position in the feed, starting at 0
+    pub sequence: u64,
+    /// Unix timestamp (ms) of when the Commit was applied
+    pub applied_at: i64,
+    /// The Resource that the Commit changed
+    pub subject: String,
+    /// The Drive that contains the Resource
+    pub drive: Option<String>,
+    /// The Classes of the Resource, or of the destroyed Resource
+    pub classes: Vec<String>,
+    pub destroyed: bool,
+    /// The Commit as JSON-AD. Kept here, so the feed stays complete when Commits are compacted.
+    pub commit: serde_json::Value,
+}
+
+impl ChangeEntry {
+    /// Describes the Commit of `commit_response` at position `sequence`.
+    /// Finds the Drive of the Resource if `drive` is `None`.
+    pub fn new(
+        sequence: u64,
+        commit_response: &CommitResponse,
+        drive: Option<String>,
+        store: &impl Storelike,
+    ) -> AtomicResult<Self> {
+        let resource = commit_response
+            .resource_new
+            .as_ref()
+            .or(commit_response.resource_old.as_ref())
+            .ok_or("Commit changes no Resource")?;
+        let classes = resource
+            .get(urls::IS_A)
+            .and_then(|is_a| is_a.to_subjects(None))
+            .unwrap_or_default();
+        let commit = serde_json::from_str(&commit_response.commit_resource.to_json_ad()?)
+            .map_err(|e| format!("Failed to serialize Commit. {}", e))?;
+        Ok(ChangeEntry {
+            sequence,
+            applied_at: crate::utils::now(),
+            subject: commit_response.commit.subject.clone(),
+            drive: drive.or_else(|| crate::hierarchy::find_drive(store, resource)),
+            classes,
+            destroyed: commit_response.resource_new.is_none(),
+            commit,
+        })
+    }
+
+    pub fn to_json(&self) -> AtomicResult<String> {
+        serde_json::to_string(self).map_err(|e| format!("Failed to serialize change. {}", e).into())
+    }
+}
+
+/// Filters for reading the changefeed. Empty fields match everything.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeFilter {
+    /// Only entries after this sequence, which is the cursor of the last entry a consumer has seen
+    pub since: Option<u64>,
+    pub drive: Option<String>,
+    /// Only Resources that are an instance of this Class
+    pub class: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl ChangeFilter {
+    pub fn matches(&self, entry: &ChangeEntry) -> bool {
+        self.since.map(|s| entry.sequence > s).unwrap_or(true)
+            && self
+                .drive
+                .as_ref()
+                .map(|d| entry.drive.as_ref() == Some(d))
+                .unwrap_or(true)
+            && self
+                .class
+                .as_ref()
+                .map(|c| entry.classes.contains(c))
+                .unwrap_or(true)
+    }
+}
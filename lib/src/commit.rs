@@ -5,10 +5,13 @@ use std::collections::{HashMap, HashSet};
 use urls::{SET, SIGNER};
 
 use crate::{
-    agents::{decode_base64, encode_base64},
+    agents::{decode_base64, encode_base64, ForAgent},
+    audit::{audit_write, AuditEvent, AuditEventKind},
     datatype::DataType,
+    delegation::Delegation,
     errors::AtomicResult,
     resources::PropVals,
+    schema::{Class, LinkPolicy},
     urls,
     values::SubResource,
     Atom, Resource, Storelike, Value,
@@ -42,8 +45,11 @@ pub struct CommitApplied {
 /// Describes options for applying a Commit.
 /// Skip the checks you don't need to get better performance, or if you want to break the rules a little.
 pub struct CommitOpts {
-    /// Makes sure all `required` properties are present.
+    /// Makes sure all `required` properties are present, and that values match the Datatype of their Property.
     pub validate_schema: bool,
+    /// Checks whether links point to instances of the classtype of their Property, see [LinkPolicy].
+    /// Only used if `validate_schema` is enabled.
+    pub validate_links: bool,
     /// Checks the public key and the signature of the Commit.
     pub validate_signature: bool,
     /// Checks whether the Commit isn't too old, or has been created in the future.
@@ -56,13 +62,15 @@ pub struct CommitOpts {
     /// Updates the indexes in the Store. Is a bit more costly.
     pub update_index: bool,
     /// For who the right checks will be perormed. If empty, the signer of the Commit will be used.
-    pub validate_for_agent: Option<String>,
+    /// Pass a [ForAgent::Delegated] to limit the checks to the scope of a delegation token.
+    pub validate_for_agent: Option<ForAgent>,
 }
 
 impl CommitOpts {
     pub fn no_validations_no_index() -> Self {
         Self {
             validate_schema: false,
+            validate_links: false,
             validate_signature: false,
             validate_timestamp: false,
             validate_rights: false,
@@ -105,6 +113,9 @@ pub struct Commit {
     /// The previously applied commit to this Resource.
     #[serde(rename = "https://atomicdata.dev/properties/previousCommit")]
     pub previous_commit: Option<String>,
+    /// Base64 encoded [Delegation] token, if the Commit is signed by a delegated key instead of one of the signer's own keys.
+    #[serde(rename = "https://atomicdata.dev/properties/delegation")]
+    pub delegation: Option<String>,
     /// The URL of the Commit
     pub url: Option<String>,
 }
@@ -153,33 +164,124 @@ impl Commit {
         Ok(())
     }
 
-    /// Check if the Commit's signature matches the signer's public key.
-    pub fn validate_signature(&self, store: &impl Storelike) -> AtomicResult<()> {
+    /// Check if the Commit's signature matches one of the signer's public keys.
+    /// Agents can have multiple keys (see [crate::agents::get_agent_keys]).
+    /// Commits signed by a revoked key or with an expired delegation token are only accepted if `received_at` is before the revocation or expiry.
+    /// The signer chooses `createdAt`, so pass the current time for new Commits, and `createdAt` only for Commits that were checked when they were received.
+    pub fn validate_signature(&self, store: &impl Storelike, received_at: i64) -> AtomicResult<()> {
         let commit = self;
         let signature = match commit.signature.as_ref() {
             Some(sig) => sig,
             None => return Err("No signature set".into()),
         };
-        let pubkey_b64 = store
-            .get_resource(&commit.signer)?
-            .get(urls::PUBLIC_KEY)?
-            .to_string();
-        let agent_pubkey = decode_base64(&pubkey_b64)?;
+        if let Some(encoded) = &commit.delegation {
+            return commit.validate_delegated_signature(encoded, signature, store, received_at);
+        }
+        let signer = store.get_resource(&commit.signer)?;
+        let keys = crate::agents::get_agent_keys(&signer)?;
+        if keys.is_empty() {
+            return Err(format!("Signer {} has no public keys", commit.signer).into());
+        }
         let stringified_commit = commit.serialize_deterministically_json_ad(store)?;
-        let peer_public_key =
-            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, agent_pubkey);
         let signature_bytes = decode_base64(signature)?;
-        peer_public_key
-            .verify(stringified_commit.as_bytes(), &signature_bytes)
+        // Passkeys can not sign Commits, only Ed25519 keys can.
+        let keys = keys
+            .into_iter()
+            .filter(|k| k.kind == crate::agents::KeyKind::Ed25519);
+        for key in keys {
+            let agent_pubkey = decode_base64(&key.public_key)?;
+            let peer_public_key =
+                ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, agent_pubkey);
+            if peer_public_key
+                .verify(stringified_commit.as_bytes(), &signature_bytes)
+                .is_ok()
+            {
+                if !key.is_valid_at(received_at) {
+                    return Err(format!(
+                        "Commit was signed by key {} of {}, which has been revoked.",
+                        key.public_key, commit.signer
+                    )
+                    .into());
+                }
+                return Ok(());
+            }
+        }
+        Err(format!(
+            "Incorrect signature for Commit. This could be due to an error during signing or serialization of the commit. Compare this to the serialized commit in the client: {}",
+            stringified_commit,
+        )
+        .into())
+    }
+
+    /// Checks the delegation token at the time the Commit was received, and whether its delegate signed the Commit.
+    fn validate_delegated_signature(
+        &self,
+        encoded: &str,
+        signature: &str,
+        store: &impl Storelike,
+        received_at: i64,
+    ) -> AtomicResult<()> {
+        let delegation = Delegation::decode(encoded)?;
+        if delegation.agent != self.signer {
+            return Err("The delegation token of the Commit was not issued by its signer".into());
+        }
+        delegation
+            .verify(store, received_at)
+            .map_err(|e| format!("Invalid delegation token in Commit. {}", e))?;
+        let stringified_commit = self.serialize_deterministically_json_ad(store)?;
+        let delegate_key = decode_base64(&delegation.delegate_key)?;
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, delegate_key)
+            .verify(stringified_commit.as_bytes(), &decode_base64(signature)?)
             .map_err(|_e| {
                 format!(
-                    "Incorrect signature for Commit. This could be due to an error during signing or serialization of the commit. Compare this to the serialized commit in the client: {}",
+                    "Incorrect signature for delegated Commit. Compare this to the serialized commit in the client: {}",
                     stringified_commit,
                 )
             })?;
         Ok(())
     }
 
+    /// Whether `public_key` signed this Commit, or issued the delegation token that it was signed with.
+    /// Does not check whether the key belongs to the signer.
+    pub(crate) fn is_signed_with(
+        &self,
+        public_key: &str,
+        store: &impl Storelike,
+    ) -> AtomicResult<bool> {
+        let Some(signature) = &self.signature else {
+            return Ok(false);
+        };
+        if let Some(encoded) = &self.delegation {
+            let mut delegation = Delegation::decode(encoded)?;
+            while let Some(proof) = delegation.proof {
+                delegation = *proof;
+            }
+            return Ok(delegation.issuer_key == public_key);
+        }
+        let stringified_commit = self.serialize_deterministically_json_ad(store)?;
+        Ok(ring::signature::UnparsedPublicKey::new(
+            &ring::signature::ED25519,
+            decode_base64(public_key)?,
+        )
+        .verify(stringified_commit.as_bytes(), &decode_base64(signature)?)
+        .is_ok())
+    }
+
+    /// The Agent for which the rights of this Commit are checked.
+    /// Delegated Commits are limited to the rights and scope of their token.
+    pub(crate) fn rights_agent(&self, opts: &CommitOpts) -> AtomicResult<ForAgent> {
+        let agent = opts
+            .validate_for_agent
+            .clone()
+            .unwrap_or_else(|| self.signer.as_str().into());
+        match (&self.delegation, &agent) {
+            (Some(encoded), ForAgent::AgentSubject(subject)) if subject == &self.signer => {
+                Ok(ForAgent::Delegated(Box::new(Delegation::decode(encoded)?)))
+            }
+            _ => Ok(agent),
+        }
+    }
+
     /// Performs the checks specified in CommitOpts and constructs a new Resource.
     /// Warning: Does not save the new resource to the Store - doet not delete if it `destroy: true`.
     /// Use [Storelike::apply_commit] to save the resource to the Store.
@@ -197,7 +299,13 @@ impl Commit {
         }
 
         if opts.validate_signature {
-            commit.validate_signature(store)?;
+            // Commits from history (e.g. replicated ones) are checked at the time they were created, new ones at the time they are received.
+            let received_at = if opts.validate_timestamp {
+                crate::utils::now()
+            } else {
+                commit.created_at
+            };
+            commit.validate_signature(store, received_at)?;
         }
         if opts.validate_timestamp {
             commit.validate_timestamp()?;
@@ -220,7 +328,7 @@ impl Commit {
         };
 
         let mut applied = commit
-            .apply_changes(resource_old.clone(), store)
+            .apply_changes_with(resource_old.clone(), store, opts.validate_schema)
             .map_err(|e| {
                 format!(
                     "Error applying changes to Resource {}. {}",
@@ -229,9 +337,20 @@ impl Commit {
             })?;
 
         if opts.validate_rights {
-            let validate_for = opts.validate_for_agent.as_ref().unwrap_or(&commit.signer);
+            let validate_for = commit.rights_agent(opts)?;
+            if validate_for == ForAgent::Sudo {
+                store.audit(
+                    AuditEvent::new(AuditEventKind::Sudo, &validate_for, &commit.subject)
+                        .with_message("Commit applied without rights checks"),
+                );
+            }
             if is_new {
-                crate::hierarchy::check_append(store, &applied.resource_new, &validate_for.into())?;
+                audit_write(
+                    store,
+                    &commit.subject,
+                    &validate_for,
+                    crate::hierarchy::check_append(store, &applied.resource_new, &validate_for),
+                )?;
             } else {
                 // Set a parent only if the rights checks are to be validated.
                 // If there is no explicit parent set on the previous resource, use a default.
@@ -245,12 +364,22 @@ impl Commit {
                     )?;
                 }
                 // This should use the _old_ resource, no the new one, as the new one might maliciously give itself write rights.
-                crate::hierarchy::check_write(store, &resource_old, &validate_for.into())?;
+                audit_write(
+                    store,
+                    &commit.subject,
+                    &validate_for,
+                    crate::hierarchy::check_write(store, &resource_old, &validate_for),
+                )?;
             }
         };
         // Check if all required props are there
         if opts.validate_schema {
             applied.resource_new.check_required_props(store)?;
+            if opts.validate_links {
+                commit.check_links(&applied.resource_new, store)?;
+            }
+            commit.check_class_hierarchy(&applied.resource_new, store)?;
+            commit.check_unique(&applied.resource_new, store)?;
         }
 
         let commit_resource: Resource = commit.into_resource(store)?;
@@ -282,6 +411,100 @@ impl Commit {
         })
     }
 
+    /// Checks whether the links that are set or pushed by this Commit point to Resources of the right Class.
+    /// Uses the [LinkPolicy] of the Drive of the Resource.
+    fn check_links(&self, resource_new: &Resource, store: &impl Storelike) -> AtomicResult<()> {
+        if self.destroy.unwrap_or(false) {
+            return Ok(());
+        }
+        let changed = self
+            .set
+            .iter()
+            .flat_map(|set| set.keys())
+            .chain(self.push.iter().flat_map(|push| push.keys()));
+        let mut policy = None;
+        for prop in changed {
+            let property = store.get_property(prop)?;
+            if property.class_type.is_none() {
+                continue;
+            }
+            let policy =
+                *policy.get_or_insert_with(|| LinkPolicy::for_resource(resource_new, store));
+            if let Ok(value) = resource_new.get(prop) {
+                property.check_links(value, policy, store)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the unique constraints of the Properties that this Commit changes.
+    fn check_unique(&self, resource_new: &Resource, store: &impl Storelike) -> AtomicResult<()> {
+        for prop in self.unique_props(resource_new) {
+            if let Ok(property) = store.get_property(prop) {
+                property.check_unique(resource_new, store)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the unique constraints of the Properties that this Commit changes against the Resources of the other Commits that are applied together with it.
+    pub(crate) fn check_unique_among(
+        &self,
+        resource_new: &Resource,
+        others: &[&Resource],
+        store: &impl Storelike,
+    ) -> AtomicResult<()> {
+        if others.is_empty() {
+            return Ok(());
+        }
+        for prop in self.unique_props(resource_new) {
+            if let Ok(property) = store.get_property(prop) {
+                property.check_unique_among(resource_new, others, store)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The Properties of which the unique constraints have to be checked.
+    /// Changing the parent or the classes can move a Resource into another scope, so then all Properties are checked.
+    fn unique_props<'a>(&'a self, resource_new: &'a Resource) -> Vec<&'a String> {
+        if self.destroy.unwrap_or(false) {
+            return Vec::new();
+        }
+        let changed: Vec<&String> = self
+            .set
+            .iter()
+            .flat_map(|set| set.keys())
+            .chain(self.push.iter().flat_map(|push| push.keys()))
+            .collect();
+        let moved = changed
+            .iter()
+            .any(|prop| *prop == urls::PARENT || *prop == urls::IS_A);
+        if moved {
+            resource_new.get_propvals().keys().collect()
+        } else {
+            changed
+        }
+    }
+
+    /// Prevents cycles in [urls::SUB_CLASS_OF], which would make a Class inherit from itself.
+    fn check_class_hierarchy(
+        &self,
+        resource_new: &Resource,
+        store: &impl Storelike,
+    ) -> AtomicResult<()> {
+        let changed = self
+            .set
+            .iter()
+            .chain(self.push.iter())
+            .any(|propvals| propvals.contains_key(urls::SUB_CLASS_OF));
+        if !changed || self.destroy.unwrap_or(false) {
+            return Ok(());
+        }
+        Class::from_resource(resource_new.clone())?.get_superclasses(store)?;
+        Ok(())
+    }
+
     /// Checks if the Commit has been created in the future or if it is expired.
     #[tracing::instrument(skip_all)]
     pub fn validate_timestamp(&self) -> AtomicResult<()> {
@@ -292,9 +515,20 @@ impl Commit {
     /// Optionally also returns the updated Atoms.
     #[tracing::instrument(skip(store))]
     pub fn apply_changes(
+        &self,
+        resource: Resource,
+        store: &impl Storelike,
+    ) -> AtomicResult<CommitApplied> {
+        self.apply_changes_with(resource, store, true)
+    }
+
+    /// Like [Commit::apply_changes], but only checks whether values match the Datatypes of their Properties if `validate_datatypes` is true.
+    /// The constraints of the Properties are always checked.
+    fn apply_changes_with(
         &self,
         mut resource: Resource,
         store: &impl Storelike,
+        validate_datatypes: bool,
     ) -> AtomicResult<CommitApplied> {
         let resource_unedited = resource.clone();
 
@@ -319,14 +553,27 @@ impl Commit {
         }
         if let Some(set) = self.set.clone() {
             for (prop, new_val) in set.iter() {
-                resource
-                    .set(prop.into(), new_val.to_owned(), store)
-                    .map_err(|e| {
-                        format!(
-                            "Failed to set property '{}' to '{}' in Commit. Error: {}",
-                            prop, new_val, e
-                        )
-                    })?;
+                if validate_datatypes {
+                    resource
+                        .set(prop.into(), new_val.to_owned(), store)
+                        .map_err(|e| {
+                            format!(
+                                "Failed to set property '{}' to '{}' in Commit. Error: {}",
+                                prop, new_val, e
+                            )
+                        })?;
+                } else {
+                    store
+                        .get_property(prop)?
+                        .check_value(new_val)
+                        .map_err(|e| {
+                            format!(
+                                "Failed to set property '{}' to '{}' in Commit. Error: {}",
+                                prop, new_val, e
+                            )
+                        })?;
+                    resource.set_unsafe(prop.into(), new_val.to_owned());
+                }
 
                 let new_atom =
                     Atom::new(resource.get_subject().clone(), prop.into(), new_val.clone());
@@ -340,25 +587,45 @@ impl Commit {
         }
         if let Some(push) = self.push.clone() {
             for (prop, vec) in push.iter() {
-                let mut old_vec = match resource.get(prop) {
-                    Ok(val) => match val {
-                        Value::ResourceArray(res_arr) => res_arr.clone(),
-                        _other => return Err("Wrong datatype when pushing to array".into()),
-                    },
-                    Err(_) => Vec::new(),
-                };
-                let new_vec = match vec {
-                    Value::ResourceArray(res_arr) => res_arr.clone(),
+                // Both ResourceArrays and typed Arrays can be pushed to
+                let (merged, added): (Value, Vec<Value>) = match (resource.get(prop).ok(), vec) {
+                    (None | Some(Value::ResourceArray(_)), Value::ResourceArray(new_vec)) => {
+                        let mut old_vec = match resource.get(prop) {
+                            Ok(Value::ResourceArray(res_arr)) => res_arr.clone(),
+                            _ => Vec::new(),
+                        };
+                        old_vec.append(&mut new_vec.clone());
+                        (
+                            old_vec.into(),
+                            new_vec.iter().cloned().map(Value::from).collect(),
+                        )
+                    }
+                    (None | Some(Value::Array(_)), Value::Array(new_items)) => {
+                        let mut items = match resource.get(prop) {
+                            Ok(Value::Array(items)) => items.clone(),
+                            _ => Vec::new(),
+                        };
+                        items.append(&mut new_items.clone());
+                        (Value::Array(items), new_items.clone())
+                    }
                     _other => return Err("Wrong datatype when pushing to array".into()),
                 };
-                old_vec.append(&mut new_vec.clone());
-                resource.set_unsafe(prop.into(), old_vec.into());
-                for added_resource in new_vec {
-                    let atom = Atom::new(
-                        resource.get_subject().clone(),
-                        prop.into(),
-                        added_resource.into(),
-                    );
+                let property = store.get_property(prop)?;
+                if validate_datatypes && !merged.has_datatype(&property.data_type) {
+                    return Err(format!(
+                        "Failed to push to property '{}' in Commit. Wanted datatype '{}', got '{}'",
+                        prop,
+                        property.data_type,
+                        merged.datatype()
+                    )
+                    .into());
+                }
+                property.check_value(&merged).map_err(|e| {
+                    format!("Failed to push to property '{}' in Commit. {}", prop, e)
+                })?;
+                resource.set_unsafe(prop.into(), merged);
+                for added_value in added {
+                    let atom = Atom::new(resource.get_subject().clone(), prop.into(), added_value);
                     add_atoms.push(atom);
                 }
             }
@@ -406,6 +673,10 @@ impl Commit {
             Ok(found) => Some(found.to_string()),
             Err(_) => None,
         };
+        let delegation = match resource.get(urls::DELEGATION) {
+            Ok(found) => Some(found.to_string()),
+            Err(_) => None,
+        };
         let signature = resource.get(urls::SIGNATURE)?.to_string();
         let url = Some(resource.get_subject().into());
 
@@ -418,19 +689,21 @@ impl Commit {
             remove,
             destroy,
             previous_commit,
+            delegation,
             signature: Some(signature),
             url,
         })
     }
 
     /// Converts the Commit into a Resource with Atomic Values.
-    /// Creates an identifier using the server_url
+    /// Keeps the URL of the Commit if it has one (e.g. when it was replicated from another server), otherwise creates an identifier using the server_url
     /// Works for both Signed and Unsigned Commits
     #[tracing::instrument(skip(store))]
     pub fn into_resource(&self, store: &impl Storelike) -> AtomicResult<Resource> {
-        let commit_subject = match self.signature.as_ref() {
-            Some(sig) => format!("{}/commits/{}", store.get_server_url(), sig),
-            None => {
+        let commit_subject = match (&self.url, self.signature.as_ref()) {
+            (Some(url), _) => url.clone(),
+            (None, Some(sig)) => format!("{}/commits/{}", store.get_server_url(), sig),
+            (None, None) => {
                 let now = crate::utils::now();
                 format!("{}/commitsUnsigned/{}", store.get_server_url(), now)
             }
@@ -478,6 +751,9 @@ impl Commit {
             SIGNER.into(),
             Value::new(&self.signer, &DataType::AtomicUrl)?,
         );
+        if let Some(delegation) = &self.delegation {
+            resource.set_unsafe(urls::DELEGATION.into(), delegation.clone().into());
+        }
         if let Some(signature) = &self.signature {
             resource.set_unsafe(urls::SIGNATURE.into(), signature.clone().into());
         }
@@ -534,6 +810,10 @@ pub struct CommitBuilder {
     /// The previous Commit that was applied to the target resource (the subject) of this Commit. You should be able to follow these from Commit to Commit to establish an audit trail.
     /// https://atomicdata.dev/properties/previousCommit
     previous_commit: Option<String>,
+    /// Base64 encoded [Delegation] token, required when signing with a delegated key.
+    /// https://atomicdata.dev/properties/delegation
+    #[serde(default)]
+    delegation: Option<String>,
 }
 
 impl CommitBuilder {
@@ -546,6 +826,7 @@ impl CommitBuilder {
             remove: HashSet::new(),
             destroy: false,
             previous_commit: None,
+            delegation: None,
         }
     }
 
@@ -567,6 +848,18 @@ impl CommitBuilder {
         Ok(())
     }
 
+    /// Appends an item (e.g. a String or Integer) to a typed [Value::Array].
+    pub fn push_item(&mut self, property: &str, item: Value) -> AtomicResult<()> {
+        let mut items = match self.push.get(property) {
+            Some(Value::Array(items)) => items.to_owned(),
+            Some(other) => return Err(format!("Expected Array in push_item, got {}", other).into()),
+            None => Vec::new(),
+        };
+        items.push(item);
+        self.push.insert(property.into(), Value::Array(items));
+        Ok(())
+    }
+
     /// Creates the Commit and signs it using a signature.
     /// Does not send it - see [atomic_lib::client::post_commit].
     /// Private key is the base64 encoded pkcs8 for the signer.
@@ -604,6 +897,12 @@ impl CommitBuilder {
     pub fn destroy(&mut self, destroy: bool) {
         self.destroy = destroy
     }
+
+    /// Sign the Commit with the delegate key of this token, on behalf of the token's Agent.
+    pub fn set_delegation(&mut self, delegation: &Delegation) -> AtomicResult<()> {
+        self.delegation = Some(delegation.encode()?);
+        Ok(())
+    }
 }
 
 /// Signs a CommitBuilder at a specific unix timestamp.
@@ -622,6 +921,7 @@ fn sign_at(
         destroy: Some(commitbuilder.destroy),
         created_at: sign_date,
         previous_commit: commitbuilder.previous_commit,
+        delegation: commitbuilder.delegation,
         signature: None,
         push: Some(commitbuilder.push),
         url: None,
@@ -665,6 +965,7 @@ mod test {
     lazy_static::lazy_static! {
         pub static ref OPTS: CommitOpts = CommitOpts {
             validate_schema: true,
+            validate_links: true,
             validate_signature: true,
             validate_timestamp: true,
             validate_previous_commit: true,
@@ -728,6 +1029,7 @@ mod test {
             push: None,
             remove: Some(remove),
             previous_commit: None,
+            delegation: None,
             destroy: Some(destroy),
             signature: None,
             url: None,
@@ -0,0 +1,289 @@
+/*!
+Computed Properties are values that the server calculates when a Resource is served, such as the amount of children or the sum of their `amount`.
+A [Class] declares them using [urls::COMPUTED_PROPERTIES], which links to [ComputedProperty] Resources.
+These describe which Property is computed, from which source Resources, and how ([Aggregate]).
+
+Only the sources that the requesting Agent can read are used.
+The [crate::Db] caches the computed values per Agent, and invalidates them when a Commit changes the Resource or one of its sources.
+*/
+
+use crate::{
+    agents::ForAgent, errors::AtomicResult, resources::PropVals, schema::Class, storelike::Query,
+    urls, Resource, Storelike, Value,
+};
+
+/// How a computed value is calculated from its source Resources.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    /// The amount of source Resources.
+    Count,
+    /// The sum of the values of the source Resources.
+    Sum,
+    /// The lowest value of the source Resources.
+    Min,
+    /// The highest value of the source Resources.
+    Max,
+    /// The Agent that signed the last Commit of the Resource itself.
+    LastSigner,
+}
+
+impl std::str::FromStr for Aggregate {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Aggregate::Count),
+            "sum" => Ok(Aggregate::Sum),
+            "min" => Ok(Aggregate::Min),
+            "max" => Ok(Aggregate::Max),
+            "last-signer" => Ok(Aggregate::LastSigner),
+            other => Err(format!(
+                "Unknown aggregate '{}'. Use `count`, `sum`, `min`, `max` or `last-signer`",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::LastSigner => "last-signer",
+        })
+    }
+}
+
+/// Describes how the value of a single Property is computed.
+/// https://atomicdata.dev/classes/ComputedProperty
+#[derive(Clone, Debug)]
+pub struct ComputedProperty {
+    pub subject: String,
+    /// The Property whose value is computed.
+    pub computes: String,
+    pub aggregate: Aggregate,
+    /// The Property with which the source Resources link to the computed Resource. Defaults to `parent`.
+    pub source_property: String,
+    /// The Property of the source Resources that is aggregated. Required for `sum`, `min` and `max`.
+    pub value_property: Option<String>,
+}
+
+impl ComputedProperty {
+    pub fn from_resource(resource: &Resource) -> AtomicResult<ComputedProperty> {
+        let computes = resource.get(urls::COMPUTES)?.to_string();
+        let aggregate: Aggregate = resource.get(urls::AGGREGATE)?.to_string().parse()?;
+        let source_property = match resource.get(urls::SOURCE_PROPERTY) {
+            Ok(val) => val.to_string(),
+            Err(_) => urls::PARENT.into(),
+        };
+        let value_property = resource
+            .get(urls::VALUE_PROPERTY)
+            .ok()
+            .map(|val| val.to_string());
+        if value_property.is_none()
+            && matches!(aggregate, Aggregate::Sum | Aggregate::Min | Aggregate::Max)
+        {
+            return Err(format!(
+                "Computed Property {} uses `{}`, which requires a `valueProperty`",
+                resource.get_subject(),
+                aggregate
+            )
+            .into());
+        }
+        Ok(ComputedProperty {
+            subject: resource.get_subject().into(),
+            computes,
+            aggregate,
+            source_property,
+            value_property,
+        })
+    }
+
+    /// Calculates the value for `resource`. Returns `None` if there is no value, e.g. the `max` of zero sources.
+    /// Only uses the source Resources that `for_agent` can read.
+    pub fn compute(
+        &self,
+        store: &impl Storelike,
+        resource: &Resource,
+        for_agent: &ForAgent,
+    ) -> AtomicResult<Option<Value>> {
+        if self.aggregate == Aggregate::LastSigner {
+            let Ok(last_commit) = resource.get(urls::LAST_COMMIT) else {
+                return Ok(None);
+            };
+            let commit = store.get_resource(&last_commit.to_string())?;
+            return Ok(commit.get(urls::SIGNER).ok().cloned());
+        }
+
+        let mut query = Query::new_prop_val(&self.source_property, resource.get_subject());
+        query.include_nested = self.aggregate != Aggregate::Count;
+        query.for_agent = for_agent.clone();
+        let result = store.query(&query)?;
+        if self.aggregate == Aggregate::Count {
+            // The `count` of the result includes the sources that can't be read
+            return Ok(Some(Value::Integer(result.subjects.len() as i64)));
+        }
+
+        let value_property = self.value_property.as_deref().unwrap_or_default();
+        let numbers: Vec<&Value> = result
+            .resources
+            .iter()
+            .filter_map(|source| source.get(value_property).ok())
+            .filter(|value| {
+                matches!(
+                    value,
+                    Value::Integer(_) | Value::Timestamp(_) | Value::Float(_) | Value::Decimal(_)
+                )
+            })
+            .collect();
+        // Floats are aggregated as floats, integers and decimals without losing precision
+        let text = if numbers.iter().any(|v| matches!(v, Value::Float(_))) {
+            if numbers.iter().any(|v| matches!(v, Value::Decimal(_))) {
+                return Err(format!(
+                    "Computed Property {} can not aggregate both Float and Decimal values",
+                    self.subject
+                )
+                .into());
+            }
+            let floats = numbers.iter().map(|v| match v {
+                Value::Float(f) => *f,
+                Value::Integer(i) | Value::Timestamp(i) => *i as f64,
+                _ => unreachable!(),
+            });
+            let number = match self.aggregate {
+                Aggregate::Sum => Some(floats.sum()),
+                Aggregate::Min => floats.reduce(f64::min),
+                Aggregate::Max => floats.reduce(f64::max),
+                Aggregate::Count | Aggregate::LastSigner => unreachable!(),
+            };
+            number.map(|n| n.to_string())
+        } else {
+            let mut decimals = Vec::new();
+            for value in numbers {
+                decimals.push(match value {
+                    Value::Decimal(d) => parse_decimal(d)?,
+                    Value::Integer(i) | Value::Timestamp(i) => (*i as i128, 0),
+                    _ => unreachable!(),
+                });
+            }
+            // Use the same scale for all numbers, so they can be added and compared as integers
+            let scale = decimals.iter().map(|(_, scale)| *scale).max().unwrap_or(0);
+            let mut scaled = Vec::new();
+            for (unscaled, s) in decimals {
+                scaled.push(
+                    10i128
+                        .checked_pow(scale - s)
+                        .and_then(|factor| unscaled.checked_mul(factor))
+                        .ok_or("Decimal is too large to aggregate")?,
+                );
+            }
+            let number = match self.aggregate {
+                Aggregate::Sum => Some(
+                    scaled
+                        .into_iter()
+                        .try_fold(0i128, |sum, n| sum.checked_add(n))
+                        .ok_or("Sum is too large")?,
+                ),
+                Aggregate::Min => scaled.into_iter().min(),
+                Aggregate::Max => scaled.into_iter().max(),
+                Aggregate::Count | Aggregate::LastSigner => unreachable!(),
+            };
+            number.map(|n| format_decimal(n, scale))
+        };
+        let Some(text) = text else {
+            return Ok(None);
+        };
+        let property = store.get_property(&self.computes)?;
+        Ok(Some(Value::new_for_property(&text, &property)?))
+    }
+}
+
+/// Parses a decimal (e.g. `-12.50`) into an integer and the amount of digits after the point (`-1250`, `2`).
+fn parse_decimal(decimal: &str) -> AtomicResult<(i128, u32)> {
+    let (int, fraction) = decimal.split_once('.').unwrap_or((decimal, ""));
+    let unscaled = format!("{}{}", int, fraction)
+        .parse::<i128>()
+        .map_err(|e| format!("Can not aggregate decimal '{}': {}", decimal, e))?;
+    Ok((unscaled, fraction.len() as u32))
+}
+
+/// The inverse of [parse_decimal].
+fn format_decimal(unscaled: i128, scale: u32) -> String {
+    let sign = if unscaled < 0 { "-" } else { "" };
+    let digits = format!(
+        "{:0>width$}",
+        unscaled.unsigned_abs(),
+        width = scale as usize + 1
+    );
+    let (int, fraction) = digits.split_at(digits.len() - scale as usize);
+    if fraction.is_empty() {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, fraction)
+    }
+}
+
+/// Computes the values of all Computed Properties of these Classes for `resource`, as seen by `for_agent`.
+pub fn compute_properties(
+    store: &impl Storelike,
+    resource: &Resource,
+    classes: &[Class],
+    for_agent: &ForAgent,
+) -> AtomicResult<PropVals> {
+    let mut propvals = PropVals::new();
+    for class in classes {
+        for subject in &class.computed_properties {
+            let computed = ComputedProperty::from_resource(&store.get_resource(subject)?)?;
+            if let Some(value) = computed.compute(store, resource, for_agent)? {
+                propvals.insert(computed.computes.clone(), value);
+            }
+        }
+    }
+    Ok(propvals)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_aggregates() {
+        for aggregate in ["count", "sum", "min", "max", "last-signer"] {
+            let parsed: Aggregate = aggregate.parse().unwrap();
+            assert_eq!(parsed.to_string(), aggregate);
+        }
+        "average".parse::<Aggregate>().unwrap_err();
+    }
+
+    #[test]
+    fn decimals_round_trip() {
+        for decimal in [
+            "0",
+            "-12.50",
+            "0.05",
+            "-0.5",
+            "123456789012345678901234567.89",
+        ] {
+            let (unscaled, scale) = parse_decimal(decimal).unwrap();
+            assert_eq!(format_decimal(unscaled, scale), decimal);
+        }
+        let (a, _) = parse_decimal("0.1").unwrap();
+        let (b, _) = parse_decimal("0.2").unwrap();
+        assert_eq!(format_decimal(a + b, 1), "0.3");
+    }
+
+    #[test]
+    fn requires_value_property() {
+        let mut resource = Resource::new("https://localhost/total".into());
+        resource.set_unsafe(urls::COMPUTES.into(), Value::AtomicUrl(urls::NAME.into()));
+        resource.set_unsafe(urls::AGGREGATE.into(), Value::Slug("sum".into()));
+        ComputedProperty::from_resource(&resource).unwrap_err();
+        resource.set_unsafe(urls::AGGREGATE.into(), Value::Slug("count".into()));
+        let computed = ComputedProperty::from_resource(&resource).unwrap();
+        assert_eq!(computed.source_property, urls::PARENT);
+    }
+}
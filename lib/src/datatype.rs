@@ -18,9 +18,55 @@ pub enum DataType {
     String,
     Timestamp,
     Unsupported(String),
+    /// Arbitrary precision decimal number
+    Decimal,
+    Json,
+    /// Any URI, not necessarily a link to an Atomic Data resource
+    Uri,
+    /// ISO 8601 duration
+    Duration,
+    /// RFC 3339 datetime with a timezone offset
+    DateTime,
+    /// Latitude and longitude (WGS 84)
+    GeoPoint,
+    /// Ordered list of values of the item datatype, e.g. a list of tags.
+    /// Use [DataType::ResourceArray] for lists of Resources.
+    Array(Box<DataType>),
 }
 
+/// Separates the URL of [DataType::Array] from the URL of its item datatype in the string form of a [DataType].
+const ITEM_DATATYPE_PARAM: &str = "?itemDatatype=";
+
+impl DataType {
+    /// The URL of the Datatype. Unlike [DataType::to_string], this does not include the item datatype of a [DataType::Array].
+    pub fn url(&self) -> String {
+        match self {
+            DataType::Array(_) => urls::ARRAY.into(),
+            other => other.to_string(),
+        }
+    }
+
+    /// Whether this datatype can be used for the items of a [DataType::Array].
+    pub fn is_valid_array_item(&self) -> bool {
+        !matches!(
+            self,
+            DataType::Array(_)
+                | DataType::ResourceArray
+                | DataType::AtomicUrl
+                | DataType::Unsupported(_)
+        )
+    }
+}
+
+/// Parses the string form of a [DataType] (see [DataType::to_string]).
+/// The URL of [DataType::Array] without an item datatype is an Array of Strings. Properties set the item datatype using [crate::urls::ITEM_DATATYPE].
 pub fn match_datatype(string: &str) -> DataType {
+    if let Some(item) = string
+        .strip_prefix(urls::ARRAY)
+        .and_then(|rest| rest.strip_prefix(ITEM_DATATYPE_PARAM))
+    {
+        return DataType::Array(Box::new(match_datatype(item)));
+    }
     match string {
         urls::ATOMIC_URL => DataType::AtomicUrl,
         urls::BOOLEAN => DataType::Boolean,
@@ -32,6 +78,13 @@ pub fn match_datatype(string: &str) -> DataType {
         urls::SLUG => DataType::Slug,
         urls::STRING => DataType::String,
         urls::TIMESTAMP => DataType::Timestamp,
+        urls::DECIMAL => DataType::Decimal,
+        urls::JSON => DataType::Json,
+        urls::URI => DataType::Uri,
+        urls::DURATION => DataType::Duration,
+        urls::DATE_TIME => DataType::DateTime,
+        urls::GEO_POINT => DataType::GeoPoint,
+        urls::ARRAY => DataType::Array(Box::new(DataType::String)),
         unsupported_datatype => DataType::Unsupported(unsupported_datatype.into()),
     }
 }
@@ -40,19 +93,7 @@ impl std::str::FromStr for DataType {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            urls::ATOMIC_URL => DataType::AtomicUrl,
-            urls::BOOLEAN => DataType::Boolean,
-            urls::DATE => DataType::Date,
-            urls::INTEGER => DataType::Integer,
-            urls::FLOAT => DataType::Float,
-            urls::MARKDOWN => DataType::Markdown,
-            urls::RESOURCE_ARRAY => DataType::ResourceArray,
-            urls::SLUG => DataType::Slug,
-            urls::STRING => DataType::String,
-            urls::TIMESTAMP => DataType::Timestamp,
-            unsupported_datatype => DataType::Unsupported(unsupported_datatype.into()),
-        })
+        Ok(match_datatype(s))
     }
 }
 
@@ -70,6 +111,35 @@ impl fmt::Display for DataType {
             DataType::String => write!(f, "{}", urls::STRING),
             DataType::Timestamp => write!(f, "{}", urls::TIMESTAMP),
             DataType::Unsupported(url) => write!(f, "{}", url),
+            DataType::Decimal => write!(f, "{}", urls::DECIMAL),
+            DataType::Json => write!(f, "{}", urls::JSON),
+            DataType::Uri => write!(f, "{}", urls::URI),
+            DataType::Duration => write!(f, "{}", urls::DURATION),
+            DataType::DateTime => write!(f, "{}", urls::DATE_TIME),
+            DataType::GeoPoint => write!(f, "{}", urls::GEO_POINT),
+            DataType::Array(item) => write!(f, "{}{}{}", urls::ARRAY, ITEM_DATATYPE_PARAM, item),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn string_round_trip() {
+        for datatype in [
+            DataType::Integer,
+            DataType::Array(Box::new(DataType::Integer)),
+            DataType::Array(Box::new(DataType::Json)),
+            DataType::Unsupported("https://example.com/datatype".into()),
+        ] {
+            assert_eq!(match_datatype(&datatype.to_string()), datatype);
         }
+        assert_eq!(DataType::Array(Box::new(DataType::Date)).url(), urls::ARRAY);
+        assert_eq!(
+            match_datatype(urls::ARRAY),
+            DataType::Array(Box::new(DataType::String))
+        );
     }
 }
@@ -1,11 +1,17 @@
 //! Persistent, ACID compliant, threadsafe to-disk store.
 //! Powered by Sled - an embedded database.
 
+mod audit_log;
+mod changes;
 mod migrations;
+mod on_delete;
 mod prop_val_sub_index;
 mod query_index;
+mod replication;
+mod snapshots;
 #[cfg(test)]
 pub mod test;
+mod trash;
 mod trees;
 mod val_prop_sub_index;
 
@@ -16,12 +22,14 @@ use std::{
     vec,
 };
 
+use sled::Transactional;
 use tracing::{info, instrument};
 use trees::{Method, Operation, Transaction, Tree};
 
 use crate::{
     agents::ForAgent,
     atoms::IndexAtom,
+    audit::{AuditEvent, AuditEventKind},
     commit::{CommitOpts, CommitResponse},
     db::{
         query_index::{requires_query_index, NO_VALUE},
@@ -30,7 +38,7 @@ use crate::{
     endpoints::{default_endpoints, Endpoint, HandleGetContext},
     errors::{AtomicError, AtomicResult},
     resources::PropVals,
-    storelike::{Query, QueryResult, Storelike},
+    storelike::{query_including_subclasses, Query, QueryResult, Storelike},
     urls,
     values::SortableValue,
     Atom, Commit, Resource,
@@ -46,9 +54,22 @@ use self::{
     val_prop_sub_index::add_atom_to_valpropsub_index,
 };
 
+pub use replication::DEFAULT_REPLICATION_BATCH;
+pub use snapshots::{CompactionPolicy, DEFAULT_SNAPSHOT_INTERVAL};
+pub use trash::{TrashedResource, DEFAULT_TRASH_RETENTION};
+
 // A function called by the Store when a Commit is accepted
 type HandleCommit = Box<dyn Fn(&CommitResponse) + Send + Sync>;
 
+/// What else happens when a Commit destroys a Resource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DestroyEffects {
+    /// Applies the [crate::schema::OnDelete] policies, and moves the Resource and its children to the trash.
+    All,
+    /// Only removes the Resource. Used for replicated Commits, because the leader replicates everything its destroy changed.
+    None,
+}
+
 /// Inside the reference_index, each value is mapped to this type.
 /// The String on the left represents a Property URL, and the second one is the set of subjects.
 pub type PropSubjectMap = HashMap<String, HashSet<String>>;
@@ -78,12 +99,40 @@ pub struct Db {
     query_index: sled::Tree,
     /// [Tree::WatchedQueries]
     watched_queries: sled::Tree,
+    /// [Tree::AuditLog]
+    audit_log: sled::Tree,
+    /// [Tree::AuditHead]
+    audit_head: sled::Tree,
+    /// Makes sure audit entries are appended one at a time, so the hash chain stays intact.
+    audit_lock: Arc<Mutex<()>>,
+    /// Limits the audit events that anyone can cause, see [crate::audit::AuditThrottle].
+    audit_throttle: Arc<Mutex<crate::audit::AuditThrottle>>,
+    /// [Tree::Snapshots]
+    snapshots: sled::Tree,
+    /// A snapshot is stored for every n-th Commit of a Resource. `0` disables snapshots.
+    snapshot_interval: usize,
+    /// [Tree::CommitCounts]
+    commit_counts: sled::Tree,
+    /// [Tree::Trash]
+    trash: sled::Tree,
+    /// How long destroyed Resources are kept in the trash. `None` disables the trash.
+    trash_retention: Option<std::time::Duration>,
+    /// [Tree::Changes]
+    changes: sled::Tree,
+    /// [Tree::Replication]
+    replication: sled::Tree,
     /// The address where the db will be hosted, e.g. http://localhost/
     server_url: String,
     /// Endpoints are checked whenever a resource is requested. They calculate (some properties of) the resource and return it.
     endpoints: Vec<Endpoint>,
     /// Function called whenever a Commit is applied.
     on_commit: Option<Arc<HandleCommit>>,
+    /// Serializes validating and applying Commits, so checks that look at other Resources (such as unique constraints) can't be raced by a concurrent Commit.
+    commit_lock: Arc<Mutex<()>>,
+    /// Values of Computed Properties by subject and Agent, see [crate::computed]. Holds at most [COMPUTED_CACHE_SIZE] subjects.
+    computed_cache: Arc<Mutex<HashMap<String, HashMap<String, PropVals>>>>,
+    /// Subclasses by Class, see [Storelike::get_subclasses]. Cleared when [urls::SUB_CLASS_OF] changes on any Resource.
+    subclasses_cache: Arc<Mutex<HashMap<String, Vec<String>>>>,
     /// Where the DB is stored on disk.
     path: std::path::PathBuf,
 }
@@ -101,6 +150,13 @@ impl Db {
         let query_index = db.open_tree(Tree::QueryMembers)?;
         let prop_val_sub_index = db.open_tree(Tree::PropValSub)?;
         let watched_queries = db.open_tree(Tree::WatchedQueries)?;
+        let audit_log = db.open_tree(Tree::AuditLog)?;
+        let audit_head = db.open_tree(Tree::AuditHead)?;
+        let snapshots = db.open_tree(Tree::Snapshots)?;
+        let commit_counts = db.open_tree(Tree::CommitCounts)?;
+        let trash = db.open_tree(Tree::Trash)?;
+        let changes = db.open_tree(Tree::Changes)?;
+        let replication = db.open_tree(Tree::Replication)?;
         let store = Db {
             path: path.into(),
             db,
@@ -111,8 +167,22 @@ impl Db {
             prop_val_sub_index,
             server_url,
             watched_queries,
+            audit_log,
+            audit_head,
+            audit_lock: Arc::new(Mutex::new(())),
+            audit_throttle: Default::default(),
+            snapshots,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            commit_counts,
+            trash,
+            trash_retention: Some(DEFAULT_TRASH_RETENTION),
+            changes,
+            replication,
             endpoints: default_endpoints(),
             on_commit: None,
+            commit_lock: Arc::new(Mutex::new(())),
+            computed_cache: Arc::new(Mutex::new(HashMap::new())),
+            subclasses_cache: Arc::new(Mutex::new(HashMap::new())),
         };
         migrate_maybe(&store).map(|e| format!("Error during migration of database: {:?}", e))?;
         crate::populate::populate_base_models(&store)
@@ -176,6 +246,26 @@ impl Db {
         Ok(())
     }
 
+    /// Removes the Resource and its Atoms from the index in the transaction.
+    fn remove_resource_tx(
+        &self,
+        resource: &Resource,
+        transaction: &mut Transaction,
+    ) -> AtomicResult<()> {
+        let subject = resource.get_subject();
+        for (prop, val) in resource.get_propvals() {
+            let remove_atom = crate::Atom::new(subject.clone(), prop.clone(), val.clone());
+            self.remove_atom_from_index(&remove_atom, resource, transaction)?;
+        }
+        transaction.push(Operation {
+            tree: Tree::Resources,
+            method: Method::Delete,
+            key: subject.as_bytes().to_vec(),
+            val: None,
+        });
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     fn all_index_atoms(&self, include_external: bool) -> IndexIterator {
         Box::new(
@@ -208,6 +298,38 @@ impl Db {
         Ok(())
     }
 
+    /// Rebuilds the value indexes ([Tree::PropValSub] and [Tree::ValPropSub]) for a single Property.
+    /// Use this after the Datatype of a Property has changed, as this changes how its values are indexed and sorted.
+    /// Returns the amount of Resources that have the Property.
+    pub fn reindex_property(&self, property: &str) -> AtomicResult<usize> {
+        let existing = find_in_prop_val_sub_index(self, property, None)
+            .collect::<AtomicResult<Vec<IndexAtom>>>()?;
+        let mut transaction = Transaction::new();
+        let mut subjects = HashSet::new();
+        for index_atom in existing {
+            transaction.push(Operation::remove_atom_from_reference_index(&index_atom));
+            transaction.push(Operation::remove_atom_from_prop_val_sub_index(&index_atom));
+            subjects.insert(index_atom.subject);
+        }
+        let mut count = 0;
+        for subject in subjects {
+            let Ok(propvals) = self.get_propvals(&subject) else {
+                continue;
+            };
+            let Some(value) = propvals.get(property) else {
+                continue;
+            };
+            let atom = Atom::new(subject, property.into(), value.clone());
+            for index_atom in atom.to_indexable_atoms() {
+                add_atom_to_valpropsub_index(&index_atom, &mut transaction)?;
+                add_atom_to_prop_val_sub_index(&index_atom, &mut transaction)?;
+            }
+            count += 1;
+        }
+        self.apply_transaction(&mut transaction)?;
+        Ok(count)
+    }
+
     /// Internal method for fetching Resource data.
     #[instrument(skip(self))]
     fn set_propvals(&self, subject: &str, propvals: &PropVals) -> AtomicResult<()> {
@@ -321,6 +443,7 @@ impl Db {
     }
 
     /// Apply made changes to the store.
+    /// All trees are written in one sled transaction, so either all or none of the changes are persisted.
     #[instrument(skip(self))]
     fn apply_transaction(&self, transaction: &mut Transaction) -> AtomicResult<()> {
         let mut batch_resources = sled::Batch::default();
@@ -328,6 +451,12 @@ impl Db {
         let mut batch_valpropsub = sled::Batch::default();
         let mut batch_watched_queries = sled::Batch::default();
         let mut batch_query_members = sled::Batch::default();
+        let mut batch_audit_log = sled::Batch::default();
+        let mut batch_snapshots = sled::Batch::default();
+        let mut batch_commit_counts = sled::Batch::default();
+        let mut batch_trash = sled::Batch::default();
+        let mut batch_changes = sled::Batch::default();
+        let mut batch_replication = sled::Batch::default();
 
         for op in transaction.iter() {
             match op.tree {
@@ -373,14 +502,88 @@ impl Db {
                         batch_query_members.remove(op.key.clone());
                     }
                 },
+                trees::Tree::AuditLog => match op.method {
+                    trees::Method::Insert => {
+                        batch_audit_log.insert::<&[u8], &[u8]>(&op.key, op.val.as_ref().unwrap());
+                    }
+                    trees::Method::Delete => {
+                        return Err("Audit log entries can not be removed".into());
+                    }
+                },
+                trees::Tree::AuditHead => {
+                    return Err(
+                        "The audit log head is only written when appending to the audit log".into(),
+                    );
+                }
+                trees::Tree::Snapshots => match op.method {
+                    trees::Method::Insert => {
+                        batch_snapshots.insert::<&[u8], &[u8]>(&op.key, op.val.as_ref().unwrap());
+                    }
+                    trees::Method::Delete => {
+                        batch_snapshots.remove(op.key.clone());
+                    }
+                },
+                trees::Tree::CommitCounts => match op.method {
+                    trees::Method::Insert => {
+                        batch_commit_counts
+                            .insert::<&[u8], &[u8]>(&op.key, op.val.as_ref().unwrap());
+                    }
+                    trees::Method::Delete => {
+                        batch_commit_counts.remove(op.key.clone());
+                    }
+                },
+                trees::Tree::Trash => match op.method {
+                    trees::Method::Insert => {
+                        batch_trash.insert::<&[u8], &[u8]>(&op.key, op.val.as_ref().unwrap());
+                    }
+                    trees::Method::Delete => {
+                        batch_trash.remove(op.key.clone());
+                    }
+                },
+                trees::Tree::Changes => match op.method {
+                    trees::Method::Insert => {
+                        batch_changes.insert::<&[u8], &[u8]>(&op.key, op.val.as_ref().unwrap());
+                    }
+                    trees::Method::Delete => {
+                        return Err("Changefeed entries can not be removed".into());
+                    }
+                },
+                trees::Tree::Replication => match op.method {
+                    trees::Method::Insert => {
+                        batch_replication.insert::<&[u8], &[u8]>(&op.key, op.val.as_ref().unwrap());
+                    }
+                    trees::Method::Delete => {
+                        batch_replication.remove(op.key.clone());
+                    }
+                },
             }
         }
 
-        self.resources.apply_batch(batch_resources)?;
-        self.prop_val_sub_index.apply_batch(batch_propvalsub)?;
-        self.reference_index.apply_batch(batch_valpropsub)?;
-        self.watched_queries.apply_batch(batch_watched_queries)?;
-        self.query_index.apply_batch(batch_query_members)?;
+        let batches = [
+            (&self.resources, batch_resources),
+            (&self.prop_val_sub_index, batch_propvalsub),
+            (&self.reference_index, batch_valpropsub),
+            (&self.watched_queries, batch_watched_queries),
+            (&self.query_index, batch_query_members),
+            (&self.audit_log, batch_audit_log),
+            (&self.snapshots, batch_snapshots),
+            (&self.commit_counts, batch_commit_counts),
+            (&self.trash, batch_trash),
+            (&self.changes, batch_changes),
+            (&self.replication, batch_replication),
+        ];
+        let trees: Vec<&sled::Tree> = batches.iter().map(|(tree, _)| *tree).collect();
+        trees
+            .as_slice()
+            .transaction(|views| {
+                for (view, (_, batch)) in views.iter().zip(&batches) {
+                    view.apply_batch(batch)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError<()>| {
+                format!("Failed to apply transaction. {:?}", e)
+            })?;
 
         Ok(())
     }
@@ -479,102 +682,175 @@ impl Db {
         }
         Ok(())
     }
-}
 
-impl Drop for Db {
-    fn drop(&mut self) {
-        match self.db.flush() {
-            Ok(..) => (),
-            Err(e) => eprintln!("Failed to flush the database: {}", e),
-        };
-    }
-}
-
-impl Storelike for Db {
-    #[instrument(skip(self))]
-    fn add_atoms(&self, atoms: Vec<Atom>) -> AtomicResult<()> {
-        // Start with a nested HashMap, containing only strings.
-        let mut map: HashMap<String, Resource> = HashMap::new();
-        for atom in atoms {
-            match map.get_mut(&atom.subject) {
-                // Resource exists in map
-                Some(resource) => {
-                    resource
-                        .set_string(atom.property.clone(), &atom.value.to_string(), self)
-                        .map_err(|e| format!("Failed adding attom {}. {}", atom, e))?;
+    /// Removes the cached Computed Properties of a changed Resource, and of the Resources it links to (before or after the change).
+    /// Clears the whole cache if the change affects the definitions, such as a Class or Property,
+    /// or if it changes rights, which can affect the sources that Agents can read anywhere below the Resource.
+    /// Also clears the cached subclasses if the Resource is (or was) a subclass.
+    fn invalidate_computed(&self, subject: &str, old: Option<&PropVals>, new: Option<&PropVals>) {
+        if [old, new]
+            .into_iter()
+            .flatten()
+            .any(|propvals| propvals.contains_key(urls::SUB_CLASS_OF))
+        {
+            self.subclasses_cache.lock().unwrap().clear();
+        }
+        let mut cache = self.computed_cache.lock().unwrap();
+        if cache.is_empty() {
+            return;
+        }
+        // New Resources have no descendants yet, so their rights only affect themselves
+        let changes_rights = old.is_some()
+            && [urls::READ, urls::WRITE, urls::PARENT].iter().any(|prop| {
+                let value = |propvals: Option<&PropVals>| {
+                    propvals.and_then(|p| p.get(*prop)).map(|v| v.to_string())
+                };
+                value(old) != value(new)
+            });
+        if changes_rights {
+            cache.clear();
+            return;
+        }
+        let mut affected = vec![subject.to_string()];
+        for propvals in [old, new].into_iter().flatten() {
+            for (prop, val) in propvals {
+                if prop == urls::IS_A {
+                    let defines = val.to_subjects(None).unwrap_or_default().iter().any(|c| {
+                        c == urls::CLASS || c == urls::PROPERTY || c == urls::COMPUTED_PROPERTY
+                    });
+                    if defines {
+                        cache.clear();
+                        return;
+                    }
                 }
-                // Resource does not exist
-                None => {
-                    let mut resource = Resource::new(atom.subject.clone());
-                    resource
-                        .set_string(atom.property.clone(), &atom.value.to_string(), self)
-                        .map_err(|e| format!("Failed adding attom {}. {}", atom, e))?;
-                    map.insert(atom.subject, resource);
+                if let crate::Value::AtomicUrl(_) | crate::Value::ResourceArray(_) = val {
+                    affected.extend(val.to_subjects(None).unwrap_or_default());
                 }
             }
         }
-        for (_subject, resource) in map.iter() {
-            self.add_resource(resource)?
+        for subject in affected {
+            cache.remove(&subject);
         }
-        self.db.flush()?;
-        Ok(())
     }
 
-    #[instrument(skip(self, resource), fields(sub = %resource.get_subject()))]
-    fn add_resource_opts(
+    /// Applies a Commit. The `effects` determine what else happens if it destroys a Resource.
+    /// [crate::schema::OnDelete] policies are applied in the same transaction, and the Agent of the Commit needs write rights for every Resource they change.
+    pub(crate) fn apply_commit_with_policies(
         &self,
-        resource: &Resource,
-        check_required_props: bool,
-        update_index: bool,
-        overwrite_existing: bool,
-    ) -> AtomicResult<()> {
-        // This only works if no external functions rely on using add_resource for atom-like operations!
-        // However, add_atom uses set_propvals, which skips the validation.
-        let existing = self.get_propvals(resource.get_subject()).ok();
-        if !overwrite_existing && existing.is_some() {
-            return Err(format!(
-                "Failed to add: '{}', already exists, should not be overwritten.",
-                resource.get_subject()
-            )
-            .into());
+        commit: Commit,
+        opts: &CommitOpts,
+        effects: DestroyEffects,
+    ) -> AtomicResult<CommitResponse> {
+        let store = self;
+
+        // Held until the changes are written, so no other Commit is validated against the state before this one
+        let commit_guard = self.commit_lock.lock().unwrap();
+
+        let commit_response = commit.validate_and_build_response(opts, store)?;
+
+        // Fails before anything changes if a `restrict` policy is violated
+        let on_delete_commits = match (&commit_response.resource_old, &commit_response.resource_new)
+        {
+            (Some(_old), None) if effects == DestroyEffects::All => {
+                let for_agent = if opts.validate_rights {
+                    commit_response.commit.rights_agent(opts)?
+                } else {
+                    ForAgent::Sudo
+                };
+                let plan =
+                    on_delete::plan_on_delete(store, &commit_response.commit.subject, &for_agent)?;
+                on_delete::on_delete_commits(store, plan)?
+            }
+            _ => Vec::new(),
+        };
+
+        let trash = effects != DestroyEffects::None && self.trash_retention.is_some();
+        let mut transaction = Transaction::new();
+        store.stage_commit(&commit_response, opts, trash, &mut transaction)?;
+        let mut responses = vec![commit_response];
+        for (commit, opts) in on_delete_commits {
+            let subject = commit.subject.clone();
+            let response = commit
+                .validate_and_build_response(&opts, store)
+                .map_err(|e| format!("Failed to apply onDelete policy to {}: {}", subject, e))?;
+            store.stage_commit(&response, &opts, trash, &mut transaction)?;
+            responses.push(response);
         }
-        if check_required_props {
-            resource.check_required_props(self)?;
+        if trash {
+            store.stage_trash_children(&mut responses, &mut transaction)?;
         }
-        if update_index {
-            let mut transaction = Transaction::new();
-            if let Some(pv) = existing {
-                let subject = resource.get_subject();
-                for (prop, val) in pv.iter() {
-                    // Possible performance hit - these clones can be replaced by modifying remove_atom_from_index
-                    let remove_atom = crate::Atom::new(subject.into(), prop.into(), val.clone());
-                    self.remove_atom_from_index(&remove_atom, resource, &mut transaction)
-                        .map_err(|e| {
-                            format!("Failed to remove atom from index {}. {}", remove_atom, e)
-                        })?;
-                }
+        store.apply_transaction(&mut transaction)?;
+        drop(commit_guard);
+
+        for response in &responses {
+            store.finish_commit(response)?;
+        }
+
+        Ok(responses.swap_remove(0))
+    }
+
+    /// Validates all Commits, and applies them in a single transaction, so either all or none of them are applied.
+    /// Each Commit is validated against the state before any of them is applied, so every Commit has to change a different Resource.
+    /// Destroy Commits are not supported, because their [crate::schema::OnDelete] policies and children are applied separately.
+    pub(crate) fn apply_commits_atomically(
+        &self,
+        commits: Vec<(Commit, CommitOpts)>,
+    ) -> AtomicResult<Vec<CommitResponse>> {
+        let commit_guard = self.commit_lock.lock().unwrap();
+        let mut transaction = Transaction::new();
+        let mut responses = Vec::with_capacity(commits.len());
+        let mut subjects = HashSet::new();
+        for (commit, opts) in commits {
+            if !subjects.insert(commit.subject.clone()) {
+                return Err(format!(
+                    "Multiple Commits for {} can not be applied together",
+                    commit.subject
+                )
+                .into());
             }
-            for a in resource.to_atoms() {
-                self.add_atom_to_index(&a, resource, &mut transaction)
-                    .map_err(|e| format!("Failed to add atom to index {}. {}", a, e))?;
+            if commit.destroy.unwrap_or(false) {
+                return Err(format!(
+                    "Destroy Commit for {} can not be applied together with other Commits",
+                    commit.subject
+                )
+                .into());
             }
-            self.apply_transaction(&mut transaction)?;
+            let commit_response = commit.validate_and_build_response(&opts, self)?;
+            // The store does not contain the Resources of the earlier Commits yet, so they are compared here
+            if let (true, Some(resource_new)) =
+                (opts.validate_schema, &commit_response.resource_new)
+            {
+                let others: Vec<&Resource> = responses
+                    .iter()
+                    .filter_map(|response: &CommitResponse| response.resource_new.as_ref())
+                    .collect();
+                commit_response
+                    .commit
+                    .check_unique_among(resource_new, &others, self)?;
+            }
+            self.stage_commit(&commit_response, &opts, false, &mut transaction)?;
+            responses.push(commit_response);
         }
-        self.set_propvals(resource.get_subject(), resource.get_propvals())
+        self.apply_transaction(&mut transaction)?;
+        drop(commit_guard);
+
+        for commit_response in &responses {
+            self.finish_commit(commit_response)?;
+        }
+        Ok(responses)
     }
 
-    /// Apply a single signed Commit to the Db.
-    /// Creates, edits or destroys a resource.
-    /// Allows for control over which validations should be performed.
-    /// Returns the generated Commit, the old Resource and the new Resource.
-    #[tracing::instrument(skip(self))]
-    fn apply_commit(&self, commit: Commit, opts: &CommitOpts) -> AtomicResult<CommitResponse> {
+    /// Adds the changes of a validated Commit to the transaction: the Commit itself, the new Resource, the indexes and the changefeed.
+    /// If `trash` is true, a destroyed Resource is moved to the trash.
+    fn stage_commit(
+        &self,
+        commit_response: &CommitResponse,
+        opts: &CommitOpts,
+        trash: bool,
+        transaction: &mut Transaction,
+    ) -> AtomicResult<()> {
         let store = self;
 
-        let commit_response = commit.validate_and_build_response(opts, store)?;
-
-        let mut transaction = Transaction::new();
-
         // BEFORE APPLY COMMIT HANDLERS
         // TODO: Move to something dynamic
         if let Some(resource_new) = &commit_response.resource_new {
@@ -596,10 +872,10 @@ impl Storelike for Db {
         }
 
         // Save the Commit to the Store. We can skip the required props checking, but we need to make sure the commit hasn't been applied before.
-        store.add_resource_tx(&commit_response.commit_resource, &mut transaction)?;
+        store.add_resource_tx(&commit_response.commit_resource, transaction)?;
         // We still need to index the Commit!
         for atom in commit_response.commit_resource.to_atoms() {
-            store.add_atom_to_index(&atom, &commit_response.commit_resource, &mut transaction)?;
+            store.add_atom_to_index(&atom, &commit_response.commit_resource, transaction)?;
         }
 
         match (&commit_response.resource_old, &commit_response.resource_new) {
@@ -609,35 +885,76 @@ impl Storelike for Db {
             (Some(_old), None) => {
                 assert_eq!(_old.get_subject(), &commit_response.commit.subject);
                 assert!(&commit_response.commit.destroy.expect("Resource was removed but `commit.destroy` was not set!"));
-                self.remove_resource(&commit_response.commit.subject)?;
+                if trash {
+                    self.add_to_trash(_old, commit_response, transaction)?;
+                }
+                self.remove_resource_tx(_old, transaction)?;
             },
             _ => {}
         };
 
         if let Some(new) = &commit_response.resource_new {
-            self.add_resource_tx(new, &mut transaction)?;
+            // A Resource that is created again is no longer in the trash
+            if self.trash.contains_key(new.get_subject().as_bytes())? {
+                transaction.push(Operation {
+                    tree: Tree::Trash,
+                    method: Method::Delete,
+                    key: new.get_subject().as_bytes().to_vec(),
+                    val: None,
+                });
+            }
+            self.add_resource_tx(new, transaction)?;
+            self.add_snapshot_maybe(&commit_response.commit_resource, new, transaction)?;
         }
 
         if opts.update_index {
-            if let Some(old) = &commit_response.resource_old {
+            // Destroyed Resources have been removed from the index by `remove_resource_tx`
+            if let (Some(old), Some(_new)) =
+                (&commit_response.resource_old, &commit_response.resource_new)
+            {
                 for atom in &commit_response.remove_atoms {
                     store
-                        .remove_atom_from_index(atom, old, &mut transaction)
+                        .remove_atom_from_index(atom, old, transaction)
                         .map_err(|e| format!("Error removing atom from index: {e}  Atom: {e}"))?
                 }
             }
             if let Some(new) = &commit_response.resource_new {
                 for atom in &commit_response.add_atoms {
                     store
-                        .add_atom_to_index(atom, new, &mut transaction)
+                        .add_atom_to_index(atom, new, transaction)
                         .map_err(|e| format!("Error adding atom to index: {e}  Atom: {e}"))?
                 }
             }
         }
 
-        store.apply_transaction(&mut transaction)?;
+        store.add_change_tx(commit_response, transaction)?;
+        Ok(())
+    }
+
+    /// Runs the side effects of an applied Commit: clears caches, calls the `on_commit` handler and the plugins.
+    fn finish_commit(&self, commit_response: &CommitResponse) -> AtomicResult<()> {
+        let store = self;
+        store.invalidate_computed(
+            &commit_response.commit.subject,
+            commit_response
+                .resource_old
+                .as_ref()
+                .map(|r| r.get_propvals()),
+            commit_response
+                .resource_new
+                .as_ref()
+                .map(|r| r.get_propvals()),
+        );
+
+        if commit_response.resource_new.is_none() {
+            store.audit(AuditEvent::new(
+                AuditEventKind::Destroy,
+                &ForAgent::AgentSubject(commit_response.commit.signer.clone()),
+                &commit_response.commit.subject,
+            ));
+        }
 
-        store.handle_commit(&commit_response);
+        store.handle_commit(commit_response);
 
         // AFTER APPLY COMMIT HANDLERS
         // Commit has been checked and saved.
@@ -657,7 +974,116 @@ impl Storelike for Db {
                 };
             }
         }
-        Ok(commit_response)
+        Ok(())
+    }
+}
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        match self.db.flush() {
+            Ok(..) => (),
+            Err(e) => eprintln!("Failed to flush the database: {}", e),
+        };
+    }
+}
+
+impl Storelike for Db {
+    fn get_subclasses(&self, class: &str) -> AtomicResult<Vec<String>> {
+        if let Some(subclasses) = self.subclasses_cache.lock().unwrap().get(class) {
+            return Ok(subclasses.clone());
+        }
+        let subclasses = crate::storelike::find_subclasses(self, class)?;
+        self.subclasses_cache
+            .lock()
+            .unwrap()
+            .insert(class.to_string(), subclasses.clone());
+        Ok(subclasses)
+    }
+
+    #[instrument(skip(self))]
+    fn add_atoms(&self, atoms: Vec<Atom>) -> AtomicResult<()> {
+        // Start with a nested HashMap, containing only strings.
+        let mut map: HashMap<String, Resource> = HashMap::new();
+        for atom in atoms {
+            match map.get_mut(&atom.subject) {
+                // Resource exists in map
+                Some(resource) => {
+                    resource
+                        .set_string(atom.property.clone(), &atom.value.to_string(), self)
+                        .map_err(|e| format!("Failed adding attom {}. {}", atom, e))?;
+                }
+                // Resource does not exist
+                None => {
+                    let mut resource = Resource::new(atom.subject.clone());
+                    resource
+                        .set_string(atom.property.clone(), &atom.value.to_string(), self)
+                        .map_err(|e| format!("Failed adding attom {}. {}", atom, e))?;
+                    map.insert(atom.subject, resource);
+                }
+            }
+        }
+        for (_subject, resource) in map.iter() {
+            self.add_resource(resource)?
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    #[instrument(skip(self, resource), fields(sub = %resource.get_subject()))]
+    fn add_resource_opts(
+        &self,
+        resource: &Resource,
+        check_required_props: bool,
+        update_index: bool,
+        overwrite_existing: bool,
+    ) -> AtomicResult<()> {
+        // This only works if no external functions rely on using add_resource for atom-like operations!
+        // However, add_atom uses set_propvals, which skips the validation.
+        let existing = self.get_propvals(resource.get_subject()).ok();
+        if !overwrite_existing && existing.is_some() {
+            return Err(format!(
+                "Failed to add: '{}', already exists, should not be overwritten.",
+                resource.get_subject()
+            )
+            .into());
+        }
+        if check_required_props {
+            resource.check_required_props(self)?;
+        }
+        self.invalidate_computed(
+            resource.get_subject(),
+            existing.as_ref(),
+            Some(resource.get_propvals()),
+        );
+        if update_index {
+            let mut transaction = Transaction::new();
+            if let Some(pv) = existing {
+                let subject = resource.get_subject();
+                for (prop, val) in pv.iter() {
+                    // Possible performance hit - these clones can be replaced by modifying remove_atom_from_index
+                    let remove_atom = crate::Atom::new(subject.into(), prop.into(), val.clone());
+                    self.remove_atom_from_index(&remove_atom, resource, &mut transaction)
+                        .map_err(|e| {
+                            format!("Failed to remove atom from index {}. {}", remove_atom, e)
+                        })?;
+                }
+            }
+            for a in resource.to_atoms() {
+                self.add_atom_to_index(&a, resource, &mut transaction)
+                    .map_err(|e| format!("Failed to add atom to index {}. {}", a, e))?;
+            }
+            self.apply_transaction(&mut transaction)?;
+        }
+        self.set_propvals(resource.get_subject(), resource.get_propvals())
+    }
+
+    /// Apply a single signed Commit to the Db.
+    /// Creates, edits or destroys a resource.
+    /// Allows for control over which validations should be performed.
+    /// Returns the generated Commit, the old Resource and the new Resource.
+    #[tracing::instrument(skip(self))]
+    fn apply_commit(&self, commit: Commit, opts: &CommitOpts) -> AtomicResult<CommitResponse> {
+        self.apply_commit_with_policies(commit, opts, DestroyEffects::All)
     }
 
     fn get_server_url(&self) -> &str {
@@ -748,7 +1174,8 @@ impl Storelike for Db {
         // Whether the resource has dynamic properties
         let mut has_dynamic = false;
         // If a certain class needs to be extended, add it to this match statement
-        for class in resource.get_classes(self)? {
+        let classes = resource.get_classes(self)?;
+        for class in &classes {
             match class.subject.as_ref() {
                 crate::urls::COLLECTION => {
                     has_dynamic = true;
@@ -792,6 +1219,49 @@ impl Storelike for Db {
                 _ => {}
             }
         }
+        if classes.iter().any(|c| !c.computed_properties.is_empty()) {
+            has_dynamic = true;
+            if !skip_dynamic {
+                // Agents may see different values, because they can read different sources.
+                // Delegated Agents are limited by the scope of their token, so their values are not cached.
+                let agent_key = match for_agent {
+                    ForAgent::Delegated(_) => None,
+                    other => Some(other.to_string()),
+                };
+                let cached = agent_key.as_ref().and_then(|agent_key| {
+                    self.computed_cache
+                        .lock()
+                        .unwrap()
+                        .get(&removed_query_params)
+                        .and_then(|by_agent| by_agent.get(agent_key))
+                        .cloned()
+                });
+                let computed = match cached {
+                    Some(computed) => computed,
+                    None => {
+                        let computed = crate::computed::compute_properties(
+                            self, &resource, &classes, for_agent,
+                        )?;
+                        if let Some(agent_key) = agent_key {
+                            let mut cache = self.computed_cache.lock().unwrap();
+                            if cache.len() >= COMPUTED_CACHE_SIZE
+                                && !cache.contains_key(&removed_query_params)
+                            {
+                                cache.clear();
+                            }
+                            cache
+                                .entry(removed_query_params.clone())
+                                .or_default()
+                                .insert(agent_key, computed.clone());
+                        }
+                        computed
+                    }
+                };
+                for (prop, val) in computed {
+                    resource.set_unsafe(prop, val);
+                }
+            }
+        }
         dynamic_span.exit();
 
         // make sure the actual subject matches the one requested - It should not be changed in the logic above
@@ -808,6 +1278,53 @@ impl Storelike for Db {
         Ok(resource)
     }
 
+    fn audit(&self, event: crate::audit::AuditEvent) {
+        let events = match self.audit_throttle.lock() {
+            Ok(mut throttle) => throttle.admit(event, crate::utils::now()),
+            Err(e) => {
+                tracing::error!("Audit throttle lock is poisoned. {}", e);
+                vec![event]
+            }
+        };
+        for event in events {
+            if let Err(e) = self.append_audit_event(event) {
+                tracing::error!("Failed to write to the audit log: {}", e);
+            }
+        }
+    }
+
+    fn get_snapshot(&self, commit_url: &str) -> Option<PropVals> {
+        match self.read_snapshot(commit_url) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::error!("Failed to read snapshot of {}: {}", commit_url, e);
+                None
+            }
+        }
+    }
+
+    fn handle_not_found(
+        &self,
+        subject: &str,
+        _error: AtomicError,
+        for_agent: Option<&crate::agents::Agent>,
+    ) -> AtomicResult<Resource> {
+        // Mirrored Resources only change through replicated Commits, so they are never fetched from the leader
+        if self.is_mirrored(subject) {
+            return Err(AtomicError::not_found(format!(
+                "Failed to retrieve mirrored Resource locally: '{}'",
+                subject
+            )));
+        }
+        if subject.starts_with(self.get_server_url()) {
+            return Err(AtomicError::not_found(format!(
+                "Failed to retrieve locally: '{}'",
+                subject
+            )));
+        }
+        self.fetch_resource(subject, for_agent)
+    }
+
     fn handle_commit(&self, commit_response: &CommitResponse) {
         if let Some(fun) = &self.on_commit {
             fun(commit_response);
@@ -819,11 +1336,13 @@ impl Storelike for Db {
     /// Tries `query_cache`, which you should implement yourself.
     #[instrument(skip(self))]
     fn query(&self, q: &Query) -> AtomicResult<QueryResult> {
-        if requires_query_index(q) {
-            return self.query_complex(q);
-        }
+        query_including_subclasses(self, q, |q| {
+            if requires_query_index(q) {
+                return self.query_complex(q);
+            }
 
-        self.query_basic(q)
+            self.query_basic(q)
+        })
     }
 
     #[instrument(skip(self))]
@@ -897,11 +1416,8 @@ impl Storelike for Db {
         let mut transaction = Transaction::new();
         if let Ok(found) = self.get_propvals(subject) {
             let resource = Resource::from_propvals(found, subject.to_string());
-            for (prop, val) in resource.get_propvals() {
-                let remove_atom = crate::Atom::new(subject.into(), prop.clone(), val.clone());
-                self.remove_atom_from_index(&remove_atom, &resource, &mut transaction)?;
-            }
-            let _found = self.resources.remove(subject.as_bytes())?;
+            self.remove_resource_tx(&resource, &mut transaction)?;
+            self.invalidate_computed(subject, Some(resource.get_propvals()), None);
         } else {
             return Err(format!(
                 "Resource {} could not be deleted, because it was not found in the store.",
@@ -916,12 +1432,19 @@ impl Storelike for Db {
     fn set_default_agent(&self, agent: crate::agents::Agent) {
         self.default_agent.lock().unwrap().replace(agent);
     }
+
+    fn validate(&self) -> crate::validate::ValidationReport {
+        crate::validate::validate_store(self, false)
+    }
 }
 
 fn corrupt_db_message(subject: &str) -> String {
     format!("Could not deserialize item {} from database. DB is possibly corrupt, could be due to an update or a lack of migrations. Restore to a previous version, export your data and import your data again.", subject)
 }
 
+/// The maximum amount of subjects in the cache of Computed Properties. The cache is cleared when it is full.
+const COMPUTED_CACHE_SIZE: usize = 10_000;
+
 const DB_CORRUPT_MSG: &str = "Could not deserialize item from database. DB is possibly corrupt, could be due to an update or a lack of migrations. Restore to a previous version, export your data and import your data again.";
 
 impl std::fmt::Debug for Db {
@@ -0,0 +1,112 @@
+//! Stores the [crate::audit] log in [Tree::AuditLog], and its signed head in [Tree::AuditHead].
+
+use sled::Transactional;
+
+use crate::{
+    audit::{verify_chain, AuditEntry, AuditEvent, AuditFilter, AuditHead},
+    errors::AtomicResult,
+    Db, Storelike,
+};
+
+const HEAD_KEY: &[u8] = b"head";
+
+use super::trees::Tree;
+
+impl Db {
+    /// Appends an event to the audit log, linked to the last entry.
+    /// The new head is signed by the default Agent, if it has a private key.
+    /// Fails if the current head does not point to the last entry.
+    pub fn append_audit_event(&self, event: AuditEvent) -> AtomicResult<AuditEntry> {
+        let _lock = self
+            .audit_lock
+            .lock()
+            .map_err(|e| format!("Audit log lock is poisoned. {}", e))?;
+        let previous = match self.audit_log.last()? {
+            Some((_key, val)) => Some(parse_entry(&val)?),
+            None => None,
+        };
+        // Appending after the log was truncated would hide it, so the log stops accepting entries instead
+        if let (Some(head), Some(previous)) = (self.audit_head()?, &previous) {
+            if head.sequence != previous.sequence || head.hash != previous.hash {
+                return Err(format!(
+                    "Audit log head points to entry {}, but the last entry is {}. The audit log may have been tampered with.",
+                    head.sequence, previous.sequence
+                )
+                .into());
+            }
+        }
+        let entry = AuditEntry::new(event, previous.as_ref())?;
+        let head = AuditHead::new(&entry, self.get_default_agent().ok().as_ref())?;
+        let entry_json = entry.to_json()?;
+        let head_json = serde_json::to_string(&head)
+            .map_err(|e| format!("Failed to serialize audit log head. {}", e))?;
+        (&self.audit_log, &self.audit_head)
+            .transaction(|(log, heads)| {
+                log.insert(&entry.sequence.to_be_bytes(), entry_json.as_bytes())?;
+                heads.insert(HEAD_KEY, head_json.as_bytes())?;
+                Ok::<(), sled::transaction::ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| format!("Failed to append to the audit log. {:?}", e))?;
+        Ok(entry)
+    }
+
+    /// Returns all entries in the audit log, oldest first.
+    pub fn audit_entries(&self) -> impl Iterator<Item = AtomicResult<AuditEntry>> {
+        self.audit_log.iter().map(|item| {
+            let (_key, val) =
+                item.map_err(|e| format!("Failed reading {}. {}", Tree::AuditLog, e))?;
+            parse_entry(&val)
+        })
+    }
+
+    /// Returns the entries in the audit log that match the filter, oldest first.
+    pub fn query_audit_log(&self, filter: &AuditFilter) -> AtomicResult<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+        for entry in self.audit_entries() {
+            let entry = entry?;
+            if filter.matches(&entry) {
+                entries.push(entry);
+                if Some(entries.len()) == filter.limit {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Returns the signed head of the audit log, if anything has been logged.
+    pub fn audit_head(&self) -> AtomicResult<Option<AuditHead>> {
+        match self.audit_head.get(HEAD_KEY)? {
+            Some(val) => {
+                Ok(Some(serde_json::from_slice(&val).map_err(|e| {
+                    format!("Could not deserialize audit log head. {}", e)
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Checks whether the audit log has been tampered with. Returns the amount of entries.
+    /// The head has to be signed by the public key of the default Agent, so rewriting the chain or removing entries from the end is detected.
+    /// Someone with access to an older copy of the database could still restore it as a whole, so keep an exported [Db::audit_head] to compare with.
+    pub fn verify_audit_log(&self) -> AtomicResult<u64> {
+        let count = verify_chain(self.audit_entries())?;
+        let last = match self.audit_log.last()? {
+            Some((_key, val)) => parse_entry(&val)?,
+            None => {
+                if self.audit_head()?.is_some() {
+                    return Err("Audit log is empty, but its head points to an entry".into());
+                }
+                return Ok(0);
+            }
+        };
+        let head = self.audit_head()?.ok_or("Audit log has no head")?;
+        head.verify(&last, &self.get_default_agent()?.public_key)?;
+        Ok(count)
+    }
+}
+
+fn parse_entry(val: &[u8]) -> AtomicResult<AuditEntry> {
+    serde_json::from_slice(val)
+        .map_err(|e| format!("Could not deserialize audit log entry. {}", e).into())
+}
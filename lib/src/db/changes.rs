@@ -0,0 +1,97 @@
+//! Stores the [crate::changes] feed in [Tree::Changes].
+
+use std::time::Duration;
+
+use crate::{
+    changes::{ChangeEntry, ChangeFilter},
+    commit::CommitResponse,
+    errors::AtomicResult,
+    Db,
+};
+
+use super::trees::{Method, Operation, Transaction, Tree};
+
+impl Db {
+    /// Adds the Commit to the changefeed, after the last entry.
+    /// Must be called while holding the commit lock, so the sequence follows the apply order.
+    /// Entries that are already staged in the transaction are counted, so several Commits can share one transaction.
+    pub(crate) fn add_change_tx(
+        &self,
+        commit_response: &CommitResponse,
+        transaction: &mut Transaction,
+    ) -> AtomicResult<()> {
+        let staged = transaction
+            .iter()
+            .filter(|op| matches!(op.tree, Tree::Changes))
+            .count() as u64;
+        let sequence = self.last_change_sequence()?.map(|s| s + 1).unwrap_or(0) + staged;
+        // The parents of trashed children have been removed already, so use the Drive from the trash
+        let drive = match &commit_response.resource_new {
+            None => self
+                .get_trashed_tx(&commit_response.commit.subject, transaction)?
+                .and_then(|trashed| trashed.drive),
+            Some(_) => None,
+        };
+        let entry = ChangeEntry::new(sequence, commit_response, drive, self)?;
+        transaction.push(Operation {
+            tree: Tree::Changes,
+            method: Method::Insert,
+            key: sequence.to_be_bytes().to_vec(),
+            val: Some(entry.to_json()?.into_bytes()),
+        });
+        Ok(())
+    }
+
+    /// Returns the sequence of the last entry in the changefeed.
+    pub fn last_change_sequence(&self) -> AtomicResult<Option<u64>> {
+        match self.changes.last()? {
+            Some((key, _val)) => Ok(Some(parse_sequence(&key)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the entries in the changefeed that match the filter, in apply order.
+    pub fn query_changes(&self, filter: &ChangeFilter) -> AtomicResult<Vec<ChangeEntry>> {
+        let start = match filter.since {
+            Some(since) => since.saturating_add(1),
+            None => 0,
+        };
+        let mut entries = Vec::new();
+        for item in self.changes.range(start.to_be_bytes()..) {
+            let (_key, val) =
+                item.map_err(|e| format!("Failed reading {}. {}", Tree::Changes, e))?;
+            let entry: ChangeEntry = serde_json::from_slice(&val)
+                .map_err(|e| format!("Could not deserialize change. {}", e))?;
+            if filter.matches(&entry) {
+                entries.push(entry);
+                if Some(entries.len()) == filter.limit {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Blocks until an entry after `since` is added to the changefeed, or until the timeout has passed.
+    /// Returns whether there are new entries.
+    pub fn wait_for_changes(&self, since: Option<u64>, timeout: Duration) -> AtomicResult<bool> {
+        let mut subscriber = self.changes.watch_prefix(vec![]);
+        let has_new = |last: Option<u64>| match (last, since) {
+            (Some(last), Some(since)) => last > since,
+            (last, None) => last.is_some(),
+            (None, Some(_)) => false,
+        };
+        // Entries may have been added before the subscription started
+        if has_new(self.last_change_sequence()?) {
+            return Ok(true);
+        }
+        Ok(subscriber.next_timeout(timeout).is_ok())
+    }
+}
+
+fn parse_sequence(key: &[u8]) -> AtomicResult<u64> {
+    let bytes: [u8; 8] = key
+        .try_into()
+        .map_err(|_| format!("Invalid key in {}", Tree::Changes))?;
+    Ok(u64::from_be_bytes(bytes))
+}
@@ -0,0 +1,191 @@
+//! Enforces the [OnDelete] policies of Properties when a Resource is destroyed.
+//! Uses the {Value}-{Property}-{Subject} index to find the Resources that link to the destroyed Resource.
+//! The Agent destroying the Resource needs write rights for every Resource that is destroyed or changed by a policy.
+//! These changes are applied in the same transaction as the destroy.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    agents::ForAgent,
+    commit::{CommitBuilder, CommitOpts},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    schema::OnDelete,
+    urls,
+    values::SubResource,
+    Commit, Db, Resource, Storelike, Value,
+};
+
+use super::val_prop_sub_index::find_in_val_prop_sub_index;
+
+/// What has to change when a Resource is destroyed.
+#[derive(Debug, Default)]
+pub struct OnDeletePlan {
+    /// Resources that are destroyed as well, because of a `cascade` policy.
+    pub cascade: Vec<String>,
+    /// Resources from which the links to a destroyed Resource are removed, because of a `set-null` policy.
+    pub set_null: Vec<Resource>,
+}
+
+/// Returns the (subject, property) pairs of the local Resources that link to `subject`.
+fn find_references(store: &Db, subject: &str) -> AtomicResult<Vec<(String, String)>> {
+    let mut references = Vec::new();
+    for atom in find_in_val_prop_sub_index(store, &Value::AtomicUrl(subject.into()), None) {
+        let atom = atom?;
+        if atom.subject != subject && atom.subject.starts_with(store.get_server_url()) {
+            references.push((atom.subject, atom.property));
+        }
+    }
+    Ok(references)
+}
+
+/// Walks the Resources that link to `subject`, and determines what happens to them when it is destroyed.
+/// Returns an error listing the referencing subjects if a `restrict` policy is violated.
+/// Resources that are destroyed by a `cascade` policy are destroyed together with their children.
+/// Fails if `for_agent` can't write one of the Resources that are destroyed or changed.
+pub fn plan_on_delete(
+    store: &Db,
+    subject: &str,
+    for_agent: &ForAgent,
+) -> AtomicResult<OnDeletePlan> {
+    let mut destroyed: HashSet<String> = HashSet::from([subject.to_string()]);
+    let mut queue: VecDeque<String> = VecDeque::from([subject.to_string()]);
+    let mut cascade = Vec::new();
+    // Target, referencing subject, property
+    let mut set_null: Vec<(String, String, String)> = Vec::new();
+    let mut restricted: Vec<(String, String)> = Vec::new();
+
+    while let Some(target) = queue.pop_front() {
+        for (referencing, property) in find_references(store, &target)? {
+            let policy = if property == urls::PARENT && target != subject {
+                OnDelete::Cascade
+            } else {
+                match store.get_property(&property) {
+                    Ok(prop) => prop.on_delete,
+                    Err(_) => OnDelete::Ignore,
+                }
+            };
+            match policy {
+                OnDelete::Ignore => {}
+                OnDelete::Restrict => restricted.push((target.clone(), referencing)),
+                OnDelete::SetNull => set_null.push((target.clone(), referencing, property)),
+                OnDelete::Cascade => {
+                    if destroyed.insert(referencing.clone()) {
+                        cascade.push(referencing.clone());
+                        queue.push_back(referencing);
+                    }
+                }
+            }
+        }
+    }
+
+    // Links from Resources that are destroyed as well don't matter
+    let mut blocking: Vec<String> = restricted
+        .into_iter()
+        .filter(|(_target, referencing)| !destroyed.contains(referencing))
+        .map(|(_target, referencing)| referencing)
+        .collect();
+    if !blocking.is_empty() {
+        blocking.sort();
+        blocking.dedup();
+        return Err(format!(
+            "Can't destroy {}, because it is referenced by: {}",
+            subject,
+            blocking.join(", ")
+        )
+        .into());
+    }
+
+    for cascaded in &cascade {
+        check_write(store, &store.get_resource(cascaded)?, for_agent)?;
+    }
+
+    let mut updated: Vec<Resource> = Vec::new();
+    for (target, referencing, property) in set_null {
+        if destroyed.contains(&referencing) {
+            continue;
+        }
+        let index = match updated.iter().position(|r| r.get_subject() == &referencing) {
+            Some(index) => index,
+            None => {
+                updated.push(store.get_resource(&referencing)?);
+                updated.len() - 1
+            }
+        };
+        remove_link(&mut updated[index], &property, &target, store)?;
+    }
+    for resource in &updated {
+        check_write(store, resource, for_agent)?;
+        resource.check_required_props(store).map_err(|e| {
+            format!(
+                "Can't destroy {}, because the link from {} can't be removed: {}",
+                subject,
+                resource.get_subject(),
+                e
+            )
+        })?;
+    }
+
+    Ok(OnDeletePlan {
+        cascade,
+        set_null: updated,
+    })
+}
+
+/// Removes `target` from the value of `property`. Removes the whole property if no links remain.
+fn remove_link(
+    resource: &mut Resource,
+    property: &str,
+    target: &str,
+    store: &Db,
+) -> AtomicResult<()> {
+    let remaining = match resource.get(property)? {
+        Value::ResourceArray(items) => items
+            .iter()
+            .filter(|item| !matches!(item, SubResource::Subject(s) if s == target))
+            .cloned()
+            .collect::<Vec<SubResource>>(),
+        _ => Vec::new(),
+    };
+    if remaining.is_empty() {
+        resource.remove_propval(property);
+    } else {
+        resource.set(property.into(), Value::ResourceArray(remaining), store)?;
+    }
+    Ok(())
+}
+
+/// Creates the Commits that apply the plan, signed by the default Agent.
+/// The rights have been checked by [plan_on_delete], so the Commits are not checked again.
+pub fn on_delete_commits(
+    store: &Db,
+    plan: OnDeletePlan,
+) -> AtomicResult<Vec<(Commit, CommitOpts)>> {
+    let agent = store.get_default_agent()?;
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_links: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        update_index: true,
+        validate_for_agent: Some(agent.subject.as_str().into()),
+    };
+    let mut commits = Vec::new();
+    for resource in plan.set_null {
+        let commit = resource
+            .get_commit_builder()
+            .clone()
+            .sign(&agent, store, &resource)?;
+        commits.push((commit, opts.clone()));
+    }
+    for subject in plan.cascade {
+        let resource = store.get_resource(&subject)?;
+        let mut builder = CommitBuilder::new(subject);
+        builder.destroy(true);
+        let commit = builder.sign(&agent, store, &resource)?;
+        commits.push((commit, opts.clone()));
+    }
+    Ok(commits)
+}
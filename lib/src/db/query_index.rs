@@ -152,7 +152,12 @@ fn find_matching_propval<'a>(
     if let Some(property) = &q_filter.property {
         if let Ok(matched_val) = resource.get(property) {
             if let Some(filter_val) = &q_filter.value {
-                if matched_val.to_string() == filter_val.to_string() {
+                // Typed Arrays match any of their items, other values have to be equal
+                let matches = match matched_val {
+                    Value::Array(_) => matched_val.contains_value(filter_val),
+                    other => other.to_string() == filter_val.to_string(),
+                };
+                if matches {
                     return Some(property);
                 }
             } else {
@@ -315,10 +320,6 @@ pub fn update_indexed_member(
     Ok(())
 }
 
-/// Maximum string length for values in the query_index. Should be long enough to contain pretty long URLs, but not very long documents.
-// Consider moving this to [Value::to_sortable_string]
-pub const MAX_LEN: usize = 120;
-
 /// Creates a key for a collection + value combination.
 /// These are designed to be lexicographically sortable.
 #[tracing::instrument()]
@@ -331,14 +332,7 @@ pub fn create_query_index_key(
     q_filter_bytes.push(SEPARATION_BIT);
 
     let mut value_bytes: Vec<u8> = if let Some(val) = value {
-        let val_string = val;
-        let shorter = if val_string.len() > MAX_LEN {
-            &val_string[0..MAX_LEN]
-        } else {
-            val_string
-        };
-        let lowercase = shorter.to_lowercase();
-        lowercase.as_bytes().to_vec()
+        crate::values::sort_key(val).into_bytes()
     } else {
         vec![0]
     };
@@ -493,6 +487,56 @@ pub mod test {
         assert_eq!(sorted, expected);
     }
 
+    #[test]
+    fn sortable_extended_datatypes() {
+        let q = QueryFilter {
+            property: Some("http://example.org/prop".to_string()),
+            value: None,
+            sort_by: None,
+        };
+        let key = |val: &Value| {
+            create_query_index_key(&q, Some(&val.to_sortable_string()), None).unwrap()
+        };
+        let assert_sorted = |values: Vec<Value>| {
+            for pair in values.windows(2) {
+                assert!(
+                    key(&pair[0]) < key(&pair[1]),
+                    "{} should sort before {}",
+                    pair[0],
+                    pair[1]
+                );
+            }
+        };
+
+        assert_sorted(
+            [
+                "-100", "-12.5", "-12", "-0.5", "0", "0.05", "0.5", "9", "12", "12.05", "12.5",
+                "100",
+            ]
+            .iter()
+            .map(|d| Value::Decimal(d.to_string()))
+            .collect(),
+        );
+        // Compared in UTC: 08:00, 09:30, 09:30:00.5, 10:00
+        assert_sorted(
+            [
+                "2024-03-01T10:00:00+02:00",
+                "2024-03-01T09:30:00+00:00",
+                "2024-03-01T09:30:00.5Z",
+                "2024-03-01T05:00:00-05:00",
+            ]
+            .iter()
+            .map(|d| Value::DateTime(d.to_string()))
+            .collect(),
+        );
+        assert_sorted(
+            ["PT90M", "P1D", "P1W", "P1M"]
+                .iter()
+                .map(|d| Value::Duration(d.to_string()))
+                .collect(),
+        );
+    }
+
     #[test]
     fn should_update_or_not() {
         let store = &Db::init_temp("should_update_or_not").unwrap();
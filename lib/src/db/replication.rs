@@ -0,0 +1,179 @@
+//! Stores the [ReplicationStatus] of mirrored Drives in [Tree::Replication], and applies the Commits that are replicated from their leaders.
+
+use std::time::Duration;
+
+use crate::{
+    changes::ChangeEntry,
+    commit::CommitOpts,
+    errors::AtomicResult,
+    replication::{commit_from_entry, fetch_changes, ReplicationStatus},
+    Db, Storelike,
+};
+
+use super::{trees::Tree, DestroyEffects};
+
+/// By default, up to 100 Commits are replicated per sync.
+pub const DEFAULT_REPLICATION_BATCH: usize = 100;
+
+impl Db {
+    /// Starts mirroring a Drive of another server. Call [Db::sync_replication] to replicate its Commits.
+    pub fn add_mirror(&self, drive: &str) -> AtomicResult<ReplicationStatus> {
+        let status = ReplicationStatus::new(drive)?;
+        if status.is_leader_subject(self.get_server_url()) {
+            return Err(format!(
+                "Can't mirror {}, because it is hosted by this server",
+                drive
+            )
+            .into());
+        }
+        if let Some(existing) = self.get_replication_status(drive)? {
+            return Ok(existing);
+        }
+        self.save_replication_status(&status)?;
+        Ok(status)
+    }
+
+    /// Stops mirroring a Drive. The replicated Resources are kept.
+    pub fn remove_mirror(&self, drive: &str) -> AtomicResult<()> {
+        if self.replication.remove(drive.as_bytes())?.is_none() {
+            return Err(format!("{} is not mirrored", drive).into());
+        }
+        Ok(())
+    }
+
+    pub fn get_replication_status(&self, drive: &str) -> AtomicResult<Option<ReplicationStatus>> {
+        match self.replication.get(drive.as_bytes())? {
+            Some(val) => Ok(Some(serde_json::from_slice(&val).map_err(|e| {
+                format!(
+                    "Could not deserialize replication status of {}. {}",
+                    drive, e
+                )
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the status of all mirrored Drives.
+    pub fn list_mirrors(&self) -> AtomicResult<Vec<ReplicationStatus>> {
+        self.replication
+            .iter()
+            .map(|item| {
+                let (_key, val) =
+                    item.map_err(|e| format!("Failed reading {}. {}", Tree::Replication, e))?;
+                serde_json::from_slice(&val)
+                    .map_err(|e| format!("Could not deserialize replication status. {}", e).into())
+            })
+            .collect()
+    }
+
+    fn save_replication_status(&self, status: &ReplicationStatus) -> AtomicResult<()> {
+        self.replication
+            .insert(status.drive.as_bytes(), status.to_json()?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Whether `subject` is a mirrored Drive, or a Resource whose URL is inside the path of one.
+    pub fn is_mirrored(&self, subject: &str) -> bool {
+        match self.list_mirrors() {
+            Ok(mirrors) => mirrors.iter().any(|m| m.is_drive_subject(subject)),
+            Err(e) => {
+                tracing::error!("Failed to read mirrored Drives: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Verifies and applies the changes of a mirrored Drive in order, and moves its cursor past them.
+    /// Changes before the cursor and Commits that are already stored are skipped.
+    /// Returns the amount of applied Commits.
+    pub fn apply_replicated_changes(
+        &self,
+        drive: &str,
+        entries: &[ChangeEntry],
+    ) -> AtomicResult<usize> {
+        let mut status = self
+            .get_replication_status(drive)?
+            .ok_or_else(|| format!("{} is not mirrored", drive))?;
+        // The leader has checked the rights, schema and order of the Commits already.
+        // The follower only needs to make sure that they are signed by their signer.
+        // Destroys are not trashed here, because the leader replicates the destroys of the children as well.
+        let opts = CommitOpts {
+            validate_schema: false,
+            validate_links: false,
+            validate_signature: true,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            update_index: true,
+            validate_for_agent: None,
+        };
+        let mut applied = 0;
+        for entry in entries {
+            if status.cursor.is_some_and(|cursor| entry.sequence <= cursor) {
+                continue;
+            }
+            let commit = commit_from_entry(entry, self)?;
+            let commit_url = commit.url.clone().ok_or("Replicated Commit has no URL")?;
+            if !status.is_leader_subject(&commit.subject) || !status.is_leader_subject(&commit_url)
+            {
+                return Err(format!(
+                    "Replicated Commit {} is not hosted by leader {}",
+                    commit_url, status.leader
+                )
+                .into());
+            }
+            if self.get_propvals(&commit_url).is_err() {
+                // Mirrored Resources are not fetched on demand, so fetch the signer before it's needed for the signature check
+                if self.get_propvals(&commit.signer).is_err() {
+                    self.fetch_resource(&commit.signer, self.get_default_agent().ok().as_ref())
+                        .map_err(|e| format!("Could not fetch signer of {}. {}", commit_url, e))?;
+                }
+                self.apply_commit_with_policies(commit, &opts, DestroyEffects::None)
+                    .map_err(|e| format!("Failed to apply replicated {}: {}", commit_url, e))?;
+                status.applied += 1;
+                applied += 1;
+            }
+            status.cursor = Some(entry.sequence);
+            status.lag_ms = Some(crate::utils::now() - entry.applied_at);
+            self.save_replication_status(&status)?;
+        }
+        Ok(applied)
+    }
+
+    /// Fetches the next changes of a mirrored Drive from its leader and applies them.
+    /// If there are no new changes, the leader waits up to `wait` for one.
+    /// The default Agent is used to read the changefeed of the leader.
+    pub fn sync_replication(
+        &self,
+        drive: &str,
+        limit: usize,
+        wait: Duration,
+    ) -> AtomicResult<ReplicationStatus> {
+        let status = self
+            .get_replication_status(drive)?
+            .ok_or_else(|| format!("{} is not mirrored", drive))?;
+        let agent = self.get_default_agent().ok();
+        let result = fetch_changes(&status, limit, wait, agent.as_ref()).and_then(|entries| {
+            self.apply_replicated_changes(drive, &entries)?;
+            Ok(entries.len())
+        });
+
+        let mut status = self
+            .get_replication_status(drive)?
+            .ok_or_else(|| format!("{} is not mirrored", drive))?;
+        status.last_sync_at = Some(crate::utils::now());
+        match &result {
+            Ok(received) => {
+                status.caught_up = *received < limit;
+                status.last_error = None;
+            }
+            Err(e) => {
+                status.caught_up = false;
+                status.last_error = Some(e.to_string());
+            }
+        }
+        self.save_replication_status(&status)?;
+        result?;
+        Ok(status)
+    }
+}
@@ -0,0 +1,196 @@
+//! Stores versions of Resources in [Tree::Snapshots], so [crate::plugins::versioning::construct_version] only has to replay the Commits after the nearest snapshot.
+//! Also compacts version histories, by removing old intermediate Commits.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    agents::ForAgent,
+    errors::AtomicResult,
+    plugins::versioning::{construct_version, get_commits_for_resource},
+    resources::PropVals,
+    urls, Db, Resource, Storelike, Value,
+};
+
+use super::{
+    prop_val_sub_index::find_in_prop_val_sub_index,
+    trees::{Method, Operation, Transaction, Tree},
+};
+
+/// By default, a snapshot is stored for every 100th Commit of a Resource.
+pub const DEFAULT_SNAPSHOT_INTERVAL: usize = 100;
+
+/// Determines which Commits are kept when a version history is compacted.
+/// The first Commit of a Resource is always kept, because it shows who created it.
+#[derive(Clone, Debug)]
+pub struct CompactionPolicy {
+    /// The amount of most recent Commits that are kept. At least one is always kept.
+    pub keep_recent: usize,
+    /// Commits created after this timestamp (milliseconds since epoch) are kept as well.
+    pub keep_after: Option<i64>,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        CompactionPolicy {
+            keep_recent: DEFAULT_SNAPSHOT_INTERVAL,
+            keep_after: None,
+        }
+    }
+}
+
+/// Snapshots are stored without `lastCommit`, so they match versions that are constructed by replaying Commits.
+fn snapshot_bin(resource: &Resource) -> AtomicResult<Vec<u8>> {
+    let mut propvals = resource.get_propvals().clone();
+    propvals.remove(urls::LAST_COMMIT);
+    Ok(bincode::serialize(&propvals)?)
+}
+
+impl Db {
+    /// Sets for which Commits a snapshot is stored: one for every n-th Commit of a Resource. `0` disables snapshots.
+    pub fn set_snapshot_interval(&mut self, interval: usize) {
+        self.snapshot_interval = interval;
+    }
+
+    pub(crate) fn read_snapshot(&self, commit_url: &str) -> AtomicResult<Option<PropVals>> {
+        match self.snapshots.get(commit_url.as_bytes())? {
+            Some(bin) => Ok(Some(bincode::deserialize(&bin).map_err(|e| {
+                format!("Could not deserialize snapshot of {}. {}", commit_url, e)
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `resource` as the version that `commit_url` created.
+    pub fn save_snapshot(&self, commit_url: &str, resource: &Resource) -> AtomicResult<()> {
+        self.snapshots
+            .insert(commit_url.as_bytes(), snapshot_bin(resource)?)?;
+        Ok(())
+    }
+
+    /// Returns the amount of Commits applied to `subject`, including the ones staged in the transaction.
+    /// Resources without a counter, such as those created before counters existed, are counted once using the index.
+    fn commit_count(&self, subject: &str, transaction: &Transaction) -> AtomicResult<u64> {
+        let staged = transaction.iter().rev().find(|op| {
+            matches!(op.tree, Tree::CommitCounts) && op.key.as_slice() == subject.as_bytes()
+        });
+        let stored = match staged {
+            Some(op) => op.val.clone(),
+            None => self
+                .commit_counts
+                .get(subject.as_bytes())?
+                .map(|v| v.to_vec()),
+        };
+        match stored {
+            Some(bin) => {
+                Ok(u64::from_be_bytes(bin.as_slice().try_into().map_err(
+                    |_| format!("Invalid commit count for {}", subject),
+                )?))
+            }
+            None => {
+                let value = Value::AtomicUrl(subject.into());
+                Ok(find_in_prop_val_sub_index(self, urls::SUBJECT, Some(&value)).count() as u64)
+            }
+        }
+    }
+
+    /// Counts the Commit, and adds a snapshot of `resource_new` to the transaction if the Commit is an n-th Commit of the Resource.
+    pub(crate) fn add_snapshot_maybe(
+        &self,
+        commit_resource: &Resource,
+        resource_new: &Resource,
+        transaction: &mut Transaction,
+    ) -> AtomicResult<()> {
+        let subject = resource_new.get_subject();
+        let count = self.commit_count(subject, transaction)? + 1;
+        transaction.push(Operation {
+            tree: Tree::CommitCounts,
+            method: Method::Insert,
+            key: subject.as_bytes().to_vec(),
+            val: Some(count.to_be_bytes().to_vec()),
+        });
+        if self.snapshot_interval != 0 && count.is_multiple_of(self.snapshot_interval as u64) {
+            transaction.push(Operation {
+                tree: Tree::Snapshots,
+                method: Method::Insert,
+                key: commit_resource.get_subject().as_bytes().to_vec(),
+                val: Some(snapshot_bin(resource_new)?),
+            });
+        }
+        Ok(())
+    }
+
+    /// Stores the missing snapshots for the Commits of `subject`, e.g. for histories that were created before snapshots existed.
+    /// Returns the amount of stored snapshots.
+    pub fn create_snapshots(&self, subject: &str) -> AtomicResult<usize> {
+        if self.snapshot_interval == 0 {
+            return Ok(0);
+        }
+        let commits = get_commits_for_resource(subject, self)?;
+        let mut version = Resource::new(subject.into());
+        let mut created = 0;
+        for (index, commit) in commits.iter().enumerate() {
+            let Some(url) = &commit.url else {
+                continue;
+            };
+            version = match self.read_snapshot(url)? {
+                Some(propvals) => Resource::from_propvals(propvals, subject.into()),
+                None => commit.apply_changes(version, self)?.resource_new,
+            };
+            if (index + 1).is_multiple_of(self.snapshot_interval)
+                && self.read_snapshot(url)?.is_none()
+            {
+                self.save_snapshot(url, &version)?;
+                created += 1;
+            }
+        }
+        Ok(created)
+    }
+
+    /// Removes the Commits of `subject` that the policy does not keep.
+    /// Stores a snapshot for the oldest remaining recent Commit, so all remaining versions can still be constructed.
+    /// The remaining Commits are not changed, so their signatures can still be verified.
+    /// Returns the amount of removed Commits.
+    pub fn compact_history(&self, subject: &str, policy: &CompactionPolicy) -> AtomicResult<usize> {
+        let commits = get_commits_for_resource(subject, self)?;
+        let mut first_kept = commits.len().saturating_sub(policy.keep_recent.max(1));
+        if let Some(keep_after) = policy.keep_after {
+            while first_kept > 1 && commits[first_kept - 1].created_at > keep_after {
+                first_kept -= 1;
+            }
+        }
+        // The first Commit is always kept
+        if first_kept <= 1 {
+            return Ok(0);
+        }
+        let Some(base_url) = &commits[first_kept].url else {
+            return Err(format!("Commit for {} has no URL", subject).into());
+        };
+        if self.read_snapshot(base_url)?.is_none() {
+            let base = construct_version(base_url, self, &ForAgent::Sudo)?;
+            self.save_snapshot(base_url, &base)?;
+        }
+        let mut removed = 0;
+        for commit in &commits[1..first_kept] {
+            if let Some(url) = &commit.url {
+                self.remove_resource(url)?;
+                self.snapshots.remove(url.as_bytes())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Compacts the version histories of all Resources, including destroyed ones. See [Db::compact_history].
+    /// Returns the amount of removed Commits.
+    pub fn compact_all_histories(&self, policy: &CompactionPolicy) -> AtomicResult<usize> {
+        let mut subjects = BTreeSet::new();
+        for atom in find_in_prop_val_sub_index(self, urls::SUBJECT, None) {
+            subjects.insert(atom?.ref_value);
+        }
+        let mut removed = 0;
+        for subject in subjects {
+            removed += self.compact_history(&subject, policy)?;
+        }
+        Ok(removed)
+    }
+}
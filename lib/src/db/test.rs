@@ -1,4 +1,4 @@
-use crate::{agents::ForAgent, urls, Value};
+use crate::{agents::ForAgent, datatype::DataType, urls, Value};
 
 use super::*;
 use ntest::timeout;
@@ -573,3 +573,1294 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
         "Modifying the filtered value did not remove the item from the results"
     );
 }
+
+#[test]
+fn audit_log() {
+    use crate::audit::{AuditEvent, AuditEventKind, AuditFilter};
+
+    let store = Db::init_temp("audit_log").unwrap();
+    let subject = format!("{}/audited", store.get_server_url());
+    let mut resource = Resource::new(subject.clone());
+    resource
+        .set(urls::NAME.into(), Value::String("first".into()), &store)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+
+    // Another Agent is not allowed to edit the resource
+    let intruder = store.create_agent(Some("intruder")).unwrap();
+    let mut edited = store.get_resource(&subject).unwrap();
+    edited
+        .set(urls::NAME.into(), Value::String("hacked".into()), &store)
+        .unwrap();
+    let commit = edited
+        .get_commit_builder()
+        .clone()
+        .sign(&intruder, &store, &edited)
+        .unwrap();
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_links: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    store.apply_commit(commit, &opts).unwrap_err();
+
+    store
+        .get_resource(&subject)
+        .unwrap()
+        .destroy(&store)
+        .unwrap();
+
+    let denied = store
+        .query_audit_log(&AuditFilter {
+            kind: Some(AuditEventKind::WriteDenied),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(denied.len(), 1);
+    assert_eq!(denied[0].event.agent.as_ref(), Some(&intruder.subject));
+    assert_eq!(denied[0].event.subject.as_ref(), Some(&subject));
+
+    let destroyed = store
+        .query_audit_log(&AuditFilter {
+            kind: Some(AuditEventKind::Destroy),
+            subject: Some(subject.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(destroyed.len(), 1);
+
+    assert!(store.verify_audit_log().unwrap() >= 2);
+
+    // Removing the last entry no longer matches the signed head
+    let (last_key, _) = store.audit_log.last().unwrap().unwrap();
+    store.audit_log.remove(last_key).unwrap();
+    store.verify_audit_log().unwrap_err();
+    store
+        .append_audit_event(AuditEvent::new(
+            AuditEventKind::Sudo,
+            &ForAgent::Sudo,
+            &subject,
+        ))
+        .unwrap_err();
+}
+
+#[test]
+fn audit_log_throttles_denied_reads() {
+    use crate::audit::{AuditEvent, AuditEventKind};
+
+    let store = Db::init_temp("audit_log_throttles_denied_reads").unwrap();
+    let count = store.audit_entries().count();
+    for _ in 0..10 {
+        store.audit(AuditEvent::new(
+            AuditEventKind::ReadDenied,
+            &ForAgent::Public,
+            "https://localhost/secret",
+        ));
+    }
+    assert_eq!(store.audit_entries().count(), count + 1);
+    store.verify_audit_log().unwrap();
+}
+
+#[test]
+fn subclass_queries() {
+    use crate::schema::Class;
+
+    let store = Db::init_temp("subclass_queries").unwrap();
+    let class = |shortname: &str, sub_class_of: Vec<String>| Class {
+        requires: vec![urls::NAME.into()],
+        recommends: vec![],
+        shortname: shortname.into(),
+        description: shortname.into(),
+        subject: format!("{}/{}", store.get_server_url(), shortname),
+        sub_class_of,
+        computed_properties: vec![],
+    };
+    let person = class("person", vec![]);
+    let employee = class("employee", vec![person.subject.clone()]);
+    let manager = class("manager", vec![employee.subject.clone()]);
+    for c in [&person, &employee, &manager] {
+        store.add_resource(&c.to_resource()).unwrap();
+    }
+
+    let mut subjects = Vec::new();
+    for (name, class) in [("carol", &manager), ("alice", &person), ("bob", &employee)] {
+        let mut instance = Resource::new_instance(&class.subject, &store).unwrap();
+        instance
+            .set(urls::NAME.into(), Value::String(name.into()), &store)
+            .unwrap();
+        instance.save_locally(&store).unwrap();
+        subjects.push(instance.get_subject().to_string());
+    }
+
+    let mut q = Query::new_class(&person.subject);
+    q.include_nested = false;
+    let res = store.query(&q).unwrap();
+    assert_eq!(res.count, 3);
+
+    q.sort_by = Some(urls::NAME.into());
+    q.offset = 1;
+    q.limit = Some(1);
+    let res = store.query(&q).unwrap();
+    assert_eq!(
+        res.subjects,
+        vec![subjects[2].clone()],
+        "sorted, with offset"
+    );
+
+    let res = store.query(&Query::new_class(&employee.subject)).unwrap();
+    assert_eq!(res.count, 2);
+    assert_eq!(res.resources.len(), 2);
+
+    // Instances of both a Class and its subclass are counted once, also outside of the page
+    let mut both = Resource::new_instance(&person.subject, &store).unwrap();
+    both.set(
+        urls::IS_A.into(),
+        Value::from(vec![person.subject.clone(), employee.subject.clone()]),
+        &store,
+    )
+    .unwrap();
+    both.set(urls::NAME.into(), Value::String("both".into()), &store)
+        .unwrap();
+    both.save_locally(&store).unwrap();
+    let mut unsorted = Query::new_class(&person.subject);
+    unsorted.limit = Some(1);
+    let res = store.query(&unsorted).unwrap();
+    assert_eq!(res.count, 4);
+    assert_eq!(res.subjects.len(), 1);
+    both.destroy(&store).unwrap();
+
+    // The merged results have the same order as the index: lowercased values, then subjects
+    let mut daves = Vec::new();
+    for (name, class) in [("Dave", &employee), ("dave", &manager)] {
+        let mut instance = Resource::new_instance(&class.subject, &store).unwrap();
+        instance
+            .set(urls::NAME.into(), Value::String(name.into()), &store)
+            .unwrap();
+        instance.save_locally(&store).unwrap();
+        daves.push(instance.get_subject().to_string());
+    }
+    daves.sort();
+    q.offset = 3;
+    q.limit = Some(2);
+    assert_eq!(store.query(&q).unwrap().subjects, daves);
+    q.sort_desc = true;
+    q.offset = 0;
+    daves.reverse();
+    assert_eq!(store.query(&q).unwrap().subjects, daves);
+
+    // New subclasses are found after the cached subclasses are cleared
+    let intern = class("intern", vec![employee.subject.clone()]);
+    store.add_resource(&intern.to_resource()).unwrap();
+    let mut instance = Resource::new_instance(&intern.subject, &store).unwrap();
+    instance
+        .set(urls::NAME.into(), Value::String("erin".into()), &store)
+        .unwrap();
+    instance.save_locally(&store).unwrap();
+    let res = store.query(&Query::new_class(&employee.subject)).unwrap();
+    assert_eq!(res.count, 5);
+}
+
+#[test]
+fn typed_array_push_and_query() {
+    use crate::schema::Property;
+
+    let store = Db::init_temp("typed_array_push_and_query").unwrap();
+    let tags = Property {
+        class_type: None,
+        data_type: DataType::Array(Box::new(DataType::String)),
+        shortname: "tags".into(),
+        description: "tags".into(),
+        subject: format!("{}/tags", store.get_server_url()),
+        allows_only: None,
+        constraints: Default::default(),
+        on_delete: Default::default(),
+    };
+    store.add_resource(&tags.to_resource()).unwrap();
+
+    let mut resource = Resource::new_generate_subject(&store);
+    let initial = Value::new(r#"["red", "green"]"#, &tags.data_type).unwrap();
+    resource.set(tags.subject.clone(), initial, &store).unwrap();
+    resource.save_locally(&store).unwrap();
+
+    resource
+        .push_item(&tags.subject, Value::String("blue".into()))
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+    let saved = store.get_resource(resource.get_subject()).unwrap();
+    assert_eq!(
+        saved.get(&tags.subject).unwrap().to_string(),
+        r#"["red","green","blue"]"#
+    );
+
+    for tag in ["green", "blue"] {
+        let res = store
+            .query(&Query::new_prop_val(&tags.subject, tag))
+            .unwrap();
+        assert_eq!(res.subjects, vec![resource.get_subject().clone()], "{tag}");
+    }
+    let res = store
+        .query(&Query::new_prop_val(&tags.subject, "purple"))
+        .unwrap();
+    assert_eq!(res.count, 0);
+
+    resource
+        .push_item(&tags.subject, Value::Integer(1))
+        .unwrap();
+    resource.save_locally(&store).unwrap_err();
+}
+
+#[test]
+fn validate_db() {
+    let store = Db::init_temp("validate_db").unwrap();
+    let mut resource = Resource::new_generate_subject(&store);
+    resource
+        .set(urls::NAME.into(), Value::String("valid".into()), &store)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+    let report = store.validate();
+    assert!(report.resource_count > 100);
+    assert!(report.is_valid(), "{}", report);
+}
+
+#[test]
+fn commits_applied_atomically() {
+    use crate::commit::{CommitBuilder, CommitOpts};
+
+    let store = Db::init_temp("commits_applied_atomically").unwrap();
+    let agent = store.get_default_agent().unwrap();
+    let opts = CommitOpts {
+        update_index: true,
+        ..CommitOpts::no_validations_no_index()
+    };
+    let commit = |subject: &str, name: &str| {
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::NAME.into(), Value::String(name.into()));
+        let commit = builder
+            .sign(&agent, &store, &Resource::new(subject.into()))
+            .unwrap();
+        (commit, opts.clone())
+    };
+    let first = format!("{}/first", store.get_server_url());
+    let second = format!("{}/second", store.get_server_url());
+
+    // Every Commit is validated against the same state, so they can't change the same Resource
+    let err = store
+        .apply_commits_atomically(vec![commit(&first, "a"), commit(&first, "b")])
+        .unwrap_err();
+    assert!(err.to_string().contains(&first), "{}", err);
+    store.get_resource(&first).unwrap_err();
+
+    store
+        .apply_commits_atomically(vec![commit(&first, "a"), commit(&second, "b")])
+        .unwrap();
+    assert_eq!(
+        store
+            .get_resource(&second)
+            .unwrap()
+            .get(urls::NAME)
+            .unwrap()
+            .to_string(),
+        "b"
+    );
+}
+
+#[test]
+fn on_delete_policies() {
+    use crate::schema::{OnDelete, Property};
+
+    let store = Db::init_temp("on_delete_policies").unwrap();
+    let make_property = |shortname: &str, data_type: DataType, on_delete: OnDelete| {
+        let property = Property {
+            class_type: None,
+            data_type,
+            shortname: shortname.into(),
+            description: shortname.into(),
+            subject: format!("{}/{}", store.get_server_url(), shortname),
+            allows_only: None,
+            constraints: Default::default(),
+            on_delete,
+        };
+        store.add_resource(&property.to_resource()).unwrap();
+        property.subject
+    };
+    let owner = make_property("owner", DataType::AtomicUrl, OnDelete::Restrict);
+    let part_of = make_property("part-of", DataType::AtomicUrl, OnDelete::Cascade);
+    let related = make_property("related", DataType::ResourceArray, OnDelete::SetNull);
+    let create = |propvals: Vec<(&str, Value)>| {
+        let mut resource = Resource::new_generate_subject(&store);
+        for (prop, val) in propvals {
+            resource.set(prop.into(), val, &store).unwrap();
+        }
+        resource.save_locally(&store).unwrap();
+        resource.get_subject().clone()
+    };
+
+    let target = create(vec![]);
+    let other = create(vec![]);
+    let owned = create(vec![(&owner, Value::AtomicUrl(target.clone()))]);
+    let part = create(vec![(&part_of, Value::AtomicUrl(target.clone()))]);
+    let part_child = create(vec![(urls::PARENT, Value::AtomicUrl(part.clone()))]);
+    let linking = create(vec![(&related, vec![target.clone(), other.clone()].into())]);
+
+    let err = store
+        .get_resource(&target)
+        .unwrap()
+        .destroy(&store)
+        .unwrap_err();
+    assert!(err.to_string().contains(&owned), "{}", err);
+    assert!(store.get_resource(&target).is_ok());
+    assert!(store.get_resource(&part).is_ok());
+    assert_eq!(
+        store
+            .get_resource(&linking)
+            .unwrap()
+            .get(&related)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap(),
+        vec![target.clone(), other.clone()]
+    );
+
+    store.get_resource(&owned).unwrap().destroy(&store).unwrap();
+    store
+        .get_resource(&target)
+        .unwrap()
+        .destroy(&store)
+        .unwrap();
+    assert!(store.get_resource(&target).is_err());
+    assert!(store.get_resource(&part).is_err());
+    assert!(store.get_resource(&part_child).is_err());
+    assert_eq!(
+        store
+            .get_resource(&linking)
+            .unwrap()
+            .get(&related)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap(),
+        vec![other]
+    );
+
+    // Cascaded destroys need write rights for every Resource, and fail as a whole
+    let agent = store.create_agent(Some("editor")).unwrap();
+    let writable = create(vec![(urls::WRITE, vec![agent.subject.clone()].into())]);
+    let protected = create(vec![(&part_of, Value::AtomicUrl(writable.clone()))]);
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_links: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+    };
+    let destroy = |subject: &str| {
+        let mut builder = crate::commit::CommitBuilder::new(subject.into());
+        builder.destroy(true);
+        builder
+            .sign(&agent, &store, &store.get_resource(subject).unwrap())
+            .unwrap()
+    };
+    let err = store.apply_commit(destroy(&writable), &opts).unwrap_err();
+    assert!(err.message.contains("write"), "{}", err);
+    assert!(store.get_resource(&writable).is_ok());
+    assert!(store.get_resource(&protected).is_ok());
+
+    let mut resource = store.get_resource(&protected).unwrap();
+    resource
+        .push(urls::WRITE, agent.subject.clone().into(), true)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+    store.apply_commit(destroy(&writable), &opts).unwrap();
+    assert!(store.get_resource(&writable).is_err());
+    assert!(store.get_resource(&protected).is_err());
+}
+
+#[test]
+fn computed_properties() {
+    use crate::schema::{Class, Property};
+
+    let store = Db::init_temp("computed_properties").unwrap();
+    let server = store.get_server_url().to_string();
+    let property = |shortname: &str, data_type: DataType| {
+        let property = Property {
+            class_type: None,
+            data_type,
+            shortname: shortname.into(),
+            description: shortname.into(),
+            subject: format!("{}/{}", server, shortname),
+            allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
+        };
+        store.add_resource(&property.to_resource()).unwrap();
+        property.subject
+    };
+    let amount = property("amount", DataType::Integer);
+    let total = property("total", DataType::Integer);
+    let lines = property("lines", DataType::Integer);
+    let editor = property("editor", DataType::AtomicUrl);
+    let computed = |shortname: &str, computes: &str, aggregate: &str| {
+        let mut resource = Resource::new(format!("{}/{}", server, shortname));
+        resource.set_class(urls::COMPUTED_PROPERTY);
+        resource.set_unsafe(urls::COMPUTES.into(), Value::AtomicUrl(computes.into()));
+        resource.set_unsafe(urls::AGGREGATE.into(), Value::Slug(aggregate.into()));
+        resource.set_unsafe(
+            urls::VALUE_PROPERTY.into(),
+            Value::AtomicUrl(amount.clone()),
+        );
+        store.add_resource(&resource).unwrap();
+        resource.get_subject().clone()
+    };
+    let invoice_class = Class {
+        requires: vec![],
+        recommends: vec![],
+        shortname: "invoice".into(),
+        description: "invoice".into(),
+        subject: format!("{}/invoice", server),
+        sub_class_of: vec![],
+        computed_properties: vec![
+            computed("total-amount", &total, "sum"),
+            computed("line-count", &lines, "count"),
+            computed("last-editor", &editor, "last-signer"),
+        ],
+    };
+    store.add_resource(&invoice_class.to_resource()).unwrap();
+
+    let invoice = Resource::new_instance(&invoice_class.subject, &store)
+        .unwrap()
+        .save_locally(&store)
+        .unwrap()
+        .resource_new
+        .unwrap();
+    let add_line = |amount_val: i64| {
+        let mut line = Resource::new_generate_subject(&store);
+        line.set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(invoice.get_subject().clone()),
+            &store,
+        )
+        .unwrap();
+        line.set(amount.clone(), Value::Integer(amount_val), &store)
+            .unwrap();
+        line.save_locally(&store).unwrap();
+        line
+    };
+    let get = |prop: &str| {
+        store
+            .get_resource_extended(invoice.get_subject(), false, &ForAgent::Sudo)
+            .unwrap()
+            .get(prop)
+            .unwrap()
+            .to_string()
+    };
+
+    let mut first = add_line(3);
+    add_line(4);
+    assert_eq!(get(&total), "7");
+    assert_eq!(get(&lines), "2");
+    let agent = store.get_default_agent().unwrap().subject;
+    assert_eq!(get(&editor), agent);
+
+    // Changes to the sources invalidate the cached values
+    add_line(5);
+    assert_eq!(get(&total), "12");
+    first
+        .set(amount.clone(), Value::Integer(10), &store)
+        .unwrap();
+    first.save_locally(&store).unwrap();
+    assert_eq!(get(&total), "19");
+    first.destroy(&store).unwrap();
+    assert_eq!(get(&total), "9");
+    assert_eq!(get(&lines), "2");
+
+    let skipped = store
+        .get_resource_extended(invoice.get_subject(), true, &ForAgent::Sudo)
+        .unwrap();
+    assert!(skipped.get(&total).is_err());
+    assert!(skipped.get(urls::INCOMPLETE).is_ok());
+
+    // Sources that the Agent can't read are left out, and the values are cached per Agent
+    let budget_of = property("budget-of", DataType::AtomicUrl);
+    let mut budget_total = Resource::new(format!("{}/budget-total", server));
+    budget_total.set_class(urls::COMPUTED_PROPERTY);
+    budget_total.set_unsafe(urls::COMPUTES.into(), Value::AtomicUrl(total.clone()));
+    budget_total.set_unsafe(urls::AGGREGATE.into(), Value::Slug("sum".into()));
+    budget_total.set_unsafe(
+        urls::SOURCE_PROPERTY.into(),
+        Value::AtomicUrl(budget_of.clone()),
+    );
+    budget_total.set_unsafe(
+        urls::VALUE_PROPERTY.into(),
+        Value::AtomicUrl(amount.clone()),
+    );
+    store.add_resource(&budget_total).unwrap();
+    let budget_class = Class {
+        requires: vec![],
+        recommends: vec![],
+        shortname: "budget".into(),
+        description: "budget".into(),
+        subject: format!("{}/budget", server),
+        sub_class_of: vec![],
+        computed_properties: vec![budget_total.get_subject().clone()],
+    };
+    store.add_resource(&budget_class.to_resource()).unwrap();
+    let reader_agent = store.create_agent(Some("reader")).unwrap();
+    let reader = reader_agent.subject.clone();
+    let readable_by = |resource: &mut Resource| {
+        resource.set_unsafe(urls::READ.into(), vec![reader.clone()].into());
+    };
+    let mut budget = Resource::new_instance(&budget_class.subject, &store).unwrap();
+    readable_by(&mut budget);
+    let budget = budget.save_locally(&store).unwrap().resource_new.unwrap();
+    for (amount_val, readable) in [(2, true), (30, false)] {
+        let mut item = Resource::new_generate_subject(&store);
+        item.set_unsafe(
+            budget_of.clone(),
+            Value::AtomicUrl(budget.get_subject().clone()),
+        );
+        item.set_unsafe(amount.clone(), Value::Integer(amount_val));
+        if readable {
+            readable_by(&mut item);
+        }
+        item.save_locally(&store).unwrap();
+    }
+    let get_budget = |for_agent: &ForAgent| {
+        store
+            .get_resource_extended(budget.get_subject(), false, for_agent)
+            .unwrap()
+            .get(&total)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(get_budget(&ForAgent::Sudo), "32");
+    assert_eq!(get_budget(&ForAgent::AgentSubject(reader.clone())), "2");
+    assert_eq!(get_budget(&ForAgent::Sudo), "32");
+
+    // The reader can read the items in a folder
+    let mut folder = Resource::new_generate_subject(&store);
+    readable_by(&mut folder);
+    folder.save_locally(&store).unwrap();
+    let mut item = Resource::new_generate_subject(&store);
+    item.set_unsafe(
+        budget_of.clone(),
+        Value::AtomicUrl(budget.get_subject().clone()),
+    );
+    item.set_unsafe(
+        urls::PARENT.into(),
+        Value::AtomicUrl(folder.get_subject().clone()),
+    );
+    item.set_unsafe(amount.clone(), Value::Integer(5));
+    item.save_locally(&store).unwrap();
+    assert_eq!(get_budget(&ForAgent::AgentSubject(reader.clone())), "7");
+
+    // A token of the reader only uses the sources in its scope, and does not share the reader's cached values
+    let token = crate::delegation::Delegation::new(
+        &reader_agent,
+        &crate::agents::generate_keypair().unwrap().public,
+        budget.get_subject(),
+        &[crate::hierarchy::Right::Read],
+        crate::utils::now() + 60_000,
+    )
+    .unwrap();
+    assert_eq!(get_budget(&ForAgent::Delegated(Box::new(token))), "0");
+    assert_eq!(get_budget(&ForAgent::AgentSubject(reader.clone())), "7");
+
+    // Revoking the rights on the folder invalidates the cached values of its items
+    let mut folder = store.get_resource(folder.get_subject()).unwrap();
+    folder.remove_propval(urls::READ);
+    folder.save_locally(&store).unwrap();
+    assert_eq!(get_budget(&ForAgent::AgentSubject(reader.clone())), "2");
+
+    // Decimals are aggregated without losing precision
+    let price = property("price", DataType::Decimal);
+    let price_total = property("price-total", DataType::Decimal);
+    let mut price_sum = Resource::new(format!("{}/price-sum", server));
+    price_sum.set_class(urls::COMPUTED_PROPERTY);
+    price_sum.set_unsafe(urls::COMPUTES.into(), Value::AtomicUrl(price_total.clone()));
+    price_sum.set_unsafe(urls::AGGREGATE.into(), Value::Slug("sum".into()));
+    price_sum.set_unsafe(urls::VALUE_PROPERTY.into(), Value::AtomicUrl(price.clone()));
+    store.add_resource(&price_sum).unwrap();
+    let order_class = Class {
+        requires: vec![],
+        recommends: vec![],
+        shortname: "order".into(),
+        description: "order".into(),
+        subject: format!("{}/order", server),
+        sub_class_of: vec![],
+        computed_properties: vec![price_sum.get_subject().clone()],
+    };
+    store.add_resource(&order_class.to_resource()).unwrap();
+    let order = Resource::new_instance(&order_class.subject, &store)
+        .unwrap()
+        .save_locally(&store)
+        .unwrap()
+        .resource_new
+        .unwrap();
+    for price_val in ["0.1", "0.2", "10000000000000000.05"] {
+        let mut line = Resource::new_generate_subject(&store);
+        line.set_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(order.get_subject().clone()),
+        );
+        line.set_unsafe(price.clone(), Value::Decimal(price_val.into()));
+        line.save_locally(&store).unwrap();
+    }
+    let order = store
+        .get_resource_extended(order.get_subject(), false, &ForAgent::Sudo)
+        .unwrap();
+    assert_eq!(
+        order.get(&price_total).unwrap().to_string(),
+        "10000000000000000.35"
+    );
+}
+
+#[test]
+fn unique_constraints() {
+    use crate::{
+        commit::{CommitBuilder, CommitOpts},
+        schema::{Class, Property, UniqueConstraint, UniqueScope, ValueConstraints},
+    };
+
+    let store = Db::init_temp("unique_constraints").unwrap();
+    let email = Property {
+        class_type: None,
+        data_type: DataType::String,
+        shortname: "email".into(),
+        description: "email".into(),
+        subject: format!("{}/email", store.get_server_url()),
+        allows_only: None,
+        constraints: ValueConstraints {
+            unique: Some(UniqueConstraint {
+                scope: UniqueScope::Drive,
+                class: None,
+            }),
+            ..Default::default()
+        },
+        on_delete: Default::default(),
+    };
+    store.add_resource(&email.to_resource()).unwrap();
+    let drive = |name: &str| {
+        let mut drive = Resource::new(format!("{}/{}", store.get_server_url(), name));
+        drive.set_class(urls::DRIVE);
+        store.add_resource(&drive).unwrap();
+        drive.get_subject().clone()
+    };
+    let (drive_a, drive_b) = (drive("drive-a"), drive("drive-b"));
+    let create = |parent: &str, value: &str| {
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set(urls::PARENT.into(), Value::AtomicUrl(parent.into()), &store)
+            .unwrap();
+        resource
+            .set(email.subject.clone(), Value::String(value.into()), &store)
+            .unwrap();
+        resource.save_locally(&store).map(|_| resource)
+    };
+
+    let mut first = create(&drive_a, "a@example.com").unwrap();
+    let err = create(&drive_a, "a@example.com").unwrap_err();
+    assert!(err.to_string().contains(first.get_subject()), "{}", err);
+    // Other Drives have their own scope
+    let mut other = create(&drive_b, "a@example.com").unwrap();
+    // Saving the same value again is not a conflict with itself
+    first
+        .set(urls::NAME.into(), Value::String("first".into()), &store)
+        .unwrap();
+    first.save_locally(&store).unwrap();
+    // Moving a Resource to another Drive checks that scope
+    other
+        .set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(drive_a.clone()),
+            &store,
+        )
+        .unwrap();
+    let err = other.save_locally(&store).unwrap_err();
+    assert!(err.to_string().contains(urls::UNIQUE), "{}", err);
+
+    // Concurrent Commits can't both claim a value
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let store = store.clone();
+            let subject = email.subject.clone();
+            let parent = drive_a.clone();
+            std::thread::spawn(move || {
+                let mut resource = Resource::new_generate_subject(&store);
+                resource
+                    .set(urls::PARENT.into(), Value::AtomicUrl(parent), &store)
+                    .unwrap();
+                resource
+                    .set(subject, Value::String("race@example.com".into()), &store)
+                    .unwrap();
+                resource.save_locally(&store).is_ok()
+            })
+        })
+        .collect();
+    let succeeded = handles
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .filter(|ok| *ok)
+        .count();
+    assert_eq!(succeeded, 1);
+
+    // Commits that are applied together can't both claim a value
+    let opts = CommitOpts {
+        validate_schema: true,
+        update_index: true,
+        ..CommitOpts::no_validations_no_index()
+    };
+    let agent = store.get_default_agent().unwrap();
+    let commit = |value: &str| {
+        let subject = format!(
+            "{}/{}",
+            store.get_server_url(),
+            crate::utils::random_string(8)
+        );
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(drive_b.clone()));
+        builder.set(email.subject.clone(), Value::String(value.into()));
+        let commit = builder
+            .sign(&agent, &store, &Resource::new(subject))
+            .unwrap();
+        (commit, opts.clone())
+    };
+    let err = store
+        .apply_commits_atomically(vec![
+            commit("batch@example.com"),
+            commit("batch@example.com"),
+        ])
+        .unwrap_err();
+    assert!(err.to_string().contains(urls::UNIQUE), "{}", err);
+    store
+        .apply_commits_atomically(vec![commit("one@example.com"), commit("two@example.com")])
+        .unwrap();
+
+    // Instances of subclasses are compared as instances of the uniqueClass
+    let class = |shortname: &str, sub_class_of: Vec<String>| Class {
+        requires: vec![],
+        recommends: vec![],
+        shortname: shortname.into(),
+        description: shortname.into(),
+        subject: format!("{}/{}", store.get_server_url(), shortname),
+        sub_class_of,
+        computed_properties: vec![],
+    };
+    let person = class("person", vec![]);
+    let employee = class("employee", vec![person.subject.clone()]);
+    for c in [&person, &employee] {
+        store.add_resource(&c.to_resource()).unwrap();
+    }
+    let username = Property {
+        shortname: "username".into(),
+        description: "username".into(),
+        subject: format!("{}/username", store.get_server_url()),
+        constraints: ValueConstraints {
+            unique: Some(UniqueConstraint {
+                scope: UniqueScope::Server,
+                class: Some(person.subject.clone()),
+            }),
+            ..Default::default()
+        },
+        ..email.clone()
+    };
+    store.add_resource(&username.to_resource()).unwrap();
+    let create_instance = |class: &str| {
+        let mut resource = Resource::new_generate_subject(&store);
+        resource.set_class(class);
+        resource
+            .set(
+                username.subject.clone(),
+                Value::String("alice".into()),
+                &store,
+            )
+            .unwrap();
+        resource.save_locally(&store).map(|_| resource)
+    };
+    let alice = create_instance(&employee.subject).unwrap();
+    let err = create_instance(&person.subject).unwrap_err();
+    assert!(err.to_string().contains(alice.get_subject()), "{}", err);
+    // Resources that are not a person can use the same username
+    create_instance(urls::DRIVE).unwrap();
+}
+
+#[test]
+fn diff_endpoint() {
+    let store = Db::init_temp("diff_endpoint").unwrap();
+    let mut resource = Resource::new_generate_subject(&store);
+    resource
+        .set(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("one\ntwo\n".into()),
+            &store,
+        )
+        .unwrap();
+    let first = resource.save_locally(&store).unwrap().commit_resource;
+    resource
+        .set(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("one\nthree\n".into()),
+            &store,
+        )
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+
+    let url = format!(
+        "{}/diff?subject={}&from={}",
+        store.get_server_url(),
+        urlencoding::encode(resource.get_subject()),
+        urlencoding::encode(first.get_subject())
+    );
+    let response = store
+        .get_resource_extended(&url, false, &ForAgent::Sudo)
+        .unwrap();
+    let diff: serde_json::Value =
+        serde_json::from_str(&response.get(urls::DIFF).unwrap().to_string()).unwrap();
+    let properties = diff["properties"].as_array().unwrap();
+    // The description changed, and `lastCommit` was added by saving
+    let description = properties
+        .iter()
+        .find(|p| p["property"] == urls::DESCRIPTION)
+        .unwrap();
+    assert_eq!(description["kind"], "changed");
+    let kinds: Vec<&str> = description["lines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, vec!["equal", "delete", "insert"]);
+}
+
+#[test]
+fn snapshots_and_compaction() {
+    use crate::plugins::versioning::construct_version;
+
+    let mut store = Db::init_temp("snapshots_and_compaction").unwrap();
+    store.set_snapshot_interval(3);
+    let mut resource = Resource::new_generate_subject(&store);
+    let mut commits = Vec::new();
+    for i in 0..10 {
+        resource
+            .set(urls::NAME.into(), Value::String(format!("v{}", i)), &store)
+            .unwrap();
+        let response = resource.save_locally(&store).unwrap();
+        commits.push(response.commit_resource.get_subject().clone());
+        // Commits are sorted by their timestamp
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    let name_at = |commit: &str| {
+        construct_version(commit, &store, &ForAgent::Sudo)
+            .unwrap()
+            .get(urls::NAME)
+            .unwrap()
+            .to_string()
+    };
+
+    // Every third Commit has a snapshot
+    assert!(store.get_snapshot(&commits[2]).is_some());
+    assert!(store.get_snapshot(&commits[3]).is_none());
+    assert_eq!(name_at(&commits[7]), "v7");
+
+    let policy = CompactionPolicy {
+        keep_recent: 3,
+        keep_after: None,
+    };
+    assert_eq!(
+        store
+            .compact_history(resource.get_subject(), &policy)
+            .unwrap(),
+        6
+    );
+    store.get_resource(&commits[3]).unwrap_err();
+    for index in [0, 7, 8, 9] {
+        assert_eq!(name_at(&commits[index]), format!("v{}", index));
+        let commit = Commit::from_resource(store.get_resource(&commits[index]).unwrap()).unwrap();
+        commit
+            .validate_signature(&store, commit.created_at)
+            .unwrap();
+    }
+    // Compacting again removes nothing
+    assert_eq!(store.compact_all_histories(&policy).unwrap(), 0);
+
+    // Removed Commits are still counted, so snapshots keep their interval
+    for i in 10..12 {
+        resource
+            .set(urls::NAME.into(), Value::String(format!("v{}", i)), &store)
+            .unwrap();
+        let response = resource.save_locally(&store).unwrap();
+        commits.push(response.commit_resource.get_subject().clone());
+    }
+    assert!(store.get_snapshot(&commits[10]).is_none());
+    assert!(store.get_snapshot(&commits[11]).is_some());
+}
+
+#[test]
+fn trash() {
+    let mut store = Db::init_temp("trash").unwrap();
+    let drive = store.get_server_url().to_string();
+    let create = |store: &Db, parent: &str| {
+        let mut resource = Resource::new_generate_subject(store);
+        resource
+            .set(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+            .unwrap();
+        resource.save_locally(store).unwrap();
+        resource.get_subject().clone()
+    };
+    let parent = create(&store, &drive);
+    let child = create(&store, &parent);
+    let grandchild = create(&store, &child);
+
+    // The children are destroyed in the same transaction as their parent
+    let sequence = store.last_change_sequence().unwrap().unwrap();
+    store
+        .get_resource(&parent)
+        .unwrap()
+        .destroy(&store)
+        .unwrap();
+    store.get_resource(&parent).unwrap_err();
+    store.get_resource(&child).unwrap_err();
+    store.get_resource(&grandchild).unwrap_err();
+    assert_eq!(store.last_change_sequence().unwrap(), Some(sequence + 3));
+    let children = store
+        .query(&Query::new_prop_val(urls::PARENT, &parent))
+        .unwrap();
+    assert_eq!(children.count, 0);
+    // The child is listed with its parent
+    let listed: Vec<String> = store
+        .list_trash(&drive)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.subject)
+        .collect();
+    assert_eq!(listed, vec![parent.clone()]);
+    let url = format!("{}/trash", drive);
+    let response = store
+        .get_resource_extended(&url, false, &ForAgent::Sudo)
+        .unwrap();
+    assert_eq!(
+        response
+            .get(urls::TRASHED_RESOURCES)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap(),
+        vec![parent.clone()]
+    );
+
+    let restored = store.restore_from_trash(&parent).unwrap();
+    assert_eq!(
+        restored,
+        vec![parent.clone(), child.clone(), grandchild.clone()]
+    );
+    store.get_resource(&child).unwrap();
+    let children = store
+        .query(&Query::new_prop_val(urls::PARENT, &parent))
+        .unwrap();
+    assert_eq!(children.subjects, vec![child.clone()]);
+    assert!(store.get_trashed(&parent).unwrap().is_none());
+
+    // Purged Resources can't be restored
+    store
+        .get_resource(&parent)
+        .unwrap()
+        .destroy(&store)
+        .unwrap();
+    assert_eq!(store.purge_from_trash(&parent).unwrap(), 3);
+    store.restore_from_trash(&parent).unwrap_err();
+
+    // A Resource that is created again is removed from the trash
+    let recreated = create(&store, &drive);
+    store
+        .get_resource(&recreated)
+        .unwrap()
+        .destroy(&store)
+        .unwrap();
+    let mut resource = Resource::new(recreated.clone());
+    resource
+        .set(urls::PARENT.into(), Value::AtomicUrl(drive.clone()), &store)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+    assert!(store.get_trashed(&recreated).unwrap().is_none());
+    assert!(!store
+        .list_trash(&drive)
+        .unwrap()
+        .iter()
+        .any(|entry| entry.subject == recreated));
+
+    // Resources without a parent, such as Drives, are kept in the trash of the server
+    let mut other_drive = Resource::new(format!("{}/other-drive", drive));
+    other_drive.set_class(urls::DRIVE);
+    other_drive.save_locally(&store).unwrap();
+    other_drive.destroy(&store).unwrap();
+    let url = format!("{}/trash", drive);
+    let response = store
+        .get_resource_extended(&url, false, &ForAgent::Sudo)
+        .unwrap();
+    assert_eq!(
+        response
+            .get(urls::TRASHED_RESOURCES)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap(),
+        vec![other_drive.get_subject().clone()]
+    );
+    store.restore_from_trash(other_drive.get_subject()).unwrap();
+
+    // The retention period starts when the Resource is trashed, not when the Commit was signed
+    let other = create(&store, &drive);
+    let mut builder = crate::commit::CommitBuilder::new(other.clone());
+    builder.destroy(true);
+    let agent = store.get_default_agent().unwrap();
+    let commit = builder
+        .sign(&agent, &store, &store.get_resource(&other).unwrap())
+        .unwrap();
+    let signed_at = commit.created_at;
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    store
+        .apply_commit(commit, &CommitOpts::no_validations_no_index())
+        .unwrap();
+    assert!(store.get_trashed(&other).unwrap().unwrap().trashed_at > signed_at);
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    store.set_trash_retention(Some(std::time::Duration::ZERO));
+    assert_eq!(store.purge_expired_trash().unwrap(), 1);
+
+    // Without a trash, destroying is permanent
+    store.set_trash_retention(None);
+    let permanent = create(&store, &drive);
+    store
+        .get_resource(&permanent)
+        .unwrap()
+        .destroy(&store)
+        .unwrap();
+    assert!(store.get_trashed(&permanent).unwrap().is_none());
+}
+
+#[test]
+fn changes_feed() {
+    use crate::changes::ChangeFilter;
+
+    let store = Db::init_temp("changes_feed").unwrap();
+    let start = store.last_change_sequence().unwrap();
+    let mut first = Resource::new_instance(urls::AGENT, &store).unwrap();
+    first
+        .set(
+            urls::PUBLIC_KEY.into(),
+            Value::String(crate::agents::generate_keypair().unwrap().public),
+            &store,
+        )
+        .unwrap();
+    first.save_locally(&store).unwrap();
+    let mut second = Resource::new_generate_subject(&store);
+    second
+        .set(urls::NAME.into(), Value::String("second".into()), &store)
+        .unwrap();
+    second
+        .set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            &store,
+        )
+        .unwrap();
+    second.save_locally(&store).unwrap();
+    second.destroy(&store).unwrap();
+
+    let all = store
+        .query_changes(&ChangeFilter {
+            since: start,
+            ..Default::default()
+        })
+        .unwrap();
+    let subjects: Vec<&str> = all.iter().map(|e| e.subject.as_str()).collect();
+    assert_eq!(
+        subjects,
+        vec![
+            first.get_subject(),
+            second.get_subject(),
+            second.get_subject()
+        ]
+    );
+    assert!(all.windows(2).all(|w| w[1].sequence == w[0].sequence + 1));
+    assert!(all[2].destroyed);
+    assert_eq!(all[0].commit[urls::SUBJECT], *first.get_subject());
+
+    // Resume from a cursor, and filter by Class
+    let after_first = store
+        .query_changes(&ChangeFilter {
+            since: Some(all[0].sequence),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(after_first.len(), 2);
+    let agents = store
+        .query_changes(&ChangeFilter {
+            since: start,
+            class: Some(urls::AGENT.into()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(agents.len(), 1);
+    let in_drive = store
+        .query_changes(&ChangeFilter {
+            since: start,
+            drive: Some(store.get_server_url().into()),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(in_drive.len(), 2);
+
+    // Long-poll
+    let last = store.last_change_sequence().unwrap();
+    assert!(!store
+        .wait_for_changes(last, std::time::Duration::from_millis(10))
+        .unwrap());
+    let waiting = store.clone();
+    let handle = std::thread::spawn(move || {
+        waiting
+            .wait_for_changes(last, std::time::Duration::from_secs(10))
+            .unwrap()
+    });
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    Resource::new_generate_subject(&store)
+        .save_locally(&store)
+        .unwrap();
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn replication() {
+    use crate::changes::ChangeFilter;
+
+    let leader_path = ".temp/db/replication_leader";
+    let _ = std::fs::remove_dir_all(leader_path);
+    let leader = Db::init(
+        std::path::Path::new(leader_path),
+        "https://leader.example".into(),
+    )
+    .unwrap();
+    let agent = leader.create_agent(None).unwrap();
+    leader.set_default_agent(agent.clone());
+    leader.populate().unwrap();
+    let drive = leader.get_server_url().to_string();
+
+    let follower = Db::init_temp("replication_follower").unwrap();
+    follower.get_resource(follower.get_server_url()).unwrap();
+    follower.add_mirror(follower.get_server_url()).unwrap_err();
+    let status = follower.add_mirror(&drive).unwrap();
+    assert_eq!(status.leader, drive);
+    assert!(follower.is_mirrored(&format!("{}/things/1", drive)));
+    assert!(!follower.is_mirrored("https://leader.example.com/things/1"));
+    // Only the subtree of a mirrored Drive is mirrored, not the whole leader
+    follower.add_mirror("https://other.example/drive").unwrap();
+    assert!(follower.is_mirrored("https://other.example/drive/things/1"));
+    assert!(!follower.is_mirrored("https://other.example/things/1"));
+    follower
+        .remove_mirror("https://other.example/drive")
+        .unwrap();
+    // The signer is normally fetched from the leader
+    follower
+        .add_resource(&leader.get_resource(&agent.subject).unwrap())
+        .unwrap();
+
+    let start = leader.last_change_sequence().unwrap();
+    let mut resource = Resource::new_generate_subject(&leader);
+    resource
+        .set(urls::NAME.into(), Value::String("first".into()), &leader)
+        .unwrap();
+    resource
+        .set(
+            urls::PARENT.into(),
+            Value::AtomicUrl(drive.clone()),
+            &leader,
+        )
+        .unwrap();
+    resource.save_locally(&leader).unwrap();
+    resource
+        .set(urls::NAME.into(), Value::String("second".into()), &leader)
+        .unwrap();
+    resource.save_locally(&leader).unwrap();
+    let subject = resource.get_subject().clone();
+    // Mirrored Resources are never fetched from the leader
+    follower.get_resource(&subject).unwrap_err();
+
+    let changes = |since| {
+        leader
+            .query_changes(&ChangeFilter {
+                since,
+                drive: Some(drive.clone()),
+                ..Default::default()
+            })
+            .unwrap()
+    };
+    let entries = changes(start);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        follower.apply_replicated_changes(&drive, &entries).unwrap(),
+        2
+    );
+    let mirrored = follower.get_resource(&subject).unwrap();
+    assert_eq!(mirrored.get(urls::NAME).unwrap().to_string(), "second");
+    // The Commit URLs of the leader are kept, so the version history matches
+    assert_eq!(
+        mirrored.get(urls::LAST_COMMIT).unwrap().to_string(),
+        leader
+            .get_resource(&subject)
+            .unwrap()
+            .get(urls::LAST_COMMIT)
+            .unwrap()
+            .to_string()
+    );
+    let status = follower.get_replication_status(&drive).unwrap().unwrap();
+    assert_eq!(status.cursor, Some(entries[1].sequence));
+    assert_eq!(status.applied, 2);
+    assert!(status.lag_ms.is_some());
+
+    // Entries before the cursor are skipped
+    assert_eq!(
+        follower.apply_replicated_changes(&drive, &entries).unwrap(),
+        0
+    );
+
+    // Tampered Commits fail the signature check
+    resource.destroy(&leader).unwrap();
+    let mut tampered = changes(status.cursor);
+    assert_eq!(tampered.len(), 1);
+    let destroy = tampered[0].clone();
+    tampered[0].commit[urls::DESTROY] = serde_json::Value::Bool(false);
+    tampered[0].commit[urls::SET] = serde_json::json!({ urls::NAME: "tampered" });
+    follower
+        .apply_replicated_changes(&drive, &tampered)
+        .unwrap_err();
+    assert_eq!(
+        follower
+            .get_resource(&subject)
+            .unwrap()
+            .get(urls::NAME)
+            .unwrap()
+            .to_string(),
+        "second"
+    );
+
+    // Commits for subjects of other servers are rejected
+    let mut foreign = destroy.clone();
+    foreign.commit[urls::SUBJECT] =
+        serde_json::Value::String(format!("{}/foreign", follower.get_server_url()));
+    follower
+        .apply_replicated_changes(&drive, &[foreign])
+        .unwrap_err();
+
+    follower
+        .apply_replicated_changes(&drive, &[destroy])
+        .unwrap();
+    follower.get_propvals(&subject).unwrap_err();
+    // The leader replicates the destroys of the children, so the follower doesn't trash anything itself
+    assert!(follower.get_trashed(&subject).unwrap().is_none());
+
+    follower.remove_mirror(&drive).unwrap();
+    assert!(follower.list_mirrors().unwrap().is_empty());
+}
@@ -0,0 +1,306 @@
+//! Keeps destroyed Resources in [Tree::Trash], so they can be restored until the retention period has passed.
+//! When a Resource is moved to the trash, its children are destroyed (and trashed) in the same transaction.
+//! Trashed Resources whose parent is in the trash as well are listed, restored and purged together with that parent.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts, CommitResponse},
+    errors::AtomicResult,
+    plugins::versioning::revert_commit_builder,
+    resources::PropVals,
+    urls, Db, Resource, Storelike, Value,
+};
+
+use super::{
+    trees::{Method, Operation, Transaction, Tree},
+    val_prop_sub_index::find_in_val_prop_sub_index,
+};
+
+/// By default, trashed Resources are purged after 30 days.
+pub const DEFAULT_TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A destroyed Resource, as stored in the trash.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrashedResource {
+    pub subject: String,
+    /// The Resource as it was before it was destroyed.
+    pub propvals: PropVals,
+    /// The Drive that contained the Resource.
+    pub drive: Option<String>,
+    pub parent: Option<String>,
+    /// Unix timestamp (ms)
+    pub trashed_at: i64,
+    /// The Agent that signed the destroy Commit.
+    pub trashed_by: String,
+    /// The Commit that destroyed the Resource.
+    pub destroy_commit: String,
+}
+
+impl TrashedResource {
+    /// The Resource as it was before it was destroyed, with `trashedAt` and `trashedBy` added.
+    pub fn to_resource(&self) -> Resource {
+        let mut resource = Resource::from_propvals(self.propvals.clone(), self.subject.clone());
+        resource.set_unsafe(urls::TRASHED_AT.into(), Value::Timestamp(self.trashed_at));
+        resource.set_unsafe(
+            urls::TRASHED_BY.into(),
+            Value::AtomicUrl(self.trashed_by.clone()),
+        );
+        resource
+    }
+}
+
+impl Db {
+    /// Sets how long destroyed Resources are kept in the trash. `None` disables the trash, so destroyed Resources are removed immediately.
+    pub fn set_trash_retention(&mut self, retention: Option<Duration>) {
+        self.trash_retention = retention;
+    }
+
+    pub fn get_trash_retention(&self) -> Option<Duration> {
+        self.trash_retention
+    }
+
+    /// Returns the trashed version of `subject`, if it's in the trash.
+    pub fn get_trashed(&self, subject: &str) -> AtomicResult<Option<TrashedResource>> {
+        match self.trash.get(subject.as_bytes())? {
+            Some(bin) => Ok(Some(bincode::deserialize(&bin).map_err(|e| {
+                format!("Could not deserialize trashed Resource {}. {}", subject, e)
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [Db::get_trashed], but also finds Resources that are moved to the trash in the transaction.
+    pub(crate) fn get_trashed_tx(
+        &self,
+        subject: &str,
+        transaction: &Transaction,
+    ) -> AtomicResult<Option<TrashedResource>> {
+        let staged = transaction
+            .iter()
+            .rev()
+            .find(|op| matches!(op.tree, Tree::Trash) && op.key.as_slice() == subject.as_bytes());
+        match staged {
+            Some(Operation {
+                val: Some(bin),
+                method: Method::Insert,
+                ..
+            }) => Ok(Some(bincode::deserialize(bin).map_err(|e| {
+                format!("Could not deserialize trashed Resource {}. {}", subject, e)
+            })?)),
+            Some(_removed) => Ok(None),
+            None => self.get_trashed(subject),
+        }
+    }
+
+    /// Returns all trashed Resources, including the ones whose parent is in the trash.
+    pub fn trash_entries(&self) -> impl Iterator<Item = AtomicResult<TrashedResource>> {
+        self.trash.iter().map(|item| {
+            let (_key, val) = item.map_err(|e| format!("Failed reading {}. {}", Tree::Trash, e))?;
+            bincode::deserialize(&val)
+                .map_err(|e| format!("Could not deserialize trashed Resource. {}", e).into())
+        })
+    }
+
+    /// Returns the trashed Resources of a Drive whose parent is not in the trash, most recent first.
+    pub fn list_trash(&self, drive: &str) -> AtomicResult<Vec<TrashedResource>> {
+        let mut list = Vec::new();
+        for entry in self.trash_entries() {
+            let entry = entry?;
+            let parent_trashed = match &entry.parent {
+                Some(parent) => self.trash.contains_key(parent.as_bytes())?,
+                None => false,
+            };
+            if entry.drive.as_deref() == Some(drive) && !parent_trashed {
+                list.push(entry);
+            }
+        }
+        list.sort_by_key(|entry| std::cmp::Reverse(entry.trashed_at));
+        Ok(list)
+    }
+
+    fn trashed_children(&self, subject: &str) -> AtomicResult<Vec<TrashedResource>> {
+        let mut children = Vec::new();
+        for child in self.trash_entries() {
+            let child = child?;
+            if child.parent.as_deref() == Some(subject) {
+                children.push(child);
+            }
+        }
+        Ok(children)
+    }
+
+    /// Adds the Resource that a Commit destroys to the trash in the transaction.
+    pub(crate) fn add_to_trash(
+        &self,
+        resource: &Resource,
+        commit_response: &CommitResponse,
+        transaction: &mut Transaction,
+    ) -> AtomicResult<()> {
+        let parent = resource.get(urls::PARENT).ok().map(|p| p.to_string());
+        // Children are trashed after their parent has been removed, so they use its Drive.
+        // Resources without a Drive, such as destroyed Drives themselves, are kept in the trash of the server.
+        let drive = match &parent {
+            Some(parent) => match self.get_trashed_tx(parent, transaction)? {
+                Some(trashed_parent) => trashed_parent.drive,
+                None => crate::hierarchy::find_drive(self, resource),
+            },
+            None => None,
+        }
+        .or_else(|| Some(self.get_server_url().to_string()));
+        let entry = TrashedResource {
+            subject: resource.get_subject().clone(),
+            propvals: resource.get_propvals().clone(),
+            drive,
+            parent,
+            trashed_at: crate::utils::now(),
+            trashed_by: commit_response.commit.signer.clone(),
+            destroy_commit: commit_response.commit_resource.get_subject().clone(),
+        };
+        transaction.push(Operation {
+            tree: Tree::Trash,
+            method: Method::Insert,
+            key: entry.subject.as_bytes().to_vec(),
+            val: Some(bincode::serialize(&entry)?),
+        });
+        Ok(())
+    }
+
+    /// Adds Commits that destroy the children of the Resources that `responses` destroy to the transaction, which moves them to the trash as well.
+    /// The Commits are signed by the default Agent, and their responses are added to `responses`.
+    pub(crate) fn stage_trash_children(
+        &self,
+        responses: &mut Vec<CommitResponse>,
+        transaction: &mut Transaction,
+    ) -> AtomicResult<()> {
+        let mut destroyed: Vec<String> = responses
+            .iter()
+            .filter(|r| r.resource_new.is_none())
+            .map(|r| r.commit.subject.clone())
+            .collect();
+        let mut agent = None;
+        while let Some(subject) = destroyed.pop() {
+            let value = Value::AtomicUrl(subject.clone());
+            for atom in find_in_val_prop_sub_index(self, &value, Some(urls::PARENT)) {
+                let child = atom?.subject;
+                if !child.starts_with(self.get_server_url()) {
+                    continue;
+                }
+                if let Some(staged) = responses.iter().find(|r| r.commit.subject == child) {
+                    // Destroyed by an onDelete policy, or no longer a child after one
+                    let still_child = staged.resource_new.as_ref().is_some_and(|new| {
+                        new.get(urls::PARENT)
+                            .map(|p| p.to_string() == subject)
+                            .unwrap_or(false)
+                    });
+                    if still_child {
+                        return Err(format!(
+                            "Can't move {} to the trash with its parent {}, because an onDelete policy changes it",
+                            child, subject
+                        )
+                        .into());
+                    }
+                    continue;
+                }
+                let agent = match &agent {
+                    Some(agent) => agent,
+                    None => agent.insert(self.get_default_agent()?),
+                };
+                let opts = CommitOpts {
+                    validate_schema: false,
+                    validate_links: false,
+                    validate_signature: false,
+                    validate_timestamp: false,
+                    validate_rights: false,
+                    validate_previous_commit: false,
+                    update_index: true,
+                    validate_for_agent: Some(agent.subject.as_str().into()),
+                };
+                let resource = self.get_resource(&child)?;
+                let mut builder = CommitBuilder::new(child.clone());
+                builder.destroy(true);
+                let response = builder
+                    .sign(agent, self, &resource)?
+                    .validate_and_build_response(&opts, self)
+                    .map_err(|e| format!("Failed to move {} to the trash: {}", child, e))?;
+                self.stage_commit(&response, &opts, true, transaction)?;
+                responses.push(response);
+                destroyed.push(child);
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores a trashed Resource and its trashed children, using Commits signed by the default Agent.
+    /// Returns the subjects of the restored Resources.
+    pub fn restore_from_trash(&self, subject: &str) -> AtomicResult<Vec<String>> {
+        let entry = self
+            .get_trashed(subject)?
+            .ok_or_else(|| format!("{} is not in the trash", subject))?;
+        if self.get_propvals(subject).is_ok() {
+            return Err(format!("Can't restore {}, because it already exists", subject).into());
+        }
+        // The new Commit follows the one that destroyed the Resource
+        let mut destroyed = Resource::new(subject.into());
+        destroyed.set_unsafe(
+            urls::LAST_COMMIT.into(),
+            Value::AtomicUrl(entry.destroy_commit.clone()),
+        );
+        let target = Resource::from_propvals(entry.propvals.clone(), subject.into());
+        let agent = self.get_default_agent()?;
+        let commit = revert_commit_builder(&destroyed, &target).sign(&agent, self, &destroyed)?;
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_links: true,
+            validate_signature: false,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: true,
+            update_index: true,
+            validate_for_agent: Some(agent.subject.as_str().into()),
+        };
+        // Also removes it from the trash
+        self.apply_commit(commit, &opts)
+            .map_err(|e| format!("Failed to restore {}: {}", subject, e))?;
+
+        let mut restored = vec![subject.to_string()];
+        for child in self.trashed_children(subject)? {
+            restored.extend(self.restore_from_trash(&child.subject)?);
+        }
+        Ok(restored)
+    }
+
+    /// Permanently removes a trashed Resource and its trashed children.
+    /// Returns the amount of purged Resources.
+    pub fn purge_from_trash(&self, subject: &str) -> AtomicResult<usize> {
+        if !self.trash.contains_key(subject.as_bytes())? {
+            return Err(format!("{} is not in the trash", subject).into());
+        }
+        let mut purged = 1;
+        for child in self.trashed_children(subject)? {
+            purged += self.purge_from_trash(&child.subject)?;
+        }
+        self.trash.remove(subject.as_bytes())?;
+        Ok(purged)
+    }
+
+    /// Permanently removes the Resources that have been in the trash for longer than the retention period.
+    /// Returns the amount of purged Resources.
+    pub fn purge_expired_trash(&self) -> AtomicResult<usize> {
+        let Some(retention) = self.trash_retention else {
+            return Ok(0);
+        };
+        let cutoff = crate::utils::now() - retention.as_millis() as i64;
+        let mut purged = 0;
+        for entry in self.trash_entries() {
+            let entry = entry?;
+            if entry.trashed_at < cutoff {
+                self.trash.remove(entry.subject.as_bytes())?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+}
@@ -16,6 +16,20 @@ pub enum Tree {
     /// Reference index, used for queries where the value (or one of the values, in case of an array) is but the subject is not.
     /// Index sorted by {Value}-{Property}-{Subject}.
     ValPropSub,
+    /// Append-only log of [crate::audit::AuditEntry]s. Key: sequence number (big endian), Value: JSON.
+    AuditLog,
+    /// The signed [crate::audit::AuditHead] of the audit log. Key: `head`, Value: JSON.
+    AuditHead,
+    /// Versions of Resources, used as starting points when constructing versions. Key: Commit URL, Value: [PropVals](crate::resources::PropVals)
+    Snapshots,
+    /// The amount of Commits applied to each Resource, used for deciding when to store a snapshot. Key: Subject, Value: count (big endian u64)
+    CommitCounts,
+    /// Destroyed Resources that can still be restored. Key: Subject, Value: [crate::db::TrashedResource]
+    Trash,
+    /// Append-only feed of applied Commits, see [crate::changes]. Key: sequence number (big endian), Value: JSON.
+    Changes,
+    /// Drives that are mirrored from another server. Key: Drive subject, Value: JSON [crate::replication::ReplicationStatus].
+    Replication,
 }
 
 const RESOURCES: &str = "resources_v1";
@@ -23,6 +37,13 @@ const VALPROPSUB: &str = "reference_index_v1";
 const QUERY_MEMBERS: &str = "members_index";
 const PROPVALSUB: &str = "prop_val_sub_index";
 const QUERIES_WATCHED: &str = "watched_queries";
+const AUDIT_LOG: &str = "audit_log_v1";
+const AUDIT_HEAD: &str = "audit_head_v1";
+const SNAPSHOTS: &str = "snapshots_v1";
+const COMMIT_COUNTS: &str = "commit_counts_v1";
+const TRASH: &str = "trash_v1";
+const CHANGES: &str = "changes_v1";
+const REPLICATION: &str = "replication_v1";
 
 impl std::fmt::Display for Tree {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -32,6 +53,13 @@ impl std::fmt::Display for Tree {
             Tree::PropValSub => f.write_str(PROPVALSUB),
             Tree::ValPropSub => f.write_str(VALPROPSUB),
             Tree::QueryMembers => f.write_str(QUERY_MEMBERS),
+            Tree::AuditLog => f.write_str(AUDIT_LOG),
+            Tree::AuditHead => f.write_str(AUDIT_HEAD),
+            Tree::Snapshots => f.write_str(SNAPSHOTS),
+            Tree::CommitCounts => f.write_str(COMMIT_COUNTS),
+            Tree::Trash => f.write_str(TRASH),
+            Tree::Changes => f.write_str(CHANGES),
+            Tree::Replication => f.write_str(REPLICATION),
         }
     }
 }
@@ -45,6 +73,13 @@ impl AsRef<[u8]> for Tree {
             Tree::PropValSub => PROPVALSUB.as_bytes(),
             Tree::ValPropSub => VALPROPSUB.as_bytes(),
             Tree::QueryMembers => QUERY_MEMBERS.as_bytes(),
+            Tree::AuditLog => AUDIT_LOG.as_bytes(),
+            Tree::AuditHead => AUDIT_HEAD.as_bytes(),
+            Tree::Snapshots => SNAPSHOTS.as_bytes(),
+            Tree::CommitCounts => COMMIT_COUNTS.as_bytes(),
+            Tree::Trash => TRASH.as_bytes(),
+            Tree::Changes => CHANGES.as_bytes(),
+            Tree::Replication => REPLICATION.as_bytes(),
         }
     }
 }
@@ -0,0 +1,463 @@
+//! Delegation tokens are signed capabilities that allow a key to act on behalf of an Agent.
+//! A token is limited to a subtree (the `scope`), a set of [Right]s and an expiry date.
+//! The holder of a token can delegate a narrower token to another key, which creates a capability chain.
+//! https://docs.atomicdata.dev/authentication.html#delegation-tokens
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::{check_agent_key, decode_base64, encode_base64, verify_public_key, Agent},
+    commit::sign_message,
+    errors::AtomicResult,
+    hierarchy::Right,
+    urls, Resource, Storelike,
+};
+
+/// A signed capability that allows the holder of `delegate_key` to act as `agent`, within `scope`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Delegation {
+    /// The Agent on whose behalf the delegate acts.
+    #[serde(rename = "https://atomicdata.dev/properties/delegation/agent")]
+    pub agent: String,
+    /// The public key that signed this token.
+    /// Either one of the keys of the Agent, or the `delegate_key` of the `proof`.
+    #[serde(rename = "https://atomicdata.dev/properties/delegation/issuerKey")]
+    pub issuer_key: String,
+    /// The public key that is allowed to use this token.
+    #[serde(rename = "https://atomicdata.dev/properties/delegation/delegateKey")]
+    pub delegate_key: String,
+    /// Subject of the Resource that (including its children) can be accessed.
+    #[serde(rename = "https://atomicdata.dev/properties/delegation/scope")]
+    pub scope: String,
+    /// URLs of the granted rights: [urls::READ], [urls::WRITE] and / or [urls::APPEND].
+    #[serde(rename = "https://atomicdata.dev/properties/delegation/rights")]
+    pub rights: Vec<String>,
+    /// Unix timestamp (ms) after which the token can no longer be used.
+    #[serde(rename = "https://atomicdata.dev/properties/delegation/expiresAt")]
+    pub expires_at: i64,
+    /// The token from which this one is derived. Empty if it is signed by the Agent itself.
+    #[serde(
+        rename = "https://atomicdata.dev/properties/delegation/proof",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub proof: Option<Box<Delegation>>,
+    /// Base64 encoded signature of the deterministically serialized token.
+    #[serde(
+        rename = "https://atomicdata.dev/properties/delegation/signature",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub signature: Option<String>,
+}
+
+impl Delegation {
+    /// Creates and signs a token that allows `delegate_key` to use `rights` on `scope` until `expires_at`.
+    /// The `issuer` is the Agent itself, so it needs a private key.
+    pub fn new(
+        issuer: &Agent,
+        delegate_key: &str,
+        scope: &str,
+        rights: &[Right],
+        expires_at: i64,
+    ) -> AtomicResult<Delegation> {
+        verify_public_key(delegate_key)?;
+        Delegation {
+            agent: issuer.subject.clone(),
+            issuer_key: issuer.public_key.clone(),
+            delegate_key: delegate_key.into(),
+            scope: scope.into(),
+            rights: rights.iter().map(|r| r.to_string()).collect(),
+            expires_at,
+            proof: None,
+            signature: None,
+        }
+        .sign(issuer)
+    }
+
+    /// Derives a new token from this one, signed by the holder of this token.
+    /// The new token can not be broader than this one: this is checked when it is verified.
+    pub fn delegate(
+        &self,
+        holder: &Agent,
+        delegate_key: &str,
+        scope: &str,
+        rights: &[Right],
+        expires_at: i64,
+    ) -> AtomicResult<Delegation> {
+        verify_public_key(delegate_key)?;
+        if holder.public_key != self.delegate_key {
+            return Err("Only the holder of a delegation token can delegate it further".into());
+        }
+        Delegation {
+            agent: self.agent.clone(),
+            issuer_key: holder.public_key.clone(),
+            delegate_key: delegate_key.into(),
+            scope: scope.into(),
+            rights: rights.iter().map(|r| r.to_string()).collect(),
+            expires_at,
+            proof: Some(Box::new(self.clone())),
+            signature: None,
+        }
+        .sign(holder)
+    }
+
+    fn sign(mut self, signer: &Agent) -> AtomicResult<Delegation> {
+        let private_key = signer
+            .private_key
+            .as_ref()
+            .ok_or("No private key in agent")?;
+        let signature = sign_message(
+            &self.serialize_deterministically()?,
+            private_key,
+            &signer.public_key,
+        )?;
+        self.signature = Some(signature);
+        Ok(self)
+    }
+
+    /// Serializes the token (including its proofs) without its own signature, using JCS.
+    pub fn serialize_deterministically(&self) -> AtomicResult<String> {
+        let mut unsigned = self.clone();
+        unsigned.signature = None;
+        let json = serde_jcs::to_string(&unsigned)
+            .map_err(|e| format!("Failed to serialize delegation token: {}", e))?;
+        Ok(json)
+    }
+
+    /// Base64 encoded JSON, used in the `x-atomic-delegation` header and in Commits.
+    pub fn encode(&self) -> AtomicResult<String> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize delegation token: {}", e))?;
+        Ok(encode_base64(json.as_bytes()))
+    }
+
+    pub fn decode(encoded: &str) -> AtomicResult<Delegation> {
+        let json =
+            decode_base64(encoded).map_err(|e| format!("Malformed delegation token. {}", e))?;
+        let delegation: Delegation = serde_json::from_slice(&json)
+            .map_err(|e| format!("Malformed delegation token. {}", e))?;
+        Ok(delegation)
+    }
+
+    /// Checks the entire capability chain at `timestamp`:
+    /// signatures, expiry dates, and whether every token is at most as powerful as its proof.
+    /// The first token in the chain must be signed by a non-revoked key of the Agent.
+    pub fn verify(&self, store: &impl Storelike, timestamp: i64) -> AtomicResult<()> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or("Delegation token is not signed")?;
+        let issuer_key = decode_base64(&self.issuer_key)?;
+        let signature_bytes = decode_base64(signature)?;
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, issuer_key)
+            .verify(
+                self.serialize_deterministically()?.as_bytes(),
+                &signature_bytes,
+            )
+            .map_err(|_| "Incorrect signature for delegation token")?;
+        if timestamp >= self.expires_at {
+            return Err(format!(
+                "Delegation token for {} has expired at {}",
+                self.scope, self.expires_at
+            )
+            .into());
+        }
+        for right in &self.rights {
+            right.parse::<Right>()?;
+        }
+
+        match &self.proof {
+            Some(proof) => {
+                proof.verify(store, timestamp)?;
+                if proof.agent != self.agent {
+                    return Err("Delegation token is derived from a token of another agent".into());
+                }
+                if proof.delegate_key != self.issuer_key {
+                    return Err(
+                        "Delegation token is not signed by the holder of its proof token".into(),
+                    );
+                }
+                if self.expires_at > proof.expires_at {
+                    return Err(
+                        "Delegation token expires later than the token it is derived from".into(),
+                    );
+                }
+                if let Some(right) = self.rights.iter().find(|r| !proof.rights.contains(r)) {
+                    return Err(format!(
+                        "Delegation token grants {}, which the token it is derived from does not",
+                        right
+                    )
+                    .into());
+                }
+                let scope = store.get_resource(&self.scope)?;
+                if !is_in_scope(store, &scope, &proof.scope)? {
+                    return Err(format!(
+                        "Scope {} is outside of the scope of the token it is derived from ({})",
+                        self.scope, proof.scope
+                    )
+                    .into());
+                }
+            }
+            None => check_agent_key(store, &self.agent, &self.issuer_key, timestamp)?,
+        }
+        Ok(())
+    }
+
+    /// Throws if the token does not grant `right` for `resource`.
+    /// Does not check the rights of the Agent itself, see [crate::hierarchy::check_rights].
+    pub fn check_scope(
+        &self,
+        store: &impl Storelike,
+        resource: &Resource,
+        right: &Right,
+    ) -> AtomicResult<()> {
+        if !self.rights.contains(&right.to_string()) {
+            return Err(crate::errors::AtomicError::unauthorized(format!(
+                "Delegation token does not grant {}",
+                right
+            )));
+        }
+        // Commits are in scope when their target is.
+        let target = match resource.get(urls::SUBJECT) {
+            Ok(subject) => store.get_resource(&subject.to_string())?,
+            Err(_) => resource.clone(),
+        };
+        if !is_in_scope(store, &target, &self.scope)? {
+            return Err(crate::errors::AtomicError::unauthorized(format!(
+                "{} is outside of the scope of the delegation token ({})",
+                target.get_subject(),
+                self.scope
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `resource` is `scope` itself, or one of its (grand)children.
+fn is_in_scope(store: &impl Storelike, resource: &Resource, scope: &str) -> AtomicResult<bool> {
+    if resource.get_subject() == scope {
+        return Ok(true);
+    }
+    Ok(resource
+        .get_parent_tree(store)?
+        .iter()
+        .any(|parent| parent.get_subject() == scope))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::ForAgent, hierarchy::check_rights, Value};
+
+    fn setup() -> (crate::Store, Agent, Agent, String) {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("issuer")).unwrap();
+        let bot = Agent::new(Some("bot"), &store).unwrap();
+        let folder = "https://localhost/ci_folder";
+        let mut folder_resource = Resource::new(folder.into());
+        folder_resource.set_unsafe(urls::PARENT.into(), Value::AtomicUrl(agent.subject.clone()));
+        store.add_resource(&folder_resource).unwrap();
+        (store, agent, bot, folder.into())
+    }
+
+    #[test]
+    fn scoped_token() {
+        let (store, agent, bot, folder) = setup();
+        let token = Delegation::new(
+            &agent,
+            &bot.public_key,
+            &folder,
+            &[Right::Write],
+            crate::utils::now() + 10000,
+        )
+        .unwrap();
+        let decoded = Delegation::decode(&token.encode().unwrap()).unwrap();
+        assert_eq!(decoded, token);
+        decoded.verify(&store, crate::utils::now()).unwrap();
+
+        let for_agent = ForAgent::Delegated(Box::new(decoded));
+        let mut child = Resource::new("https://localhost/ci_folder/child".into());
+        child.set_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        check_rights(&store, &child, &for_agent, Right::Write).unwrap();
+        check_rights(&store, &child, &for_agent, Right::Read).unwrap_err();
+
+        // The agent itself is outside of the scope
+        let agent_resource = store.get_resource(&agent.subject).unwrap();
+        check_rights(&store, &agent_resource, &for_agent, Right::Write).unwrap_err();
+
+        token
+            .verify(&store, crate::utils::now() + 20000)
+            .expect_err("Expired tokens should fail");
+    }
+
+    #[test]
+    fn token_chain() {
+        let (store, agent, bot, folder) = setup();
+        let expires_at = crate::utils::now() + 10000;
+        let token = Delegation::new(
+            &agent,
+            &bot.public_key,
+            &folder,
+            &[Right::Write, Right::Append],
+            expires_at,
+        )
+        .unwrap();
+        let job = Agent::new(Some("job"), &store).unwrap();
+        let narrower = token
+            .delegate(&bot, &job.public_key, &folder, &[Right::Append], expires_at)
+            .unwrap();
+        narrower.verify(&store, crate::utils::now()).unwrap();
+
+        let broader = token
+            .delegate(&bot, &job.public_key, &folder, &[Right::Read], expires_at)
+            .unwrap();
+        broader.verify(&store, crate::utils::now()).unwrap_err();
+
+        let outside = token
+            .delegate(
+                &bot,
+                &job.public_key,
+                &agent.subject,
+                &[Right::Write],
+                expires_at,
+            )
+            .unwrap();
+        outside.verify(&store, crate::utils::now()).unwrap_err();
+
+        // Someone who does not hold the token can not sign a derived one
+        let mut forged = narrower.clone();
+        forged.rights = vec![urls::WRITE.into()];
+        forged.verify(&store, crate::utils::now()).unwrap_err();
+    }
+
+    #[test]
+    fn delegated_commit() {
+        use crate::commit::{CommitBuilder, CommitOpts};
+
+        let (store, agent, bot, folder) = setup();
+        let token = Delegation::new(
+            &agent,
+            &bot.public_key,
+            &folder,
+            &[Right::Append],
+            crate::utils::now() + 10000,
+        )
+        .unwrap();
+        // The bot signs on behalf of the agent, using its own private key
+        let delegate =
+            Agent::from_private_key_and_subject(bot.private_key.as_ref().unwrap(), &agent.subject)
+                .unwrap();
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_links: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_previous_commit: false,
+            validate_rights: true,
+            validate_for_agent: Some(agent.subject.as_str().into()),
+            update_index: true,
+        };
+
+        let sign = |subject: &str, parent: &str| {
+            let mut builder = CommitBuilder::new(subject.into());
+            builder.set(urls::PARENT.into(), Value::AtomicUrl(parent.into()));
+            builder.set_delegation(&token).unwrap();
+            builder
+                .sign(&delegate, &store, &Resource::new(subject.into()))
+                .unwrap()
+        };
+
+        let inside = sign("https://localhost/ci_folder/report", &folder);
+        let commit_resource = store.apply_commit(inside, &opts).unwrap().commit_resource;
+        assert!(commit_resource.get(urls::DELEGATION).is_ok());
+
+        let outside = sign("https://localhost/elsewhere", &agent.subject);
+        store.apply_commit(outside, &opts).unwrap_err();
+
+        // Without the token, the delegated key is not one of the agent's keys
+        let mut builder = CommitBuilder::new("https://localhost/ci_folder/other".into());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        let unsigned = Resource::new("https://localhost/ci_folder/other".into());
+        let commit = builder.sign(&delegate, &store, &unsigned).unwrap();
+        store.apply_commit(commit, &opts).unwrap_err();
+
+        // Expired tokens can not be used by backdating the Commit
+        let expired = Delegation::new(
+            &agent,
+            &bot.public_key,
+            &folder,
+            &[Right::Append],
+            crate::utils::now() - 1,
+        )
+        .unwrap();
+        let subject = "https://localhost/ci_folder/late";
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        builder.set_delegation(&expired).unwrap();
+        let mut backdated = builder
+            .sign(&delegate, &store, &Resource::new(subject.into()))
+            .unwrap();
+        backdated.created_at -= 60 * 1000;
+        backdated.signature = None;
+        let serialized = backdated
+            .serialize_deterministically_json_ad(&store)
+            .unwrap();
+        backdated.signature = Some(
+            crate::commit::sign_message(
+                &serialized,
+                bot.private_key.as_ref().unwrap(),
+                &bot.public_key,
+            )
+            .unwrap(),
+        );
+        let err = store.apply_commit(backdated, &opts).unwrap_err();
+        assert!(err.message.contains("delegation"), "{}", err);
+    }
+
+    #[test]
+    fn delegated_import() {
+        use crate::parse::{ParseOpts, SaveOpts};
+
+        let (store, agent, bot, folder) = setup();
+        let token = Delegation::new(
+            &agent,
+            &bot.public_key,
+            &folder,
+            &[Right::Write],
+            crate::utils::now() + 10000,
+        )
+        .unwrap();
+        let parse_opts = |importer: &str| ParseOpts {
+            importer: Some(importer.into()),
+            for_agent: ForAgent::Delegated(Box::new(token.clone())),
+            signer: Some(agent.clone()),
+            save: SaveOpts::Commit,
+            overwrite_outside: true,
+            keep_non_url_items: false,
+        };
+        let json = |subject: &str, parent: &str| {
+            format!(
+                r#"{{"@id": "{}", "{}": "{}", "{}": "imported"}}"#,
+                subject,
+                urls::PARENT,
+                parent,
+                urls::NAME
+            )
+        };
+        store
+            .import(
+                &json("https://localhost/ci_folder/imported", &folder),
+                &parse_opts(&folder),
+            )
+            .unwrap();
+        // The Commits of an import are checked against the scope of the token too
+        store
+            .import(
+                &json("https://localhost/outside", &agent.subject),
+                &parse_opts(&agent.subject),
+            )
+            .unwrap_err();
+    }
+}
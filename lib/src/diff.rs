@@ -0,0 +1,403 @@
+/*!
+Compares two versions of a Resource, and describes what changed per Property.
+Arrays get a list of inserted and deleted items, Markdown gets a line-level diff.
+Versions are constructed from Commits using [crate::plugins::versioning::construct_version].
+*/
+
+use similar::{capture_diff_slices, Algorithm, ChangeTag, TextDiff};
+
+use crate::{
+    agents::ForAgent, errors::AtomicResult, plugins::versioning::construct_version,
+    serialize::val_to_serde, Resource, Storelike, Value,
+};
+
+/// What happened to a Property between two versions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+/// A single step in an array or line diff.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+impl EditKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EditKind::Equal => "equal",
+            EditKind::Insert => "insert",
+            EditKind::Delete => "delete",
+        }
+    }
+
+    fn from_tag(tag: ChangeTag) -> Self {
+        match tag {
+            ChangeTag::Equal => EditKind::Equal,
+            ChangeTag::Insert => EditKind::Insert,
+            ChangeTag::Delete => EditKind::Delete,
+        }
+    }
+
+    fn sign(&self) -> char {
+        match self {
+            EditKind::Equal => ' ',
+            EditKind::Insert => '+',
+            EditKind::Delete => '-',
+        }
+    }
+}
+
+/// An array item or a line that is kept, inserted or deleted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub kind: EditKind,
+    /// Position in the old version, for kept and deleted items.
+    pub old_index: Option<usize>,
+    /// Position in the new version, for kept and inserted items.
+    pub new_index: Option<usize>,
+    pub value: String,
+}
+
+impl Edit {
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "kind": self.kind.as_str(),
+            "value": self.value,
+        });
+        if let Some(index) = self.old_index {
+            json["oldIndex"] = index.into();
+        }
+        if let Some(index) = self.new_index {
+            json["newIndex"] = index.into();
+        }
+        json
+    }
+}
+
+/// The change of a single Property.
+#[derive(Clone, Debug)]
+pub struct PropertyDiff {
+    pub property: String,
+    pub kind: ChangeKind,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+    /// Inserted and deleted items, if both versions are (Resource)Arrays.
+    pub items: Vec<Edit>,
+    /// All lines, if both versions are Markdown.
+    pub lines: Vec<Edit>,
+}
+
+impl PropertyDiff {
+    pub fn to_json(&self) -> AtomicResult<serde_json::Value> {
+        let mut json = serde_json::json!({
+            "property": self.property,
+            "kind": self.kind.as_str(),
+        });
+        if let Some(from) = &self.from {
+            json["from"] = val_to_serde(from.clone())?;
+        }
+        if let Some(to) = &self.to {
+            json["to"] = val_to_serde(to.clone())?;
+        }
+        if !self.items.is_empty() {
+            json["items"] = self.items.iter().map(Edit::to_json).collect();
+        }
+        if !self.lines.is_empty() {
+            json["lines"] = self.lines.iter().map(Edit::to_json).collect();
+        }
+        Ok(json)
+    }
+}
+
+/// The differences between two versions of a Resource.
+#[derive(Clone, Debug)]
+pub struct ResourceDiff {
+    pub subject: String,
+    /// The Commit of the old version. `None` means before the first Commit.
+    pub from: Option<String>,
+    /// The Commit of the new version. `None` means the current version.
+    pub to: Option<String>,
+    /// Sorted by Property.
+    pub properties: Vec<PropertyDiff>,
+}
+
+impl ResourceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    pub fn to_json(&self) -> AtomicResult<serde_json::Value> {
+        Ok(serde_json::json!({
+            "subject": self.subject,
+            "from": self.from,
+            "to": self.to,
+            "properties": self
+                .properties
+                .iter()
+                .map(PropertyDiff::to_json)
+                .collect::<AtomicResult<Vec<_>>>()?,
+        }))
+    }
+}
+
+impl std::fmt::Display for ResourceDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} properties changed in {}",
+            self.properties.len(),
+            self.subject
+        )?;
+        for diff in &self.properties {
+            write!(f, "{} {}", diff.kind.as_str(), diff.property)?;
+            match (&diff.from, &diff.to) {
+                _ if !diff.lines.is_empty() => {
+                    writeln!(f)?;
+                    for line in &diff.lines {
+                        write!(f, "{}{}", line.kind.sign(), line.value)?;
+                        if !line.value.ends_with('\n') {
+                            writeln!(f)?;
+                        }
+                    }
+                }
+                _ if !diff.items.is_empty() => {
+                    writeln!(f)?;
+                    for item in &diff.items {
+                        writeln!(f, "{}{}", item.kind.sign(), item.value)?;
+                    }
+                }
+                (Some(from), Some(to)) => writeln!(f, ": '{}' => '{}'", from, to)?,
+                (None, Some(to)) => writeln!(f, ": '{}'", to)?,
+                (Some(from), None) => writeln!(f, ": '{}'", from)?,
+                (None, None) => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn array_items(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::ResourceArray(items) => Some(items.iter().map(|i| i.to_string()).collect()),
+        Value::Array(items) => Some(items.iter().map(|i| i.to_string()).collect()),
+        _ => None,
+    }
+}
+
+/// Compares two Values of the same Property. Returns `None` if they are equal.
+pub fn diff_values(
+    property: &str,
+    from: Option<&Value>,
+    to: Option<&Value>,
+) -> Option<PropertyDiff> {
+    let kind = match (from, to) {
+        (None, None) => return None,
+        (None, Some(_)) => ChangeKind::Added,
+        (Some(_), None) => ChangeKind::Removed,
+        (Some(from), Some(to)) => {
+            if from.datatype() == to.datatype() && from.to_string() == to.to_string() {
+                return None;
+            }
+            ChangeKind::Changed
+        }
+    };
+    let mut items = Vec::new();
+    let mut lines = Vec::new();
+    if let (Some(from), Some(to)) = (from, to) {
+        if let (Some(old), Some(new)) = (array_items(from), array_items(to)) {
+            for op in capture_diff_slices(Algorithm::Myers, &old, &new) {
+                for change in op.iter_changes(&old, &new) {
+                    if change.tag() != ChangeTag::Equal {
+                        items.push(Edit {
+                            kind: EditKind::from_tag(change.tag()),
+                            old_index: change.old_index(),
+                            new_index: change.new_index(),
+                            value: change.value().to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        if let (Value::Markdown(old), Value::Markdown(new)) = (from, to) {
+            for change in TextDiff::from_lines(old, new).iter_all_changes() {
+                lines.push(Edit {
+                    kind: EditKind::from_tag(change.tag()),
+                    old_index: change.old_index(),
+                    new_index: change.new_index(),
+                    value: change.value().to_string(),
+                });
+            }
+        }
+    }
+    Some(PropertyDiff {
+        property: property.into(),
+        kind,
+        from: from.cloned(),
+        to: to.cloned(),
+        items,
+        lines,
+    })
+}
+
+/// Compares all Properties of two Resources.
+pub fn diff_resources(from: &Resource, to: &Resource) -> Vec<PropertyDiff> {
+    let mut properties: Vec<&String> = from
+        .get_propvals()
+        .keys()
+        .chain(to.get_propvals().keys())
+        .collect();
+    properties.sort();
+    properties.dedup();
+    properties
+        .into_iter()
+        .filter_map(|prop| diff_values(prop, from.get(prop).ok(), to.get(prop).ok()))
+        .collect()
+}
+
+/// Compares two versions of a Resource, identified by the Commits that created them.
+/// Without `from`, compares with the empty Resource before the first Commit.
+/// Without `to`, compares with the current version.
+pub fn diff_versions(
+    store: &impl Storelike,
+    subject: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    for_agent: &ForAgent,
+) -> AtomicResult<ResourceDiff> {
+    let version = |commit: Option<&str>, default: &dyn Fn() -> AtomicResult<Resource>| {
+        let Some(commit) = commit else {
+            return default();
+        };
+        let version = construct_version(commit, store, for_agent)?;
+        if version.get_subject() != subject {
+            return Err(format!("Commit {} does not belong to {}", commit, subject).into());
+        }
+        Ok(version)
+    };
+    let current = || -> AtomicResult<Resource> {
+        let resource = store.get_resource(subject)?;
+        crate::hierarchy::check_read(store, &resource, for_agent)?;
+        Ok(resource)
+    };
+    let old = version(from, &|| Ok(Resource::new(subject.into())))?;
+    let new = version(to, &current)?;
+    Ok(ResourceDiff {
+        subject: subject.into(),
+        from: from.map(|c| c.to_string()),
+        to: to.map(|c| c.to_string()),
+        properties: diff_resources(&old, &new),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{urls, Store};
+
+    #[test]
+    fn diffs_arrays_and_markdown() {
+        let from = Value::from(vec!["a".to_string(), "b".into(), "c".into()]);
+        let to = Value::from(vec!["a".to_string(), "c".into(), "d".into()]);
+        let diff = diff_values(urls::WRITE, Some(&from), Some(&to)).unwrap();
+        assert_eq!(diff.kind, ChangeKind::Changed);
+        let items: Vec<(EditKind, &str)> = diff
+            .items
+            .iter()
+            .map(|e| (e.kind, e.value.as_str()))
+            .collect();
+        assert_eq!(
+            items,
+            vec![(EditKind::Delete, "b"), (EditKind::Insert, "d")]
+        );
+        assert_eq!(diff.items[1].new_index, Some(2));
+
+        let from = Value::Markdown("# Title\nFirst\nSecond\n".into());
+        let to = Value::Markdown("# Title\nFirst!\nSecond\n".into());
+        let diff = diff_values(urls::DESCRIPTION, Some(&from), Some(&to)).unwrap();
+        let signs: String = diff.lines.iter().map(|l| l.kind.sign()).collect();
+        assert_eq!(signs, " -+ ");
+
+        assert!(diff_values(urls::NAME, Some(&from), Some(&from)).is_none());
+    }
+
+    #[test]
+    fn diffs_versions() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(None).unwrap();
+        store.set_default_agent(agent);
+        let subject = "http://localhost/diffed";
+        let mut resource = Resource::new(subject.into());
+        resource
+            .set_string(urls::NAME.into(), "first", &store)
+            .unwrap();
+        resource
+            .set_string(urls::SHORTNAME.into(), "first", &store)
+            .unwrap();
+        let first = resource.save_locally(&store).unwrap().commit_resource;
+        resource
+            .set_string(urls::NAME.into(), "second", &store)
+            .unwrap();
+        resource.remove_propval(urls::SHORTNAME);
+        resource
+            .set_string(urls::DESCRIPTION.into(), "Hi", &store)
+            .unwrap();
+        let second = resource.save_locally(&store).unwrap().commit_resource;
+
+        let diff = diff_versions(
+            &store,
+            subject,
+            Some(first.get_subject()),
+            Some(second.get_subject()),
+            &ForAgent::Sudo,
+        )
+        .unwrap();
+        let kinds: Vec<(&str, ChangeKind)> = diff
+            .properties
+            .iter()
+            .map(|p| (p.property.as_str(), p.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (urls::DESCRIPTION, ChangeKind::Added),
+                (urls::NAME, ChangeKind::Changed),
+                (urls::SHORTNAME, ChangeKind::Removed),
+            ]
+        );
+
+        let initial = diff_versions(
+            &store,
+            subject,
+            None,
+            Some(first.get_subject()),
+            &ForAgent::Sudo,
+        )
+        .unwrap();
+        assert_eq!(initial.properties.len(), 2);
+        diff_versions(
+            &store,
+            "http://localhost/other",
+            Some(first.get_subject()),
+            None,
+            &ForAgent::Sudo,
+        )
+        .unwrap_err();
+    }
+}
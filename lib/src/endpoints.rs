@@ -78,15 +78,23 @@ pub fn default_endpoints() -> Vec<Endpoint> {
     vec![
         plugins::versioning::version_endpoint(),
         plugins::versioning::all_versions_endpoint(),
+        plugins::diff::diff_endpoint(),
         plugins::path::path_endpoint(),
         plugins::search::search_endpoint(),
         plugins::files::upload_endpoint(),
         plugins::files::download_endpoint(),
         plugins::export::export_endpoint(),
+        plugins::audit_log::audit_log_endpoint(),
+        plugins::changes::changes_endpoint(),
+        plugins::replication::replication_endpoint(),
+        plugins::validate::validate_endpoint(),
+        plugins::trash::trash_endpoint(),
         #[cfg(feature = "html")]
         plugins::bookmark::bookmark_endpoint(),
         plugins::importer::import_endpoint(),
+        plugins::migrate_property::migrate_property_endpoint(),
         plugins::query::query_endpoint(),
+        plugins::passkey::passkey_login_endpoint(),
         #[cfg(debug_assertions)]
         plugins::prunetests::prune_tests_endpoint(),
     ]
@@ -6,7 +6,7 @@ use core::fmt;
 
 use crate::{agents::ForAgent, errors::AtomicResult, storelike::Query, urls, Resource, Storelike};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Right {
     /// Full read access to the resource and its children.
     /// [urls::READ]
@@ -30,6 +30,19 @@ impl fmt::Display for Right {
     }
 }
 
+impl std::str::FromStr for Right {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            urls::READ => Ok(Right::Read),
+            urls::WRITE => Ok(Right::Write),
+            urls::APPEND => Ok(Right::Append),
+            other => Err(format!("Unknown right: {}", other).into()),
+        }
+    }
+}
+
 /// Looks for children relations, adds to the resource. Performs a Query, might be expensive.
 pub fn add_children(store: &impl Storelike, resource: &mut Resource) -> AtomicResult<Resource> {
     let results = store.query(&Query::new_prop_val(urls::PARENT, resource.get_subject()))?;
@@ -39,6 +52,24 @@ pub fn add_children(store: &impl Storelike, resource: &mut Resource) -> AtomicRe
     Ok(resource.to_owned())
 }
 
+/// Returns the subject of the Drive that contains the Resource, which can be the Resource itself.
+/// Falls back to the root of the hierarchy if none of the parents is a Drive.
+pub fn find_drive(store: &impl Storelike, resource: &Resource) -> Option<String> {
+    let tree = std::iter::once(resource.clone())
+        .chain(resource.get_parent_tree(store).unwrap_or_default())
+        .collect::<Vec<Resource>>();
+    let drive = tree
+        .iter()
+        .find(|r| {
+            r.get(urls::IS_A)
+                .and_then(|is_a| is_a.to_subjects(None))
+                .map(|classes| classes.iter().any(|c| c == urls::DRIVE))
+                .unwrap_or(false)
+        })
+        .or(tree.last());
+    drive.map(|d| d.get_subject().clone())
+}
+
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
 pub fn check_write(
@@ -107,6 +138,12 @@ pub fn check_rights(
     if for_agent_enum == &ForAgent::Sudo {
         return Ok("Sudo has root access, and can edit anything.".into());
     }
+    if let ForAgent::Delegated(delegation) = for_agent_enum {
+        // The delegate can never do more than the Agent that issued the token.
+        delegation.check_scope(store, resource, &right)?;
+        let issuer = ForAgent::AgentSubject(delegation.agent.clone());
+        return check_rights(store, resource, &issuer, right);
+    }
     let for_agent = for_agent_enum.to_string();
     if resource.get_subject() == &for_agent {
         return Ok("Agents can always edit themselves or their children.".into());
@@ -60,15 +60,21 @@ assert!(fetched_new_resource.get_shortname("description", &store).unwrap().to_st
 
 pub mod agents;
 pub mod atoms;
+pub mod audit;
 pub mod authentication;
+pub mod changes;
 pub mod client;
 pub mod collections;
 pub mod commit;
+pub mod computed;
 #[cfg(feature = "config")]
 pub mod config;
 pub mod datatype;
 #[cfg(feature = "db")]
 pub mod db;
+pub mod delegation;
+#[cfg(feature = "db")]
+pub mod diff;
 #[cfg(feature = "db")]
 pub mod endpoints;
 pub mod errors;
@@ -78,8 +84,11 @@ pub mod parse;
 #[cfg(feature = "db")]
 pub mod plugins;
 pub mod populate;
+pub mod replication;
 pub mod resources;
 pub mod schema;
+#[cfg(feature = "db")]
+pub mod schema_migration;
 pub mod serialize;
 pub mod store;
 pub mod storelike;
@@ -37,6 +37,10 @@ pub struct ParseOpts {
     /// This can be a dangerous value if true, because it can overwrite _all_ resources where the `for_agen` has write rights.
     /// Only parse items from sources that you trust!
     pub overwrite_outside: bool,
+    /// Keeps strings that are not URLs in Resource Arrays as they are, instead of returning an error.
+    /// Some existing data contains these, such as the parameters of Endpoints.
+    /// Use this when the data has to stay exactly the same, e.g. to verify the signature of a replicated Commit.
+    pub keep_non_url_items: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -62,6 +66,7 @@ impl std::default::Default for ParseOpts {
             for_agent: ForAgent::Sudo,
             overwrite_outside: true,
             save: SaveOpts::Save,
+            keep_non_url_items: false,
         }
     }
 }
@@ -218,8 +223,9 @@ pub fn parse_json_ad_commit_resource(
 ) -> AtomicResult<Resource> {
     let json: Map<String, serde_json::Value> = serde_json::from_str(string)?;
     let signature = json
-        .get(urls::SUBJECT)
-        .ok_or("No subject field in Commit.")?
+        .get(urls::SIGNATURE)
+        .and_then(|signature| signature.as_str())
+        .ok_or("No signature field in Commit.")?
         .to_string();
     let subject = format!("{}/commits/{}", store.get_server_url(), signature);
     let mut resource = Resource::new(subject);
@@ -286,6 +292,14 @@ fn parse_json_ad_map_to_resource(
 
         prop = try_to_subject(&prop, &prop)?;
 
+        // Booleans, arrays and objects can mean different things depending on the Property
+        let datatype = match val {
+            serde_json::Value::Bool(_)
+            | serde_json::Value::Array(_)
+            | serde_json::Value::Object(_) => store.get_property(&prop).ok().map(|p| p.data_type),
+            _ => None,
+        };
+
         let atomic_val = match val {
             serde_json::Value::Null => {
                 return Err(AtomicError::parse_error(
@@ -294,12 +308,32 @@ fn parse_json_ad_map_to_resource(
                     Some(&prop),
                 ));
             }
+            // JSON values are stored as-is, so objects and arrays are not parsed as resources
+            json @ (serde_json::Value::Bool(_)
+            | serde_json::Value::Array(_)
+            | serde_json::Value::Object(_))
+                if datatype == Some(DataType::Json) =>
+            {
+                Value::new_json(json)
+            }
+            // Typed arrays contain plain values, not resources
+            serde_json::Value::Array(items) if matches!(datatype, Some(DataType::Array(_))) => {
+                let property = store.get_property(&prop)?;
+                Value::new_for_property(&serde_json::Value::Array(items).to_string(), &property)
+                    .map_err(|e| {
+                        AtomicError::parse_error(
+                            &format!("Unable to parse array for prop {prop}: {e}"),
+                            subject.as_deref(),
+                            Some(&prop),
+                        )
+                    })?
+            }
             serde_json::Value::Bool(bool) => Value::Boolean(bool),
             serde_json::Value::Number(num) => {
                 let property = store.get_property(&prop)?;
                 // Also converts numbers to strings, not sure what to think about this.
                 // Does not result in invalid atomic data, but does allow for weird inputs
-                Value::new(&num.to_string(), &property.data_type)?
+                Value::new_for_property(&num.to_string(), &property)?
             }
             serde_json::Value::String(str) => {
                 // LocalIDs are mapped to @ids by appending the `localId` to the `importer`'s `parent`.
@@ -324,9 +358,10 @@ fn parse_json_ad_map_to_resource(
                     DataType::AtomicUrl => {
                         // If the value is not a valid URL, and we have an importer, we can generate_id_from_local_id
                         let url = try_to_subject(&str, &prop)?;
-                        Value::new(&url, &property.data_type)?
+                        Value::new_for_property(&url, &property)?
                     }
-                    other => Value::new(&str.to_string(), &other).map_err(|e| {
+                    DataType::Json => Value::new_json(serde_json::Value::String(str)),
+                    _ => Value::new_for_property(&str, &property).map_err(|e| {
                         AtomicError::parse_error(
                             &format!("Unable to parse value for prop {prop}: {e}. Value: {str}"),
                             subject.as_deref(),
@@ -341,6 +376,11 @@ fn parse_json_ad_map_to_resource(
                 let mut newvec: Vec<SubResource> = Vec::new();
                 for v in arr {
                     match v {
+                        serde_json::Value::String(str)
+                            if parse_opts.keep_non_url_items && check_valid_url(&str).is_err() =>
+                        {
+                            newvec.push(SubResource::Subject(str))
+                        }
                         serde_json::Value::String(str) => {
                             let url = try_to_subject(&str, &prop)?;
                             newvec.push(SubResource::Subject(url))
@@ -414,11 +454,13 @@ fn parse_json_ad_map_to_resource(
                 let commit = r.get_commit_builder().clone().sign(&signer, store, &r)?;
                 let opts = CommitOpts {
                     validate_schema: true,
+                    // Imported Resources can link to each other before all of them are created
+                    validate_links: false,
                     validate_signature: true,
                     validate_timestamp: false,
                     validate_rights: parse_opts.for_agent != ForAgent::Sudo,
                     validate_previous_commit: false,
-                    validate_for_agent: Some(parse_opts.for_agent.to_string()),
+                    validate_for_agent: Some(parse_opts.for_agent.clone()),
                     update_index: true,
                 };
 
@@ -444,6 +486,67 @@ mod test {
     use super::*;
     use crate::Storelike;
 
+    #[test]
+    fn parse_and_serialize_extended_datatypes() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        for (shortname, datatype) in [
+            ("settings", DataType::Json),
+            ("theme", DataType::Json),
+            ("columns", DataType::Json),
+            ("price", DataType::Decimal),
+            ("location", DataType::GeoPoint),
+            ("sizes", DataType::Array(Box::new(DataType::Integer))),
+        ] {
+            let property = crate::schema::Property {
+                class_type: None,
+                data_type: datatype,
+                shortname: shortname.into(),
+                subject: format!("https://example.com/{}", shortname),
+                description: shortname.into(),
+                allows_only: None,
+                constraints: Default::default(),
+                on_delete: Default::default(),
+            };
+            store.add_resource(&property.to_resource()).unwrap();
+        }
+        let json_input = r#"{
+            "@id": "https://example.com/shop",
+            "https://example.com/settings": { "theme": "dark", "tags": ["a", "b"] },
+            "https://example.com/theme": "{\"dark\": true}",
+            "https://example.com/columns": 3,
+            "https://example.com/price": "12345678901234567890.10",
+            "https://example.com/location": "52.3676,4.9041",
+            "https://example.com/sizes": [38, 40, 42]
+          }"#;
+        let resource = parse_json_ad_resource(json_input, &store, &ParseOpts::default()).unwrap();
+        assert!(matches!(
+            resource.get("https://example.com/settings").unwrap(),
+            Value::Json(_)
+        ));
+        // JSON-AD values of Json Properties are the JSON value itself, also for strings and numbers
+        for (prop, json) in [
+            (
+                "https://example.com/theme",
+                serde_json::json!("{\"dark\": true}"),
+            ),
+            ("https://example.com/columns", serde_json::json!(3)),
+        ] {
+            let value = resource.get(prop).unwrap();
+            assert!(matches!(value, Value::Json(s) if s == &json.to_string()));
+            let parsed = Value::new(&value.to_string(), &DataType::Json).unwrap();
+            assert_eq!(parsed.to_string(), json.to_string());
+        }
+        assert!(resource
+            .get("https://example.com/sizes")
+            .unwrap()
+            .contains_value(&Value::Integer(40)));
+        let json_output = resource.to_json_ad().unwrap();
+        let in_value: serde_json::Value = serde_json::from_str(json_input).unwrap();
+        let out_value: serde_json::Value = serde_json::from_str(&json_output).unwrap();
+        assert_eq!(in_value, out_value);
+    }
+
     #[test]
     fn parse_and_serialize_json_ad() {
         let store = crate::Store::init().unwrap();
@@ -616,6 +719,7 @@ mod test {
             for_agent: ForAgent::Sudo,
             overwrite_outside: false,
             importer: Some(importer.clone()),
+            keep_non_url_items: false,
         };
 
         store.import(json, &parse_opts).unwrap();
@@ -638,6 +742,7 @@ mod test {
             signer: Some(store.get_default_agent().unwrap()),
             overwrite_outside: false,
             importer: Some(importer.clone()),
+            keep_non_url_items: false,
         };
 
         store
@@ -696,6 +801,7 @@ mod test {
             for_agent: agent.subject.into(),
             overwrite_outside: false,
             importer: Some(importer),
+            keep_non_url_items: false,
         };
 
         // We can't allow this to happen, so we expect an error
@@ -761,6 +867,7 @@ mod test {
             // not the one performing the import, because we don't have their private key.
             signer: Some(store.get_default_agent().unwrap()),
             save: crate::parse::SaveOpts::Commit,
+            keep_non_url_items: false,
         };
 
         store.import(json, &parse_opts).unwrap();
@@ -0,0 +1,31 @@
+use crate::endpoints::Endpoint;
+
+pub fn audit_log_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/audit-log".to_string(),
+        params: vec![
+            "kind".into(),
+            "agent".into(),
+            "subject".into(),
+            "from".into(),
+            "until".into(),
+            "limit".into(),
+        ],
+        description: r#"Returns the audit log of the server as JSON lines. Only Agents with write rights to the root of the server can read it.
+
+The log records failed authentication, denied reads and writes, `Sudo` usage, accepted Invites and destroyed Resources.
+Each entry contains the hash of the previous one, so tampering with the log can be detected.
+
+Use with the following (optional) parameters
+- **kind**: One of `authenticationFailed`, `readDenied`, `writeDenied`, `sudo`, `inviteAccepted` or `destroy`.
+- **agent**: Subject of the Agent that performed the action.
+- **subject**: Subject of the targeted Resource.
+- **from** / **until**: Unix timestamps (ms).
+- **limit**: Maximum amount of entries.
+"#
+        .to_string(),
+        shortname: "audit-log".to_string(),
+        handle: None,
+        handle_post: None,
+    }
+}
@@ -0,0 +1,28 @@
+use crate::endpoints::Endpoint;
+
+pub fn changes_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/changes".to_string(),
+        params: vec![
+            "since".into(),
+            "drive".into(),
+            "class".into(),
+            "limit".into(),
+            "wait".into(),
+        ],
+        description: r#"Returns the Commits that the server applied, in apply order, as JSON lines. Each line has a `sequence`: pass the last one you've seen as `since` to resume from there.
+Requires read rights for the `drive`, or write rights to the root of the server if no `drive` is given.
+
+Use with the following (optional) parameters
+- **since**: Only return Commits after this sequence.
+- **drive**: Only Commits of Resources in this Drive.
+- **class**: Only Commits of Resources that are an instance of this Class.
+- **limit**: Maximum amount of Commits, defaults to 100.
+- **wait**: If there are no new Commits, wait up to this amount of seconds (max 60) for one to be applied.
+"#
+        .to_string(),
+        shortname: "changes".to_string(),
+        handle: None,
+        handle_post: None,
+    }
+}
@@ -0,0 +1,61 @@
+/*!
+Shows what changed between two versions of a Resource. See [crate::diff].
+*/
+
+use crate::{
+    diff::diff_versions,
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    urls, Resource, Value,
+};
+
+pub fn diff_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/diff".to_string(),
+        params: vec!["subject".into(), "from".into(), "to".into()],
+        description: r#"Compares two versions of a Resource, and returns the changes per Property: added, removed and changed values, inserted and deleted array items, and line-level changes for Markdown.
+
+- **subject**: The Resource to compare.
+- **from**: URL of the Commit that created the old version. If empty, compares with the Resource before its first Commit.
+- **to**: URL of the Commit that created the new version. If empty, compares with the current version.
+"#
+        .to_string(),
+        shortname: "diff".to_string(),
+        handle: Some(handle_diff_request),
+        handle_post: None,
+    }
+}
+
+#[tracing::instrument]
+fn handle_diff_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        subject,
+        store,
+        for_agent,
+    } = context;
+    let mut target = None;
+    let mut from = None;
+    let mut to = None;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "subject" => target = Some(v.to_string()),
+            "from" if !v.is_empty() => from = Some(v.to_string()),
+            "to" if !v.is_empty() => to = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    let Some(target) = target else {
+        return diff_endpoint().to_resource(store);
+    };
+
+    let diff = diff_versions(store, &target, from.as_deref(), to.as_deref(), for_agent)?;
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_class(urls::ENDPOINT_RESPONSE);
+    resource.set_unsafe(urls::STATUS.to_string(), 200.into());
+    resource.set_unsafe(urls::RESPONSE_MESSAGE.to_string(), diff.to_string().into());
+    resource.set_unsafe(
+        urls::DIFF.to_string(),
+        Value::Json(diff.to_json()?.to_string()),
+    );
+    Ok(resource)
+}
@@ -78,6 +78,7 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
         // not the one performing the import, because we don't have their private key.
         signer: Some(store.get_default_agent()?),
         save: crate::parse::SaveOpts::Commit,
+        keep_non_url_items: false,
     };
 
     if let Some(json_string) = json {
@@ -1,5 +1,6 @@
 use crate::{
     agents::{Agent, ForAgent},
+    audit::{AuditEvent, AuditEventKind},
     errors::AtomicResult,
     urls,
     utils::check_valid_url,
@@ -101,6 +102,18 @@ pub fn construct_invite_redirect(
         // Also add read rights
         add_rights(&agent, target, false, store)?;
     }
+    store.audit(
+        AuditEvent::new(
+            AuditEventKind::InviteAccepted,
+            &ForAgent::AgentSubject(agent.clone()),
+            target,
+        )
+        .with_message(format!(
+            "Accepted Invite {} with {} rights",
+            invite_resource.get_subject(),
+            if write { "write" } else { "read" }
+        )),
+    );
 
     // Construct the Redirect Resource, which might provide the Client with a Subject for his Agent.
     let mut redirect = Resource::new_instance(urls::REDIRECT, store)?;
@@ -0,0 +1,111 @@
+/*!
+Changes the Datatype of a Property and converts its existing values.
+See [crate::schema_migration].
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    agents::ForAgent,
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    schema_migration::{datatype_from_urls, migrate_property, ConversionRule, Migration},
+    urls, Resource,
+};
+
+pub fn migrate_property_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/migrate-property".to_string(),
+        params: vec![
+            "property".into(),
+            "datatype".into(),
+            "item-datatype".into(),
+            "rule".into(),
+            "dry-run".into(),
+        ],
+        description: r#"Changes the Datatype of a Property, and converts the existing values of that Property using signed Commits. Requires write rights for the Property and for every Resource with a value that changes. Send a POST request with these query parameters:
+
+- **property**: Subject of the Property.
+- **datatype**: URL of the new Datatype.
+- **item-datatype**: URL of the item Datatype, if the new Datatype is an Array.
+- **rule**: `cast` (default) parses the current values as the new Datatype. `map` replaces values using a JSON object (current value => new value) in the request body, and casts the others. `drop` removes the values that can't be cast.
+- **dry-run**: If `true`, only returns a report of what would change.
+
+Nothing changes if some values can't be converted.
+"#
+        .to_string(),
+        shortname: "migrate-property".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_post),
+    }
+}
+
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    migrate_property_endpoint().to_resource(context.store)
+}
+
+#[tracing::instrument]
+fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        body,
+        for_agent,
+        subject,
+    } = context;
+    let mut property = None;
+    let mut datatype = None;
+    let mut item_datatype = None;
+    let mut rule = "cast".to_string();
+    let mut dry_run = false;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "property" => property = Some(v.to_string()),
+            "datatype" => datatype = Some(v.to_string()),
+            "item-datatype" => item_datatype = Some(v.to_string()),
+            "rule" => rule = v.to_string(),
+            "dry-run" => dry_run = v == "true",
+            _ => {}
+        }
+    }
+    let property = property.ok_or("No `property` specified")?;
+    let datatype = datatype.ok_or("No `datatype` specified")?;
+
+    if for_agent == &ForAgent::Public {
+        return Err("No agent specified for migration".into());
+    }
+
+    let table = if body.is_empty() {
+        None
+    } else {
+        let table: HashMap<String, String> = serde_json::from_slice(&body).map_err(|e| {
+            format!(
+                "Expected a JSON object with strings (current value => new value) in the body: {}",
+                e
+            )
+        })?;
+        Some(table)
+    };
+
+    let migration = Migration {
+        property,
+        data_type: datatype_from_urls(&datatype, item_datatype.as_deref())?,
+        rule: ConversionRule::from_name(&rule, table)?,
+        dry_run,
+        for_agent: for_agent.clone(),
+    };
+    let report = migrate_property(store, &migration)?;
+    let status = if report.applied || (dry_run && report.failed.is_empty()) {
+        200
+    } else {
+        400
+    };
+
+    let mut resource = Resource::new_generate_subject(store);
+    resource.set_class(urls::ENDPOINT_RESPONSE);
+    resource.set_unsafe(urls::STATUS.to_string(), status.into());
+    resource.set_unsafe(
+        urls::RESPONSE_MESSAGE.to_string(),
+        report.to_string().into(),
+    );
+    Ok(resource)
+}
@@ -39,12 +39,20 @@ pub mod importer;
 pub mod invite;
 
 // Endpoints
+pub mod audit_log;
 #[cfg(feature = "html")]
 pub mod bookmark;
+pub mod changes;
+pub mod diff;
 pub mod export;
 pub mod files;
+pub mod migrate_property;
+pub mod passkey;
 pub mod path;
 pub mod prunetests;
 pub mod query;
+pub mod replication;
 pub mod search;
+pub mod trash;
+pub mod validate;
 pub mod versioning;
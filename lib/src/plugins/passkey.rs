@@ -0,0 +1,27 @@
+use crate::{
+    authentication::{login_with_passkey, PasskeyAssertion},
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    urls, Resource,
+};
+
+pub fn passkey_login_endpoint() -> Endpoint {
+    Endpoint {
+        path: urls::PATH_PASSKEY_LOGIN.into(),
+        params: [].into(),
+        description: "Log in with a passkey (WebAuthn credential) of your Agent. POST a JSON passkey assertion, which adds its short-lived session key to the Agent.".to_string(),
+        shortname: "passkey-login".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_passkey_login),
+    }
+}
+
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    passkey_login_endpoint().to_resource(context.store)
+}
+
+fn handle_passkey_login(context: HandlePostContext) -> AtomicResult<Resource> {
+    let assertion: PasskeyAssertion = serde_json::from_slice(&context.body)
+        .map_err(|e| format!("Invalid passkey assertion. {}", e))?;
+    login_with_passkey(context.store, &assertion)
+}
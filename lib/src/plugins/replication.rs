@@ -0,0 +1,16 @@
+use crate::endpoints::Endpoint;
+
+pub fn replication_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/replication".to_string(),
+        params: vec![],
+        description: r#"Returns the status of the Drives that this server mirrors from other servers, as JSON lines.
+Each line shows the `leader`, the `cursor` in its changefeed, the amount of `applied` Commits, the `lagMs` of the last replicated Commit and whether the mirror is `caughtUp`.
+Requires write rights to the root of the server.
+"#
+        .to_string(),
+        shortname: "replication".to_string(),
+        handle: None,
+        handle_post: None,
+    }
+}
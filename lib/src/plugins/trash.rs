@@ -0,0 +1,120 @@
+/*!
+Lists, restores and purges the destroyed Resources in the trash of a Drive.
+*/
+
+use crate::{
+    agents::ForAgent,
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    urls,
+    values::SubResource,
+    Db, Resource, Storelike, Value,
+};
+
+pub fn trash_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/trash".to_string(),
+        params: vec!["drive".into(), "restore".into(), "purge".into()],
+        description: r#"Destroyed Resources are moved to the trash of their Drive, together with their children, and are purged when the retention period has passed. Requires write rights for the Drive.
+
+- **drive**: The Drive of which the trash is shown. Defaults to the root of the server.
+
+Send a POST request with one of these parameters:
+
+- **restore**: Subject of a trashed Resource. Restores it and its trashed children using new Commits.
+- **purge**: Subject of a trashed Resource. Permanently removes it and its trashed children.
+"#
+        .to_string(),
+        shortname: "trash".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_post),
+    }
+}
+
+fn check_drive_write(store: &Db, drive: &str, for_agent: &ForAgent) -> AtomicResult<()> {
+    if for_agent == &ForAgent::Public {
+        return Err("No agent specified for the trash".into());
+    }
+    crate::hierarchy::check_write(store, &store.get_resource(drive)?, for_agent)?;
+    Ok(())
+}
+
+#[tracing::instrument]
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        subject,
+        store,
+        for_agent,
+    } = context;
+    let mut drive = None;
+    for (k, v) in subject.query_pairs() {
+        if k == "drive" {
+            drive = Some(v.to_string());
+        }
+    }
+    if drive.is_none() && for_agent == &ForAgent::Public {
+        return trash_endpoint().to_resource(store);
+    }
+    let drive = drive.unwrap_or_else(|| store.get_server_url().to_string());
+    check_drive_write(store, &drive, for_agent)?;
+
+    let trashed: Vec<SubResource> = store
+        .list_trash(&drive)?
+        .iter()
+        .map(|entry| SubResource::Resource(Box::new(entry.to_resource())))
+        .collect();
+    let mut resource = Resource::new(subject.to_string());
+    resource.set_unsafe(
+        urls::NAME.into(),
+        Value::String(format!("Trash of {}", drive)),
+    );
+    resource.set_unsafe(
+        urls::TRASHED_RESOURCES.into(),
+        Value::ResourceArray(trashed),
+    );
+    Ok(resource)
+}
+
+#[tracing::instrument]
+fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let HandlePostContext {
+        store,
+        for_agent,
+        subject,
+        ..
+    } = context;
+    let mut restore = None;
+    let mut purge = None;
+    for (k, v) in subject.query_pairs() {
+        match k.as_ref() {
+            "restore" => restore = Some(v.to_string()),
+            "purge" => purge = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    let target = restore
+        .as_ref()
+        .or(purge.as_ref())
+        .ok_or("Specify a `restore` or `purge` subject")?;
+    let entry = store
+        .get_trashed(target)?
+        .ok_or_else(|| format!("{} is not in the trash", target))?;
+    let drive = entry
+        .drive
+        .unwrap_or_else(|| store.get_server_url().to_string());
+    check_drive_write(store, &drive, for_agent)?;
+
+    let message = if restore.is_some() {
+        let restored = store.restore_from_trash(target)?;
+        format!("Restored {} Resources", restored.len())
+    } else {
+        let purged = store.purge_from_trash(target)?;
+        format!("Permanently removed {} Resources", purged)
+    };
+
+    let mut resource = Resource::new_generate_subject(store);
+    resource.set_class(urls::ENDPOINT_RESPONSE);
+    resource.set_unsafe(urls::STATUS.to_string(), 200.into());
+    resource.set_unsafe(urls::RESPONSE_MESSAGE.to_string(), message.into());
+    Ok(resource)
+}
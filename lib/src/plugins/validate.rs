@@ -0,0 +1,45 @@
+/*!
+Validates all Resources in the Store, and returns a [crate::validate::ValidationReport].
+*/
+
+use crate::{
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    validate::validate_store,
+    Resource, Storelike,
+};
+
+pub fn validate_endpoint() -> Endpoint {
+    Endpoint {
+        path: "/validate".to_string(),
+        params: vec!["fetch-external".into()],
+        description: r#"Validates all Resources on this server, and returns a ValidationReport with the problems that were found. Only Agents with write rights to the root of the server can use it.
+
+Checks datatypes and constraints, required Properties, parents, links to other Resources and rights.
+
+- **fetch-external**: If `true`, links to external Resources that are not cached are fetched. This can take a while.
+"#
+        .to_string(),
+        shortname: "validate".to_string(),
+        handle: Some(handle_validate_request),
+        handle_post: None,
+    }
+}
+
+#[tracing::instrument]
+fn handle_validate_request(context: HandleGetContext) -> AtomicResult<Resource> {
+    let HandleGetContext {
+        subject,
+        store,
+        for_agent,
+    } = context;
+    let root = store.get_resource(store.get_server_url())?;
+    check_write(store, &root, for_agent)?;
+
+    let fetch_external = subject
+        .query_pairs()
+        .any(|(k, v)| k == "fetch-external" && v == "true");
+    let report = validate_store(store, fetch_external);
+    Ok(report.to_resource(subject.as_str()))
+}
@@ -1,12 +1,13 @@
 use tracing::warn;
 
 use crate::{
-    agents::ForAgent,
+    agents::{Agent, ForAgent},
     collections::CollectionBuilder,
+    commit::{CommitBuilder, CommitOpts, CommitResponse},
     endpoints::{Endpoint, HandleGetContext},
     errors::AtomicResult,
     storelike::Query,
-    urls, AtomicError, Commit, Resource, Storelike,
+    urls, AtomicError, Commit, Resource, Storelike, Value,
 };
 
 pub fn version_endpoint() -> Endpoint {
@@ -91,7 +92,10 @@ fn handle_all_versions_request(context: HandleGetContext) -> AtomicResult<Resour
 
 /// Searches the local store for all commits with this subject, returns sorted from old to new.
 #[tracing::instrument(skip(store))]
-fn get_commits_for_resource(subject: &str, store: &impl Storelike) -> AtomicResult<Vec<Commit>> {
+pub(crate) fn get_commits_for_resource(
+    subject: &str,
+    store: &impl Storelike,
+) -> AtomicResult<Vec<Commit>> {
     let mut q = Query::new_prop_val(urls::SUBJECT, subject);
     q.sort_by = Some(urls::CREATED_AT.into());
     let result = store.query(&q)?;
@@ -120,7 +124,8 @@ pub fn get_initial_commit_for_resource(
 }
 
 /// Constructs a Resource version for a specific Commit
-/// Only works if the current store has the required Commits
+/// Only works if the current store has the required Commits.
+/// Starts at the nearest earlier snapshot (see [Storelike::get_snapshot]), and replays the Commits after it.
 #[tracing::instrument(skip(store))]
 pub fn construct_version(
     commit_url: &str,
@@ -130,23 +135,122 @@ pub fn construct_version(
     let commit = store.get_resource(commit_url)?;
     // Get all the commits for the subject of that Commit
     let subject = &commit.get(urls::SUBJECT)?.to_string();
-    let current_resource = store.get_resource(subject)?;
-    crate::hierarchy::check_read(store, &current_resource, for_agent)?;
+    // Destroyed Resources are checked using the constructed version
+    let current_resource = store.get_resource(subject).ok();
+    if let Some(current) = &current_resource {
+        crate::hierarchy::check_read(store, current, for_agent)?;
+    }
     let commits = get_commits_for_resource(subject, store)?;
+    // If the target Commit is missing, all Commits are applied
+    let end = commits
+        .iter()
+        .position(|c| c.url.as_deref() == Some(commit_url))
+        .map(|index| index + 1)
+        .unwrap_or(commits.len());
     let mut version = Resource::new(subject.into());
-    for commit in commits {
-        if let Some(current_commit) = commit.url.clone() {
-            let applied = commit.apply_changes(version, store)?;
-            version = applied.resource_new;
-            // Stop iterating when the target commit has been applied.
-            if current_commit == commit_url {
-                break;
-            }
+    let mut start = 0;
+    for index in (0..end).rev() {
+        let snapshot = commits[index]
+            .url
+            .as_deref()
+            .and_then(|url| store.get_snapshot(url));
+        if let Some(propvals) = snapshot {
+            version = Resource::from_propvals(propvals, subject.into());
+            start = index + 1;
+            break;
         }
     }
+    for commit in &commits[start..end] {
+        if commit.url.is_some() {
+            version = commit.apply_changes(version, store)?.resource_new;
+        }
+    }
+    if current_resource.is_none() {
+        crate::hierarchy::check_read(store, &version, for_agent)?;
+    }
     Ok(version)
 }
 
+/// Builds a Commit that changes `current` into `target`, such as an earlier version of the same Resource.
+/// `lastCommit` is set by the server, so it is never changed.
+/// Sign it using `current`, so that its `previousCommit` is set.
+pub fn revert_commit_builder(current: &Resource, target: &Resource) -> CommitBuilder {
+    let mut builder = CommitBuilder::new(current.get_subject().into());
+    for diff in crate::diff::diff_resources(current, target) {
+        if diff.property == urls::LAST_COMMIT {
+            continue;
+        }
+        match diff.to {
+            Some(value) => builder.set(diff.property, value),
+            None => builder.remove(diff.property),
+        }
+    }
+    builder
+}
+
+/// Reverts a Resource to the version that `commit_url` created, using a new Commit signed by `agent`.
+/// Resources that have been destroyed since are restored.
+/// The Agent needs write rights for the Resource, or append rights for its parent if it was destroyed.
+#[tracing::instrument(skip(store, agent))]
+pub fn revert_to_version(
+    store: &impl Storelike,
+    commit_url: &str,
+    agent: &Agent,
+) -> AtomicResult<CommitResponse> {
+    let for_agent: ForAgent = agent.subject.clone().into();
+    let target = construct_version(commit_url, store, &for_agent)?;
+    let subject = target.get_subject().clone();
+    let current = match store.get_resource(&subject) {
+        Ok(current) => current,
+        Err(_) => {
+            // The new Commit follows the one that destroyed the Resource
+            let mut destroyed = Resource::new(subject.clone());
+            let commits = get_commits_for_resource(&subject, store)?;
+            if let Some(last) = commits.last().and_then(|c| c.url.clone()) {
+                destroyed.set_unsafe(urls::LAST_COMMIT.into(), Value::AtomicUrl(last));
+            }
+            destroyed
+        }
+    };
+    let changed = crate::diff::diff_resources(&current, &target)
+        .iter()
+        .any(|diff| diff.property != urls::LAST_COMMIT);
+    if !changed {
+        return Err(format!("{} is already at the version of {}", subject, commit_url).into());
+    }
+    let commit = revert_commit_builder(&current, &target).sign(agent, store, &current)?;
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_links: true,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: true,
+        validate_previous_commit: true,
+        update_index: true,
+        validate_for_agent: Some(agent.subject.as_str().into()),
+    };
+    store.apply_commit(commit, &opts)
+}
+
+/// Restores a destroyed Resource to the version before it was destroyed. See [revert_to_version].
+pub fn restore_resource(
+    store: &impl Storelike,
+    subject: &str,
+    agent: &Agent,
+) -> AtomicResult<CommitResponse> {
+    if store.get_resource(subject).is_ok() {
+        return Err(format!("{} has not been destroyed", subject).into());
+    }
+    let commits = get_commits_for_resource(subject, store)?;
+    let last = commits
+        .iter()
+        .rev()
+        .find(|commit| !commit.destroy.unwrap_or(false))
+        .and_then(|commit| commit.url.clone())
+        .ok_or_else(|| AtomicError::not_found(format!("No commits found for {}", subject)))?;
+    revert_to_version(store, &last, agent)
+}
+
 /// Creates the versioning URL for some specific Commit
 fn construct_version_endpoint_url(store: &impl Storelike, commit_url: &str) -> String {
     format!(
@@ -225,4 +329,52 @@ mod test {
             second_val
         );
     }
+
+    #[test]
+    #[cfg(feature = "db")]
+    fn reverts_and_restores() {
+        let store = crate::Db::init_temp("reverts_and_restores").unwrap();
+        let agent = store.get_default_agent().unwrap();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        resource
+            .set_string(crate::urls::NAME.into(), "first", &store)
+            .unwrap();
+        resource
+            .set_string(crate::urls::SHORTNAME.into(), "first", &store)
+            .unwrap();
+        let first_commit = resource.save_locally(&store).unwrap().commit_resource;
+        resource
+            .set_string(crate::urls::NAME.into(), "second", &store)
+            .unwrap();
+        resource.remove_propval(crate::urls::SHORTNAME);
+        resource.save_locally(&store).unwrap();
+        let subject = resource.get_subject().clone();
+
+        let response = revert_to_version(&store, first_commit.get_subject(), &agent).unwrap();
+        assert_eq!(response.commit.signer, agent.subject);
+        assert!(response.commit.previous_commit.is_some());
+        let reverted = store.get_resource(&subject).unwrap();
+        assert_eq!(reverted.get(urls::NAME).unwrap().to_string(), "first");
+        assert_eq!(reverted.get(urls::SHORTNAME).unwrap().to_string(), "first");
+        // Nothing left to revert
+        revert_to_version(&store, first_commit.get_subject(), &agent).unwrap_err();
+
+        store
+            .get_resource(&subject)
+            .unwrap()
+            .destroy(&store)
+            .unwrap();
+        assert!(store.get_resource(&subject).is_err());
+        restore_resource(&store, &subject, &agent).unwrap();
+        let restored = store.get_resource(&subject).unwrap();
+        assert_eq!(restored.get(urls::NAME).unwrap().to_string(), "first");
+        restore_resource(&store, &subject, &agent).unwrap_err();
+    }
 }
@@ -30,6 +30,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A short name of something. It can only contain letters, numbers and dashes `-`. Use dashes to denote spaces between words. Not case sensitive - lowercase only. Useful in programming contexts where the user should be able to type something short to identify a specific thing.".into(),
             subject: urls::SHORTNAME.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: None,
@@ -38,6 +40,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.".into(),
             subject: urls::DESCRIPTION.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: Some(urls::CLASS.into()),
@@ -46,6 +50,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A list of Classes of which the thing is an instance of. The Classes of a Resource determine which Properties are recommended and required.".into(),
             subject: urls::IS_A.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: Some(urls::DATATYPE_CLASS.into()),
@@ -54,6 +60,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Datatype of a property, such as String or Timestamp.".into(),
             subject: urls::DATATYPE_PROP.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: Some(urls::CLASS.into()),
@@ -64,6 +72,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
                .into(),
             subject: urls::CLASSTYPE_PROP.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -72,6 +82,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Properties that are not required, but recommended for this Class.".into(),
             subject: urls::RECOMMENDS.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -80,6 +92,18 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Properties that are required for this Class.".into(),
             subject: urls::REQUIRES.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
+        },
+        Property {
+            class_type: Some(urls::CLASS.into()),
+            data_type: DataType::ResourceArray,
+            shortname: "sub-class-of".into(),
+            description: "The Classes that this Class inherits from. Instances of this Class get the required and recommended Properties of these Classes, and are also treated as instances of these Classes.".into(),
+            subject: urls::SUB_CLASS_OF.into(),
+            allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: None,
@@ -88,6 +112,8 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The parent of a Resource sets the hierarchical structure of the Resource, and therefore also the rights / grants. It is used for both navigation, structure and authorization. Parents are the inverse of [children](https://atomicdata.dev/properties/children).".into(),
             subject: urls::PARENT.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         },
         Property {
             class_type: None,
@@ -96,23 +122,29 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "Restricts this Property to only the values inside this one. This essentially turns the Property into an `enum`.".into(),
             subject: urls::ALLOWS_ONLY.into(),
             allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
         }
     ];
 
     let classes = vec![
         Class {
             requires: vec![urls::SHORTNAME.into(), urls::DATATYPE_PROP.into(), urls::DESCRIPTION.into()],
-            recommends: vec![urls::CLASSTYPE_PROP.into(), urls::IS_DYNAMIC.into(), urls::IS_LOCKED.into(), urls::ALLOWS_ONLY.into()],
+            recommends: vec![urls::CLASSTYPE_PROP.into(), urls::IS_DYNAMIC.into(), urls::IS_LOCKED.into(), urls::ALLOWS_ONLY.into(), urls::MIN.into(), urls::MAX.into(), urls::MIN_FLOAT.into(), urls::MAX_FLOAT.into(), urls::MIN_LENGTH.into(), urls::MAX_LENGTH.into(), urls::PATTERN.into(), urls::ITEM_DATATYPE.into(), urls::ON_DELETE.into(), urls::UNIQUE.into(), urls::UNIQUE_SCOPE.into(), urls::UNIQUE_CLASS.into()],
             shortname: "property".into(),
             description: "A Property is a single field in a Class. It's the thing that a property field in an Atom points to. An example is `birthdate`. An instance of Property requires various Properties, most notably a `datatype` (e.g. `string` or `integer`), a human readable `description` (such as the thing you're reading), and a `shortname`.".into(),
             subject: urls::PROPERTY.into(),
+            sub_class_of: vec![],
+            computed_properties: vec![],
         },
         Class {
             requires: vec![urls::SHORTNAME.into(), urls::DESCRIPTION.into()],
-            recommends: vec![urls::RECOMMENDS.into(), urls::REQUIRES.into()],
+            recommends: vec![urls::RECOMMENDS.into(), urls::REQUIRES.into(), urls::SUB_CLASS_OF.into(), urls::COMPUTED_PROPERTIES.into()],
             shortname: "class".into(),
             description: "A Class describes an abstract concept, such as 'Person' or 'Blogpost'. It describes the data shape of data (which fields are required and recommended) and explains what the concept represents. It is convention to use Uppercase in its URL.Resources use the [is-a](https://atomicdata.dev/properties/isA) attribute to indicate which classes they are instances of. Note that in Atomic Data, a Resource can have several Classes - not just a single one.".into(),
             subject: urls::CLASS.into(),
+            sub_class_of: vec![],
+            computed_properties: vec![],
         },
         Class {
             requires: vec![urls::SHORTNAME.into(), urls::DESCRIPTION.into()],
@@ -121,19 +153,28 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description:
                 "A Datatype describes a possible type of value, such as 'string' or 'integer'.".into(),
             subject: urls::DATATYPE_CLASS.into(),
+            sub_class_of: vec![],
+            computed_properties: vec![],
         },
         Class {
             requires: vec![urls::PUBLIC_KEY.into()],
-            recommends: vec![urls::NAME.into(), urls::DESCRIPTION.into(), urls::DRIVES.into()],
+            recommends: vec![urls::NAME.into(), urls::DESCRIPTION.into(), urls::DRIVES.into(), urls::AGENT_KEYS.into()],
             shortname: "agent".into(),
             description:
                 "An Agent is a user that can create or modify data. It has two keys: a private and a public one. The private key should be kept secret. The public key is used to verify signatures (on [Commits](https://atomicdata.dev/classes/Commit)) set by the of the Agent.".into(),
             subject: urls::AGENT.into(),
+            sub_class_of: vec![],
+            computed_properties: vec![],
         }
     ];
 
     for p in properties {
         let mut resource = p.to_resource();
+        // The description Property has the Markdown datatype
+        resource.set_unsafe(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(p.description.clone()),
+        );
         resource.set_unsafe(
             urls::PARENT.into(),
             Value::AtomicUrl("https://atomicdata.dev/properties".into()),
@@ -143,6 +184,10 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
 
     for c in classes {
         let mut resource = c.to_resource();
+        resource.set_unsafe(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(c.description.clone()),
+        );
         resource.set_unsafe(
             urls::PARENT.into(),
             Value::AtomicUrl("https://atomicdata.dev/classes".into()),
@@ -0,0 +1,137 @@
+/*!
+Mirrors a Drive of another AtomicServer (the leader) to this one (the follower), by reading the [crate::changes] feed of the leader.
+The follower verifies the signature of every replicated Commit, and applies them in the order of the feed, keeping the Commit URLs of the leader.
+Mirrored Resources are read-only on the follower, because it only accepts Commits for its own subjects.
+See [crate::Db::sync_replication].
+*/
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::Agent,
+    changes::ChangeEntry,
+    errors::AtomicResult,
+    parse::{parse_json_ad_resource, ParseOpts, SaveOpts},
+    Commit, Storelike,
+};
+
+/// The progress of a mirrored Drive.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicationStatus {
+    /// Server URL of the leader, e.g. `https://example.com`
+    pub leader: String,
+    /// The mirrored Drive
+    pub drive: String,
+    /// Sequence of the last replicated entry in the changefeed of the leader
+    pub cursor: Option<u64>,
+    /// Amount of replicated Commits
+    pub applied: u64,
+    /// Unix timestamp (ms) of the last sync with the leader
+    pub last_sync_at: Option<i64>,
+    /// Milliseconds between the leader and the follower applying the last replicated Commit
+    pub lag_ms: Option<i64>,
+    /// Whether the last sync received all changes that the leader had
+    pub caught_up: bool,
+    pub last_error: Option<String>,
+}
+
+impl ReplicationStatus {
+    /// Status for a Drive that has not been replicated yet.
+    pub fn new(drive: &str) -> AtomicResult<Self> {
+        let url = url::Url::parse(drive).map_err(|e| format!("Invalid Drive {}. {}", drive, e))?;
+        Ok(ReplicationStatus {
+            leader: url.origin().ascii_serialization(),
+            drive: drive.to_string(),
+            cursor: None,
+            applied: 0,
+            last_sync_at: None,
+            lag_ms: None,
+            caught_up: false,
+            last_error: None,
+        })
+    }
+
+    /// Whether the leader hosts `subject`.
+    pub fn is_leader_subject(&self, subject: &str) -> bool {
+        subject == self.leader || subject.starts_with(&format!("{}/", self.leader))
+    }
+
+    /// Whether `subject` is the mirrored Drive, or its URL is inside the path of the Drive.
+    pub fn is_drive_subject(&self, subject: &str) -> bool {
+        let drive = self.drive.trim_end_matches('/');
+        subject == drive || subject.starts_with(&format!("{}/", drive))
+    }
+
+    pub fn to_json(&self) -> AtomicResult<String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize replication status. {}", e).into())
+    }
+}
+
+/// Fetches the changes of the mirrored Drive after its cursor from the leader.
+/// The leader waits up to `wait` for new changes if there are none.
+/// The `agent` needs read rights for the Drive.
+pub fn fetch_changes(
+    status: &ReplicationStatus,
+    limit: usize,
+    wait: Duration,
+    agent: Option<&Agent>,
+) -> AtomicResult<Vec<ChangeEntry>> {
+    let mut url = url::Url::parse(&format!("{}/changes", status.leader))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("drive", &status.drive);
+        if let Some(cursor) = status.cursor {
+            query.append_pair("since", &cursor.to_string());
+        }
+        query.append_pair("limit", &limit.to_string());
+        query.append_pair("wait", &wait.as_secs().to_string());
+    }
+    let url = url.to_string();
+    let client = ureq::builder()
+        .timeout(wait + Duration::from_secs(10))
+        .build();
+    let mut request = client.get(&url);
+    if let Some(agent) = agent {
+        for (key, value) in crate::client::get_authentication_headers(&url, agent)? {
+            request = request.set(&key, &value);
+        }
+    }
+    let body = match request.call() {
+        Ok(resp) => resp
+            .into_string()
+            .map_err(|e| format!("Could not read changes from {}: {}", url, e))?,
+        Err(ureq::Error::Status(status, resp)) => {
+            return Err(format!(
+                "Could not fetch changes from {}. Status: {}. Body: {}",
+                url,
+                status,
+                resp.into_string().unwrap_or_default()
+            )
+            .into())
+        }
+        Err(e) => return Err(format!("Could not fetch changes from {}: {}", url, e).into()),
+    };
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("Invalid change from {}. {}", url, e).into())
+        })
+        .collect()
+}
+
+/// Parses the Commit of a replicated change. Keeps the Commit URL of the leader.
+pub fn commit_from_entry(entry: &ChangeEntry, store: &impl Storelike) -> AtomicResult<Commit> {
+    let parse_opts = ParseOpts {
+        save: SaveOpts::DontSave,
+        keep_non_url_items: true,
+        ..Default::default()
+    };
+    let resource = parse_json_ad_resource(&entry.commit.to_string(), store, &parse_opts)
+        .map_err(|e| format!("Invalid Commit in change {}. {}", entry.sequence, e))?;
+    Commit::from_resource(resource)
+}
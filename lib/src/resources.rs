@@ -95,12 +95,19 @@ impl Resource {
     }
 
     /// Checks if the classes are there, if not, fetches them.
+    /// Includes the superclasses of these classes (see [Class::get_superclasses]).
     /// Returns an empty vector if there are no classes found.
     pub fn get_classes(&self, store: &impl Storelike) -> AtomicResult<Vec<Class>> {
         let mut classes: Vec<Class> = Vec::new();
         if let Ok(val) = self.get(crate::urls::IS_A) {
             for class in val.to_subjects(None)? {
-                classes.push(store.get_class(&class)?)
+                let class = store.get_class(&class)?;
+                let superclasses = class.get_superclasses(store)?;
+                for class in std::iter::once(class).chain(superclasses) {
+                    if !classes.iter().any(|c| c.subject == class.subject) {
+                        classes.push(class);
+                    }
+                }
             }
         }
         Ok(classes)
@@ -275,6 +282,20 @@ impl Resource {
         Ok(self)
     }
 
+    /// Appends an item to a typed [Value::Array], and adds it to the `push` of the next Commit.
+    /// Does not check the datatype of the item, this happens when the Commit is applied.
+    pub fn push_item(&mut self, property: &str, item: Value) -> AtomicResult<&mut Self> {
+        let mut items = match self.propvals.get(property) {
+            Some(Value::Array(items)) => items.to_owned(),
+            Some(_other) => return Err("Wrong datatype, expected Array".into()),
+            None => Vec::new(),
+        };
+        items.push(item.clone());
+        self.propvals.insert(property.into(), Value::Array(items));
+        self.commit.push_item(property, item)?;
+        Ok(self)
+    }
+
     /// Remove a propval from a resource by property URL.
     pub fn remove_propval(&mut self, property_url: &str) {
         self.propvals.remove_entry(property_url);
@@ -358,10 +379,11 @@ impl Resource {
         }
         let opts = CommitOpts {
             validate_schema: true,
+            validate_links: true,
             validate_signature: false,
             validate_timestamp: false,
             validate_rights: false,
-            validate_for_agent: agent.subject.into(),
+            validate_for_agent: Some(agent.subject.into()),
             // TODO: auto-merge should work before we enable this https://github.com/atomicdata-dev/atomic-server/issues/412
             validate_previous_commit: false,
             update_index: true,
@@ -386,10 +408,11 @@ impl Resource {
         let commit = commitbuilder.sign(&agent, store, self)?;
         let opts = CommitOpts {
             validate_schema: true,
+            validate_links: true,
             validate_signature: false,
             validate_timestamp: false,
             validate_rights: false,
-            validate_for_agent: agent.subject.into(),
+            validate_for_agent: Some(agent.subject.into()),
             // https://github.com/atomicdata-dev/atomic-server/issues/412
             validate_previous_commit: false,
             update_index: true,
@@ -428,7 +451,7 @@ impl Resource {
                 e
             )
         })?;
-        let val = Value::new(value, &fullprop.data_type)?;
+        let val = Value::new_for_property(value, &fullprop)?;
         self.set_unsafe(property_url, val);
         Ok(self)
     }
@@ -444,29 +467,8 @@ impl Resource {
         store: &impl Storelike,
     ) -> AtomicResult<&mut Self> {
         let full_prop = store.get_property(&property)?;
-        if let Some(allowed) = full_prop.allows_only {
-            let error = Err(format!(
-                "Property '{}' does not allow value '{}'. Allowed: {:?}",
-                property, value, allowed
-            )
-            .into());
-
-            match &value {
-                Value::ResourceArray(value_array) => {
-                    for item in value_array {
-                        if !allowed.contains(&item.to_string()) {
-                            return error;
-                        }
-                    }
-                }
-                _ => {
-                    if !allowed.contains(&value.to_string()) {
-                        return error;
-                    }
-                }
-            }
-        }
-        if full_prop.data_type == value.datatype() {
+        full_prop.check_value(&value)?;
+        if value.has_datatype(&full_prop.data_type) {
             self.set_unsafe(property, value);
             Ok(self)
         } else {
@@ -500,7 +502,7 @@ impl Resource {
         store: &impl Storelike,
     ) -> AtomicResult<&mut Self> {
         let fullprop = self.resolve_shortname_to_property(property, store)?;
-        let fullval = Value::new(value, &fullprop.data_type)?;
+        let fullval = Value::new_for_property(value, &fullprop)?;
         self.set_unsafe(fullprop.subject, fullval);
         Ok(self)
     }
@@ -699,6 +701,7 @@ mod test {
                 commit,
                 &CommitOpts {
                     validate_schema: true,
+                    validate_links: true,
                     validate_signature: true,
                     validate_timestamp: true,
                     validate_rights: false,
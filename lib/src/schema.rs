@@ -1,6 +1,12 @@
 //! Structs and models at the core of Atomic Schema (Class, Property, Datatype).
 
-use crate::{datatype::DataType, errors::AtomicResult, urls, Resource, Value};
+use crate::{
+    datatype::DataType,
+    errors::{AtomicError, AtomicResult},
+    urls,
+    values::SubResource,
+    Resource, Storelike, Value,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -18,6 +24,252 @@ pub struct Property {
     /// Restricts values to be only one of these Subjects.
     /// https://atomicdata.dev/properties/allowsOnly
     pub allows_only: Option<Vec<String>>,
+    /// Restrictions on the values, such as a range or a pattern.
+    pub constraints: ValueConstraints,
+    /// What happens to Resources that link to a destroyed Resource using this Property.
+    /// https://atomicdata.dev/properties/onDelete
+    pub on_delete: OnDelete,
+}
+
+/// How links in Commits are checked against the [Property::class_type].
+/// See [Property::check_links].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkPolicy {
+    /// Links to Resources on this server are checked, if they exist.
+    /// Links to missing and external Resources are accepted, and external Resources are not fetched.
+    Lenient,
+    /// Every linked Resource must exist, or be fetchable if it is external, and must be an instance of the classtype.
+    /// Enabled by setting [urls::STRICT_SCHEMA] on a Drive.
+    Strict,
+}
+
+impl LinkPolicy {
+    /// Uses the [urls::STRICT_SCHEMA] setting of the Resource itself or its closest parent that has one.
+    pub fn for_resource(resource: &Resource, store: &impl Storelike) -> LinkPolicy {
+        let setting = std::iter::once(resource.clone())
+            .chain(resource.get_parent_tree(store).unwrap_or_default())
+            .find_map(|r| r.get(urls::STRICT_SCHEMA).ok().map(|v| v.to_bool()));
+        match setting {
+            Some(Ok(true)) => LinkPolicy::Strict,
+            _ => LinkPolicy::Lenient,
+        }
+    }
+}
+
+/// What happens to the Resources that link to a Resource when it is destroyed.
+/// Set per [Property] using [urls::ON_DELETE], and enforced by the [crate::Db] when a destroy Commit is applied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum OnDelete {
+    /// Nothing happens, the link keeps pointing to the destroyed Resource.
+    #[default]
+    Ignore,
+    /// The Resource can't be destroyed while other Resources link to it.
+    Restrict,
+    /// The Resources that link to it are destroyed too.
+    Cascade,
+    /// The link is removed from the Resources that link to it.
+    SetNull,
+}
+
+impl std::str::FromStr for OnDelete {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(OnDelete::Ignore),
+            "restrict" => Ok(OnDelete::Restrict),
+            "cascade" => Ok(OnDelete::Cascade),
+            "set-null" => Ok(OnDelete::SetNull),
+            other => Err(format!(
+                "Unknown onDelete policy '{}'. Use `ignore`, `restrict`, `cascade` or `set-null`",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+impl std::fmt::Display for OnDelete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OnDelete::Ignore => "ignore",
+            OnDelete::Restrict => "restrict",
+            OnDelete::Cascade => "cascade",
+            OnDelete::SetNull => "set-null",
+        })
+    }
+}
+
+/// Restrictions on the Values of a [Property], which are checked on every write.
+/// See [Property::check_value].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValueConstraints {
+    /// Minimum for numbers, or the minimum amount of items in a ResourceArray or Array.
+    /// https://atomicdata.dev/properties/min
+    pub min: Option<i64>,
+    /// Maximum for numbers, or the maximum amount of items in a ResourceArray or Array.
+    /// https://atomicdata.dev/properties/max
+    pub max: Option<i64>,
+    /// https://atomicdata.dev/properties/minFloat
+    pub min_float: Option<f64>,
+    /// https://atomicdata.dev/properties/maxFloat
+    pub max_float: Option<f64>,
+    /// Minimum amount of characters in a text value.
+    /// https://atomicdata.dev/properties/minLength
+    pub min_length: Option<i64>,
+    /// Maximum amount of characters in a text value.
+    /// https://atomicdata.dev/properties/maxLength
+    pub max_length: Option<i64>,
+    /// Regular expression that the entire text value must match.
+    /// https://atomicdata.dev/properties/pattern
+    pub pattern: Option<String>,
+    /// No two Resources in the same scope may share a value.
+    /// https://atomicdata.dev/properties/unique
+    pub unique: Option<UniqueConstraint>,
+}
+
+/// Requires that no two Resources share a value for a [Property]. See [Property::check_unique].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UniqueConstraint {
+    /// https://atomicdata.dev/properties/uniqueScope
+    pub scope: UniqueScope,
+    /// If set, only instances of this Class are compared.
+    /// https://atomicdata.dev/properties/uniqueClass
+    pub class: Option<String>,
+}
+
+impl UniqueConstraint {
+    /// Whether `resource` is compared at all: it has to be an instance of the Class, or of one of its subclasses.
+    fn applies_to(&self, resource: &Resource, store: &impl Storelike) -> bool {
+        let Some(class) = &self.class else {
+            return true;
+        };
+        resource
+            .get_classes(store)
+            .map(|classes| {
+                classes
+                    .iter()
+                    .any(|c| c.is_subclass_of(class, store).unwrap_or(false))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Among which Resources a [UniqueConstraint] applies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum UniqueScope {
+    /// All Resources on the server.
+    #[default]
+    Server,
+    /// Resources in the same Drive.
+    Drive,
+    /// Resources with the same parent.
+    Parent,
+}
+
+impl std::str::FromStr for UniqueScope {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "server" => Ok(UniqueScope::Server),
+            "drive" => Ok(UniqueScope::Drive),
+            "parent" => Ok(UniqueScope::Parent),
+            other => Err(format!(
+                "Unknown unique scope '{}'. Use `server`, `drive` or `parent`",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+impl std::fmt::Display for UniqueScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UniqueScope::Server => "server",
+            UniqueScope::Drive => "drive",
+            UniqueScope::Parent => "parent",
+        })
+    }
+}
+
+impl UniqueScope {
+    /// Returns the subject that identifies the scope of the Resource, e.g. its Drive.
+    fn key(&self, resource: &Resource, store: &impl Storelike) -> Option<String> {
+        match self {
+            UniqueScope::Server => None,
+            UniqueScope::Parent => resource.get(urls::PARENT).ok().map(|p| p.to_string()),
+            UniqueScope::Drive => crate::hierarchy::find_drive(store, resource),
+        }
+    }
+}
+
+impl ValueConstraints {
+    pub fn from_resource(resource: &Resource) -> AtomicResult<ValueConstraints> {
+        let int = |prop: &str| -> AtomicResult<Option<i64>> {
+            resource.get(prop).ok().map(|v| v.to_int()).transpose()
+        };
+        let float = |prop: &str| -> AtomicResult<Option<f64>> {
+            resource.get(prop).ok().map(|v| v.to_float()).transpose()
+        };
+        Ok(ValueConstraints {
+            min: int(urls::MIN)?,
+            max: int(urls::MAX)?,
+            min_float: float(urls::MIN_FLOAT)?,
+            max_float: float(urls::MAX_FLOAT)?,
+            min_length: int(urls::MIN_LENGTH)?,
+            max_length: int(urls::MAX_LENGTH)?,
+            pattern: resource.get(urls::PATTERN).ok().map(|v| v.to_string()),
+            unique: match resource.get(urls::UNIQUE).map(|v| v.to_bool()) {
+                Ok(Ok(true)) => Some(UniqueConstraint {
+                    scope: match resource.get(urls::UNIQUE_SCOPE) {
+                        Ok(scope) => scope.to_string().parse()?,
+                        Err(_) => UniqueScope::Server,
+                    },
+                    class: resource.get(urls::UNIQUE_CLASS).ok().map(|v| v.to_string()),
+                }),
+                _ => None,
+            },
+        })
+    }
+
+    fn set_on_resource(&self, resource: &mut Resource) {
+        let ints = [
+            (urls::MIN, self.min),
+            (urls::MAX, self.max),
+            (urls::MIN_LENGTH, self.min_length),
+            (urls::MAX_LENGTH, self.max_length),
+        ];
+        for (prop, val) in ints {
+            if let Some(val) = val {
+                resource.set_unsafe(prop.into(), Value::Integer(val));
+            }
+        }
+        for (prop, val) in [
+            (urls::MIN_FLOAT, self.min_float),
+            (urls::MAX_FLOAT, self.max_float),
+        ] {
+            if let Some(val) = val {
+                resource.set_unsafe(prop.into(), Value::Float(val));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            resource.set_unsafe(urls::PATTERN.into(), Value::String(pattern.clone()));
+        }
+        if let Some(unique) = &self.unique {
+            resource.set_unsafe(urls::UNIQUE.into(), Value::Boolean(true));
+            if unique.scope != UniqueScope::Server {
+                resource.set_unsafe(
+                    urls::UNIQUE_SCOPE.into(),
+                    Value::Slug(unique.scope.to_string()),
+                );
+            }
+            if let Some(class) = &unique.class {
+                resource.set_unsafe(urls::UNIQUE_CLASS.into(), Value::AtomicUrl(class.clone()));
+            }
+        }
+    }
 }
 
 impl PartialEq for Property {
@@ -29,7 +281,22 @@ impl PartialEq for Property {
 impl Property {
     /// Fetches a property by URL, returns a Property instance
     pub fn from_resource(resource: Resource) -> AtomicResult<Property> {
-        let data_type = resource.get(urls::DATATYPE_PROP)?.to_string().parse()?;
+        let mut data_type = resource.get(urls::DATATYPE_PROP)?.to_string().parse()?;
+        if let DataType::Array(_) = data_type {
+            let item: DataType = match resource.get(urls::ITEM_DATATYPE) {
+                Ok(item) => item.to_string().parse()?,
+                Err(_) => DataType::String,
+            };
+            if !item.is_valid_array_item() {
+                return Err(format!(
+                    "Property {} has an invalid item datatype {}. Arrays can not contain arrays or links.",
+                    resource.get_subject(),
+                    item
+                )
+                .into());
+            }
+            data_type = DataType::Array(Box::new(item));
+        }
         let shortname = resource.get(urls::SHORTNAME)?.to_string();
         let description = resource.get(urls::DESCRIPTION)?.to_string();
         let class_type = match resource.get(urls::CLASSTYPE_PROP) {
@@ -40,6 +307,11 @@ impl Property {
             Ok(classtype) => Some(classtype.to_subjects(None)?),
             Err(_) => None,
         };
+        let constraints = ValueConstraints::from_resource(&resource)?;
+        let on_delete = match resource.get(urls::ON_DELETE) {
+            Ok(policy) => policy.to_string().parse()?,
+            Err(_) => OnDelete::Ignore,
+        };
 
         Ok(Property {
             class_type,
@@ -47,10 +319,272 @@ impl Property {
             shortname,
             description,
             allows_only,
+            constraints,
+            on_delete,
             subject: resource.get_subject().into(),
         })
     }
 
+    /// Checks whether the Value is allowed by [Property::allows_only] and the [ValueConstraints].
+    /// Does not check the datatype.
+    /// The error names the violated constraint.
+    pub fn check_value(&self, value: &Value) -> AtomicResult<()> {
+        if let Some(allowed) = &self.allows_only {
+            let items = match value {
+                Value::ResourceArray(items) => items.iter().map(|i| i.to_string()).collect(),
+                Value::Array(items) => items.iter().map(|i| i.to_string()).collect(),
+                other => vec![other.to_string()],
+            };
+            for item in items {
+                if !allowed.contains(&item) {
+                    return Err(self.violation(
+                        urls::ALLOWS_ONLY,
+                        value,
+                        format!("allowed values are {:?}", allowed),
+                    ));
+                }
+            }
+        }
+
+        let c = &self.constraints;
+        let number = match value {
+            Value::Integer(i) | Value::Timestamp(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Decimal(d) => d.parse().ok(),
+            _ => None,
+        };
+        // For arrays, `min` and `max` count the items.
+        let size = match value {
+            Value::ResourceArray(items) => Some(items.len() as f64),
+            Value::Array(items) => Some(items.len() as f64),
+            _ => number,
+        };
+        if let Some(size) = size {
+            if let Some(min) = c.min {
+                if size < min as f64 {
+                    return Err(self.violation(urls::MIN, value, format!("minimum is {}", min)));
+                }
+            }
+            if let Some(max) = c.max {
+                if size > max as f64 {
+                    return Err(self.violation(urls::MAX, value, format!("maximum is {}", max)));
+                }
+            }
+        }
+        if let Some(number) = number {
+            if let Some(min) = c.min_float {
+                if number < min {
+                    return Err(self.violation(
+                        urls::MIN_FLOAT,
+                        value,
+                        format!("minimum is {}", min),
+                    ));
+                }
+            }
+            if let Some(max) = c.max_float {
+                if number > max {
+                    return Err(self.violation(
+                        urls::MAX_FLOAT,
+                        value,
+                        format!("maximum is {}", max),
+                    ));
+                }
+            }
+        }
+
+        let text = match value {
+            Value::String(s) | Value::Markdown(s) | Value::Slug(s) | Value::Uri(s) => Some(s),
+            _ => None,
+        };
+        if let Some(text) = text {
+            let length = text.chars().count() as i64;
+            if let Some(min) = c.min_length {
+                if length < min {
+                    return Err(self.violation(
+                        urls::MIN_LENGTH,
+                        value,
+                        format!("minimum length is {} characters, got {}", min, length),
+                    ));
+                }
+            }
+            if let Some(max) = c.max_length {
+                if length > max {
+                    return Err(self.violation(
+                        urls::MAX_LENGTH,
+                        value,
+                        format!("maximum length is {} characters, got {}", max, length),
+                    ));
+                }
+            }
+            if let Some(pattern) = &c.pattern {
+                let re = regex::Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+                    format!(
+                        "Property '{}' has an invalid pattern '{}'. {}",
+                        self.subject, pattern, e
+                    )
+                })?;
+                if !re.is_match(text) {
+                    return Err(self.violation(
+                        urls::PATTERN,
+                        value,
+                        format!("must match the pattern '{}'", pattern),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks whether the Resources that the Value links to are instances of [Property::class_type].
+    /// See [LinkPolicy] for how missing and external Resources are handled.
+    pub fn check_links(
+        &self,
+        value: &Value,
+        policy: LinkPolicy,
+        store: &impl Storelike,
+    ) -> AtomicResult<()> {
+        let Some(class_type) = &self.class_type else {
+            return Ok(());
+        };
+        let items = match value {
+            Value::ResourceArray(items) => items.clone(),
+            Value::AtomicUrl(subject) => vec![SubResource::Subject(subject.clone())],
+            Value::NestedResource(nested) => vec![nested.clone()],
+            Value::Resource(resource) => vec![SubResource::Resource(resource.clone())],
+            _ => return Ok(()),
+        };
+        for item in items {
+            let is_a = match &item {
+                SubResource::Nested(propvals) => propvals.get(urls::IS_A).cloned(),
+                SubResource::Resource(resource) => resource.get(urls::IS_A).ok().cloned(),
+                SubResource::Subject(subject) => {
+                    let is_local = store
+                        .get_self_url()
+                        .map(|url| subject.starts_with(&url))
+                        .unwrap_or(false);
+                    if !is_local && policy == LinkPolicy::Lenient {
+                        continue;
+                    }
+                    match store.get_resource(subject) {
+                        Ok(resource) => resource.get(urls::IS_A).ok().cloned(),
+                        Err(_) if policy == LinkPolicy::Lenient => continue,
+                        Err(e) => {
+                            return Err(self.violation(
+                                urls::CLASSTYPE_PROP,
+                                value,
+                                format!("linked resource {} can not be found. {}", subject, e),
+                            ))
+                        }
+                    }
+                }
+            };
+            let classes = match is_a {
+                Some(is_a) => is_a.to_subjects(None)?,
+                None => Vec::new(),
+            };
+            let is_instance = classes.iter().any(|class| {
+                class == class_type
+                    || store
+                        .get_class(class)
+                        .and_then(|c| c.is_subclass_of(class_type, store))
+                        .unwrap_or(false)
+            });
+            if !is_instance {
+                return Err(self.violation(
+                    urls::CLASSTYPE_PROP,
+                    value,
+                    format!("{} is not an instance of {}", item, class_type),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the [UniqueConstraint] of this Property: no other Resource in the same scope may share (an item of) the value of `resource`.
+    /// Uses the Property-Value-Subject index to find candidates.
+    pub fn check_unique(&self, resource: &Resource, store: &impl Storelike) -> AtomicResult<()> {
+        let Some(unique) = &self.constraints.unique else {
+            return Ok(());
+        };
+        let Ok(value) = resource.get(&self.subject) else {
+            return Ok(());
+        };
+        if !unique.applies_to(resource, store) {
+            return Ok(());
+        }
+        let scope = unique.scope.key(resource, store);
+        for item in value.to_reference_index_strings().unwrap_or_default() {
+            let mut query = crate::storelike::Query::new_prop_val(&self.subject, &item);
+            query.include_nested = false;
+            for subject in store.query(&query)?.subjects {
+                if &subject == resource.get_subject() {
+                    continue;
+                }
+                let Ok(other) = store.get_resource(&subject) else {
+                    continue;
+                };
+                if unique.applies_to(&other, store) && unique.scope.key(&other, store) == scope {
+                    return Err(self.violation(
+                        urls::UNIQUE,
+                        value,
+                        format!("{} already uses '{}'", subject, item),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [Property::check_unique], but compares `resource` with `others`, which are not in the store yet.
+    /// Used for Commits that are applied together.
+    pub fn check_unique_among(
+        &self,
+        resource: &Resource,
+        others: &[&Resource],
+        store: &impl Storelike,
+    ) -> AtomicResult<()> {
+        let Some(unique) = &self.constraints.unique else {
+            return Ok(());
+        };
+        let Ok(value) = resource.get(&self.subject) else {
+            return Ok(());
+        };
+        if !unique.applies_to(resource, store) {
+            return Ok(());
+        }
+        let items = value.to_reference_index_strings().unwrap_or_default();
+        let scope = unique.scope.key(resource, store);
+        for other in others {
+            let Ok(other_value) = other.get(&self.subject) else {
+                continue;
+            };
+            let Some(item) = other_value
+                .to_reference_index_strings()
+                .unwrap_or_default()
+                .into_iter()
+                .find(|item| items.contains(item))
+            else {
+                continue;
+            };
+            if unique.applies_to(other, store) && unique.scope.key(other, store) == scope {
+                return Err(self.violation(
+                    urls::UNIQUE,
+                    value,
+                    format!("{} also uses '{}'", other.get_subject(), item),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn violation(&self, constraint: &str, value: &Value, explanation: String) -> AtomicError {
+        format!(
+            "Value '{}' for property '{}' violates constraint {}: {}",
+            value, self.shortname, constraint, explanation
+        )
+        .into()
+    }
+
     /// Convert to resource.
     pub fn to_resource(&self) -> Resource {
         let mut resource = Resource::new(self.subject.clone());
@@ -65,14 +599,30 @@ impl Property {
         );
         resource.set_unsafe(
             urls::DATATYPE_PROP.into(),
-            Value::AtomicUrl(self.data_type.to_string()),
+            Value::AtomicUrl(self.data_type.url()),
         );
+        if let DataType::Array(item) = &self.data_type {
+            resource.set_unsafe(
+                urls::ITEM_DATATYPE.into(),
+                Value::AtomicUrl(item.to_string()),
+            );
+        }
         if let Some(classtype) = &self.class_type {
             resource.set_unsafe(
                 urls::CLASSTYPE_PROP.into(),
                 Value::AtomicUrl(classtype.clone()),
             );
         }
+        if let Some(allows_only) = &self.allows_only {
+            resource.set_unsafe(urls::ALLOWS_ONLY.into(), Value::from(allows_only.clone()));
+        }
+        self.constraints.set_on_resource(&mut resource);
+        if self.on_delete != OnDelete::Ignore {
+            resource.set_unsafe(
+                urls::ON_DELETE.into(),
+                Value::Slug(self.on_delete.to_string()),
+            );
+        }
 
         resource
     }
@@ -86,6 +636,13 @@ pub struct Class {
     pub description: String,
     /// URL
     pub subject: String,
+    /// Classes that this Class inherits its requires and recommends from.
+    /// Instances of this Class are also instances of these Classes.
+    /// https://atomicdata.dev/properties/subClassOf
+    pub sub_class_of: Vec<String>,
+    /// Properties that the server computes for instances of this Class, see [crate::computed].
+    /// https://atomicdata.dev/properties/computedProperties
+    pub computed_properties: Vec<String>,
 }
 
 impl Class {
@@ -105,6 +662,16 @@ impl Class {
             }
         }
 
+        let sub_class_of = match resource.get(urls::SUB_CLASS_OF) {
+            Ok(val) => val.to_subjects(None)?,
+            Err(_) => Vec::new(),
+        };
+
+        let computed_properties = match resource.get(urls::COMPUTED_PROPERTIES) {
+            Ok(val) => val.to_subjects(None)?,
+            Err(_) => Vec::new(),
+        };
+
         let shortname = resource.get(urls::SHORTNAME)?.to_string();
         let description = resource.get(urls::DESCRIPTION)?.to_string();
 
@@ -114,9 +681,60 @@ impl Class {
             shortname,
             subject: resource.get_subject().into(),
             description,
+            sub_class_of,
+            computed_properties,
         })
     }
 
+    /// Fetches all Classes that this Class inherits from, the direct ones first.
+    /// Returns an error if the Class is (indirectly) a subclass of itself.
+    pub fn get_superclasses(&self, store: &impl Storelike) -> AtomicResult<Vec<Class>> {
+        let mut superclasses: Vec<Class> = Vec::new();
+        let mut queue: std::collections::VecDeque<String> =
+            self.sub_class_of.iter().cloned().collect();
+        while let Some(subject) = queue.pop_front() {
+            if subject == self.subject {
+                return Err(format!("Class {} is a subclass of itself", self.subject).into());
+            }
+            if superclasses.iter().any(|c| c.subject == subject) {
+                continue;
+            }
+            let superclass = store.get_class(&subject)?;
+            queue.extend(superclass.sub_class_of.iter().cloned());
+            superclasses.push(superclass);
+        }
+        Ok(superclasses)
+    }
+
+    /// Returns a copy of this Class that also contains the requires and recommends of all its superclasses.
+    pub fn with_inherited(&self, store: &impl Storelike) -> AtomicResult<Class> {
+        let mut class = self.clone();
+        for superclass in self.get_superclasses(store)? {
+            for prop in superclass.requires {
+                if !class.requires.contains(&prop) {
+                    class.requires.push(prop);
+                }
+            }
+            for prop in superclass.recommends {
+                if !class.recommends.contains(&prop) && !class.requires.contains(&prop) {
+                    class.recommends.push(prop);
+                }
+            }
+        }
+        Ok(class)
+    }
+
+    /// Whether this Class is `class`, or inherits from it.
+    pub fn is_subclass_of(&self, class: &str, store: &impl Storelike) -> AtomicResult<bool> {
+        if self.subject == class {
+            return Ok(true);
+        }
+        Ok(self
+            .get_superclasses(store)?
+            .iter()
+            .any(|superclass| superclass.subject == class))
+    }
+
     /// Converts Class to a Resource
     pub fn to_resource(&self) -> Resource {
         let mut resource = Resource::new(self.subject.clone());
@@ -138,6 +756,211 @@ impl Class {
                 Value::from(self.recommends.clone()),
             );
         }
+        if !self.sub_class_of.is_empty() {
+            resource.set_unsafe(
+                urls::SUB_CLASS_OF.into(),
+                Value::from(self.sub_class_of.clone()),
+            );
+        }
+        if !self.computed_properties.is_empty() {
+            resource.set_unsafe(
+                urls::COMPUTED_PROPERTIES.into(),
+                Value::from(self.computed_properties.clone()),
+            );
+        }
         resource
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Value;
+
+    fn rating_property(store: &impl Storelike) -> Property {
+        let mut property = Property {
+            class_type: None,
+            data_type: DataType::Integer,
+            shortname: "rating".into(),
+            subject: "https://localhost/properties/rating".into(),
+            description: "Number of stars".into(),
+            allows_only: None,
+            constraints: ValueConstraints {
+                min: Some(1),
+                max: Some(5),
+                ..Default::default()
+            },
+            on_delete: Default::default(),
+        };
+        store.add_resource(&property.to_resource()).unwrap();
+        property = store.get_property(&property.subject).unwrap();
+        property
+    }
+
+    #[test]
+    fn checks_constraints() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let rating = rating_property(&store);
+        assert_eq!(rating.constraints.max, Some(5));
+        rating.check_value(&Value::Integer(3)).unwrap();
+        let err = rating.check_value(&Value::Integer(6)).unwrap_err();
+        assert!(err.message.contains(urls::MAX), "{}", err);
+        Value::new_for_property("0", &rating).unwrap_err();
+
+        let code = Property {
+            data_type: DataType::String,
+            shortname: "code".into(),
+            subject: "https://localhost/properties/code".into(),
+            constraints: ValueConstraints {
+                max_length: Some(3),
+                pattern: Some("[A-Z]+".into()),
+                ..Default::default()
+            },
+            ..rating.clone()
+        };
+        code.check_value(&Value::String("ABC".into())).unwrap();
+        let err = code.check_value(&Value::String("ABCD".into())).unwrap_err();
+        assert!(err.message.contains(urls::MAX_LENGTH), "{}", err);
+        let err = code.check_value(&Value::String("AB1".into())).unwrap_err();
+        assert!(err.message.contains(urls::PATTERN), "{}", err);
+
+        // Commits are checked too
+        let mut resource = crate::Resource::new("https://localhost/review".into());
+        resource
+            .set(rating.subject.clone(), Value::Integer(4), &store)
+            .unwrap();
+        resource
+            .set(rating.subject.clone(), Value::Integer(10), &store)
+            .unwrap_err();
+        let mut builder = crate::commit::CommitBuilder::new(resource.get_subject().into());
+        builder.set(rating.subject.clone(), Value::Integer(10));
+        let agent = store.create_agent(None).unwrap();
+        let commit = builder.sign(&agent, &store, &resource).unwrap();
+        let err = commit
+            .validate_and_build_response(
+                &crate::commit::CommitOpts::no_validations_no_index(),
+                &store,
+            )
+            .unwrap_err();
+        assert!(err.message.contains(urls::MAX), "{}", err);
+    }
+
+    #[test]
+    fn checks_classtype_links() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let drives = store.get_property(urls::DRIVES).unwrap();
+        let mut drive = crate::Resource::new_instance(urls::DRIVE, &store).unwrap();
+        drive
+            .set(urls::NAME.into(), Value::String("drive".into()), &store)
+            .unwrap();
+        store.add_resource(&drive).unwrap();
+        let mut other = crate::Resource::new_generate_subject(&store);
+        other
+            .set(
+                urls::PARENT.into(),
+                Value::AtomicUrl(drive.get_subject().into()),
+                &store,
+            )
+            .unwrap();
+        store.add_resource(&other).unwrap();
+        let missing = format!("{}/missing", store.get_server_url());
+        let external = "https://example.invalid/some-drive".to_string();
+
+        let links = |subjects: &[&String]| {
+            Value::from(subjects.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+        };
+        let lenient = LinkPolicy::for_resource(&other, &store);
+        assert_eq!(lenient, LinkPolicy::Lenient);
+        drives
+            .check_links(
+                &links(&[drive.get_subject(), &missing, &external]),
+                lenient,
+                &store,
+            )
+            .unwrap();
+        let err = drives
+            .check_links(&links(&[other.get_subject()]), lenient, &store)
+            .unwrap_err();
+        assert!(err.message.contains(urls::CLASSTYPE_PROP), "{}", err);
+
+        drive
+            .set(urls::STRICT_SCHEMA.into(), Value::Boolean(true), &store)
+            .unwrap();
+        store.add_resource(&drive).unwrap();
+        let strict = LinkPolicy::for_resource(&other, &store);
+        assert_eq!(strict, LinkPolicy::Strict);
+        drives
+            .check_links(&links(&[drive.get_subject()]), strict, &store)
+            .unwrap();
+        drives
+            .check_links(&links(&[&missing]), strict, &store)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn inherits_from_superclass() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("tester")).unwrap();
+        store.set_default_agent(agent);
+        let person = Class {
+            requires: vec![urls::NAME.into()],
+            recommends: vec![],
+            shortname: "person".into(),
+            description: "Someone".into(),
+            subject: format!("{}/person", store.get_server_url()),
+            sub_class_of: vec![],
+            computed_properties: vec![],
+        };
+        let employee = Class {
+            requires: vec![urls::DESCRIPTION.into()],
+            recommends: vec![],
+            shortname: "employee".into(),
+            description: "Someone who works here".into(),
+            subject: format!("{}/employee", store.get_server_url()),
+            sub_class_of: vec![person.subject.clone()],
+            computed_properties: vec![],
+        };
+        store.add_resource(&person.to_resource()).unwrap();
+        store.add_resource(&employee.to_resource()).unwrap();
+
+        let inherited = employee.with_inherited(&store).unwrap();
+        assert_eq!(
+            inherited.requires,
+            vec![urls::DESCRIPTION.to_string(), urls::NAME.to_string()]
+        );
+
+        let mut instance = Resource::new_instance(&employee.subject, &store).unwrap();
+        instance
+            .set(
+                urls::DESCRIPTION.into(),
+                Value::Markdown("Hi".into()),
+                &store,
+            )
+            .unwrap();
+        instance.check_required_props(&store).unwrap_err();
+        instance
+            .set(urls::NAME.into(), Value::String("Alice".into()), &store)
+            .unwrap();
+        instance.check_required_props(&store).unwrap();
+        store.add_resource(&instance).unwrap();
+        let persons = store
+            .query(&crate::storelike::Query::new_class(&person.subject))
+            .unwrap();
+        assert_eq!(persons.subjects, vec![instance.get_subject().to_string()]);
+
+        // Only the new value is sent in the Commit
+        let mut cyclic = Resource::new(person.subject.clone());
+        cyclic
+            .set(
+                urls::SUB_CLASS_OF.into(),
+                Value::from(vec![employee.subject.clone()]),
+                &store,
+            )
+            .unwrap();
+        let err = cyclic.save_locally(&store).unwrap_err();
+        assert!(err.message.contains("subclass of itself"), "{}", err);
+    }
+}
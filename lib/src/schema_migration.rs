@@ -0,0 +1,405 @@
+/*!
+Schema migrations change the [DataType] of a [Property], and convert the existing values of that Property.
+Without a migration, values that don't match the new Datatype become invalid.
+
+A migration first creates a report of what would change.
+The Agent running the migration needs write rights for the Property and for every Resource with a value that changes, also for dry runs, because the report contains these values.
+If it is not a dry run and all values can be converted, it updates the Property and the Resources using signed Commits that are applied in a single transaction, and rebuilds the value index of the Property.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    agents::ForAgent,
+    commit::CommitOpts,
+    datatype::{match_datatype, DataType},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    schema::Property,
+    storelike::Query,
+    urls, Commit, Db, Resource, Storelike, Value,
+};
+
+/// How the existing values of a Property are converted to the new [DataType].
+#[derive(Clone, Debug)]
+pub enum ConversionRule {
+    /// Parses the current value as the new Datatype, e.g. the string `"12"` becomes the integer `12`.
+    Cast,
+    /// Replaces values using the table (current value => new value) before parsing them as the new Datatype.
+    /// Values that are not in the table are cast.
+    Map(HashMap<String, String>),
+    /// Casts values where possible, and removes the values that can't be cast.
+    Drop,
+}
+
+impl ConversionRule {
+    /// Creates a rule from its name: `cast`, `map` or `drop`.
+    /// The `map` rule requires a table.
+    pub fn from_name(name: &str, table: Option<HashMap<String, String>>) -> AtomicResult<Self> {
+        match (name, table) {
+            ("cast", _) => Ok(ConversionRule::Cast),
+            ("map", Some(table)) => Ok(ConversionRule::Map(table)),
+            ("map", None) => Err("The `map` rule requires a table of values".into()),
+            ("drop", _) => Ok(ConversionRule::Drop),
+            (other, _) => Err(format!(
+                "Unknown conversion rule '{}'. Use `cast`, `map` or `drop`",
+                other
+            )
+            .into()),
+        }
+    }
+}
+
+/// A change of the Datatype of a single Property.
+#[derive(Clone, Debug)]
+pub struct Migration {
+    /// Subject of the Property
+    pub property: String,
+    /// The new Datatype
+    pub data_type: DataType,
+    pub rule: ConversionRule,
+    /// Only creates the report, does not change anything.
+    pub dry_run: bool,
+    /// The Agent running the migration, whose write rights are checked.
+    pub for_agent: ForAgent,
+}
+
+/// Describes what a [Migration] changes (or would change, for dry runs).
+#[derive(Clone, Debug)]
+pub struct MigrationReport {
+    pub property: String,
+    pub from: DataType,
+    pub to: DataType,
+    /// Subject, old value, new value
+    pub converted: Vec<(String, Value, Value)>,
+    /// Subject and the removed value
+    pub dropped: Vec<(String, Value)>,
+    /// Subject, value and why it can't be converted
+    pub failed: Vec<(String, Value, String)>,
+    /// Amount of values that are already valid for the new Datatype
+    pub unchanged: usize,
+    /// Whether the Property and the Resources have been updated
+    pub applied: bool,
+}
+
+impl std::fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Migrating {} from {} to {}: {} converted, {} dropped, {} failed, {} unchanged.",
+            self.property,
+            self.from,
+            self.to,
+            self.converted.len(),
+            self.dropped.len(),
+            self.failed.len(),
+            self.unchanged
+        )?;
+        for (subject, old, new) in &self.converted {
+            writeln!(f, "Convert {}: '{}' => '{}'", subject, old, new)?;
+        }
+        for (subject, old) in &self.dropped {
+            writeln!(f, "Drop {}: '{}'", subject, old)?;
+        }
+        for (subject, old, error) in &self.failed {
+            writeln!(f, "Fail {}: '{}'. {}", subject, old, error)?;
+        }
+        if self.applied {
+            writeln!(f, "Migration applied.")
+        } else if self.failed.is_empty() {
+            writeln!(f, "Dry run, nothing changed.")
+        } else {
+            writeln!(
+                f,
+                "Nothing changed, because some values can't be converted. Use the `map` or `drop` rule for these."
+            )
+        }
+    }
+}
+
+/// Creates a [DataType] from its URL. Arrays also need the URL of their item Datatype.
+pub fn datatype_from_urls(datatype: &str, item_datatype: Option<&str>) -> AtomicResult<DataType> {
+    let data_type = match (match_datatype(datatype), item_datatype) {
+        (DataType::Array(_), Some(item)) => DataType::Array(Box::new(match_datatype(item))),
+        (data_type, _) => data_type,
+    };
+    match &data_type {
+        DataType::Unsupported(url) => Err(format!("Unsupported datatype: {}", url).into()),
+        DataType::Array(item) if !item.is_valid_array_item() => {
+            Err(format!("Arrays can't contain items of datatype {}", item).into())
+        }
+        _ => Ok(data_type),
+    }
+}
+
+/// Changes the Datatype of a Property and converts its existing values.
+/// Nothing changes if this is a dry run, or if some values can't be converted.
+/// Fails if the Agent of the migration can't write the Property or one of the Resources with values that change.
+/// Commits are signed by the default Agent of the store.
+pub fn migrate_property(store: &Db, migration: &Migration) -> AtomicResult<MigrationReport> {
+    let property = store.get_property(&migration.property)?;
+    let mut property_resource = store.get_resource(&property.subject)?;
+    check_write(store, &property_resource, &migration.for_agent)?;
+    let target = Property {
+        data_type: migration.data_type.clone(),
+        ..property.clone()
+    };
+    let mut report = MigrationReport {
+        property: property.subject.clone(),
+        from: property.data_type.clone(),
+        to: target.data_type.clone(),
+        converted: Vec::new(),
+        dropped: Vec::new(),
+        failed: Vec::new(),
+        unchanged: 0,
+        applied: false,
+    };
+
+    let mut query = Query::new();
+    query.property = Some(property.subject.clone());
+    query.include_external = true;
+    query.include_nested = false;
+    // Array values have one index entry per item
+    let subjects: HashSet<String> = store.query(&query)?.subjects.into_iter().collect();
+    let mut subjects: Vec<String> = subjects.into_iter().collect();
+    subjects.sort();
+
+    // Resources with values that change, and their new value (`None` if it is dropped)
+    let mut changed: Vec<(Resource, Option<Value>)> = Vec::new();
+    for subject in subjects {
+        let resource = store.get_resource(&subject)?;
+        let Ok(value) = resource.get(&property.subject) else {
+            continue;
+        };
+        let value = value.clone();
+        let new = match convert_value(&value, &target, &migration.rule) {
+            Ok(new)
+                if new.datatype() == value.datatype() && new.to_string() == value.to_string() =>
+            {
+                report.unchanged += 1;
+                continue;
+            }
+            Ok(new) => {
+                report.converted.push((subject, value, new.clone()));
+                Some(new)
+            }
+            Err(_) if matches!(migration.rule, ConversionRule::Drop) => {
+                report.dropped.push((subject, value));
+                None
+            }
+            Err(e) => {
+                report.failed.push((subject, value, e.to_string()));
+                None
+            }
+        };
+        check_write(store, &resource, &migration.for_agent)?;
+        changed.push((resource, new));
+    }
+
+    if migration.dry_run || !report.failed.is_empty() {
+        return Ok(report);
+    }
+
+    let agent = store.get_default_agent()?;
+    property_resource.set(
+        urls::DATATYPE_PROP.into(),
+        Value::AtomicUrl(target.data_type.url()),
+        store,
+    )?;
+    match &target.data_type {
+        DataType::Array(item) => {
+            property_resource.set(
+                urls::ITEM_DATATYPE.into(),
+                Value::AtomicUrl(item.to_string()),
+                store,
+            )?;
+        }
+        _ if property_resource.get(urls::ITEM_DATATYPE).is_ok() => {
+            property_resource.remove_propval(urls::ITEM_DATATYPE)
+        }
+        _ => {}
+    }
+    let mut commits: Vec<(Commit, CommitOpts)> = vec![(
+        property_resource
+            .get_commit_builder()
+            .clone()
+            .sign(&agent, store, &property_resource)?,
+        migration_opts(&agent.subject, true),
+    )];
+
+    // The new values don't match the current Datatype of the Property, so the schema is validated here
+    for (mut resource, new) in changed {
+        match new {
+            Some(new) => {
+                resource.set_unsafe(property.subject.clone(), new);
+            }
+            None => resource.remove_propval(&property.subject),
+        }
+        resource
+            .check_required_props(store)
+            .map_err(|e| format!("Failed to migrate {}: {}", resource.get_subject(), e))?;
+        let commit = resource
+            .get_commit_builder()
+            .clone()
+            .sign(&agent, store, &resource)?;
+        commits.push((commit, migration_opts(&agent.subject, false)));
+    }
+
+    store.apply_commits_atomically(commits)?;
+    store.reindex_property(&property.subject)?;
+    report.applied = true;
+    Ok(report)
+}
+
+/// The rights have been checked for the Agent of the migration, not for the signer.
+fn migration_opts(signer: &str, validate_schema: bool) -> CommitOpts {
+    CommitOpts {
+        validate_schema,
+        validate_links: validate_schema,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        update_index: true,
+        validate_for_agent: Some(signer.into()),
+    }
+}
+
+fn convert_value(value: &Value, target: &Property, rule: &ConversionRule) -> AtomicResult<Value> {
+    let current = value.to_string();
+    let input = match rule {
+        ConversionRule::Map(table) => table.get(&current).unwrap_or(&current),
+        ConversionRule::Cast | ConversionRule::Drop => &current,
+    };
+    Value::new_for_property(input, target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Resource;
+
+    fn setup(id: &str) -> (Db, String, Vec<String>) {
+        let store = Db::init_temp(id).unwrap();
+        let property = Property {
+            class_type: None,
+            data_type: DataType::String,
+            shortname: "size".into(),
+            description: "size".into(),
+            subject: format!("{}/size", store.get_server_url()),
+            allows_only: None,
+            constraints: Default::default(),
+            on_delete: Default::default(),
+        };
+        store.add_resource(&property.to_resource()).unwrap();
+        let mut subjects = Vec::new();
+        for size in ["12", "large", "3"] {
+            let mut resource = Resource::new_generate_subject(&store);
+            resource
+                .set(property.subject.clone(), Value::String(size.into()), &store)
+                .unwrap();
+            resource.save_locally(&store).unwrap();
+            subjects.push(resource.get_subject().clone());
+        }
+        (store, property.subject, subjects)
+    }
+
+    #[test]
+    fn cast_fails_without_changes() {
+        let (store, property, _subjects) = setup("migration_cast");
+        let migration = Migration {
+            property: property.clone(),
+            data_type: DataType::Integer,
+            rule: ConversionRule::Cast,
+            dry_run: false,
+            for_agent: ForAgent::Sudo,
+        };
+        let report = migrate_property(&store, &migration).unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.converted.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(
+            store.get_property(&property).unwrap().data_type,
+            DataType::String
+        );
+    }
+
+    #[test]
+    fn map_and_drop() {
+        let (store, property, subjects) = setup("migration_map_drop");
+        let table = HashMap::from([("large".to_string(), "40".to_string())]);
+        let mut migration = Migration {
+            property: property.clone(),
+            data_type: DataType::Decimal,
+            rule: ConversionRule::Map(table),
+            dry_run: true,
+            for_agent: ForAgent::Sudo,
+        };
+        let report = migrate_property(&store, &migration).unwrap();
+        assert_eq!(report.converted.len(), 3);
+        assert!(!report.applied);
+
+        migration.rule = ConversionRule::Drop;
+        migration.dry_run = false;
+        let report = migrate_property(&store, &migration).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.dropped.len(), 1);
+        assert_eq!(
+            store.get_property(&property).unwrap().data_type,
+            DataType::Decimal
+        );
+        let migrated = store.get_resource(&subjects[0]).unwrap();
+        assert!(matches!(migrated.get(&property).unwrap(), Value::Decimal(d) if d == "12"));
+        let dropped = store.get_resource(&subjects[1]).unwrap();
+        assert!(dropped.get(&property).is_err());
+
+        // Decimals sort by their numeric value, strings don't
+        let mut query = Query::new();
+        query.property = Some(property);
+        query.sort_by = query.property.clone();
+        let result = store.query(&query).unwrap();
+        assert_eq!(
+            result.subjects,
+            vec![subjects[2].clone(), subjects[0].clone()]
+        );
+    }
+
+    #[test]
+    fn requires_write_rights() {
+        let (store, property, subjects) = setup("migration_rights");
+        let agent = store.create_agent(Some("stranger")).unwrap();
+        let mut migration = Migration {
+            property: property.clone(),
+            data_type: DataType::Integer,
+            rule: ConversionRule::Drop,
+            dry_run: true,
+            for_agent: agent.subject.clone().into(),
+        };
+        // The report would contain the values, so dry runs are checked as well
+        assert!(migrate_property(&store, &migration).is_err());
+
+        // Write rights for the Property and some of the Resources are not enough
+        let grant = |subject: &str| {
+            let mut resource = store.get_resource(subject).unwrap();
+            resource
+                .push(urls::WRITE, agent.subject.clone().into(), true)
+                .unwrap();
+            resource.save_locally(&store).unwrap();
+        };
+        grant(&property);
+        grant(&subjects[0]);
+        grant(&subjects[2]);
+        migration.dry_run = false;
+        assert!(migrate_property(&store, &migration).is_err());
+        assert_eq!(
+            store.get_property(&property).unwrap().data_type,
+            DataType::String
+        );
+        let unchanged = store.get_resource(&subjects[0]).unwrap();
+        assert_eq!(unchanged.get(&property).unwrap().to_string(), "12");
+
+        grant(&subjects[1]);
+        let report = migrate_property(&store, &migration).unwrap();
+        assert!(report.applied);
+    }
+}
@@ -24,7 +24,7 @@ pub fn resources_to_json_ad(resources: &[Resource]) -> AtomicResult<String> {
 /// Converts an Atomic Value to a Serde Value.
 // TODO: Accept JSON-LD / JSON as options
 // https://github.com/atomicdata-dev/atomic-server/issues/315
-fn val_to_serde(value: Value) -> AtomicResult<SerdeValue> {
+pub(crate) fn val_to_serde(value: Value) -> AtomicResult<SerdeValue> {
     let json_val: SerdeValue = match value {
         Value::AtomicUrl(val) => SerdeValue::String(val),
         Value::Date(val) => SerdeValue::String(val),
@@ -57,6 +57,21 @@ fn val_to_serde(value: Value) -> AtomicResult<SerdeValue> {
         Value::Timestamp(val) => SerdeValue::Number(val.into()),
         Value::Unsupported(val) => SerdeValue::String(val.value),
         Value::Boolean(val) => SerdeValue::Bool(val),
+        // Decimals are serialized as strings, so no precision is lost
+        Value::Decimal(val) => SerdeValue::String(val),
+        Value::Json(val) => {
+            serde_json::from_str(&val).map_err(|e| format!("Invalid JSON value {}. {}", val, e))?
+        }
+        Value::Uri(val) => SerdeValue::String(val),
+        Value::Duration(val) => SerdeValue::String(val),
+        Value::DateTime(val) => SerdeValue::String(val),
+        Value::GeoPoint(val) => SerdeValue::String(val.to_string()),
+        Value::Array(items) => SerdeValue::Array(
+            items
+                .into_iter()
+                .map(val_to_serde)
+                .collect::<AtomicResult<Vec<SerdeValue>>>()?,
+        ),
         // TODO: fix this for nested resources in json and json-ld serialization, because this will cause them to fall back to json-ad
         Value::NestedResource(res) => match res {
             crate::values::SubResource::Resource(r) => crate::serialize::propvals_to_json_ad_map(
@@ -138,8 +153,20 @@ pub fn propvals_to_json_ld(
                     );
                     obj.into()
                 }
+                DataType::Decimal => {
+                    typed_context(prop_url, "http://www.w3.org/2001/XMLSchema#decimal")
+                }
+                DataType::Duration => {
+                    typed_context(prop_url, "http://www.w3.org/2001/XMLSchema#duration")
+                }
+                DataType::DateTime => {
+                    typed_context(prop_url, "http://www.w3.org/2001/XMLSchema#dateTime")
+                }
+                DataType::Uri => typed_context(prop_url, "@id"),
+                // The value is embedded as-is, not interpreted as JSON-LD
+                DataType::Json => typed_context(prop_url, "@json"),
                 DataType::Markdown => prop_url.as_str().into(),
-                DataType::ResourceArray => {
+                DataType::ResourceArray | DataType::Array(_) => {
                     let mut obj = Map::new();
                     obj.insert("@id".into(), prop_url.as_str().into());
                     // Plain JSON-LD Arrays are not ordered. Here, they are converted into an RDF List.
@@ -167,6 +194,14 @@ pub fn propvals_to_json_ld(
     Ok(obj)
 }
 
+/// A JSON-LD context item that sets the `@type` of the values of a property.
+fn typed_context(prop_url: &str, value_type: &str) -> SerdeValue {
+    let mut obj = Map::new();
+    obj.insert("@id".into(), prop_url.into());
+    obj.insert("@type".into(), value_type.into());
+    obj.into()
+}
+
 pub fn serialize_json_array(items: &[String]) -> AtomicResult<String> {
     let string = serde_json::to_string(items)?;
     Ok(string)
@@ -187,9 +222,9 @@ pub fn atoms_to_ntriples(atoms: Vec<crate::Atom>, store: &impl Storelike) -> Ato
         };
         let datatype = store.get_property(&atom.property)?.data_type;
         let value = &atom.value.to_string();
-        let datatype_url = datatype.to_string();
+        let datatype_url = datatype.url();
         let object: Term = match &datatype {
-            DataType::AtomicUrl => NamedNode { iri: value }.into(),
+            DataType::AtomicUrl | DataType::Uri => NamedNode { iri: value }.into(),
             // Maybe these should be converted to RDF collections / lists?
             // DataType::ResourceArray => {}
             DataType::String => Literal::Simple { value }.into(),
@@ -226,9 +261,9 @@ pub fn atoms_to_turtle(atoms: Vec<crate::Atom>, store: &impl Storelike) -> Atomi
         };
         let datatype = store.get_property(&atom.property)?.data_type;
         let value = &atom.value.to_string();
-        let datatype_url = datatype.to_string();
+        let datatype_url = datatype.url();
         let object: Term = match &datatype {
-            DataType::AtomicUrl => NamedNode { iri: value }.into(),
+            DataType::AtomicUrl | DataType::Uri => NamedNode { iri: value }.into(),
             // Maybe these should be converted to RDF collections / lists?
             // DataType::ResourceArray => {}
             DataType::String => Literal::Simple { value }.into(),
@@ -283,7 +318,8 @@ mod test {
   "https://atomicdata.dev/properties/recommends": [
     "https://atomicdata.dev/properties/name",
     "https://atomicdata.dev/properties/description",
-    "https://atomicdata.dev/properties/drives"
+    "https://atomicdata.dev/properties/drives",
+    "https://atomicdata.dev/properties/agentKeys"
   ],
     "https://atomicdata.dev/properties/requires": [
     "https://atomicdata.dev/properties/publicKey"
@@ -328,7 +364,8 @@ mod test {
             "recommends": [
               "https://atomicdata.dev/properties/name",
               "https://atomicdata.dev/properties/description",
-              "https://atomicdata.dev/properties/drives"
+              "https://atomicdata.dev/properties/drives",
+              "https://atomicdata.dev/properties/agentKeys"
             ],
             "requires": [
               "https://atomicdata.dev/properties/publicKey"
@@ -381,7 +418,8 @@ mod test {
             "recommends": [
               "https://atomicdata.dev/properties/name",
               "https://atomicdata.dev/properties/description",
-              "https://atomicdata.dev/properties/drives"
+              "https://atomicdata.dev/properties/drives",
+              "https://atomicdata.dev/properties/agentKeys"
             ],
             "requires": [
               "https://atomicdata.dev/properties/publicKey"
@@ -176,6 +176,12 @@ pub trait Storelike: Sized {
         Class::from_resource(resource)
     }
 
+    /// Finds all Classes that (indirectly) inherit from this Class, using [urls::SUB_CLASS_OF].
+    /// Returns an empty vector if there are none.
+    fn get_subclasses(&self, class: &str) -> AtomicResult<Vec<String>> {
+        find_subclasses(self, class)
+    }
+
     /// Finds all classes (isA) for any subject.
     /// Returns an empty vector if there are none.
     fn get_classes_for_subject(&self, subject: &str) -> AtomicResult<Vec<Class>> {
@@ -209,6 +215,16 @@ pub trait Storelike: Sized {
         Ok(resource)
     }
 
+    /// Records a security relevant event in the audit log, see [crate::audit].
+    /// Does nothing by default. Implement this if your store should keep an audit log.
+    fn audit(&self, _event: crate::audit::AuditEvent) {}
+
+    /// Returns the stored version of a Resource right after this Commit was applied, if there is one.
+    /// Used as a starting point by [crate::plugins::versioning::construct_version], so not all Commits have to be replayed.
+    fn get_snapshot(&self, _commit_url: &str) -> Option<crate::resources::PropVals> {
+        None
+    }
+
     /// This function is called whenever a Commit is applied.
     /// Implement this if you want to have custom handlers for Commits.
     fn handle_commit(&self, _commit_response: &CommitResponse) {}
@@ -357,12 +373,12 @@ pub trait Storelike: Sized {
 
     /// Performs a light validation, without fetching external data
     fn validate(&self) -> crate::validate::ValidationReport {
-        crate::validate::validate_store(self, false)
+        crate::validate::validate_store_sequential(self, false)
     }
 }
 
 /// Use this to construct a list of Resources
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Query {
     /// Filter by Property
     pub property: Option<String>,
@@ -413,7 +429,8 @@ impl Query {
         q
     }
 
-    /// Search for instances of some Class
+    /// Search for instances of some Class.
+    /// Instances of its subclasses are included, too.
     pub fn new_class(class: &str) -> Self {
         let mut q = Self::new();
         q.property = Some(urls::IS_A.into());
@@ -428,6 +445,106 @@ impl Default for Query {
     }
 }
 
+/// Queries the Classes that (indirectly) inherit from `class`. See [Storelike::get_subclasses].
+pub(crate) fn find_subclasses(store: &impl Storelike, class: &str) -> AtomicResult<Vec<String>> {
+    let mut subclasses: Vec<String> = Vec::new();
+    let mut queue = vec![class.to_string()];
+    while let Some(current) = queue.pop() {
+        let mut q = Query::new_prop_val(urls::SUB_CLASS_OF, &current);
+        q.include_external = true;
+        q.include_nested = false;
+        for subject in store.query(&q)?.subjects {
+            if subject != class && !subclasses.contains(&subject) {
+                queue.push(subject.clone());
+                subclasses.push(subject);
+            }
+        }
+    }
+    Ok(subclasses)
+}
+
+/// Runs `run` for the Query, and if it searches for instances of a Class, for each of the subclasses of that Class.
+/// Merges the results in the order of the query index (see [crate::values::sort_key]), and applies the offset and limit of the Query to the merged results.
+pub(crate) fn query_including_subclasses(
+    store: &impl Storelike,
+    q: &Query,
+    run: impl Fn(&Query) -> AtomicResult<QueryResult>,
+) -> AtomicResult<QueryResult> {
+    let class = match (&q.property, &q.value) {
+        (Some(prop), Some(val)) if prop == urls::IS_A => val.to_string(),
+        _ => return run(q),
+    };
+    let subclasses = store.get_subclasses(&class)?;
+    if subclasses.is_empty() {
+        return run(q);
+    }
+    let classes: Vec<String> = std::iter::once(class).chain(subclasses).collect();
+
+    // Every page of the merged results is within the first `offset + limit` results of each Class
+    let mut hits: Vec<(String, String, Option<Resource>)> = Vec::new();
+    for class in &classes {
+        let mut class_query = q.clone();
+        class_query.value = Some(Value::AtomicUrl(class.clone()));
+        class_query.offset = 0;
+        class_query.limit = q.limit.map(|limit| limit + q.offset);
+        let result = run(&class_query)?;
+        let mut resources = result.resources.into_iter();
+        for subject in result.subjects {
+            let resource = resources.next();
+            if hits.iter().any(|(_, s, _)| s == &subject) {
+                continue;
+            }
+            let sort_value = match &q.sort_by {
+                Some(sort_by) => {
+                    let value = match &resource {
+                        Some(r) => r.get(sort_by).ok().map(|v| v.to_sortable_string()),
+                        None => store
+                            .get_resource(&subject)
+                            .ok()
+                            .and_then(|r| r.get(sort_by).ok().map(|v| v.to_sortable_string())),
+                    };
+                    crate::values::sort_key(&value.unwrap_or_default())
+                }
+                None => String::new(),
+            };
+            hits.push((sort_value, subject, resource));
+        }
+    }
+    // Like the index, ties are sorted by subject
+    hits.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+    if q.sort_desc {
+        hits.reverse();
+    }
+
+    // Instances of both a Class and its subclass should be counted once, so the count needs all subjects
+    let mut all_subjects = std::collections::HashSet::new();
+    for class in &classes {
+        let mut count_query = q.clone();
+        count_query.value = Some(Value::AtomicUrl(class.clone()));
+        count_query.offset = 0;
+        count_query.limit = None;
+        count_query.include_nested = false;
+        count_query.for_agent = ForAgent::Sudo;
+        all_subjects.extend(run(&count_query)?.subjects);
+    }
+
+    let mut subjects = Vec::new();
+    let mut resources = Vec::new();
+    for (_sort_value, subject, resource) in hits
+        .into_iter()
+        .skip(q.offset)
+        .take(q.limit.unwrap_or(usize::MAX))
+    {
+        subjects.push(subject);
+        resources.extend(resource);
+    }
+    Ok(QueryResult {
+        subjects,
+        resources,
+        count: all_subjects.len(),
+    })
+}
+
 pub struct QueryResult {
     pub subjects: Vec<String>,
     pub resources: Vec<Resource>,
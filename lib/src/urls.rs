@@ -5,6 +5,9 @@ pub const CLASS: &str = "https://atomicdata.dev/classes/Class";
 pub const PROPERTY: &str = "https://atomicdata.dev/classes/Property";
 pub const DATATYPE_CLASS: &str = "https://atomicdata.dev/classes/Datatype";
 pub const COMMIT: &str = "https://atomicdata.dev/classes/Commit";
+pub const COMPUTED_PROPERTY: &str = "https://atomicdata.dev/classes/ComputedProperty";
+pub const VALIDATION_REPORT: &str = "https://atomicdata.dev/classes/ValidationReport";
+pub const VALIDATION_PROBLEM: &str = "https://atomicdata.dev/classes/ValidationProblem";
 pub const AGENT: &str = "https://atomicdata.dev/classes/Agent";
 pub const COLLECTION: &str = "https://atomicdata.dev/classes/Collection";
 pub const ENDPOINT: &str = "https://atomicdata.dev/classes/Endpoint";
@@ -23,6 +26,7 @@ pub const ONTOLOGY: &str = "https://atomicdata.dev/class/ontology";
 pub const ENDPOINT_RESPONSE: &str =
     "https://atomicdata.dev/ontology/server/class/endpoint-response";
 pub const TABLE: &str = "https://atomicdata.dev/classes/Table";
+pub const OIDC_IDENTITY: &str = "https://atomicdata.dev/classes/OidcIdentity";
 
 // Properties
 pub const SHORTNAME: &str = "https://atomicdata.dev/properties/shortname";
@@ -35,9 +39,29 @@ pub const IS_LOCKED: &str = "https://atomicdata.dev/properties/isLocked";
 pub const DATATYPE_PROP: &str = "https://atomicdata.dev/properties/datatype";
 pub const CLASSTYPE_PROP: &str = "https://atomicdata.dev/properties/classtype";
 pub const ALLOWS_ONLY: &str = "https://atomicdata.dev/properties/allowsOnly";
+pub const MIN: &str = "https://atomicdata.dev/properties/min";
+pub const MAX: &str = "https://atomicdata.dev/properties/max";
+pub const MIN_FLOAT: &str = "https://atomicdata.dev/properties/minFloat";
+pub const MAX_FLOAT: &str = "https://atomicdata.dev/properties/maxFloat";
+pub const MIN_LENGTH: &str = "https://atomicdata.dev/properties/minLength";
+pub const MAX_LENGTH: &str = "https://atomicdata.dev/properties/maxLength";
+pub const PATTERN: &str = "https://atomicdata.dev/properties/pattern";
+pub const UNIQUE: &str = "https://atomicdata.dev/properties/unique";
+pub const UNIQUE_SCOPE: &str = "https://atomicdata.dev/properties/uniqueScope";
+pub const UNIQUE_CLASS: &str = "https://atomicdata.dev/properties/uniqueClass";
+pub const STRICT_SCHEMA: &str = "https://atomicdata.dev/properties/strictSchema";
+pub const ON_DELETE: &str = "https://atomicdata.dev/properties/onDelete";
+pub const ITEM_DATATYPE: &str = "https://atomicdata.dev/properties/itemDatatype";
 // ... for Classes
 pub const REQUIRES: &str = "https://atomicdata.dev/properties/requires";
 pub const RECOMMENDS: &str = "https://atomicdata.dev/properties/recommends";
+pub const SUB_CLASS_OF: &str = "https://atomicdata.dev/properties/subClassOf";
+pub const COMPUTED_PROPERTIES: &str = "https://atomicdata.dev/properties/computedProperties";
+// ... for ComputedProperties
+pub const COMPUTES: &str = "https://atomicdata.dev/properties/computes";
+pub const AGGREGATE: &str = "https://atomicdata.dev/properties/aggregate";
+pub const SOURCE_PROPERTY: &str = "https://atomicdata.dev/properties/sourceProperty";
+pub const VALUE_PROPERTY: &str = "https://atomicdata.dev/properties/valueProperty";
 // ... for Drives
 pub const DEFAULT_ONTOLOGY: &str =
     "https://atomicdata.dev/ontology/server/property/default-ontology";
@@ -52,12 +76,31 @@ pub const CREATED_AT: &str = "https://atomicdata.dev/properties/createdAt";
 pub const SIGNATURE: &str = "https://atomicdata.dev/properties/signature";
 pub const PREVIOUS_COMMIT: &str = "https://atomicdata.dev/properties/previousCommit";
 pub const LAST_COMMIT: &str = "https://atomicdata.dev/properties/lastCommit";
+pub const DELEGATION: &str = "https://atomicdata.dev/properties/delegation";
 // ... for Agents
 pub const PUBLIC_KEY: &str = "https://atomicdata.dev/properties/publicKey";
 pub const NAME: &str = "https://atomicdata.dev/properties/name";
 pub const DRIVES: &str = "https://atomicdata.dev/properties/drives";
+pub const AGENT_KEYS: &str = "https://atomicdata.dev/properties/agentKeys";
+pub const REVOKED_AT: &str = "https://atomicdata.dev/properties/revokedAt";
+pub const PASSKEY_CREDENTIAL_ID: &str = "https://atomicdata.dev/properties/passkey/credentialId";
+pub const PASSKEY_ALGORITHM: &str = "https://atomicdata.dev/properties/passkey/algorithm";
+pub const PASSKEY_SIGN_COUNT: &str = "https://atomicdata.dev/properties/passkey/signCount";
 // ... for Collections
 pub const COLLECTION_PROPERTY: &str = "https://atomicdata.dev/properties/collection/property";
+// ... for ValidationReports
+pub const VALIDATION_RESOURCE_COUNT: &str =
+    "https://atomicdata.dev/properties/validation/resourceCount";
+pub const VALIDATION_ATOM_COUNT: &str = "https://atomicdata.dev/properties/validation/atomCount";
+pub const VALIDATION_PROBLEMS: &str = "https://atomicdata.dev/properties/validation/problems";
+pub const VALIDATION_KIND: &str = "https://atomicdata.dev/properties/validation/kind";
+pub const VALIDATION_PROPERTY: &str = "https://atomicdata.dev/properties/validation/property";
+pub const VALIDATION_MESSAGE: &str = "https://atomicdata.dev/properties/validation/message";
+// ... for Diffs
+pub const DIFF: &str = "https://atomicdata.dev/properties/diff";
+pub const TRASHED_RESOURCES: &str = "https://atomicdata.dev/properties/trashedResources";
+pub const TRASHED_AT: &str = "https://atomicdata.dev/properties/trashedAt";
+pub const TRASHED_BY: &str = "https://atomicdata.dev/properties/trashedBy";
 pub const COLLECTION_VALUE: &str = "https://atomicdata.dev/properties/collection/value";
 pub const COLLECTION_MEMBER_COUNT: &str =
     "https://atomicdata.dev/properties/collection/totalMembers";
@@ -100,6 +143,10 @@ pub const INVITE_PUBKEY: &str = "https://atomicdata.dev/properties/invite/public
 pub const INVITE_AGENT: &str = "https://atomicdata.dev/properties/invite/agent";
 pub const REDIRECT_AGENT: &str = "https://atomicdata.dev/properties/invite/redirectAgent";
 pub const EXPIRES_AT: &str = "https://atomicdata.dev/properties/invite/expiresAt";
+// ... for OpenID Connect identities
+pub const OIDC_ISSUER: &str = "https://atomicdata.dev/properties/oidc/issuer";
+pub const OIDC_SUBJECT: &str = "https://atomicdata.dev/properties/oidc/subject";
+pub const OIDC_AGENT: &str = "https://atomicdata.dev/properties/oidc/agent";
 // ... for Atoms
 pub const ATOM_SUBJECT: &str = "https://atomicdata.dev/properties/atom/subject";
 pub const ATOM_PROPERTY: &str = "https://atomicdata.dev/properties/atom/property";
@@ -142,6 +189,13 @@ pub const RESOURCE_ARRAY: &str = "https://atomicdata.dev/datatypes/resourceArray
 pub const BOOLEAN: &str = "https://atomicdata.dev/datatypes/boolean";
 pub const DATE: &str = "https://atomicdata.dev/datatypes/date";
 pub const TIMESTAMP: &str = "https://atomicdata.dev/datatypes/timestamp";
+pub const DECIMAL: &str = "https://atomicdata.dev/datatypes/decimal";
+pub const JSON: &str = "https://atomicdata.dev/datatypes/json";
+pub const URI: &str = "https://atomicdata.dev/datatypes/uri";
+pub const DURATION: &str = "https://atomicdata.dev/datatypes/duration";
+pub const DATE_TIME: &str = "https://atomicdata.dev/datatypes/dateTime";
+pub const GEO_POINT: &str = "https://atomicdata.dev/datatypes/geoPoint";
+pub const ARRAY: &str = "https://atomicdata.dev/datatypes/array";
 
 // Methods
 pub const INSERT: &str = "https://atomicdata.dev/methods/insert";
@@ -161,4 +215,5 @@ pub fn construct_path_import(base: &str) -> String {
 pub const PATH_IMPORT: &str = "/import";
 pub const PATH_FETCH_BOOKMARK: &str = "/fetch-bookmark";
 pub const PATH_QUERY: &str = "/query";
+pub const PATH_PASSKEY_LOGIN: &str = "/passkey-login";
 pub const PATH_PRUNE_TESTS: &str = "/prunetests";
@@ -1,160 +1,476 @@
-//! Validate the Store and create a ValidationReport.
-//! Might be deprecated soon, as Validation hasn't been necessary since parsing has built-in data validation.
+//! Validate all Resources in a Store and create a [ValidationReport].
+//! Resources are read from [Storelike::all_resources] and checked in parallel.
+//! The report lists every [Problem] per Resource, and can be serialized as JSON-AD using [ValidationReport::to_resource].
 
-/// Checks Atomic Data in the store for validity.
-/// Returns an Error if it is not valid.
+use std::sync::{mpsc, Mutex};
+
+use crate::{
+    datatype::DataType, resources::PropVals, urls, values::SubResource, Resource, Storelike, Value,
+};
+
+/// How many parents are followed before we consider the hierarchy broken.
+const MAX_PARENT_DEPTH: usize = 100;
+
+/// Checks all Atomic Data in the store for validity.
+/// Never stops at the first problem, but returns a report of all of them.
 ///
 /// Validates:
 ///
-/// - [X] If the Values can be parsed using their Datatype (e.g. if Integers are integers)
-/// - [X] If all required fields of the class are present
-/// - [X] If the URLs are publicly accessible
-/// - [ ] ..and return the right type of data?
-/// - [X] Returns a report, instead of throwing an error
-#[allow(dead_code, unreachable_code)]
-pub fn validate_store(
-    store: &impl crate::Storelike,
-    fetch_items: bool,
-) -> crate::validate::ValidationReport {
-    type Error = String;
-    let mut resource_count: u8 = 0;
-    let mut atom_count: u8 = 0;
-    let mut unfetchable: Vec<(String, Error)> = Vec::new();
-    let mut invalid_value: Vec<(crate::Atom, Error)> = Vec::new();
-    let mut unfetchable_props: Vec<(String, Error)> = Vec::new();
-    let mut unfetchable_classes: Vec<(String, Error)> = Vec::new();
-    // subject, property, class
-    let mut missing_props: Vec<(String, String, String)> = Vec::new();
-    for resource in store.all_resources(true) {
-        let subject = resource.get_subject();
-        let propvals = resource.get_propvals();
-        println!("Subject: {:?}", subject);
-        println!("Resource: {:?}", propvals);
-        resource_count += 1;
-
-        if fetch_items {
-            match crate::client::fetch_resource(
-                subject,
-                store,
-                store.get_default_agent().ok().as_ref(),
-            ) {
-                Ok(_) => {}
-                Err(e) => unfetchable.push((subject.clone(), e.to_string())),
+/// - If the Values match the Datatype and constraints of their Property
+/// - If all required Properties of the Classes are present
+/// - If the parent exists
+/// - If links to Resources on this server (and optionally external Resources) can be resolved
+/// - If rights are granted to Agents, and if the parents don't form a cycle
+///
+/// If `fetch_items` is true, links to external Resources that are not in the store are fetched.
+pub fn validate_store<S: Storelike + Sync>(store: &S, fetch_items: bool) -> ValidationReport {
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+    // A bounded channel, so we don't have to keep all resources in memory
+    let (sender, receiver) = mpsc::sync_channel::<Resource>(threads * 16);
+    let receiver = Mutex::new(receiver);
+    let report = Mutex::new(ValidationReport::default());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let next = receiver.lock().unwrap().recv();
+                let Ok(resource) = next else {
+                    break;
+                };
+                let problems = validate_resource(store, &resource, fetch_items);
+                report.lock().unwrap().add(&resource, problems);
+            });
+        }
+        for resource in store.all_resources(true) {
+            if sender.send(resource).is_err() {
+                break;
             }
         }
+        drop(sender);
+    });
+
+    let mut report = report.into_inner().unwrap();
+    report.sort();
+    report
+}
 
-        let mut found_props: Vec<String> = Vec::new();
+/// Like [validate_store], but checks the Resources one by one, so the store does not have to be [Sync].
+pub fn validate_store_sequential(store: &impl Storelike, fetch_items: bool) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    for resource in store.all_resources(true) {
+        let problems = validate_resource(store, &resource, fetch_items);
+        report.add(&resource, problems);
+    }
+    report.sort();
+    report
+}
 
-        for (prop_url, value) in propvals {
-            atom_count += 1;
+/// Returns all problems of a single Resource.
+pub fn validate_resource(
+    store: &impl Storelike,
+    resource: &Resource,
+    fetch_items: bool,
+) -> Vec<Problem> {
+    let subject = resource.get_subject();
+    let mut problems = Vec::new();
+    let mut problem = |kind: ProblemKind, property: Option<&str>, message: String| {
+        problems.push(Problem {
+            subject: subject.clone(),
+            kind,
+            property: property.map(String::from),
+            message,
+        })
+    };
+    // Commits describe changes from the past, so the Resources they link to might be gone
+    let is_commit = resource
+        .get(urls::IS_A)
+        .map(|classes| classes.contains_value(&Value::AtomicUrl(urls::COMMIT.into())))
+        .unwrap_or(false);
+    // The sidebar links to the `/setup` Invite, which the server only creates after populating the store
+    let setup_invite = store.get_self_url().map(|url| format!("{}/setup", url));
 
-            let property = match store.get_property(prop_url) {
-                Ok(prop) => prop,
-                Err(e) => {
-                    unfetchable_props.push((prop_url.clone(), e.to_string()));
-                    break;
-                }
-            };
-
-            // Maybe this is no longer needed, because no store uses strings anymore
-            match crate::Value::new(&value.to_string(), &property.data_type) {
-                Ok(_) => {}
-                Err(e) => invalid_value.push((
-                    crate::Atom::new(subject.clone(), prop_url.clone(), value.clone()),
-                    e.to_string(),
-                )),
-            };
-            found_props.push(prop_url.clone());
-        }
-        let classes = match store.get_classes_for_subject(subject) {
-            Ok(classes) => classes,
+    for (prop_url, value) in resource.get_propvals() {
+        let property = match store.get_property(prop_url) {
+            Ok(property) => property,
             Err(e) => {
-                unfetchable_classes.push((subject.clone(), e.to_string()));
-                break;
+                problem(ProblemKind::UnknownProperty, Some(prop_url), e.to_string());
+                continue;
             }
         };
-        for class in classes {
-            println!("Class: {:?}", class.shortname);
-            println!("Found: {:?}", found_props);
-            for required_prop_subject in class.requires {
-                match store.get_property(&required_prop_subject) {
-                    Ok(required_prop) => {
-                        println!("Required: {:?}", required_prop.shortname);
-                        if !found_props.contains(&required_prop.subject) {
-                            missing_props.push((
-                                subject.clone(),
-                                required_prop.subject.clone(),
-                                class.subject.clone(),
-                            ));
-                        }
+
+        if !value.has_datatype(&property.data_type) {
+            problem(
+                ProblemKind::InvalidValue,
+                Some(prop_url),
+                format!(
+                    "Value '{}' should have datatype {}, but has {}",
+                    value,
+                    property.data_type,
+                    value.datatype()
+                ),
+            );
+        } else if let Err(e) =
+            reparse(value, &property.data_type).and_then(|_| property.check_value(value))
+        {
+            problem(ProblemKind::InvalidValue, Some(prop_url), e.to_string());
+        }
+
+        if is_commit || prop_url == urls::PARENT {
+            continue;
+        }
+        for target in linked_subjects(value) {
+            if let Err(e) = resolve(store, &target, fetch_items) {
+                if prop_url == urls::SUBRESOURCES && setup_invite.as_ref() == Some(&target) {
+                    continue;
+                }
+                problem(
+                    ProblemKind::DanglingReference,
+                    Some(prop_url),
+                    format!("Linked Resource {} can't be found: {}", target, e),
+                );
+                continue;
+            }
+            if [urls::READ, urls::WRITE, urls::APPEND].contains(&prop_url.as_str())
+                && target != urls::PUBLIC_AGENT
+                && !is_agent(store, &target)
+            {
+                problem(
+                    ProblemKind::Rights,
+                    Some(prop_url),
+                    format!("Rights are granted to {}, which is not an Agent", target),
+                );
+            }
+        }
+    }
+
+    if let Ok(parent) = resource.get(urls::PARENT) {
+        let parent = parent.to_string();
+        if let Err(e) = resolve(store, &parent, fetch_items) {
+            problem(
+                ProblemKind::MissingParent,
+                Some(urls::PARENT),
+                format!("Parent {} can't be found: {}", parent, e),
+            );
+        } else if let Err(message) = check_parent_chain(store, resource) {
+            problem(ProblemKind::Rights, Some(urls::PARENT), message);
+        }
+    }
+
+    match resource.get_classes(store) {
+        Ok(classes) => {
+            for class in classes {
+                for required in class.requires {
+                    if resource.get(&required).is_err() {
+                        problem(
+                            ProblemKind::MissingRequired,
+                            Some(&required),
+                            format!("Required by Class {}", class.subject),
+                        );
                     }
-                    Err(e) => unfetchable.push((required_prop_subject, e.to_string())),
                 }
             }
         }
-        println!("{:?} Valid", subject);
+        Err(e) => problem(ProblemKind::UnknownClass, Some(urls::IS_A), e.to_string()),
     }
-    crate::validate::ValidationReport {
-        unfetchable,
-        unfetchable_classes,
-        unfetchable_props,
-        invalid_value,
-        resource_count,
-        atom_count,
+    problems
+}
+
+/// Checks if the value is still valid when it is parsed from its string representation.
+/// Catches values that were stored without validation, such as Slugs with spaces.
+fn reparse(value: &Value, datatype: &DataType) -> crate::errors::AtomicResult<()> {
+    match value {
+        Value::ResourceArray(_) | Value::NestedResource(_) | Value::Resource(_) => Ok(()),
+        other => Value::new(&other.to_string(), datatype).map(|_| ()),
+    }
+}
+
+/// Subjects of the Resources that the Value links to. Nested Resources are skipped.
+fn linked_subjects(value: &Value) -> Vec<String> {
+    match value {
+        Value::AtomicUrl(subject) => vec![subject.clone()],
+        Value::ResourceArray(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                SubResource::Subject(subject) => Some(subject.clone()),
+                SubResource::Resource(resource) => Some(resource.get_subject().clone()),
+                SubResource::Nested(_) => None,
+            })
+            .collect(),
+        _ => Vec::new(),
     }
 }
 
+/// Finds a Resource in the store.
+/// External Resources are only fetched if `fetch_items` is true, and are assumed to exist otherwise.
+fn resolve(
+    store: &impl Storelike,
+    subject: &str,
+    fetch_items: bool,
+) -> crate::errors::AtomicResult<()> {
+    if store.get_resource(subject).is_ok() {
+        return Ok(());
+    }
+    let is_local = store
+        .get_self_url()
+        .map(|url| subject.starts_with(&url))
+        .unwrap_or(false);
+    if is_local {
+        return Err("Not found in the store".into());
+    }
+    if fetch_items {
+        crate::client::fetch_resource(subject, store, store.get_default_agent().ok().as_ref())?;
+    }
+    Ok(())
+}
+
+fn is_agent(store: &impl Storelike, subject: &str) -> bool {
+    store
+        .get_resource(subject)
+        .and_then(|r| r.get(urls::IS_A).cloned())
+        .map(|classes| classes.contains_value(&Value::AtomicUrl(urls::AGENT.into())))
+        // We can't check external Agents that are not in the store
+        .unwrap_or(true)
+}
+
+/// Rights are inherited from parents, so these should not form a cycle.
+fn check_parent_chain(store: &impl Storelike, resource: &Resource) -> Result<(), String> {
+    let mut visited = vec![resource.get_subject().clone()];
+    let mut current = resource.get(urls::PARENT).ok().map(|v| v.to_string());
+    while let Some(parent) = current {
+        if visited.contains(&parent) {
+            return Err(format!(
+                "The parents form a cycle ({} => {}), so the rights can't be determined",
+                visited.join(" => "),
+                parent
+            ));
+        }
+        if visited.len() > MAX_PARENT_DEPTH {
+            return Err(format!(
+                "The hierarchy is deeper than {} levels",
+                MAX_PARENT_DEPTH
+            ));
+        }
+        current = store
+            .get_resource(&parent)
+            .ok()
+            .and_then(|r| r.get(urls::PARENT).ok().map(|v| v.to_string()));
+        visited.push(parent);
+    }
+    Ok(())
+}
+
+/// What kind of [Problem] was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProblemKind {
+    /// The Property of a Value can't be found
+    UnknownProperty,
+    /// A Value does not match the Datatype or constraints of its Property
+    InvalidValue,
+    /// A Property required by one of the Classes is missing
+    MissingRequired,
+    /// One of the Classes can't be found
+    UnknownClass,
+    /// The parent can't be found
+    MissingParent,
+    /// A linked Resource can't be found
+    DanglingReference,
+    /// Rights are granted to something that is not an Agent, or the parents form a cycle
+    Rights,
+}
+
+impl ProblemKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProblemKind::UnknownProperty => "unknownProperty",
+            ProblemKind::InvalidValue => "invalidValue",
+            ProblemKind::MissingRequired => "missingRequired",
+            ProblemKind::UnknownClass => "unknownClass",
+            ProblemKind::MissingParent => "missingParent",
+            ProblemKind::DanglingReference => "danglingReference",
+            ProblemKind::Rights => "rights",
+        }
+    }
+}
+
+impl std::fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Something wrong with a single Resource.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub subject: String,
+    pub kind: ProblemKind,
+    /// The Property that has the problem, if it's about a single Property
+    pub property: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    /// Converts the Problem to the PropVals of a Nested Resource.
+    pub fn to_propvals(&self) -> PropVals {
+        let mut propvals = PropVals::new();
+        propvals.insert(
+            urls::IS_A.into(),
+            vec![urls::VALIDATION_PROBLEM.to_string()].into(),
+        );
+        propvals.insert(urls::SUBJECT.into(), Value::AtomicUrl(self.subject.clone()));
+        propvals.insert(
+            urls::VALIDATION_KIND.into(),
+            Value::String(self.kind.to_string()),
+        );
+        if let Some(property) = &self.property {
+            propvals.insert(
+                urls::VALIDATION_PROPERTY.into(),
+                Value::AtomicUrl(property.clone()),
+            );
+        }
+        propvals.insert(
+            urls::VALIDATION_MESSAGE.into(),
+            Value::String(self.message.clone()),
+        );
+        propvals
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct ValidationReport {
-    pub resource_count: u8,
-    pub atom_count: u8,
-    pub unfetchable: Vec<(String, String)>,
-    pub invalid_value: Vec<(crate::Atom, String)>,
-    pub unfetchable_props: Vec<(String, String)>,
-    pub unfetchable_classes: Vec<(String, String)>,
+    pub resource_count: usize,
+    pub atom_count: usize,
+    /// Sorted by subject
+    pub problems: Vec<Problem>,
 }
 
 impl ValidationReport {
     pub fn is_valid(&self) -> bool {
-        self.unfetchable.is_empty()
-            && self.unfetchable_classes.is_empty()
-            && self.unfetchable_props.is_empty()
-            && self.invalid_value.is_empty()
+        self.problems.is_empty()
+    }
+
+    /// Counts the Resource and adds its problems.
+    fn add(&mut self, resource: &Resource, problems: Vec<Problem>) {
+        self.resource_count += 1;
+        self.atom_count += resource.get_propvals().len();
+        self.problems.extend(problems);
+    }
+
+    /// Sorts the problems by subject, so the report does not depend on the order of validation.
+    fn sort(&mut self) {
+        self.problems.sort_by(|a, b| {
+            (&a.subject, a.kind, &a.property).cmp(&(&b.subject, b.kind, &b.property))
+        });
+    }
+
+    /// Converts the report to a Resource, which can be serialized as JSON-AD.
+    /// The problems are Nested Resources.
+    pub fn to_resource(&self, subject: &str) -> Resource {
+        let mut resource = Resource::new(subject.into());
+        resource.set_class(urls::VALIDATION_REPORT);
+        resource.set_unsafe(
+            urls::VALIDATION_RESOURCE_COUNT.into(),
+            Value::Integer(self.resource_count as i64),
+        );
+        resource.set_unsafe(
+            urls::VALIDATION_ATOM_COUNT.into(),
+            Value::Integer(self.atom_count as i64),
+        );
+        resource.set_unsafe(
+            urls::VALIDATION_PROBLEMS.into(),
+            Value::ResourceArray(
+                self.problems
+                    .iter()
+                    .map(|problem| SubResource::Nested(problem.to_propvals()))
+                    .collect(),
+            ),
+        );
+        resource
     }
 }
 
 impl std::fmt::Display for ValidationReport {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        if self.is_valid() {
-            fmt.write_str("Valid!")?;
-            return Ok(());
-        }
-        for (subject, error) in &self.unfetchable {
-            fmt.write_str(&format!("Cannot fetch Resource {}: {} \n", subject, error))?;
-        }
-        for (subject, error) in &self.unfetchable_classes {
-            fmt.write_str(&format!("Cannot fetch Class {}: {} \n", subject, error))?;
-        }
-        for (subject, error) in &self.unfetchable_props {
-            fmt.write_str(&format!("Cannot fetch Property {}: {} \n", subject, error))?;
+        for problem in &self.problems {
+            match &problem.property {
+                Some(property) => writeln!(
+                    fmt,
+                    "{} ({}, {}): {}",
+                    problem.subject, problem.kind, property, problem.message
+                )?,
+                None => writeln!(
+                    fmt,
+                    "{} ({}): {}",
+                    problem.subject, problem.kind, problem.message
+                )?,
+            }
         }
-        for (atom, error) in &self.invalid_value {
-            fmt.write_str(&format!("Invalid value {:?}: {} \n", atom, error))?;
+        if self.is_valid() {
+            write!(
+                fmt,
+                "Valid! Checked {} resources and {} atoms.",
+                self.resource_count, self.atom_count
+            )
+        } else {
+            write!(
+                fmt,
+                "Found {} problems in {} resources and {} atoms.",
+                self.problems.len(),
+                self.resource_count,
+                self.atom_count
+            )
         }
-        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{Store, Storelike};
+    use super::*;
+    use crate::Store;
 
     #[test]
     fn validate_populated() {
         let store = Store::init().unwrap();
         store.populate().unwrap();
-        // let report = store.validate();
-        // assert!(report.atom_count > 30);
-        // assert!(report.resource_count > 5);
-        // assert!(report.is_valid());
+        let report = store.validate();
+        assert!(report.atom_count > 30);
+        assert!(report.resource_count > 5);
+        assert!(report.is_valid(), "{}", report);
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let mut broken = Resource::new("https://example.com/broken".into());
+        broken.set_class(urls::CLASS);
+        broken.set_unsafe(urls::SHORTNAME.into(), Value::Slug("Not a slug".into()));
+        broken.set_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl("local:store/missing".into()),
+        );
+        broken.set_unsafe(urls::WRITE.into(), vec![urls::CLASS.to_string()].into());
+        store
+            .add_resource_opts(&broken, false, false, true)
+            .unwrap();
+
+        let report = store.validate();
+        let kinds: Vec<ProblemKind> = report
+            .problems
+            .iter()
+            .filter(|p| p.subject == "https://example.com/broken")
+            .map(|p| p.kind)
+            .collect();
+        assert!(kinds.contains(&ProblemKind::InvalidValue), "{}", report);
+        assert!(kinds.contains(&ProblemKind::MissingParent), "{}", report);
+        assert!(kinds.contains(&ProblemKind::MissingRequired), "{}", report);
+        assert!(kinds.contains(&ProblemKind::Rights), "{}", report);
+
+        let json = report
+            .to_resource("local:store/validate")
+            .to_json_ad()
+            .unwrap();
+        assert!(json.contains("missingParent"));
     }
 }
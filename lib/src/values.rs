@@ -2,7 +2,7 @@
 
 use crate::{
     datatype::match_datatype, datatype::DataType, errors::AtomicResult, resources::PropVals,
-    utils::check_valid_url, Resource,
+    schema::Property, utils::check_valid_url, Resource,
 };
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -26,6 +26,20 @@ pub enum Value {
     Resource(Box<Resource>),
     Boolean(bool),
     Unsupported(UnsupportedValue),
+    /// Arbitrary precision decimal number, e.g. `-12.50`
+    Decimal(String),
+    /// Serialized JSON, e.g. `{"a":[1,2]}`
+    Json(String),
+    /// Any URI, e.g. `mailto:alice@example.com`
+    Uri(String),
+    /// ISO 8601 duration, e.g. `P1DT2H30M`
+    Duration(String),
+    /// RFC 3339 datetime with a timezone offset, e.g. `2024-03-01T09:30:00+01:00`
+    DateTime(String),
+    GeoPoint(GeoPoint),
+    /// Ordered list of Values that share a datatype, e.g. a list of tags.
+    /// See [DataType::Array].
+    Array(Vec<Value>),
 }
 
 /// A resource in a JSON-AD body can be any of these
@@ -47,10 +61,55 @@ pub struct UnsupportedValue {
     pub datatype: String,
 }
 
+/// A location on earth, in WGS 84 coordinates.
+/// Serialized as `latitude,longitude`, e.g. `52.3676,4.9041`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl std::str::FromStr for GeoPoint {
+    type Err = crate::errors::AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Not a valid geo point: {}. Needs to be `latitude,longitude`.",
+                s
+            )
+        };
+        let (lat, lon) = s.split_once(',').ok_or_else(invalid)?;
+        let latitude: f64 = lat.trim().parse().map_err(|_| invalid())?;
+        let longitude: f64 = lon.trim().parse().map_err(|_| invalid())?;
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!(
+                "Geo point {} is out of range. Latitude must be between -90 and 90, longitude between -180 and 180.",
+                s
+            )
+            .into());
+        }
+        Ok(GeoPoint {
+            latitude,
+            longitude,
+        })
+    }
+}
+
+impl fmt::Display for GeoPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
 /// Only alphanumeric characters, no spaces
 pub const SLUG_REGEX: &str = r"^[a-z0-9]+(?:-[a-z0-9]+)*$";
 /// YYYY-MM-DD
 pub const DATE_REGEX: &str = r"^\d{4}\-(0[1-9]|1[012])\-(0[1-9]|[12][0-9]|3[01])$";
+/// Optional minus sign, digits, optional decimal point followed by digits. No exponents.
+pub const DECIMAL_REGEX: &str = r"^-?\d+(\.\d+)?$";
+/// ISO 8601 duration, e.g. `P1Y2M3DT4H5M6.5S` or `P2W`
+pub const DURATION_REGEX: &str = r"^P(?:(\d+)Y)?(?:(\d+)M)?(?:(\d+)W)?(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+(?:\.\d+)?)S)?)?$";
 
 impl Value {
     /// Check if the value `q_val` is present in `val`
@@ -61,6 +120,7 @@ impl Value {
                 let subs = self.to_subjects(None).unwrap_or_default();
                 subs.iter().any(|v| v == &query_value)
             }
+            Value::Array(items) => items.iter().any(|item| item.to_string() == query_value),
             other => other.to_string() == query_value,
         }
     }
@@ -82,6 +142,30 @@ impl Value {
             Value::Resource(_) => DataType::AtomicUrl,
             Value::Boolean(_) => DataType::Boolean,
             Value::Unsupported(s) => DataType::Unsupported(s.datatype.clone()),
+            Value::Decimal(_) => DataType::Decimal,
+            Value::Json(_) => DataType::Json,
+            Value::Uri(_) => DataType::Uri,
+            Value::Duration(_) => DataType::Duration,
+            Value::DateTime(_) => DataType::DateTime,
+            Value::GeoPoint(_) => DataType::GeoPoint,
+            // Empty arrays have no items to take the datatype from. See [Value::has_datatype].
+            Value::Array(items) => DataType::Array(Box::new(
+                items
+                    .first()
+                    .map(|item| item.datatype())
+                    .unwrap_or(DataType::String),
+            )),
+        }
+    }
+
+    /// Whether the Value can be used for a Property with this DataType.
+    /// Arrays match if all their items match the item datatype.
+    pub fn has_datatype(&self, datatype: &DataType) -> bool {
+        match (self, datatype) {
+            (Value::Array(items), DataType::Array(item_datatype)) => {
+                items.iter().all(|item| item.has_datatype(item_datatype))
+            }
+            (value, datatype) => &value.datatype() == datatype,
         }
     }
 
@@ -155,7 +239,83 @@ impl Value {
                 };
                 Ok(Value::Boolean(bool))
             }
+            DataType::Decimal => {
+                let re = Regex::new(DECIMAL_REGEX).unwrap();
+                if re.is_match(value) {
+                    return Ok(Value::Decimal(value.into()));
+                }
+                Err(format!("Not a valid decimal: {}. Needs to be like `-12.50`.", value).into())
+            }
+            DataType::Json => {
+                let json: serde_json::Value = serde_json::from_str(value)
+                    .map_err(|e| format!("Not valid JSON: {}. {}", value, e))?;
+                Ok(Value::new_json(json))
+            }
+            DataType::Uri => {
+                url::Url::parse(value).map_err(|e| format!("Not a valid URI: {}. {}", value, e))?;
+                Ok(Value::Uri(value.into()))
+            }
+            DataType::Duration => {
+                duration_to_seconds(value)?;
+                Ok(Value::Duration(value.into()))
+            }
+            DataType::DateTime => {
+                chrono::DateTime::parse_from_rfc3339(value).map_err(|e| {
+                    format!(
+                        "Not a valid datetime: {}. Needs to be RFC 3339 with a timezone, like `2024-03-01T09:30:00+01:00`. {}",
+                        value, e
+                    )
+                })?;
+                Ok(Value::DateTime(value.into()))
+            }
+            DataType::GeoPoint => Ok(Value::GeoPoint(value.parse()?)),
+            DataType::Array(item_datatype) => {
+                let items: Vec<serde_json::Value> = serde_json::from_str(value).map_err(|e| {
+                    format!(
+                        "Could not deserialize Array: {}. Should be a JSON array. {}",
+                        value, e
+                    )
+                })?;
+                Value::new_array(items, item_datatype)
+            }
+        }
+    }
+
+    /// Creates a [Value::Json]. This is the JSON value itself, so a JSON string stays a string, even if it contains JSON.
+    /// JSON-AD values are converted using this, and [Value::new] uses it for the parsed text.
+    /// Parsing the [Value::to_string] of the result with [Value::new] returns the same Value.
+    pub fn new_json(json: serde_json::Value) -> Value {
+        Value::Json(json.to_string())
+    }
+
+    /// Creates a [Value::Array] from JSON items, which are converted to the item datatype.
+    pub fn new_array(
+        items: Vec<serde_json::Value>,
+        item_datatype: &DataType,
+    ) -> AtomicResult<Value> {
+        let mut values = Vec::with_capacity(items.len());
+        for item in items {
+            let value = match (item, item_datatype) {
+                (item, DataType::Json) => Value::new_json(item),
+                (serde_json::Value::String(s), datatype) => Value::new(&s, datatype)?,
+                (item @ (serde_json::Value::Array(_) | serde_json::Value::Object(_)), _) => {
+                    return Err(
+                        format!("Array items can not be arrays or objects: {}", item).into(),
+                    )
+                }
+                (other, datatype) => Value::new(&other.to_string(), datatype)?,
+            };
+            values.push(value);
         }
+        Ok(Value::Array(values))
+    }
+
+    /// Creates a new Value for a Property.
+    /// Fails if the input string does not convert, or if it violates the constraints of the Property (see [Property::check_value]).
+    pub fn new_for_property(value: &str, property: &Property) -> AtomicResult<Value> {
+        let val = Value::new(value, &property.data_type)?;
+        property.check_value(&val)?;
+        Ok(val)
     }
 
     /// Returns a new Value, accepts a datatype string
@@ -219,6 +379,17 @@ impl Value {
         }
     }
 
+    /// Returns a Float, if the Atom is a number.
+    pub fn to_float(&self) -> AtomicResult<f64> {
+        match self {
+            Value::Float(float) => Ok(*float),
+            Value::Timestamp(int) | Value::Integer(int) => Ok(*int as f64),
+            _ => self.to_string().parse::<f64>().map_err(|e| {
+                format!("Value {} cannot be converted into float. {}", self, e).into()
+            }),
+        }
+    }
+
     /// Returns a PropVals Hashmap, if the Atom is a NestedResource
     pub fn to_nested(&self) -> AtomicResult<&PropVals> {
         if let Value::NestedResource(SubResource::Nested(nested)) = self {
@@ -231,6 +402,24 @@ impl Value {
     pub fn to_sortable_string(&self) -> SortableValue {
         match self {
             Value::ResourceArray(arr) => arr.len().to_string(),
+            Value::Array(items) => items.len().to_string(),
+            Value::Decimal(decimal) => sortable_decimal(decimal),
+            Value::Duration(duration) => duration_to_seconds(duration)
+                .map(|seconds| sortable_decimal(&seconds.to_string()))
+                .unwrap_or_default(),
+            // Datetimes with different offsets are compared in UTC
+            Value::DateTime(datetime) => chrono::DateTime::parse_from_rfc3339(datetime)
+                .map(|dt| {
+                    dt.with_timezone(&chrono::Utc)
+                        .format("%Y-%m-%dT%H:%M:%S%.9fZ")
+                        .to_string()
+                })
+                .unwrap_or_else(|_| datetime.clone()),
+            Value::GeoPoint(point) => format!(
+                "{},{}",
+                sortable_decimal(&point.latitude.to_string()),
+                sortable_decimal(&point.longitude.to_string())
+            ),
             other => other.to_string(),
         }
     }
@@ -242,6 +431,8 @@ impl Value {
             // TODO: This results in wrong indexing, as some subjects will be numbers.
             Value::ResourceArray(_v) => self.to_subjects(None).unwrap_or_else(|_| vec![]),
             Value::AtomicUrl(v) => vec![v.into()],
+            // Every item is indexed, so queries can match a single item
+            Value::Array(items) => items.iter().map(|item| item.to_string()).collect(),
             // TODO We don't index nested resources for now
             Value::Resource(_r) => return None,
             Value::NestedResource(_r) => return None,
@@ -252,6 +443,63 @@ impl Value {
     }
 }
 
+/// Encodes a decimal number (e.g. `-12.5`) so that the lexicographic order matches the numeric order.
+/// Positive numbers start with `1` followed by the amount of integer digits and the digits themselves.
+/// Negative numbers start with `0`, and have their digits inverted.
+/// The terminator makes sure that 12 sorts before 12.5, but -12 after -12.5, regardless of what follows the value in a key.
+fn sortable_decimal(decimal: &str) -> SortableValue {
+    let (negative, digits) = match decimal.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, decimal),
+    };
+    let (int, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let int = int.trim_start_matches('0');
+    let fraction = fraction.trim_end_matches('0');
+    if int.is_empty() && fraction.is_empty() {
+        return "1!".into();
+    }
+    let encoded = format!("{:04}{}{}", int.len(), int, fraction);
+    if negative {
+        let inverted: String = encoded
+            .bytes()
+            .map(|digit| char::from(b'9' - digit + b'0'))
+            .collect();
+        // `~` sorts after all digits
+        format!("0{}~", inverted)
+    } else {
+        // `!` sorts before all digits
+        format!("1{}!", encoded)
+    }
+}
+
+/// Parses an ISO 8601 duration and returns the amount of seconds.
+/// Years count as 365 days, months as 30 days.
+pub fn duration_to_seconds(duration: &str) -> AtomicResult<f64> {
+    let invalid = || {
+        format!(
+            "Not a valid duration: {}. Needs to be ISO 8601, like `P1DT2H30M`.",
+            duration
+        )
+    };
+    let re = Regex::new(DURATION_REGEX).unwrap();
+    let captures = re.captures(duration).ok_or_else(invalid)?;
+    let time_part = duration.contains('T');
+    let has_time = (5..=7).any(|i| captures.get(i).is_some());
+    let has_any = (1..=7).any(|i| captures.get(i).is_some());
+    if !has_any || time_part != has_time {
+        return Err(invalid().into());
+    }
+    const DAY: f64 = 24.0 * 60.0 * 60.0;
+    let factors = [365.0 * DAY, 30.0 * DAY, 7.0 * DAY, DAY, 3600.0, 60.0, 1.0];
+    let mut seconds = 0.0;
+    for (i, factor) in factors.iter().enumerate() {
+        if let Some(amount) = captures.get(i + 1) {
+            seconds += amount.as_str().parse::<f64>().map_err(|_| invalid())? * factor;
+        }
+    }
+    Ok(seconds)
+}
+
 /// A value that is meant for checking reference indexes.
 /// short. Vectors of subjects are turned into individual ReferenceStrings.
 pub type ReferenceString = String;
@@ -259,6 +507,18 @@ pub type ReferenceString = String;
 /// String Value representing a lexicographically sortable string.
 pub type SortableValue = String;
 
+/// Maximum length (in bytes) of a [SortableValue] that is used for sorting. Should be long enough to contain pretty long URLs, but not very long documents.
+pub const MAX_SORTABLE_LEN: usize = 120;
+
+/// The part of a [SortableValue] that determines its position in sorted query results: the first [MAX_SORTABLE_LEN] bytes, lowercased.
+pub fn sort_key(value: &str) -> String {
+    let mut end = value.len().min(MAX_SORTABLE_LEN);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value[..end].to_lowercase()
+}
+
 impl From<String> for Value {
     fn from(val: String) -> Self {
         Value::String(val)
@@ -382,6 +642,19 @@ impl fmt::Display for Value {
             Value::NestedResource(n) => write!(f, "{:?}", n),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Unsupported(u) => write!(f, "{}", u.value),
+            Value::Decimal(s) => write!(f, "{}", s),
+            Value::Json(s) => write!(f, "{}", s),
+            Value::Uri(s) => write!(f, "{}", s),
+            Value::Duration(s) => write!(f, "{}", s),
+            Value::DateTime(s) => write!(f, "{}", s),
+            Value::GeoPoint(p) => write!(f, "{}", p),
+            Value::Array(items) => {
+                let json: Vec<serde_json::Value> = items
+                    .iter()
+                    .map(|item| crate::serialize::val_to_serde(item.clone()).unwrap_or_default())
+                    .collect();
+                write!(f, "{}", serde_json::Value::Array(json))
+            }
         }
     }
 }
@@ -515,4 +788,61 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn parses_extended_datatypes() {
+        let decimal = Value::new("-1234567890.123456789012345", &DataType::Decimal).unwrap();
+        assert_eq!(decimal.to_string(), "-1234567890.123456789012345");
+        Value::new("1e5", &DataType::Decimal).unwrap_err();
+
+        let json = Value::new(r#"{ "a": [1, true] }"#, &DataType::Json).unwrap();
+        assert_eq!(json.to_string(), r#"{"a":[1,true]}"#);
+        Value::new("{ nope", &DataType::Json).unwrap_err();
+        // The text is parsed as JSON, so a JSON string that contains JSON stays a string
+        let text = Value::new(r#""{\"a\":1}""#, &DataType::Json).unwrap();
+        assert_eq!(text.to_string(), r#""{\"a\":1}""#);
+        for value in [text, json, Value::new_json(5.into())] {
+            let parsed = Value::new(&value.to_string(), &DataType::Json).unwrap();
+            assert!(matches!(&parsed, Value::Json(s) if s == &value.to_string()));
+        }
+
+        Value::new("mailto:alice@example.com", &DataType::Uri).unwrap();
+        Value::new("not a uri", &DataType::Uri).unwrap_err();
+
+        assert_eq!(duration_to_seconds("P1DT2H30M").unwrap(), 95400.0);
+        assert_eq!(duration_to_seconds("PT0.5S").unwrap(), 0.5);
+        for invalid in ["P", "PT", "P1DT", "1D", "P1H"] {
+            Value::new(invalid, &DataType::Duration).unwrap_err();
+        }
+
+        Value::new("2024-03-01T09:30:00+01:00", &DataType::DateTime).unwrap();
+        Value::new("2024-03-01T09:30:00", &DataType::DateTime).unwrap_err();
+
+        let point = Value::new("52.3676, 4.9041", &DataType::GeoPoint).unwrap();
+        assert_eq!(point.to_string(), "52.3676,4.9041");
+        Value::new("91,0", &DataType::GeoPoint).unwrap_err();
+        Value::new("52.3676", &DataType::GeoPoint).unwrap_err();
+    }
+
+    #[test]
+    fn parses_typed_arrays() {
+        let integers = DataType::Array(Box::new(DataType::Integer));
+        let array = Value::new("[1, 2, 3]", &integers).unwrap();
+        assert_eq!(array.to_string(), "[1,2,3]");
+        assert!(array.has_datatype(&integers));
+        assert!(array.contains_value(&Value::Integer(2)));
+        assert!(!array.contains_value(&Value::Integer(4)));
+        assert_eq!(array.to_reference_index_strings().unwrap().len(), 3);
+        Value::new(r#"[1, "two"]"#, &integers).unwrap_err();
+        Value::new("[[1]]", &integers).unwrap_err();
+
+        let dates = DataType::Array(Box::new(DataType::Date));
+        let array = Value::new(r#"["2024-03-01", "2024-03-02"]"#, &dates).unwrap();
+        assert_eq!(array.to_string(), r#"["2024-03-01","2024-03-02"]"#);
+        Value::new(r#"["yesterday"]"#, &dates).unwrap_err();
+
+        let empty = Value::new("[]", &dates).unwrap();
+        assert!(empty.has_datatype(&dates));
+        assert!(!empty.has_datatype(&DataType::ResourceArray));
+    }
 }
@@ -9,7 +9,8 @@ use actix::{prelude::Message, Addr};
 pub struct Subscribe {
     pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
     pub subject: String,
-    pub agent: String,
+    /// The Agent of the connection, including the scope of its delegation token.
+    pub agent: atomic_lib::agents::ForAgent,
 }
 
 /// A message containing a Resource, which should be sent to subscribers
@@ -1,12 +1,18 @@
 //! App state, which is accessible from handlers
 use crate::{
-    commit_monitor::CommitMonitor, config::Config, errors::AtomicServerResult, search::SearchState,
+    commit_monitor::CommitMonitor,
+    config::Config,
+    errors::AtomicServerResult,
+    files::{FileStorage, ProcessedImages, UploadSessions},
+    oidc::PendingLogins,
+    search::SearchState,
 };
 use atomic_lib::{
     agents::{generate_public_key, Agent},
     commit::CommitResponse,
     Storelike,
 };
+use std::sync::Arc;
 
 /// The AppState contains all the relevant Context for the server.
 /// This data object is available to all handlers and actors.
@@ -23,6 +29,14 @@ pub struct AppState {
     /// The Actix Address of the CommitMonitor, which should receive updates when a commit is applied
     pub commit_monitor: actix::Addr<CommitMonitor>,
     pub search_state: SearchState,
+    /// OpenID Connect logins that are waiting for the provider to redirect back.
+    pub oidc_logins: PendingLogins,
+    /// Where uploaded files are stored
+    pub file_storage: Arc<dyn FileStorage>,
+    /// Resumable uploads that are in progress
+    pub upload_sessions: UploadSessions,
+    /// Resized and converted images that are served by `/download`
+    pub processed_images: ProcessedImages,
 }
 
 impl AppState {
@@ -41,6 +55,11 @@ impl AppState {
         }
 
         let mut store = atomic_lib::Db::init(&config.store_path, config.server_url.clone())?;
+        store.set_snapshot_interval(config.opts.snapshot_interval);
+        store.set_trash_retention(match config.opts.trash_retention_days {
+            0 => None,
+            days => Some(std::time::Duration::from_secs(days * 24 * 60 * 60)),
+        });
         let no_server_resource = store.get_resource(&config.server_url).is_err();
         if no_server_resource {
             tracing::warn!("Server URL resource not found. This is likely because the server URL has changed. Initializing a new database...");
@@ -58,9 +77,24 @@ impl AppState {
         let search_state = SearchState::new(&config)
             .map_err(|e| format!("Failed to start search service: {}", e))?;
 
+        let file_storage = crate::files::init_storage(&config)?;
+        let upload_sessions = UploadSessions::new(&config.uploads_path);
+        let processed_images = ProcessedImages::new(
+            file_storage.clone(),
+            match config.opts.processed_images_max_mb {
+                0 => None,
+                megabytes => Some(megabytes * 1024 * 1024),
+            },
+        );
+
         // Initialize commit monitor, which watches commits and sends these to the commit_monitor actor
-        let commit_monitor =
-            crate::commit_monitor::create_commit_monitor(store.clone(), search_state.clone());
+        let commit_monitor = crate::commit_monitor::create_commit_monitor(
+            store.clone(),
+            search_state.clone(),
+            file_storage.clone(),
+            upload_sessions.clone(),
+            processed_images.clone(),
+        );
 
         let commit_monitor_clone = commit_monitor.clone();
 
@@ -98,6 +132,10 @@ impl AppState {
             config,
             commit_monitor,
             search_state,
+            oidc_logins: PendingLogins::default(),
+            file_storage,
+            upload_sessions,
+            processed_images,
         })
     }
 
@@ -1,4 +1,8 @@
-use atomic_lib::{agents::ForAgent, urls, Storelike};
+use atomic_lib::{
+    agents::ForAgent,
+    audit::{AuditEvent, AuditEventKind},
+    urls, Storelike,
+};
 use atomic_server_lib::config::Opts;
 use std::{fs::File, io::Write};
 
@@ -8,11 +12,15 @@ mod commit_monitor;
 pub mod config;
 mod content_types;
 mod errors;
+mod files;
 mod handlers;
 mod helpers;
 #[cfg(feature = "https")]
 mod https;
+mod images;
 mod jsonerrors;
+mod oidc;
+mod replication;
 mod routes;
 pub mod serve;
 // #[cfg(feature = "search")]
@@ -80,14 +88,100 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
                     atomic_lib::parse::SaveOpts::Commit
                 },
                 signer: Some(appstate.store.get_default_agent()?),
+                keep_non_url_items: false,
             };
             println!("Importing...");
+            appstate.store.audit(
+                AuditEvent::new(
+                    AuditEventKind::Sudo,
+                    &ForAgent::Sudo,
+                    parse_opts.importer.as_deref().unwrap_or_default(),
+                )
+                .with_message(format!(
+                    "Imported {:?} from the command line",
+                    import_opts.file
+                )),
+            );
             appstate.store.import(&readstring, &parse_opts)?;
             appstate.search_state.add_all_resources(&appstate.store)?;
             println!("Successfully imported {:?} to store.", import_opts.file);
             println!("WARNING: Your search index is not yet updated with these imported items. Run `--rebuild-index` to fix that.");
             Ok(())
         }
+        Some(config::Command::MigrateProperty(migrate_opts)) => {
+            let table = match &migrate_opts.map {
+                Some(path) => {
+                    let json = std::fs::read_to_string(path)?;
+                    Some(
+                        serde_json::from_str(&json)
+                            .map_err(|e| format!("Invalid map file {:?}: {}", path, e))?,
+                    )
+                }
+                None => None,
+            };
+            let migration = atomic_lib::schema_migration::Migration {
+                property: migrate_opts.property.clone(),
+                data_type: atomic_lib::schema_migration::datatype_from_urls(
+                    &migrate_opts.datatype,
+                    migrate_opts.item_datatype.as_deref(),
+                )?,
+                rule: atomic_lib::schema_migration::ConversionRule::from_name(
+                    &migrate_opts.rule,
+                    table,
+                )?,
+                dry_run: migrate_opts.dry_run,
+                for_agent: atomic_lib::agents::ForAgent::Sudo,
+            };
+            let appstate = appstate::AppState::init(config.clone())?;
+            if !migration.dry_run {
+                appstate.store.audit(
+                    AuditEvent::new(AuditEventKind::Sudo, &ForAgent::Sudo, &migration.property)
+                        .with_message("Migrated the Datatype of a Property from the command line"),
+                );
+            }
+            let report =
+                atomic_lib::schema_migration::migrate_property(&appstate.store, &migration)?;
+            println!("{}", report);
+            if report.applied {
+                println!("WARNING: Your search index is not yet updated with these changes. Run `--rebuild-index` to fix that.");
+            }
+            Ok(())
+        }
+        Some(config::Command::Validate(validate_opts)) => {
+            let appstate = appstate::AppState::init(config.clone())?;
+            let report =
+                atomic_lib::validate::validate_store(&appstate.store, validate_opts.fetch_external);
+            if validate_opts.json {
+                let subject = format!("{}/validate", appstate.store.get_server_url());
+                println!("{}", report.to_resource(&subject).to_json_ad()?);
+            } else {
+                println!("{}", report);
+            }
+            if !report.is_valid() {
+                return Err(format!("Found {} problems", report.problems.len()).into());
+            }
+            Ok(())
+        }
+        Some(config::Command::CompactHistory(compact_opts)) => {
+            let appstate = appstate::AppState::init(config.clone())?;
+            let policy = atomic_lib::db::CompactionPolicy {
+                keep_recent: compact_opts.keep_recent,
+                keep_after: compact_opts
+                    .keep_days
+                    .map(|days| atomic_lib::utils::now() - days * 24 * 60 * 60 * 1000),
+            };
+            let subject = compact_opts.subject.as_deref().unwrap_or("all Resources");
+            appstate.store.audit(
+                AuditEvent::new(AuditEventKind::Sudo, &ForAgent::Sudo, subject)
+                    .with_message("Compacted version history from the command line"),
+            );
+            let removed = match &compact_opts.subject {
+                Some(subject) => appstate.store.compact_history(subject, &policy)?,
+                None => appstate.store.compact_all_histories(&policy)?,
+            };
+            println!("Removed {} Commits", removed);
+            Ok(())
+        }
         Some(config::Command::ShowConfig) => {
             println!("{:#?}", config);
             Ok(())
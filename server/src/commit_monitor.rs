@@ -12,7 +12,10 @@ use actix::{
     prelude::{Actor, Context, Handler},
     ActorStreamExt, Addr, ContextFutureSpawner,
 };
-use atomic_lib::{agents::ForAgent, Db, Storelike};
+use atomic_lib::{
+    audit::{AuditEvent, AuditEventKind},
+    urls, Db, Storelike,
+};
 use chrono::Local;
 use std::collections::{HashMap, HashSet};
 
@@ -25,11 +28,24 @@ pub struct CommitMonitor {
     search_state: SearchState,
     last_search_commit: chrono::DateTime<Local>,
     run_expensive_next_tick: bool,
+    /// When expired Resources were last purged from the trash.
+    last_trash_purge: Option<std::time::Instant>,
+    /// The purge that runs on a blocking thread, so a new one is not started before it is done.
+    purge_task: Option<actix_web::rt::task::JoinHandle<()>>,
+    /// Where uploaded files are stored, see [crate::files].
+    file_storage: std::sync::Arc<dyn crate::files::FileStorage>,
+    /// Expired sessions of resumable uploads are removed together with unused files.
+    upload_sessions: crate::files::UploadSessions,
+    /// Processed images of destroyed Files are removed.
+    processed_images: crate::files::ProcessedImages,
 }
 
 // Only runs expensive index operation (tantivy) once every x seconds
 const REBUILD_INDEX_TIME: std::time::Duration = std::time::Duration::from_secs(5);
 
+// How often Resources that have been in the trash for longer than the retention period are purged, and unused uploaded files and expired upload sessions are removed
+const PURGE_TRASH_TIME: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
 // Since his Actor only starts once, there is no need to handle its lifecycle
 impl Actor for CommitMonitor {
     type Context = Context<Self>;
@@ -61,11 +77,7 @@ impl Handler<Subscribe> for CommitMonitor {
         }
         match self.store.get_resource(&msg.subject) {
             Ok(resource) => {
-                match atomic_lib::hierarchy::check_read(
-                    &self.store,
-                    &resource,
-                    &ForAgent::AgentSubject(msg.agent.clone()),
-                ) {
+                match atomic_lib::hierarchy::check_read(&self.store, &resource, &msg.agent) {
                     Ok(_explanation) => {
                         let mut set = if let Some(set) = self.subscriptions.get(&msg.subject) {
                             set.clone()
@@ -77,6 +89,10 @@ impl Handler<Subscribe> for CommitMonitor {
                         self.subscriptions.insert(msg.subject.clone(), set);
                     }
                     Err(unauthorized_err) => {
+                        self.store.audit(
+                            AuditEvent::new(AuditEventKind::ReadDenied, &msg.agent, &msg.subject)
+                                .with_message(&unauthorized_err),
+                        );
                         tracing::debug!(
                             "Not allowed {} to subscribe to {}: {}",
                             &msg.agent,
@@ -130,6 +146,20 @@ impl CommitMonitor {
         } else {
             // If there is no new resource, it must have been deleted, so let's remove it from the search index.
             self.search_state.remove_resource(&target)?;
+            // Resized and converted versions of a destroyed File are removed. If other Files share its blob, they are processed again when needed.
+            if let Some(internal_id) = msg
+                .commit_response
+                .resource_old
+                .as_ref()
+                .and_then(|old| old.get(urls::INTERNAL_ID).ok())
+            {
+                let processed_images = self.processed_images.clone();
+                let internal_id = internal_id.to_string();
+                spawn_blocking("removing processed images", move || {
+                    processed_images.remove_variants(&internal_id)?;
+                    Ok(())
+                });
+            }
         }
         Ok(())
     }
@@ -144,6 +174,24 @@ impl CommitMonitor {
                 )
             });
         }
+        let purge_due = self
+            .last_trash_purge
+            .map(|last| last.elapsed() >= PURGE_TRASH_TIME)
+            .unwrap_or(true);
+        let purging = self
+            .purge_task
+            .as_ref()
+            .is_some_and(|task| !task.is_finished());
+        if purge_due && !purging {
+            self.last_trash_purge = Some(std::time::Instant::now());
+            let store = self.store.clone();
+            let file_storage = self.file_storage.clone();
+            let upload_sessions = self.upload_sessions.clone();
+            self.purge_task = Some(spawn_blocking("purging", move || {
+                purge(&store, file_storage.as_ref(), &upload_sessions);
+                Ok(())
+            }));
+        }
     }
 
     /// Run expensive updates that should not be run after every single Commit
@@ -174,8 +222,52 @@ impl Handler<CommitMessage> for CommitMonitor {
     }
 }
 
+/// Runs `work` on a blocking thread, because it uses the store and the [crate::files::FileStorage], which can be a remote bucket.
+/// This keeps the CommitMonitor free to handle Commits and subscriptions.
+fn spawn_blocking(
+    name: &'static str,
+    work: impl FnOnce() -> AtomicServerResult<()> + Send + 'static,
+) -> actix_web::rt::task::JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        match actix_web::web::block(work).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Error while {} in Commit Monitor: {}", name, e),
+            Err(e) => tracing::error!("Error while {} in Commit Monitor: {}", name, e),
+        }
+    })
+}
+
+/// Removes expired Resources from the trash, unused uploaded files and expired upload sessions.
+fn purge(
+    store: &Db,
+    file_storage: &dyn crate::files::FileStorage,
+    upload_sessions: &crate::files::UploadSessions,
+) {
+    match store.purge_expired_trash() {
+        Ok(0) => {}
+        Ok(purged) => tracing::info!("Purged {} Resources from the trash", purged),
+        Err(e) => tracing::error!("Error while purging the trash: {}", e),
+    }
+    match crate::files::collect_garbage(store, file_storage, crate::files::GARBAGE_GRACE_PERIOD) {
+        Ok(0) => {}
+        Ok(removed) => tracing::info!("Removed {} unused uploaded files", removed),
+        Err(e) => tracing::error!("Error while removing unused uploaded files: {}", e),
+    }
+    match upload_sessions.remove_expired(crate::files::SESSION_EXPIRY) {
+        Ok(0) => {}
+        Ok(removed) => tracing::info!("Removed {} expired upload sessions", removed),
+        Err(e) => tracing::error!("Error while removing expired upload sessions: {}", e),
+    }
+}
+
 /// Spawns a commit monitor actor
-pub fn create_commit_monitor(store: Db, search_state: SearchState) -> Addr<CommitMonitor> {
+pub fn create_commit_monitor(
+    store: Db,
+    search_state: SearchState,
+    file_storage: std::sync::Arc<dyn crate::files::FileStorage>,
+    upload_sessions: crate::files::UploadSessions,
+    processed_images: crate::files::ProcessedImages,
+) -> Addr<CommitMonitor> {
     tracing::info!("spawning commit monitor");
     crate::commit_monitor::CommitMonitor::create(|_ctx: &mut Context<CommitMonitor>| {
         CommitMonitor {
@@ -184,6 +276,11 @@ pub fn create_commit_monitor(store: Db, search_state: SearchState) -> Addr<Commi
             search_state,
             run_expensive_next_tick: false,
             last_search_commit: chrono::Local::now(),
+            last_trash_purge: None,
+            purge_task: None,
+            file_storage,
+            upload_sessions,
+            processed_images,
         }
     })
 }
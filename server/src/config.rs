@@ -94,6 +94,58 @@ pub struct Opts {
     /// Introduces random delays in the server, to simulate a slow connection. Useful for testing.
     #[clap(long, env = "ATOMIC_SLOW_MODE")]
     pub slow_mode: bool,
+
+    /// Enables login with an OpenID Connect provider (e.g. your company SSO) at `/oidc/login`. The issuer URL, e.g. `https://accounts.example.com`. Its `/.well-known/openid-configuration` should be reachable.
+    #[clap(long, env = "ATOMIC_OIDC_ISSUER", requires_all = ["oidc_client_id", "oidc_client_secret"])]
+    pub oidc_issuer: Option<String>,
+
+    /// The client ID of atomic-server, as registered at the OpenID Connect provider. Use `{server_url}/oidc/callback` as the redirect URI.
+    #[clap(long, env = "ATOMIC_OIDC_CLIENT_ID")]
+    pub oidc_client_id: Option<String>,
+
+    /// The client secret of atomic-server, as registered at the OpenID Connect provider.
+    #[clap(long, env = "ATOMIC_OIDC_CLIENT_SECRET")]
+    pub oidc_client_secret: Option<String>,
+
+    /// How long (in hours) a session that is created by an OpenID Connect login remains valid.
+    #[clap(long, default_value = "168", env = "ATOMIC_OIDC_SESSION_HOURS")]
+    pub oidc_session_hours: i64,
+
+    /// A snapshot of a Resource is stored for every n-th Commit, so older versions can be constructed without replaying all Commits. `0` disables snapshots.
+    #[clap(long, default_value = "100", env = "ATOMIC_SNAPSHOT_INTERVAL")]
+    pub snapshot_interval: usize,
+
+    /// How many days destroyed Resources are kept in the trash before they are permanently removed. `0` disables the trash, so destroyed Resources are removed immediately.
+    #[clap(long, default_value = "30", env = "ATOMIC_TRASH_RETENTION_DAYS")]
+    pub trash_retention_days: u64,
+
+    /// Drives of other servers that this server mirrors read-only, separated by commas. Their Commits are replicated from the changefeed of the server that hosts them. The default Agent of this server needs read rights for these Drives.
+    #[clap(long = "mirror", env = "ATOMIC_MIRRORS", value_delimiter = ',')]
+    pub mirrors: Vec<String>,
+
+    /// Stores uploaded files in this bucket of an S3-compatible service, instead of in the `uploads` folder of the data directory.
+    #[clap(long, env = "ATOMIC_S3_BUCKET", requires_all = ["s3_access_key_id", "s3_secret_access_key"])]
+    pub s3_bucket: Option<String>,
+
+    /// URL of the S3-compatible service, e.g. `http://localhost:9000` for MinIO. Defaults to AWS S3 in `s3_region`. Buckets are addressed using path-style URLs.
+    #[clap(long, env = "ATOMIC_S3_ENDPOINT")]
+    pub s3_endpoint: Option<String>,
+
+    /// Region of the S3 bucket.
+    #[clap(long, default_value = "us-east-1", env = "ATOMIC_S3_REGION")]
+    pub s3_region: String,
+
+    /// Access key ID for the S3 bucket.
+    #[clap(long, env = "ATOMIC_S3_ACCESS_KEY_ID")]
+    pub s3_access_key_id: Option<String>,
+
+    /// Secret access key for the S3 bucket.
+    #[clap(long, env = "ATOMIC_S3_SECRET_ACCESS_KEY")]
+    pub s3_secret_access_key: Option<String>,
+
+    /// Maximum total size in megabytes of resized and converted images. When it is exceeded, the least recently used ones are removed. `0` removes the limit.
+    #[clap(long, default_value = "1024", env = "ATOMIC_PROCESSED_IMAGES_MAX_MB")]
+    pub processed_images_max_mb: u64,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -122,6 +174,15 @@ pub enum Command {
     /// Import a JSON-AD file or stream to the store. By default creates Commits for all changes, maintaining version history. Use --force to allow importing other types of files.
     #[clap(name = "import", trailing_var_arg = true)]
     Import(ImportOpts),
+    /// Changes the Datatype of a Property, and converts its existing values using signed Commits.
+    #[clap(name = "migrate-property")]
+    MigrateProperty(MigratePropertyOpts),
+    /// Checks all Resources in the store, and reports problems such as invalid values, missing parents and broken links.
+    #[clap(name = "validate")]
+    Validate(ValidateOpts),
+    /// Removes old Commits from version histories. The first and the most recent Commits of every Resource are kept, and remain verifiable.
+    #[clap(name = "compact-history")]
+    CompactHistory(CompactHistoryOpts),
     /// Creates a `.env` file in your current directory that shows various options that you can set.
     #[clap(name = "generate-dotenv")]
     CreateDotEnv,
@@ -158,6 +219,51 @@ pub struct ImportOpts {
     pub force: bool,
 }
 
+#[derive(Parser, Clone, Debug)]
+pub struct MigratePropertyOpts {
+    /// Subject of the Property
+    #[clap(long)]
+    pub property: String,
+    /// URL of the new Datatype, e.g. https://atomicdata.dev/datatypes/integer
+    #[clap(long)]
+    pub datatype: String,
+    /// URL of the item Datatype, if the new Datatype is an Array
+    #[clap(long)]
+    pub item_datatype: Option<String>,
+    /// How existing values are converted: `cast`, `map` or `drop`
+    #[clap(long, default_value = "cast")]
+    pub rule: String,
+    /// JSON file with an object that maps current values to new values. Required for the `map` rule.
+    #[clap(long)]
+    pub map: Option<PathBuf>,
+    /// Only print what would change
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct ValidateOpts {
+    /// Also fetch linked external Resources that are not cached, to check if they exist
+    #[clap(long)]
+    pub fetch_external: bool,
+    /// Print the report as JSON-AD
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct CompactHistoryOpts {
+    /// Only compact the history of this Resource
+    #[clap(long)]
+    pub subject: Option<String>,
+    /// The amount of most recent Commits that are kept for every Resource
+    #[clap(long, default_value = "100")]
+    pub keep_recent: usize,
+    /// Also keep all Commits that are younger than this amount of days
+    #[clap(long)]
+    pub keep_days: Option<i64>,
+}
+
 /// Start atomic-server, oi mate
 #[derive(Parser, Clone, Debug)]
 pub struct ServerOpts {}
@@ -0,0 +1,326 @@
+//! Stores uploaded files as blobs, named after the SHA-256 hash of their contents.
+//! Files with the same contents share one blob, which is removed by [collect_garbage] when no File Resource refers to it anymore.
+//! Blobs are kept in a [FileStorage]: the uploads folder on disk ([LocalStorage]) or an S3-compatible bucket ([S3Storage]).
+
+mod local;
+mod processed;
+mod s3;
+mod sessions;
+
+pub use local::LocalStorage;
+pub use processed::ProcessedImages;
+pub use s3::S3Storage;
+pub use sessions::{UploadSession, UploadSessions, SESSION_EXPIRY};
+
+use std::{
+    collections::HashSet,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use atomic_lib::{storelike::Query, urls, Db, Storelike};
+use base64::Engine;
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+/// Blobs that were written less than this long ago are not collected, because their File Resource may not be saved yet.
+pub const GARBAGE_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+/// Prefix of the keys of blobs that are being uploaded.
+pub const TMP_PREFIX: &str = "tmp/";
+/// Prefix of the keys of resized and converted images.
+pub const PROCESSED_PREFIX: &str = "processed/";
+
+/// Where the blobs of uploaded files and processed images are kept.
+/// Keys are the IDs of blobs, or start with [TMP_PREFIX] or [PROCESSED_PREFIX].
+/// All methods block, so call them using `web::block` in handlers.
+pub trait FileStorage: Send + Sync {
+    /// Starts writing a new object, which is stored by [ObjectWriter::finish].
+    fn create(&self) -> AtomicServerResult<Box<dyn ObjectWriter>>;
+    /// Moves the local file at `path` to `key`.
+    fn put_file(&self, key: &str, path: &Path) -> AtomicServerResult<()>;
+    /// Returns `None` if there is no object at `key`.
+    fn head(&self, key: &str) -> AtomicServerResult<Option<StoredObject>>;
+    /// Reads the object at `key`, or only the bytes in `range`.
+    fn read(
+        &self,
+        key: &str,
+        range: Option<std::ops::Range<u64>>,
+    ) -> AtomicServerResult<Box<dyn Read + Send>>;
+    fn delete(&self, key: &str) -> AtomicServerResult<()>;
+    /// Lists the objects with keys that start with `prefix`, and contain no other `/`.
+    /// The prefix can be a folder like [PROCESSED_PREFIX], or end in the middle of a name.
+    fn list(&self, prefix: &str) -> AtomicServerResult<Vec<StoredObject>>;
+    /// Path of the object on this machine, if it is stored on disk.
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Writes a new object to a [FileStorage].
+pub trait ObjectWriter: Send {
+    fn write(&mut self, data: &[u8]) -> AtomicServerResult<()>;
+    /// Stores the written data at `key`. If there is an object at `key` already, that one is kept, and its modification time is updated.
+    fn finish(self: Box<Self>, key: &str) -> AtomicServerResult<()>;
+}
+
+/// An object in a [FileStorage].
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Uses the S3-compatible bucket if one is configured, and the uploads folder otherwise.
+pub fn init_storage(config: &Config) -> AtomicServerResult<Arc<dyn FileStorage>> {
+    let opts = &config.opts;
+    let Some(bucket) = &opts.s3_bucket else {
+        return Ok(Arc::new(LocalStorage::new(config.uploads_path.clone())));
+    };
+    let endpoint = opts
+        .s3_endpoint
+        .clone()
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", opts.s3_region));
+    let storage = S3Storage::new(
+        &endpoint,
+        bucket,
+        &opts.s3_region,
+        opts.s3_access_key_id.as_deref().unwrap_or_default(),
+        opts.s3_secret_access_key.as_deref().unwrap_or_default(),
+    )?;
+    tracing::info!(
+        "Storing uploaded files in S3 bucket {} at {}",
+        bucket,
+        endpoint
+    );
+    Ok(Arc::new(storage))
+}
+
+/// A stored blob.
+#[derive(Debug, Clone)]
+pub struct Blob {
+    /// Hex encoded SHA-256 hash of the contents. Also the key of the blob, which is used as the `internalId` of the File.
+    pub id: String,
+    /// Base64 encoded SHA-256 hash of the contents, used for the `checksum` of the File.
+    pub checksum: String,
+    pub size: u64,
+}
+
+/// Writes a blob to a [FileStorage] while hashing it. Call [BlobWriter::finish] to store it.
+pub struct BlobWriter {
+    writer: Box<dyn ObjectWriter>,
+    hasher: ring::digest::Context,
+    size: u64,
+}
+
+impl BlobWriter {
+    pub fn new(storage: &dyn FileStorage) -> AtomicServerResult<Self> {
+        Ok(BlobWriter {
+            writer: storage.create()?,
+            hasher: ring::digest::Context::new(&ring::digest::SHA256),
+            size: 0,
+        })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> AtomicServerResult<()> {
+        self.writer.write(data)?;
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Stores the blob under its hash. If a blob with the same contents exists, that one is kept.
+    pub fn finish(self) -> AtomicServerResult<Blob> {
+        let blob = blob_for_digest(self.hasher.finish(), self.size);
+        self.writer.finish(&blob.id)?;
+        Ok(blob)
+    }
+}
+
+fn blob_for_digest(digest: ring::digest::Digest, size: u64) -> Blob {
+    let hash = digest.as_ref();
+    Blob {
+        id: to_hex(hash),
+        checksum: base64::engine::general_purpose::STANDARD.encode(hash),
+        size,
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hashes the contents of `reader`.
+pub fn hash_reader(mut reader: impl Read) -> AtomicServerResult<Blob> {
+    let mut hasher = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok(blob_for_digest(hasher.finish(), size))
+}
+
+/// Checks whether the contents of `reader` still match the `checksum`.
+pub fn verify(reader: impl Read, checksum: &str, name: &str) -> AtomicServerResult<()> {
+    if hash_reader(reader)?.checksum != checksum {
+        return Err(format!(
+            "File {} is corrupted: its contents don't match its checksum",
+            name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Hashes the contents while they are read, and fails at the end if they don't match the `checksum`.
+/// This verifies a file while it is streamed, so it doesn't have to be read twice.
+pub struct VerifyingReader<R> {
+    reader: R,
+    /// `None` once the end has been reached and checked
+    hasher: Option<ring::digest::Context>,
+    size: u64,
+    checksum: String,
+    name: String,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    pub fn new(reader: R, checksum: &str, name: &str) -> Self {
+        VerifyingReader {
+            reader,
+            hasher: Some(ring::digest::Context::new(&ring::digest::SHA256)),
+            size: 0,
+            checksum: checksum.into(),
+            name: name.into(),
+        }
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if read > 0 {
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&buf[..read]);
+                self.size += read as u64;
+            }
+        } else if !buf.is_empty() {
+            if let Some(hasher) = self.hasher.take() {
+                if blob_for_digest(hasher.finish(), self.size).checksum != self.checksum {
+                    let message = format!(
+                        "File {} is corrupted: its contents don't match its checksum",
+                        self.name
+                    );
+                    tracing::error!("{}", message);
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        message,
+                    ));
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// A copy of an object on this machine, for code that needs a path (e.g. image processing).
+/// Objects that are not stored on disk are downloaded to the `tmp` folder of the uploads folder, and removed when this is dropped.
+pub struct LocalFile {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl LocalFile {
+    pub fn get(
+        storage: &dyn FileStorage,
+        key: &str,
+        uploads_path: &Path,
+    ) -> AtomicServerResult<Self> {
+        if let Some(path) = storage.local_path(key) {
+            return Ok(LocalFile {
+                path,
+                temporary: false,
+            });
+        }
+        let local = LocalFile {
+            path: temp_path(uploads_path)?,
+            temporary: true,
+        };
+        let mut file = std::fs::File::create(&local.path)?;
+        std::io::copy(&mut storage.read(key, None)?, &mut file)?;
+        Ok(local)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                tracing::warn!("Could not remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// A new path in the `tmp` folder of the uploads folder.
+pub fn temp_path(uploads_path: &Path) -> AtomicServerResult<PathBuf> {
+    let tmp_folder = uploads_path.join(TMP_PREFIX);
+    std::fs::create_dir_all(&tmp_folder)?;
+    Ok(tmp_folder.join(atomic_lib::utils::random_string(16)))
+}
+
+/// Removes the blobs that are not used by any File Resource, including the ones in the trash, and their processed images.
+/// Blobs and interrupted uploads that are younger than `min_age` are kept.
+/// Returns the amount of removed blobs.
+pub fn collect_garbage(
+    store: &Db,
+    storage: &dyn FileStorage,
+    min_age: std::time::Duration,
+) -> AtomicServerResult<usize> {
+    let is_old = |object: &StoredObject| object.modified.elapsed().unwrap_or_default() >= min_age;
+    let mut trashed = HashSet::new();
+    for entry in store.trash_entries() {
+        if let Some(internal_id) = entry?.propvals.get(urls::INTERNAL_ID) {
+            trashed.insert(internal_id.to_string());
+        }
+    }
+    let mut removed = HashSet::new();
+    for object in storage.list("")? {
+        if !is_old(&object) || trashed.contains(&object.key) {
+            continue;
+        }
+        let used = store
+            .query(&Query::new_prop_val(urls::INTERNAL_ID, &object.key))?
+            .count
+            > 0;
+        if !used {
+            storage.delete(&object.key)?;
+            removed.insert(object.key);
+        }
+    }
+    // Uploads that were interrupted
+    for object in storage.list(TMP_PREFIX)? {
+        if is_old(&object) {
+            storage.delete(&object.key)?;
+        }
+    }
+    if !removed.is_empty() {
+        for object in storage.list(PROCESSED_PREFIX)? {
+            if removed.contains(processed::blob_of_processed(&object.key)) {
+                storage.delete(&object.key)?;
+            }
+        }
+    }
+    Ok(removed.len())
+}
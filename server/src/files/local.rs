@@ -0,0 +1,169 @@
+//! Stores files in the uploads folder on disk.
+
+use std::{
+    io::{Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::errors::AtomicServerResult;
+
+use super::{FileStorage, ObjectWriter, StoredObject, TMP_PREFIX};
+
+/// Keeps objects as files in a folder. Keys with a `/` are stored in a subfolder.
+pub struct LocalStorage {
+    path: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(path: PathBuf) -> Self {
+        LocalStorage { path }
+    }
+
+    fn object_path(&self, key: &str) -> AtomicServerResult<PathBuf> {
+        let relative = Path::new(key);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(format!("Invalid file key {}", key).into());
+        }
+        Ok(self.path.join(relative))
+    }
+}
+
+impl FileStorage for LocalStorage {
+    fn create(&self) -> AtomicServerResult<Box<dyn ObjectWriter>> {
+        let tmp_folder = self.path.join(TMP_PREFIX);
+        std::fs::create_dir_all(&tmp_folder)?;
+        let tmp_path = tmp_folder.join(atomic_lib::utils::random_string(16));
+        Ok(Box::new(LocalWriter {
+            file: Some(std::fs::File::create(&tmp_path)?),
+            tmp_path,
+            path: self.path.clone(),
+        }))
+    }
+
+    fn put_file(&self, key: &str, path: &Path) -> AtomicServerResult<()> {
+        let target = self.object_path(key)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(path, target)?;
+        Ok(())
+    }
+
+    fn head(&self, key: &str) -> AtomicServerResult<Option<StoredObject>> {
+        match std::fs::metadata(self.object_path(key)?) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(StoredObject {
+                key: key.to_string(),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read(
+        &self,
+        key: &str,
+        range: Option<std::ops::Range<u64>>,
+    ) -> AtomicServerResult<Box<dyn Read + Send>> {
+        let mut file = std::fs::File::open(self.object_path(key)?)?;
+        match range {
+            Some(range) => {
+                file.seek(std::io::SeekFrom::Start(range.start))?;
+                Ok(Box::new(file.take(range.end - range.start)))
+            }
+            None => Ok(Box::new(file)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> AtomicServerResult<()> {
+        std::fs::remove_file(self.object_path(key)?)?;
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> AtomicServerResult<Vec<StoredObject>> {
+        // The prefix can end in the middle of a name, like `processed/{blob}`
+        let (folder_key, name_prefix) = match prefix.rsplit_once('/') {
+            Some((folder, name)) => (format!("{}/", folder), name),
+            None => (String::new(), prefix),
+        };
+        let folder = if folder_key.is_empty() {
+            self.path.clone()
+        } else {
+            self.object_path(&folder_key)?
+        };
+        if !folder.exists() {
+            return Ok(Vec::new());
+        }
+        let mut objects = Vec::new();
+        for entry in std::fs::read_dir(folder)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !metadata.is_file() || !name.starts_with(name_prefix) {
+                continue;
+            }
+            objects.push(StoredObject {
+                key: format!("{}{}", folder_key, name),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+        Ok(objects)
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        self.object_path(key).ok()
+    }
+}
+
+/// Writes to a file in the `tmp` folder, which is moved to its key when finished.
+struct LocalWriter {
+    /// `None` once finished
+    file: Option<std::fs::File>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl ObjectWriter for LocalWriter {
+    fn write(&mut self, data: &[u8]) -> AtomicServerResult<()> {
+        self.file
+            .as_mut()
+            .ok_or("Upload is finished")?
+            .write_all(data)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, key: &str) -> AtomicServerResult<()> {
+        let file = self.file.take().ok_or("Upload is finished")?;
+        file.sync_all()?;
+        drop(file);
+        let path = self.path.join(key);
+        if path.exists() {
+            std::fs::remove_file(&self.tmp_path)?;
+            // Touch the blob, so it is not collected before the new File Resource is saved
+            std::fs::File::options()
+                .append(true)
+                .open(&path)?
+                .set_modified(std::time::SystemTime::now())?;
+        } else {
+            std::fs::rename(&self.tmp_path, &path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LocalWriter {
+    fn drop(&mut self) {
+        // The upload was interrupted
+        if self.file.take().is_some() {
+            if let Err(e) = std::fs::remove_file(&self.tmp_path) {
+                tracing::warn!("Could not remove {}: {}", self.tmp_path.display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Keeps track of the processed images (see [crate::images]) in the [FileStorage], and removes the least recently used ones when they take up too much space.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use crate::errors::AtomicServerResult;
+
+use super::{FileStorage, StoredObject, PROCESSED_PREFIX};
+
+/// The processed images in a [FileStorage], with the total size bounded by `max_size`.
+/// The index is built from the storage when it is first used, so after a restart the modification times are used as the last use.
+#[derive(Clone)]
+pub struct ProcessedImages {
+    storage: Arc<dyn FileStorage>,
+    /// In bytes. `None` means processed images are kept until their original is removed.
+    max_size: Option<u64>,
+    index: Arc<Mutex<Option<Index>>>,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    size: u64,
+}
+
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl Index {
+    fn insert(&mut self, key: &str, size: u64) {
+        let entry = Entry {
+            size,
+            last_used: SystemTime::now(),
+        };
+        if let Some(old) = self.entries.insert(key.to_string(), entry) {
+            self.size -= old.size;
+        }
+        self.size += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.entries.remove(key) {
+            self.size -= old.size;
+        }
+    }
+}
+
+/// The key of the blob that a processed image was made from.
+pub fn blob_of_processed(key: &str) -> &str {
+    let name = key.trim_start_matches(PROCESSED_PREFIX);
+    name.split('-').next().unwrap_or_default()
+}
+
+impl ProcessedImages {
+    pub fn new(storage: Arc<dyn FileStorage>, max_size: Option<u64>) -> Self {
+        ProcessedImages {
+            storage,
+            max_size,
+            index: Default::default(),
+        }
+    }
+
+    fn index(&self) -> AtomicServerResult<MutexGuard<'_, Option<Index>>> {
+        let mut index = self.index.lock()?;
+        if index.is_none() {
+            let mut loaded = Index::default();
+            for object in self.storage.list(PROCESSED_PREFIX)? {
+                loaded.size += object.size;
+                loaded.entries.insert(
+                    object.key,
+                    Entry {
+                        size: object.size,
+                        last_used: object.modified,
+                    },
+                );
+            }
+            *index = Some(loaded);
+        }
+        Ok(index)
+    }
+
+    /// Marks a processed image as recently used.
+    pub fn used(&self, object: &StoredObject) -> AtomicServerResult<()> {
+        if let Some(index) = self.index()?.as_mut() {
+            index.insert(&object.key, object.size);
+        }
+        Ok(())
+    }
+
+    /// Registers a processed image that was just stored, and removes the least recently used ones if the maximum size is exceeded.
+    /// Returns the amount of removed images.
+    pub fn add(&self, key: &str, size: u64) -> AtomicServerResult<usize> {
+        let mut guard = self.index()?;
+        let Some(index) = guard.as_mut() else {
+            return Ok(0);
+        };
+        index.insert(key, size);
+        let Some(max_size) = self.max_size else {
+            return Ok(0);
+        };
+        if index.size <= max_size {
+            return Ok(0);
+        }
+        let mut by_last_use: Vec<(SystemTime, String)> = index
+            .entries
+            .iter()
+            .filter(|(other, _)| other.as_str() != key)
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        by_last_use.sort();
+        let mut removed = 0;
+        for (_, old_key) in by_last_use {
+            if index.size <= max_size {
+                break;
+            }
+            // Images that were removed together with their original are not in the storage anymore
+            if let Err(e) = self.storage.delete(&old_key) {
+                tracing::debug!("Could not remove processed image {}: {}", old_key, e);
+            }
+            index.remove(&old_key);
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Removes the processed images of the blob with this `internalId`. Returns the amount of removed images.
+    pub fn remove_variants(&self, internal_id: &str) -> AtomicServerResult<usize> {
+        // Files that were uploaded before they were stored by their hash only have their timestamp in the key
+        let blob = internal_id.split('-').next().unwrap_or_default();
+        let mut removed = 0;
+        // Only the keys that start with the blob are listed, instead of all processed images
+        for object in self
+            .storage
+            .list(&format!("{}{}", PROCESSED_PREFIX, blob))?
+        {
+            if blob_of_processed(&object.key) == blob {
+                self.storage.delete(&object.key)?;
+                if let Some(index) = self.index.lock()?.as_mut() {
+                    index.remove(&object.key);
+                }
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::files::LocalStorage;
+
+    #[test]
+    fn removes_least_recently_used() {
+        let folder = std::env::temp_dir().join(atomic_lib::utils::random_string(10));
+        std::fs::create_dir_all(&folder).unwrap();
+        let storage: Arc<dyn FileStorage> = Arc::new(LocalStorage::new(folder.clone()));
+        let processed = ProcessedImages::new(storage.clone(), Some(25));
+        let store = |name: &str| {
+            let key = format!("{}{}", PROCESSED_PREFIX, name);
+            let path = folder.join(name);
+            std::fs::write(&path, [0; 10]).unwrap();
+            storage.put_file(&key, &path).unwrap();
+            processed.add(&key, 10).unwrap()
+        };
+        assert_eq!(store("a-w1.webp"), 0);
+        assert_eq!(store("b-w1.webp"), 0);
+        let a = storage.head("processed/a-w1.webp").unwrap().unwrap();
+        processed.used(&a).unwrap();
+        // b is the least recently used
+        assert_eq!(store("c-w1.webp"), 1);
+        assert!(storage.head("processed/b-w1.webp").unwrap().is_none());
+        assert!(storage.head("processed/a-w1.webp").unwrap().is_some());
+
+        store("cd-w1.webp");
+        assert_eq!(processed.remove_variants("c").unwrap(), 1);
+        assert!(storage.head("processed/cd-w1.webp").unwrap().is_some());
+        assert_eq!(storage.list(PROCESSED_PREFIX).unwrap().len(), 1);
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Sessions of resumable uploads, see [crate::handlers::resumable_upload].
+//! The received bytes are kept on disk, in the `tmp/sessions` folder of the uploads folder, until the upload is complete.
+//! Then they are moved to the [super::FileStorage] as a blob.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AtomicServerResult;
+
+use super::TMP_PREFIX;
+
+/// Sessions that have not received data for this long are removed.
+pub const SESSION_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A resumable upload of a single file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    pub id: String,
+    /// Subject of the parent of the new File
+    pub parent: String,
+    pub filename: String,
+    /// Size of the complete file in bytes
+    pub length: u64,
+    /// The Agent that created the session. Only this Agent can continue it.
+    pub agent: String,
+    /// Subject of the File, once the upload is complete
+    pub file: Option<String>,
+}
+
+/// The upload sessions on disk.
+/// Every session has a `{id}.json` with the [UploadSession], and a `{id}.part` with the bytes that were received so far.
+#[derive(Clone)]
+pub struct UploadSessions {
+    folder: PathBuf,
+    /// Sessions that are receiving data right now
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Makes sure only one request at a time writes to a session. Released when dropped.
+pub struct SessionLock {
+    id: String,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        if let Ok(mut busy) = self.busy.lock() {
+            busy.remove(&self.id);
+        }
+    }
+}
+
+impl UploadSessions {
+    pub fn new(uploads_path: &Path) -> Self {
+        UploadSessions {
+            folder: uploads_path.join(TMP_PREFIX).join("sessions"),
+            busy: Default::default(),
+        }
+    }
+
+    pub fn create(
+        &self,
+        parent: &str,
+        filename: &str,
+        length: u64,
+        agent: &str,
+    ) -> AtomicServerResult<UploadSession> {
+        std::fs::create_dir_all(&self.folder)?;
+        let session = UploadSession {
+            id: atomic_lib::utils::random_string(24),
+            parent: parent.to_string(),
+            filename: filename.to_string(),
+            length,
+            agent: agent.to_string(),
+            file: None,
+        };
+        std::fs::File::create(self.part_path(&session.id)?)?;
+        self.save(&session)?;
+        Ok(session)
+    }
+
+    pub fn get(&self, id: &str) -> AtomicServerResult<UploadSession> {
+        let json = match std::fs::read(self.json_path(id)?) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(atomic_lib::AtomicError::not_found(format!(
+                    "Upload {} does not exist or has expired",
+                    id
+                ))
+                .into())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&json)
+            .map_err(|e| format!("Could not deserialize upload {}. {}", id, e).into())
+    }
+
+    pub fn save(&self, session: &UploadSession) -> AtomicServerResult<()> {
+        let json = serde_json::to_vec(session)
+            .map_err(|e| format!("Could not serialize upload {}. {}", session.id, e))?;
+        std::fs::write(self.json_path(&session.id)?, json)?;
+        Ok(())
+    }
+
+    /// Amount of bytes that were received. Equals the length once the upload is complete.
+    pub fn offset(&self, session: &UploadSession) -> AtomicServerResult<u64> {
+        if session.file.is_some() {
+            return Ok(session.length);
+        }
+        Ok(std::fs::metadata(self.part_path(&session.id)?)?.len())
+    }
+
+    /// Returns an error if another request is writing to the session.
+    pub fn lock(&self, id: &str) -> AtomicServerResult<SessionLock> {
+        let mut busy = self.busy.lock()?;
+        if !busy.insert(id.to_string()) {
+            return Err(format!("Upload {} is already receiving data", id).into());
+        }
+        Ok(SessionLock {
+            id: id.to_string(),
+            busy: self.busy.clone(),
+        })
+    }
+
+    /// The file with the bytes that were received so far.
+    pub fn part_path(&self, id: &str) -> AtomicServerResult<PathBuf> {
+        Ok(self.folder.join(format!("{}.part", valid_id(id)?)))
+    }
+
+    fn json_path(&self, id: &str) -> AtomicServerResult<PathBuf> {
+        Ok(self.folder.join(format!("{}.json", valid_id(id)?)))
+    }
+
+    /// Removes the session and the bytes that were received.
+    pub fn remove(&self, id: &str) -> AtomicServerResult<()> {
+        for path in [self.part_path(id)?, self.json_path(id)?] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the sessions that have not been used for longer than `max_age`. Returns the amount of removed sessions.
+    pub fn remove_expired(&self, max_age: Duration) -> AtomicServerResult<usize> {
+        if !self.folder.exists() {
+            return Ok(0);
+        }
+        let mut last_used: std::collections::HashMap<String, SystemTime> = Default::default();
+        for entry in std::fs::read_dir(&self.folder)? {
+            let entry = entry?;
+            let path = entry.path();
+            let Some(id) = path.file_stem().map(|id| id.to_string_lossy().to_string()) else {
+                continue;
+            };
+            let modified = entry.metadata()?.modified()?;
+            let used = last_used.entry(id).or_insert(modified);
+            *used = (*used).max(modified);
+        }
+        let mut removed = 0;
+        for (id, used) in last_used {
+            if used.elapsed().unwrap_or_default() >= max_age && !self.busy.lock()?.contains(&id) {
+                self.remove(&id)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// IDs are used in paths, so only allow the characters of [atomic_lib::utils::random_string].
+fn valid_id(id: &str) -> AtomicServerResult<&str> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(atomic_lib::AtomicError::not_found(format!("Invalid upload ID {}", id)).into());
+    }
+    Ok(id)
+}
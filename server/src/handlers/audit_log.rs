@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    audit::{audit_read, AuditFilter},
+    hierarchy::check_write,
+    Storelike,
+};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+#[derive(Deserialize, Debug)]
+pub struct AuditLogParams {
+    pub kind: Option<String>,
+    pub agent: Option<String>,
+    pub subject: Option<String>,
+    pub from: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+/// Returns the matching entries of the audit log as JSON lines.
+/// Only Agents with write rights to the root of the server can read the audit log.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn handle_audit_log(
+    appstate: web::Data<AppState>,
+    params: web::Query<AuditLogParams>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = format!("{}{}", store.get_server_url(), req.uri());
+    let for_agent = get_client_agent(req.headers(), &appstate, subject.clone())?;
+    let root = store.get_resource(store.get_server_url())?;
+    audit_read(
+        store,
+        &subject,
+        &for_agent,
+        check_write(store, &root, &for_agent),
+    )?;
+
+    let filter = AuditFilter {
+        kind: params.kind.as_deref().map(str::parse).transpose()?,
+        agent: params.agent.clone(),
+        subject: params.subject.clone(),
+        from: params.from,
+        until: params.until,
+        limit: params.limit,
+    };
+    let mut body = String::new();
+    for entry in store.query_audit_log(&filter)? {
+        body.push_str(&entry.to_json()?);
+        body.push('\n');
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/jsonl")
+        .body(body))
+}
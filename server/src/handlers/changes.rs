@@ -0,0 +1,84 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    audit::audit_read,
+    changes::ChangeFilter,
+    hierarchy::{check_read, check_write},
+    Storelike,
+};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_WAIT_SECONDS: u64 = 60;
+
+#[derive(Deserialize, Debug)]
+pub struct ChangesParams {
+    pub since: Option<u64>,
+    pub drive: Option<String>,
+    pub class: Option<String>,
+    pub limit: Option<usize>,
+    /// Seconds to wait for new Commits if there are none
+    pub wait: Option<u64>,
+}
+
+/// Returns the applied Commits after the `since` cursor as JSON lines, see [atomic_lib::changes].
+/// Requires read rights for the Drive, or write rights to the root of the server for the whole feed.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn handle_changes(
+    appstate: web::Data<AppState>,
+    params: web::Query<ChangesParams>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = format!("{}{}", store.get_server_url(), req.uri());
+    let for_agent = get_client_agent(req.headers(), &appstate, subject.clone())?;
+    let rights = match &params.drive {
+        Some(drive) => check_read(store, &store.get_resource(drive)?, &for_agent),
+        None => check_write(
+            store,
+            &store.get_resource(store.get_server_url())?,
+            &for_agent,
+        ),
+    };
+    audit_read(store, &subject, &for_agent, rights)?;
+
+    let filter = ChangeFilter {
+        since: params.since,
+        drive: params.drive.clone(),
+        class: params.class.clone(),
+        limit: Some(params.limit.unwrap_or(DEFAULT_LIMIT)),
+    };
+    let mut entries = store.query_changes(&filter)?;
+    // Long-poll: wait for new Commits, and read again when one is applied
+    if let Some(wait) = params.wait {
+        let deadline =
+            std::time::Instant::now() + std::time::Duration::from_secs(wait.min(MAX_WAIT_SECONDS));
+        let mut since = store.last_change_sequence()?.or(params.since);
+        while entries.is_empty() {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let waiting_store = store.clone();
+            let has_new = web::block(move || waiting_store.wait_for_changes(since, remaining))
+                .await
+                .map_err(|e| format!("Failed waiting for changes. {}", e))??;
+            if !has_new {
+                break;
+            }
+            entries = store.query_changes(&filter)?;
+            // New Commits that don't match the filter should not wake us up again
+            since = store.last_change_sequence()?;
+        }
+    }
+
+    let mut body = String::new();
+    for entry in entries {
+        body.push_str(&entry.to_json()?);
+        body.push('\n');
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/jsonl")
+        .body(body))
+}
@@ -28,12 +28,13 @@ pub async fn post_commit(
     }
     let opts = CommitOpts {
         validate_schema: true,
+        validate_links: true,
         validate_signature: true,
         validate_timestamp: true,
         validate_rights: true,
         // https://github.com/atomicdata-dev/atomic-server/issues/412
         validate_previous_commit: false,
-        validate_for_agent: Some(incoming_commit.signer.to_string()),
+        validate_for_agent: Some(incoming_commit.signer.as_str().into()),
         update_index: true,
     };
     let commit_response = store.apply_commit(incoming_commit, &opts)?;
@@ -1,19 +1,49 @@
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
-use actix_files::NamedFile;
-use actix_web::{web, HttpRequest, HttpResponse};
-use atomic_lib::{urls, Resource, Storelike};
-use image::GenericImageView;
-use image::{codecs::avif::AvifEncoder, ImageReader};
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    files::{temp_path, verify, FileStorage, LocalFile, VerifyingReader, PROCESSED_PREFIX},
+    helpers::get_client_agent,
+    images::ImageTransform,
+};
+use actix_files::HttpRange;
+use actix_web::{
+    http::header::{
+        ContentDisposition, DispositionParam, DispositionType, ACCEPT_RANGES, CONTENT_RANGE,
+        CONTENT_TYPE, ETAG, IF_NONE_MATCH, RANGE,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use atomic_lib::{audit::audit_read, urls, Resource, Storelike};
+use futures::Stream;
 use serde::Deserialize;
-use std::{collections::HashSet, io::Write, path::PathBuf};
+use std::{io::Read, path::Path, sync::Arc};
 
+/// Size of the chunks in which files are streamed to the client.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Query parameters for processing images, see [crate::images].
 #[serde_with::serde_as]
 #[serde_with::skip_serializing_none]
 #[derive(Deserialize, Debug)]
 pub struct DownloadParams {
+    /// Quality, 0 to 100
     pub q: Option<f32>,
+    /// Width in CSS pixels
     pub w: Option<u32>,
+    /// Height in CSS pixels
+    pub h: Option<u32>,
+    /// Format: webp, avif, jpeg or png
     pub f: Option<String>,
+    /// How the image fits in the box of `w` and `h`: contain, cover or crop
+    pub fit: Option<String>,
+    /// Horizontal position of the focal point, 0 to 1
+    pub fx: Option<f32>,
+    /// Vertical position of the focal point, 0 to 1
+    pub fy: Option<f32>,
+    /// Device pixel ratio
+    pub dpr: Option<f32>,
+    /// Clockwise rotation in degrees
+    pub rot: Option<u32>,
 }
 
 /// Downloads the File of the Resource that matches the same URL minus the `/download` path.
@@ -39,143 +69,235 @@ pub async fn handle_download(
 
     let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
     tracing::info!("handle_download: {}", subject);
-    let resource = store.get_resource_extended(&subject, false, &for_agent)?;
-    download_file_handler_partial(&resource, &req, &params, &appstate)
+    let resource = audit_read(
+        store,
+        &subject,
+        &for_agent,
+        store.get_resource_extended(&subject, false, &for_agent),
+    )?;
+    download_file_handler_partial(&resource, &req, &params, &appstate).await
 }
 
-pub fn download_file_handler_partial(
+pub async fn download_file_handler_partial(
     resource: &Resource,
     req: &HttpRequest,
     params: &web::Query<DownloadParams>,
     appstate: &AppState,
 ) -> AtomicServerResult<HttpResponse> {
-    let filename = resource
+    let internal_id = resource
         .get(urls::INTERNAL_ID)
         .map_err(|e| format!("Internal ID of file could not be resolved. {}", e))?
         .to_string();
-    let mut file_path = appstate.config.uploads_path.clone();
-    file_path.push(&filename);
+    // Files that were uploaded before they were stored by their hash have no checksum
+    let checksum = resource.get(urls::CHECKSUM).ok().map(|c| c.to_string());
+    let filename = resource
+        .get(urls::FILENAME)
+        .map(|f| f.to_string())
+        .unwrap_or_else(|_| internal_id.clone());
+    let storage = appstate.file_storage.clone();
 
     // No params were given, so we just return the file.
-    if params.q.is_none() && params.w.is_none() && params.f.is_none() {
-        let file = NamedFile::open(file_path)?;
-        return Ok(file.into_response(req));
-    }
-
-    create_processed_folder_if_not_exists(&appstate.config.uploads_path)?;
-    let processed_file_path =
-        build_prossesed_file_path(&filename, params, appstate.config.uploads_path.clone())?;
-
-    if processed_file_path.exists() {
-        let file = NamedFile::open(processed_file_path)?;
-        return Ok(file.into_response(req));
-    }
-
-    if !is_image(&file_path) {
-        return Err("Quality or with parameter are not supported for non image files".into());
-    }
-
-    process_image(&file_path, &processed_file_path, params)?;
-
-    let file = NamedFile::open(processed_file_path)?;
-    Ok(file.into_response(req))
-}
-
-pub fn build_prossesed_file_path(
-    filename: &str,
-    params: &DownloadParams,
-    base_path: PathBuf,
-) -> AtomicServerResult<PathBuf> {
-    let format = get_format(params)?;
-
-    let Some((timestamp, rest)) = filename.split_once('-') else {
-        return Err("Filename does not contain a timestamp.".into());
+    let Some(transform) = ImageTransform::from_params(params)? else {
+        let mimetype = resource
+            .get(urls::MIMETYPE)
+            .map(|m| m.to_string())
+            .unwrap_or_else(|_| "application/octet-stream".into());
+        let object = ObjectResponse {
+            // The blob is named after its hash, so it changes when the contents change
+            etag: checksum.as_ref().map(|_| format!("\"{}\"", internal_id)),
+            key: internal_id,
+            checksum,
+            mimetype,
+            filename,
+        };
+        return object.respond(storage, req).await;
     };
 
-    let mut new_filename = String::new();
-
-    new_filename.push_str(timestamp);
-
-    if let Some(quality) = &params.q {
-        new_filename.push_str(&format!("-q{}", quality));
-    }
-    if let Some(width) = &params.w {
-        new_filename.push_str(&format!("-w{}", width));
+    let key = transform.processed_key(&internal_id);
+    let object = ObjectResponse {
+        etag: checksum
+            .as_ref()
+            .map(|_| format!("\"{}\"", key.trim_start_matches(PROCESSED_PREFIX))),
+        mimetype: transform.format.mimetype().to_string(),
+        filename: Path::new(&filename)
+            .with_extension(transform.format.extension())
+            .to_string_lossy()
+            .to_string(),
+        key,
+        // The original is verified before it is processed
+        checksum: None,
+    };
+    if object.is_not_modified(req) {
+        return object.not_modified();
     }
 
-    new_filename.push_str(&format!("-{}", rest));
-    let mut processed_file_path = base_path.join("processed").join(new_filename);
-    processed_file_path.set_extension(format);
+    let uploads_path = appstate.config.uploads_path.clone();
+    let processed_key = object.key.clone();
+    let processed_images = appstate.processed_images.clone();
+    let processing_storage = storage.clone();
+    web::block(move || {
+        let storage = processing_storage.as_ref();
+        if let Some(processed) = storage.head(&processed_key)? {
+            return processed_images.used(&processed);
+        }
+        let original = LocalFile::get(storage, &internal_id, &uploads_path)?;
+        if let Some(checksum) = &checksum {
+            verify(
+                std::fs::File::open(original.path())?,
+                checksum,
+                &internal_id,
+            )?;
+        }
+        let processed_path = temp_path(&uploads_path)?;
+        if let Err(e) = transform.apply(original.path(), &processed_path) {
+            // The encoder may have created the file already
+            _ = std::fs::remove_file(&processed_path);
+            return Err(e);
+        }
+        let size = std::fs::metadata(&processed_path)?.len();
+        storage.put_file(&processed_key, &processed_path)?;
+        processed_images.add(&processed_key, size)?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Could not process image. {}", e))??;
+
+    object.respond(storage, req).await
+}
 
-    Ok(processed_file_path)
+/// An object in the [FileStorage] that is sent to the client.
+struct ObjectResponse {
+    key: String,
+    /// Verified while the whole object is sent. The response is aborted if it doesn't match.
+    checksum: Option<String>,
+    etag: Option<String>,
+    mimetype: String,
+    filename: String,
 }
 
-fn is_image(file_path: &PathBuf) -> bool {
-    if let Ok(img) = image::open(file_path) {
-        return img.dimensions() > (0, 0);
+impl ObjectResponse {
+    /// Whether the `If-None-Match` header of the request contains the ETag.
+    fn is_not_modified(&self, req: &HttpRequest) -> bool {
+        let Some(etag) = &self.etag else {
+            return false;
+        };
+        req.headers()
+            .get_all(IF_NONE_MATCH)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag)
     }
-    false
-}
 
-fn process_image(
-    file_path: &PathBuf,
-    new_path: &PathBuf,
-    params: &DownloadParams,
-) -> AtomicServerResult<()> {
-    let format = get_format(params)?;
-    let quality = params.q.unwrap_or(100.0).clamp(0.0, 100.0);
-
-    let mut img = ImageReader::open(file_path)?
-        .with_guessed_format()?
-        .decode()
-        .map_err(|e| format!("Failed to decode image: {}", e))?;
-
-    if let Some(width) = &params.w {
-        if *width < img.dimensions().0 {
-            img = img.resize(*width, 10000, image::imageops::FilterType::Lanczos3);
+    fn not_modified(&self) -> AtomicServerResult<HttpResponse> {
+        let mut response = HttpResponse::NotModified();
+        if let Some(etag) = &self.etag {
+            response.insert_header((ETAG, etag.as_str()));
         }
+        Ok(response.finish())
     }
 
-    if format == "webp" {
-        let encoder = webp::Encoder::from_image(&img)?;
-        let webp_image = match params.q {
-            Some(quality) => encoder.encode(quality),
-            None => encoder.encode(75.0),
+    /// Streams the object, or the part of it that is requested in the `Range` header.
+    async fn respond(
+        self,
+        storage: Arc<dyn FileStorage>,
+        req: &HttpRequest,
+    ) -> AtomicServerResult<HttpResponse> {
+        if self.is_not_modified(req) {
+            return self.not_modified();
+        }
+        let key = self.key.clone();
+        let head_storage = storage.clone();
+        let object = web::block(move || head_storage.head(&key))
+            .await
+            .map_err(|e| format!("Could not read file. {}", e))??
+            .ok_or_else(|| format!("File {} is missing", self.key))?;
+
+        let mut response = HttpResponse::Ok();
+        response
+            .insert_header((CONTENT_TYPE, self.mimetype.as_str()))
+            .insert_header((ACCEPT_RANGES, "bytes"))
+            .insert_header(ContentDisposition {
+                disposition: disposition_for(&self.mimetype),
+                parameters: vec![DispositionParam::Filename(self.filename.clone())],
+            });
+        if let Some(etag) = &self.etag {
+            response.insert_header((ETAG, etag.as_str()));
+        }
+        let range = match req.headers().get(RANGE).and_then(|r| r.to_str().ok()) {
+            Some(header) => match HttpRange::parse(header, object.size) {
+                // Only the first range is sent
+                Ok(ranges) => ranges
+                    .first()
+                    .map(|range| range.start..range.start + range.length),
+                Err(_) => {
+                    return Ok(HttpResponse::RangeNotSatisfiable()
+                        .insert_header((CONTENT_RANGE, format!("bytes */{}", object.size)))
+                        .finish())
+                }
+            },
+            None => None,
+        };
+        let length = match &range {
+            Some(range) => {
+                response
+                    .status(actix_web::http::StatusCode::PARTIAL_CONTENT)
+                    .insert_header((
+                        CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", range.start, range.end - 1, object.size),
+                    ));
+                range.end - range.start
+            }
+            None => object.size,
         };
 
-        let mut file = std::fs::File::create(new_path)?;
-        file.write_all(&webp_image)?;
-
-        return Ok(());
-    }
-
-    if format == "avif" {
-        let mut file = std::fs::File::create(new_path)?;
-        let encoder = AvifEncoder::new_with_speed_quality(&mut file, 8, quality as u8);
-        img.write_with_encoder(encoder)
-            .map_err(|e| format!("Failed to encode image: {}", e))?;
-
-        return Ok(());
+        // Parts of a file can't be verified, so only whole files are checked
+        let checksum = self.checksum.filter(|_| range.is_none());
+        let key = self.key;
+        let reader = web::block(move || {
+            let reader = storage.read(&key, range)?;
+            Ok::<_, crate::errors::AtomicServerError>(match checksum {
+                Some(checksum) => {
+                    Box::new(VerifyingReader::new(reader, &checksum, &key)) as Box<dyn Read + Send>
+                }
+                None => reader,
+            })
+        })
+        .await
+        .map_err(|e| format!("Could not read file. {}", e))??;
+
+        Ok(response
+            .no_chunking(length)
+            .streaming(stream_reader(reader)))
     }
-
-    Err(format!("Unsupported format: {}", format).into())
 }
 
-fn create_processed_folder_if_not_exists(base_path: &PathBuf) -> AtomicServerResult<()> {
-    let mut processed_folder = base_path.clone();
-    processed_folder.push("processed");
-    std::fs::create_dir_all(processed_folder)?;
-    Ok(())
-}
-
-fn get_format(params: &DownloadParams) -> AtomicServerResult<String> {
-    let supported_compression_formats: HashSet<String> =
-        HashSet::from_iter(vec!["webp".to_string(), "avif".to_string()]);
-
-    let format = params.f.clone().unwrap_or("webp".to_string());
-    if !supported_compression_formats.contains(&format) {
-        return Err("Unsupported format".into());
+/// Shows media in the browser, and downloads other files.
+fn disposition_for(mimetype: &str) -> DispositionType {
+    match mimetype.split('/').next() {
+        Some("image" | "text" | "video" | "audio") => DispositionType::Inline,
+        _ => DispositionType::Attachment,
     }
+}
 
-    Ok(format)
+/// Reads `reader` in chunks on the blocking thread pool.
+fn stream_reader(
+    reader: Box<dyn Read + Send>,
+) -> impl Stream<Item = Result<web::Bytes, std::io::Error>> {
+    futures::stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        let chunk = web::block(move || {
+            let mut buffer = vec![0; CHUNK_SIZE];
+            let read = reader.read(&mut buffer)?;
+            buffer.truncate(read);
+            Ok::<_, std::io::Error>((buffer, reader))
+        })
+        .await;
+        match chunk {
+            Ok(Ok((buffer, _))) if buffer.is_empty() => None,
+            Ok(Ok((buffer, reader))) => Some((Ok(web::Bytes::from(buffer)), Some(reader))),
+            Ok(Err(e)) => Some((Err(e), None)),
+            Err(e) => Some((Err(std::io::Error::other(e.to_string())), None)),
+        }
+    })
 }
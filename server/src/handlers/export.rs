@@ -259,6 +259,11 @@ impl<'a> CSVExporter<'a> {
                 self.get_name_from_propvals(resource.get_propvals(), resource.get_subject().clone())
             }
             Value::AtomicUrl(subject) => self.get_name_from_subject(subject),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| self.value_to_string(item))
+                .collect::<Vec<String>>()
+                .join(", "),
             _ => value.to_string(),
         }
     }
@@ -294,7 +299,8 @@ impl<'a> CSVExporter<'a> {
 
     fn escape_csv_value(value: String) -> String {
         let no_quotes = value.replace('"', "\"\"");
-        let reg = regex::Regex::new(r"\n|,").unwrap();
+        // Quotes only need to be escaped inside quoted fields, e.g. in JSON values
+        let reg = regex::Regex::new(r#"\n|,|""#).unwrap();
 
         if reg.is_match(&no_quotes) {
             format!("\"{}\"", no_quotes)
@@ -6,7 +6,7 @@ use crate::{
     helpers::{get_client_agent, try_extension},
 };
 use actix_web::{web, HttpResponse};
-use atomic_lib::Storelike;
+use atomic_lib::{audit::audit_read, Storelike};
 use simple_server_timing_header::Timer;
 
 /// Respond to a single resource.
@@ -67,7 +67,12 @@ pub async fn handle_get_resource(
         "no-store, no-cache, must-revalidate, private",
     ));
 
-    let resource = store.get_resource_extended(&subject, false, &for_agent)?;
+    let resource = audit_read(
+        store,
+        &subject,
+        &for_agent,
+        store.get_resource_extended(&subject, false, &for_agent),
+    )?;
     timer.add("get_resource");
 
     let response_body = match content_type {
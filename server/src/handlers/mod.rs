@@ -5,11 +5,16 @@ Most of the logic for routing and handling resides in [atomic_lib::Storelike::ge
 However, some features reside in atomic-server.
 */
 
+pub mod audit_log;
+pub mod changes;
 pub mod commit;
 pub mod download;
 pub mod export;
 pub mod get_resource;
+pub mod oidc;
 pub mod post_resource;
+pub mod replication;
+pub mod resumable_upload;
 pub mod search;
 pub mod single_page_app;
 pub mod upload;
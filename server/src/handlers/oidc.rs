@@ -0,0 +1,126 @@
+//! Login with an OpenID Connect provider. See [crate::oidc].
+
+use actix_web::{http::header, web, HttpResponse};
+use atomic_lib::agents::ForAgent;
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    oidc::{self, OidcSettings, PendingLogin},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct LoginParams {
+    /// Path on this server to return to after logging in, e.g. `/my-drive`
+    pub redirect: Option<String>,
+}
+
+/// Sends the user to the OpenID Connect provider.
+/// If the user is already signed in, the account at the provider will be linked to the current Agent.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn login(
+    appstate: web::Data<AppState>,
+    params: web::Query<LoginParams>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let settings = OidcSettings::from_config(&appstate.config)?;
+    let server_url = &appstate.config.server_url;
+    let link_agent = match get_client_agent(
+        req.headers(),
+        &appstate,
+        format!("{}/oidc/login", server_url),
+    ) {
+        Ok(ForAgent::AgentSubject(subject)) => Some(subject),
+        _ => None,
+    };
+    // Only allow redirects to this server
+    let redirect = match &params.redirect {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") => {
+            format!("{}{}", server_url, path)
+        }
+        _ => server_url.clone(),
+    };
+
+    let issuer = settings.issuer.clone();
+    let metadata = web::block(move || oidc::discover(&issuer))
+        .await
+        .map_err(|e| format!("Could not discover OpenID Connect provider. {}", e))??;
+    let state = atomic_lib::utils::random_string(32);
+    let nonce = atomic_lib::utils::random_string(32);
+    let browser = atomic_lib::utils::random_string(32);
+    let location = oidc::authorization_url(&metadata, &settings, &state, &nonce);
+    appstate.oidc_logins.insert(
+        state,
+        PendingLogin {
+            nonce,
+            link_agent,
+            redirect,
+            browser_hash: oidc::hash_browser_value(&browser),
+            created_at: atomic_lib::utils::now(),
+        },
+    )?;
+    Ok(HttpResponse::Found()
+        .cookie(oidc::login_cookie(&browser, server_url))
+        .insert_header((header::LOCATION, location))
+        .finish())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CallbackParams {
+    pub state: String,
+    pub code: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// The OpenID Connect provider sends the user here after logging in.
+/// Only works in the browser that started the login, see [oidc::LOGIN_COOKIE].
+/// Links the account to an Agent, sets the `atomic_session` cookie and redirects.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn callback(
+    appstate: web::Data<AppState>,
+    params: web::Query<CallbackParams>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let browser = req.cookie(oidc::LOGIN_COOKIE);
+    let pending = appstate
+        .oidc_logins
+        .take(&params.state, browser.as_ref().map(|c| c.value()))?;
+    if let Some(error) = &params.error {
+        return Err(atomic_lib::AtomicError::unauthorized(format!(
+            "Login at the OpenID Connect provider failed: {} {}",
+            error,
+            params.error_description.as_deref().unwrap_or_default()
+        ))
+        .into());
+    }
+    let code = params
+        .code
+        .clone()
+        .ok_or("Missing `code` in OpenID Connect callback")?;
+    let settings = OidcSettings::from_config(&appstate.config)?;
+    let store = appstate.store.clone();
+    let server_url = appstate.config.server_url.clone();
+    let redirect = pending.redirect.clone();
+
+    let cookie = web::block(move || {
+        let metadata = oidc::discover(&settings.issuer)?;
+        let claims = oidc::exchange_code(&metadata, &settings, &code)?;
+        claims.validate(&metadata, &settings, &pending.nonce)?;
+        let agent = oidc::find_or_create_agent(&store, &claims, pending.link_agent.as_deref())?;
+        let session = oidc::create_session(&store, &agent, settings.session_hours)?;
+        oidc::session_cookie(&session, &server_url, settings.session_hours)
+    })
+    .await
+    .map_err(|e| format!("OpenID Connect login failed. {}", e))??;
+
+    let mut login_cookie = oidc::login_cookie("", &appstate.config.server_url);
+    login_cookie.make_removal();
+    Ok(HttpResponse::Found()
+        .cookie(cookie)
+        .cookie(login_cookie)
+        .insert_header((header::LOCATION, redirect))
+        .finish())
+}
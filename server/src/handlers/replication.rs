@@ -0,0 +1,32 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{audit::audit_read, hierarchy::check_write, Storelike};
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+/// Returns the status of the mirrored Drives as JSON lines, see [atomic_lib::replication].
+/// Only Agents with write rights to the root of the server can read it.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn handle_replication(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = format!("{}{}", store.get_server_url(), req.uri());
+    let for_agent = get_client_agent(req.headers(), &appstate, subject.clone())?;
+    let root = store.get_resource(store.get_server_url())?;
+    audit_read(
+        store,
+        &subject,
+        &for_agent,
+        check_write(store, &root, &for_agent),
+    )?;
+
+    let mut body = String::new();
+    for status in store.list_mirrors()? {
+        body.push_str(&status.to_json()?);
+        body.push('\n');
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/jsonl")
+        .body(body))
+}
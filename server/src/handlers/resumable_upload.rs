@@ -0,0 +1,348 @@
+//! Resumable uploads on `/upload`, following the [tus protocol](https://tus.io/protocols/resumable-upload) (version 1.0.0) with the `creation`, `termination` and `expiration` extensions.
+//! - `POST /upload?parent={parent}` with `Tus-Resumable`, `Upload-Length` and a `filename` in `Upload-Metadata` creates an [UploadSession], and returns its URL in `Location`.
+//! - `PATCH /upload/{id}` appends bytes at `Upload-Offset`.
+//! - `HEAD /upload/{id}` returns the `Upload-Offset` from which to continue after a dropped connection.
+//! - `DELETE /upload/{id}` cancels the upload.
+//!
+//! Once all bytes are received, the same File Resource as for multipart uploads is created, and its subject is returned in the [FILE_HEADER].
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use actix_web::{
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE, LOCATION},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use atomic_lib::{
+    agents::ForAgent, audit::audit_write, hierarchy::check_write, AtomicError, Storelike,
+};
+use base64::Engine;
+use futures::StreamExt;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    files::{BlobWriter, UploadSession, SESSION_EXPIRY},
+    handlers::upload::{create_file_resource, UploadQuery},
+    helpers::get_client_agent,
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+/// Content type of the bodies of `PATCH` requests.
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+/// Response header with the subject of the File, once the upload is complete.
+pub const FILE_HEADER: &str = "Atomic-File";
+
+/// Tells tus clients which version and extensions are supported.
+pub async fn upload_options() -> HttpResponse {
+    tus_response(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .finish()
+}
+
+/// Starts a resumable upload. Requires write rights for the parent, like multipart uploads.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn create_upload_session(
+    appstate: web::Data<AppState>,
+    query: web::Query<UploadQuery>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    if let Some(response) = unsupported_version(&req) {
+        return Ok(response);
+    }
+    let store = &appstate.store;
+    let parent = store.get_resource(&query.parent)?;
+    let subject = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let agent = get_client_agent(req.headers(), &appstate, subject)?;
+    audit_write(
+        store,
+        &query.parent,
+        &agent,
+        check_write(store, &parent, &agent),
+    )?;
+
+    let Some(length) = header(&req, "Upload-Length").and_then(|l| l.parse().ok()) else {
+        return Ok(tus_response(StatusCode::BAD_REQUEST).body(
+            "The Upload-Length header is missing or invalid. Deferred lengths are not supported.",
+        ));
+    };
+    let metadata = parse_metadata(header(&req, "Upload-Metadata").unwrap_or_default())?;
+    let filename = metadata
+        .get("filename")
+        .ok_or("Filename is missing. Add it to the Upload-Metadata header.")?;
+    let mut session =
+        appstate
+            .upload_sessions
+            .create(&query.parent, filename, length, &agent.to_string())?;
+
+    let mut response = tus_response(StatusCode::CREATED);
+    response
+        .insert_header((LOCATION, session_url(&appstate, &session.id)))
+        .insert_header(expires_header());
+    // Empty files don't receive any bytes, so they are complete right away
+    if length == 0 {
+        let file = finish_upload(&appstate, &mut session, &agent).await?;
+        response.insert_header((FILE_HEADER, file));
+    }
+    Ok(response.finish())
+}
+
+/// Returns the offset of the upload.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn upload_session_offset(
+    id: web::Path<String>,
+    appstate: web::Data<AppState>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    if let Some(response) = unsupported_version(&req) {
+        return Ok(response);
+    }
+    let (session, _agent) = authorized_session(&appstate, &id, &req)?;
+    let offset = appstate.upload_sessions.offset(&session)?;
+    let mut response = tus_response(StatusCode::OK);
+    response
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .insert_header(("Upload-Length", session.length.to_string()))
+        .insert_header((CACHE_CONTROL, "no-store"));
+    if let Some(file) = &session.file {
+        response.insert_header((FILE_HEADER, file.as_str()));
+    }
+    Ok(response.finish())
+}
+
+/// Appends the body to the upload. The bytes that are received before the connection drops are kept.
+/// Creates the File when the last byte is received.
+#[tracing::instrument(skip(appstate, payload, req))]
+pub async fn upload_chunk(
+    id: web::Path<String>,
+    mut payload: web::Payload,
+    appstate: web::Data<AppState>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    if let Some(response) = unsupported_version(&req) {
+        return Ok(response);
+    }
+    let (mut session, agent) = authorized_session(&appstate, &id, &req)?;
+    if header(&req, CONTENT_TYPE.as_str()) != Some(OFFSET_CONTENT_TYPE) {
+        return Ok(tus_response(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            .body(format!("Content-Type must be {}", OFFSET_CONTENT_TYPE)));
+    }
+    let Some(offset) = header(&req, "Upload-Offset").and_then(|o| o.parse::<u64>().ok()) else {
+        return Ok(tus_response(StatusCode::BAD_REQUEST)
+            .body("The Upload-Offset header is missing or invalid."));
+    };
+    let sessions = &appstate.upload_sessions;
+    let _lock = match sessions.lock(&id) {
+        Ok(lock) => lock,
+        Err(e) => return Ok(tus_response(StatusCode::LOCKED).body(e.to_string())),
+    };
+    let current = sessions.offset(&session)?;
+    if offset != current {
+        return Ok(tus_response(StatusCode::CONFLICT)
+            .insert_header(("Upload-Offset", current.to_string()))
+            .body(format!(
+                "Upload-Offset is {}, but the upload continues at {}",
+                offset, current
+            )));
+    }
+    if let Some(file) = &session.file {
+        return Ok(tus_response(StatusCode::NO_CONTENT)
+            .insert_header(("Upload-Offset", current.to_string()))
+            .insert_header((FILE_HEADER, file.as_str()))
+            .finish());
+    }
+
+    let part_path = sessions.part_path(&id)?;
+    let mut part = web::block(move || std::fs::File::options().append(true).open(part_path))
+        .await
+        .map_err(|e| format!("Could not open upload {}. {}", id, e))??;
+    let mut received = current;
+    let mut interrupted = None;
+    while let Some(chunk) = payload.next().await {
+        let data = match chunk {
+            Ok(data) => data,
+            Err(e) => {
+                interrupted = Some(e.to_string());
+                break;
+            }
+        };
+        if received + data.len() as u64 > session.length {
+            return Ok(tus_response(StatusCode::PAYLOAD_TOO_LARGE)
+                .insert_header(("Upload-Offset", received.to_string()))
+                .body(format!(
+                    "The upload is larger than its Upload-Length of {} bytes",
+                    session.length
+                )));
+        }
+        received += data.len() as u64;
+        part = web::block(move || part.write_all(&data).map(|_| part))
+            .await
+            .map_err(|e| format!("Could not write upload {}. {}", id, e))??;
+    }
+    web::block(move || part.sync_all())
+        .await
+        .map_err(|e| format!("Could not write upload {}. {}", id, e))??;
+    if let Some(e) = interrupted {
+        // The received bytes are kept, so the client can continue from the new offset
+        return Err(format!("Upload {} stopped at offset {}. {}", id, received, e).into());
+    }
+
+    let mut response = tus_response(StatusCode::NO_CONTENT);
+    response
+        .insert_header(("Upload-Offset", received.to_string()))
+        .insert_header(expires_header());
+    if received == session.length {
+        let file = finish_upload(&appstate, &mut session, &agent).await?;
+        response.insert_header((FILE_HEADER, file));
+    }
+    Ok(response.finish())
+}
+
+/// Cancels the upload and removes the received bytes.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn delete_upload_session(
+    id: web::Path<String>,
+    appstate: web::Data<AppState>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    if let Some(response) = unsupported_version(&req) {
+        return Ok(response);
+    }
+    authorized_session(&appstate, &id, &req)?;
+    let _lock = match appstate.upload_sessions.lock(&id) {
+        Ok(lock) => lock,
+        Err(e) => return Ok(tus_response(StatusCode::LOCKED).body(e.to_string())),
+    };
+    appstate.upload_sessions.remove(&id)?;
+    Ok(tus_response(StatusCode::NO_CONTENT).finish())
+}
+
+/// Stores the received bytes as a blob, and saves the File Resource. Returns the subject of the File.
+async fn finish_upload(
+    appstate: &AppState,
+    session: &mut UploadSession,
+    agent: &ForAgent,
+) -> AtomicServerResult<String> {
+    let store = &appstate.store;
+    // The rights of the Agent may have changed since the upload started
+    let parent = store.get_resource(&session.parent)?;
+    audit_write(
+        store,
+        &session.parent,
+        agent,
+        check_write(store, &parent, agent),
+    )?;
+
+    let storage = appstate.file_storage.clone();
+    let part_path = appstate.upload_sessions.part_path(&session.id)?;
+    let blob = web::block(move || {
+        let mut writer = BlobWriter::new(storage.as_ref())?;
+        let mut part = std::fs::File::open(part_path)?;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = part.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            writer.write(&buffer[..read])?;
+        }
+        writer.finish()
+    })
+    .await
+    .map_err(|e| format!("Could not store upload {}. {}", session.id, e))??;
+
+    let mut resource =
+        create_file_resource(appstate, &session.parent, &session.filename, &blob).await?;
+    resource.save(store)?;
+    let subject = resource.get_subject().to_string();
+    session.file = Some(subject.clone());
+    appstate.upload_sessions.save(session)?;
+    std::fs::remove_file(appstate.upload_sessions.part_path(&session.id)?)?;
+    Ok(subject)
+}
+
+/// Returns the session, if the request is made by the Agent that created it.
+/// Requests are signed for the URL of the session.
+fn authorized_session(
+    appstate: &AppState,
+    id: &str,
+    req: &HttpRequest,
+) -> AtomicServerResult<(UploadSession, ForAgent)> {
+    let session = appstate.upload_sessions.get(id)?;
+    let agent = get_client_agent(req.headers(), appstate, session_url(appstate, id))?;
+    if agent.to_string() != session.agent {
+        return Err(AtomicError::unauthorized(format!(
+            "Upload {} was started by another Agent",
+            id
+        ))
+        .into());
+    }
+    Ok((session, agent))
+}
+
+fn session_url(appstate: &AppState, id: &str) -> String {
+    format!("{}/upload/{}", appstate.config.server_url, id)
+}
+
+fn tus_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("Tus-Resumable", TUS_VERSION));
+    response
+}
+
+/// Rejects requests for other versions of the tus protocol.
+fn unsupported_version(req: &HttpRequest) -> Option<HttpResponse> {
+    match header(req, "Tus-Resumable") {
+        Some(version) if version != TUS_VERSION => Some(
+            tus_response(StatusCode::PRECONDITION_FAILED)
+                .insert_header(("Tus-Version", TUS_VERSION))
+                .finish(),
+        ),
+        _ => None,
+    }
+}
+
+/// When the session expires if it receives no more bytes.
+fn expires_header() -> (&'static str, String) {
+    let expires =
+        chrono::DateTime::<chrono::Utc>::from(std::time::SystemTime::now() + SESSION_EXPIRY);
+    (
+        "Upload-Expires",
+        expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+    )
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Parses `Upload-Metadata`: comma separated keys, each followed by a space and a Base64 encoded value.
+fn parse_metadata(header: &str) -> AtomicServerResult<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for pair in header.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let value = base64::engine::general_purpose::STANDARD
+            .decode(value.trim())
+            .map_err(|e| format!("Invalid Upload-Metadata value for {}. {}", key, e))?;
+        let value = String::from_utf8(value)
+            .map_err(|e| format!("Invalid Upload-Metadata value for {}. {}", key, e))?;
+        metadata.insert(key.to_string(), value);
+    }
+    Ok(metadata)
+}
@@ -1,24 +1,31 @@
-use std::{ffi::OsStr, io::Write, path::Path};
+use std::{ffi::OsStr, path::Path};
 
 use actix_multipart::{Field, Multipart};
 use actix_web::{web, HttpResponse};
-use atomic_lib::{hierarchy::check_write, urls, utils::now, Resource, Storelike, Value};
+use atomic_lib::{
+    audit::audit_write, hierarchy::check_write, urls, utils::now, Resource, Storelike, Value,
+};
 use futures::{StreamExt, TryStreamExt};
 use image::GenericImageView;
 use serde::Deserialize;
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    files::{Blob, BlobWriter, LocalFile},
+    helpers::get_client_agent,
+};
 
 #[derive(Deserialize, Debug)]
 pub struct UploadQuery {
-    parent: String,
+    pub parent: String,
 }
 
 /// Allows the user to upload files tot the `/upload` endpoint.
 /// A parent Query parameter is required for checking rights and for placing the file in a Hierarchy.
 /// Creates new File resources for every submitted file.
 /// Submission is done using multipart/form-data.
-/// The file is stored in the `/uploads` directory.
+/// The file is stored in the [crate::files::FileStorage], named after its hash, see [crate::files].
 #[tracing::instrument(skip(appstate, req, body))]
 pub async fn upload_handler(
     mut body: Multipart,
@@ -37,7 +44,12 @@ pub async fn upload_handler(
             .ok_or("Path must be given")?
     );
     let agent = get_client_agent(req.headers(), &appstate, subject)?;
-    check_write(store, &parent, &agent)?;
+    audit_write(
+        store,
+        &query.parent,
+        &agent,
+        check_write(store, &parent, &agent),
+    )?;
 
     let mut created_resources: Vec<Resource> = Vec::new();
 
@@ -59,12 +71,38 @@ async fn save_file_and_create_resource(
     appstate: &web::Data<AppState>,
     parent: &str,
 ) -> AtomicServerResult<Resource> {
-    let store = &appstate.store;
     let content_type = field.content_disposition().clone();
     let filename = content_type.get_filename().ok_or("Filename is missing")?;
 
-    std::fs::create_dir_all(&appstate.config.uploads_path)?;
+    let storage = appstate.file_storage.clone();
+    let mut writer = web::block(move || BlobWriter::new(storage.as_ref()))
+        .await
+        .map_err(|e| format!("Could not store file. {}", e))??;
 
+    // Field in turn is stream of *Bytes* object
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|e| format!("Error while reading multipart data. {}", e))?;
+        // Remote storage is written to over the network, so don't block the server
+        writer = web::block(move || writer.write(&data).map(|_| writer))
+            .await
+            .map_err(|e| format!("Could not store file. {}", e))??;
+    }
+    let blob = web::block(move || writer.finish())
+        .await
+        .map_err(|e| format!("Could not store file. {}", e))??;
+
+    create_file_resource(appstate, parent, filename, &blob).await
+}
+
+/// Creates (but does not save) the File Resource for a stored [Blob].
+/// Used by multipart uploads and by [crate::handlers::resumable_upload].
+pub async fn create_file_resource(
+    appstate: &AppState,
+    parent: &str,
+    filename: &str,
+    blob: &Blob,
+) -> AtomicServerResult<Resource> {
+    let store = &appstate.store;
     let file_id = format!(
         "{}-{}",
         now(),
@@ -73,23 +111,7 @@ async fn save_file_and_create_resource(
             .replace(' ', "-")
     );
 
-    let mut file_path = appstate.config.uploads_path.clone();
-    file_path.push(&file_id);
-
-    let mut file = std::fs::File::create(&file_path)?;
-
-    // Field in turn is stream of *Bytes* object
-    while let Some(chunk) = field.next().await {
-        let data = chunk.map_err(|e| format!("Error while reading multipart data. {}", e))?;
-        // TODO: Update a SHA256 hash here for checksum
-        file.write_all(&data)?;
-    }
-
-    let byte_count: i64 = file
-        .metadata()?
-        .len()
-        .try_into()
-        .map_err(|_e| "Too large")?;
+    let byte_count: i64 = blob.size.try_into().map_err(|_e| "Too large")?;
 
     let mimetype = guess_mime_for_filename(filename);
     let subject_path = format!("files/{}", urlencoding::encode(&file_id));
@@ -100,15 +122,28 @@ async fn save_file_and_create_resource(
     resource
         .set_subject(new_subject)
         .set_string(urls::PARENT.into(), parent, store)?
-        .set_string(urls::INTERNAL_ID.into(), &file_id, store)?
+        .set_string(urls::INTERNAL_ID.into(), &blob.id, store)?
+        .set_string(urls::CHECKSUM.into(), &blob.checksum, store)?
         .set(urls::FILESIZE.into(), Value::Integer(byte_count), store)?
         .set_string(urls::MIMETYPE.into(), &mimetype, store)?
         .set_string(urls::FILENAME.into(), filename, store)?
         .set_string(urls::DOWNLOAD_URL.into(), &download_url, store)?;
 
     if mimetype.starts_with("image/") {
-        if let Ok(img) = image::ImageReader::open(&file_path)?.decode() {
-            let (width, height) = img.dimensions();
+        let storage = appstate.file_storage.clone();
+        let uploads_path = appstate.config.uploads_path.clone();
+        let blob_id = blob.id.clone();
+        let dimensions = web::block(move || {
+            let file = LocalFile::get(storage.as_ref(), &blob_id, &uploads_path)?;
+            let dimensions = image::ImageReader::open(file.path())?
+                .decode()
+                .ok()
+                .map(|img| img.dimensions());
+            AtomicServerResult::Ok(dimensions)
+        })
+        .await
+        .map_err(|e| format!("Could not read image. {}", e))??;
+        if let Some((width, height)) = dimensions {
             resource
                 .set(
                     urls::IMAGE_WIDTH.into(),
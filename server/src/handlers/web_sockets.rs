@@ -11,6 +11,7 @@ use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
 use atomic_lib::{
     agents::ForAgent,
+    audit::audit_read,
     authentication::{get_agent_from_auth_values_and_check, AuthValues},
     errors::AtomicResult,
     Db, Storelike,
@@ -112,7 +113,7 @@ fn handle_ws_message(
                             .do_send(crate::actor_messages::Subscribe {
                                 addr: ctx.address(),
                                 subject: subject.to_string(),
-                                agent: conn.agent.to_string(),
+                                agent: conn.agent.clone(),
                             });
                         conn.subscribed.insert(subject.into());
                         Ok(())
@@ -132,10 +133,13 @@ fn handle_ws_message(
                 s if s.starts_with("GET ") => {
                     let mut parts = s.split("GET ");
                     if let Some(subject) = parts.nth(1) {
-                        match conn
-                            .store
-                            .get_resource_extended(subject, false, &conn.agent)
-                        {
+                        match audit_read(
+                            &conn.store,
+                            subject,
+                            &conn.agent,
+                            conn.store
+                                .get_resource_extended(subject, false, &conn.agent),
+                        ) {
                             Ok(r) => {
                                 let serialized =
                                     r.to_json_ad().expect("Can't serialize Resource to JSON-AD");
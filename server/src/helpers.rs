@@ -31,6 +31,14 @@ pub fn get_auth_headers(
     let signature = map.get("x-atomic-signature");
     let timestamp = map.get("x-atomic-timestamp");
     let agent = map.get("x-atomic-agent");
+    let delegation = match map.get("x-atomic-delegation") {
+        Some(d) => Some(
+            d.to_str()
+                .map_err(|_e| "Only string headers allowed")?
+                .to_string(),
+        ),
+        None => None,
+    };
     match (public_key, signature, timestamp, agent) {
         (Some(pk), Some(sig), Some(ts), Some(a)) => Ok(Some(AuthValues {
             public_key: pk
@@ -51,6 +59,7 @@ pub fn get_auth_headers(
                 .parse::<i64>()
                 .map_err(|_e| "Timestamp must be a number (milliseconds since unix epoch)")?,
             requested_subject,
+            delegation,
         })),
         (None, None, None, None) => Ok(None),
         _missing => Err("Missing authentication headers. You need `x-atomic-public-key`, `x-atomic-signature`, `x-atomic-agent` and `x-atomic-timestamp` for authentication checks.".into()),
@@ -0,0 +1,523 @@
+//! Resizes, crops, rotates and converts uploaded images, for the query parameters of `/download`.
+//! The EXIF orientation of the original is applied first, so photos from cameras and phones end up the right way up.
+//! Processed images are encoded from the pixels of the original, so they never contain its EXIF metadata (e.g. the location where a photo was taken).
+//! They are stored in the [crate::files::FileStorage] and removed by [crate::files::ProcessedImages] when they have not been used for a while.
+
+use std::{
+    io::{Cursor, Write},
+    path::Path,
+};
+
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    DynamicImage, GenericImageView, ImageReader,
+};
+
+use crate::{
+    errors::AtomicServerResult, files::PROCESSED_PREFIX, handlers::download::DownloadParams,
+};
+
+/// Quality used to encode WebP, AVIF and JPEG images when `q` is not given.
+const DEFAULT_QUALITY: f32 = 75.0;
+/// Highest supported device pixel ratio.
+const MAX_DPR: f32 = 4.0;
+/// EXIF tag that describes how the camera was held.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Webp,
+    Avif,
+    Jpeg,
+    Png,
+}
+
+impl Format {
+    fn parse(format: &str) -> AtomicServerResult<Self> {
+        match format {
+            "webp" => Ok(Format::Webp),
+            "avif" => Ok(Format::Avif),
+            "jpeg" | "jpg" => Ok(Format::Jpeg),
+            "png" => Ok(Format::Png),
+            other => Err(format!(
+                "Unsupported format: {}. Use webp, avif, jpeg or png.",
+                other
+            )
+            .into()),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+            Format::Jpeg => "jpg",
+            Format::Png => "png",
+        }
+    }
+
+    pub fn mimetype(&self) -> &'static str {
+        match self {
+            Format::Webp => "image/webp",
+            Format::Avif => "image/avif",
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+        }
+    }
+}
+
+/// How an image is fitted in the box of the `w` and `h` parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// Scales the image to fit inside the box, keeping its aspect ratio.
+    Contain,
+    /// Scales the image to fill the box, and crops what falls outside of it around the focal point.
+    Cover,
+    /// Cuts the box out of the image around the focal point, without scaling.
+    Crop,
+}
+
+impl Fit {
+    fn parse(fit: &str) -> AtomicServerResult<Self> {
+        match fit {
+            "contain" => Ok(Fit::Contain),
+            "cover" => Ok(Fit::Cover),
+            "crop" => Ok(Fit::Crop),
+            other => Err(format!("Unsupported fit: {}. Use contain, cover or crop.", other).into()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Fit::Contain => "contain",
+            Fit::Cover => "cover",
+            Fit::Crop => "crop",
+        }
+    }
+}
+
+/// The validated [DownloadParams] of an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTransform {
+    pub format: Format,
+    quality: Option<f32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    fit: Fit,
+    /// Point that stays in view when cropping, as fractions of the width and height.
+    focus: (f32, f32),
+    /// Device pixel ratio, multiplies the width and height.
+    dpr: f32,
+    /// Clockwise rotation in degrees, applied after the EXIF orientation.
+    rotation: u32,
+}
+
+impl ImageTransform {
+    /// Returns `None` if no image parameters are given, so the original file should be sent.
+    pub fn from_params(params: &DownloadParams) -> AtomicServerResult<Option<Self>> {
+        let DownloadParams {
+            q,
+            w,
+            h,
+            f,
+            fit,
+            fx,
+            fy,
+            dpr,
+            rot,
+        } = params;
+        if q.is_none()
+            && w.is_none()
+            && h.is_none()
+            && f.is_none()
+            && fit.is_none()
+            && fx.is_none()
+            && fy.is_none()
+            && dpr.is_none()
+            && rot.is_none()
+        {
+            return Ok(None);
+        }
+        if *w == Some(0) || *h == Some(0) {
+            return Err("Width and height must be larger than 0".into());
+        }
+        let focus_fraction = |value: &Option<f32>| match value {
+            Some(value) if !(0.0..=1.0).contains(value) => {
+                Err("Focal point must be between 0 and 1".to_string())
+            }
+            Some(value) => Ok(*value),
+            None => Ok(0.5),
+        };
+        let dpr = dpr.unwrap_or(1.0);
+        if !(dpr > 0.0 && dpr <= MAX_DPR) {
+            return Err(format!("Device pixel ratio must be between 0 and {}", MAX_DPR).into());
+        }
+        let rotation = rot.unwrap_or(0);
+        if ![0, 90, 180, 270].contains(&rotation) {
+            return Err("Rotation must be 0, 90, 180 or 270".into());
+        }
+        Ok(Some(ImageTransform {
+            format: Format::parse(f.as_deref().unwrap_or("webp"))?,
+            quality: q.map(|q| q.clamp(0.0, 100.0)),
+            width: *w,
+            height: *h,
+            fit: fit
+                .as_deref()
+                .map(Fit::parse)
+                .transpose()?
+                .unwrap_or(Fit::Contain),
+            focus: (focus_fraction(fx)?, focus_fraction(fy)?),
+            dpr,
+            rotation,
+        }))
+    }
+
+    /// Key of the processed image in the [crate::files::FileStorage].
+    /// Starts with the `internalId` of the original, so [crate::files::collect_garbage] can find the processed images of a blob.
+    pub fn processed_key(&self, internal_id: &str) -> String {
+        // Files that were uploaded before they were stored by their hash start with a timestamp
+        let (prefix, rest) = match internal_id.split_once('-') {
+            Some((timestamp, rest)) => (timestamp, Some(rest)),
+            None => (internal_id, None),
+        };
+
+        let mut name = prefix.to_string();
+        if let Some(quality) = self.quality {
+            name.push_str(&format!("-q{}", quality));
+        }
+        if let Some(width) = self.width {
+            name.push_str(&format!("-w{}", width));
+        }
+        if let Some(height) = self.height {
+            name.push_str(&format!("-h{}", height));
+        }
+        if self.fit != Fit::Contain {
+            name.push_str(&format!(
+                "-{}-fx{}-fy{}",
+                self.fit.name(),
+                self.focus.0,
+                self.focus.1
+            ));
+        }
+        if self.dpr != 1.0 {
+            name.push_str(&format!("-dpr{}", self.dpr));
+        }
+        if self.rotation != 0 {
+            name.push_str(&format!("-r{}", self.rotation));
+        }
+        if let Some(rest) = rest {
+            let stem = Path::new(rest)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            name.push_str(&format!("-{}", stem));
+        }
+        format!("{}{}.{}", PROCESSED_PREFIX, name, self.format.extension())
+    }
+
+    /// Processes the image at `input` and writes the result to `output`.
+    pub fn apply(&self, input: &Path, output: &Path) -> AtomicServerResult<()> {
+        let bytes = std::fs::read(input)?;
+        let img = ImageReader::new(Cursor::new(&bytes))
+            .with_guessed_format()?
+            .decode()
+            .map_err(|e| format!("Failed to decode image: {}", e))?;
+        let img = apply_orientation(img, exif_orientation(&bytes).unwrap_or(1));
+        let img = match self.rotation {
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            _ => img,
+        };
+        self.encode(&self.resize(img), output)
+    }
+
+    fn resize(&self, img: DynamicImage) -> DynamicImage {
+        let (width, height) = img.dimensions();
+        let scale =
+            |size: Option<u32>| size.map(|size| ((size as f32 * self.dpr).round() as u32).max(1));
+        let (box_width, box_height) = (scale(self.width), scale(self.height));
+        if box_width.is_none() && box_height.is_none() {
+            return img;
+        }
+        // Images are never enlarged
+        let box_width = box_width.unwrap_or(width);
+        let box_height = box_height.unwrap_or(height);
+        match self.fit {
+            Fit::Contain => {
+                if box_width >= width && box_height >= height {
+                    return img;
+                }
+                img.resize(
+                    box_width.min(width),
+                    box_height.min(height),
+                    FilterType::Lanczos3,
+                )
+            }
+            Fit::Cover => {
+                let ratio = f64::max(
+                    box_width as f64 / width as f64,
+                    box_height as f64 / height as f64,
+                );
+                if ratio >= 1.0 {
+                    // The image is smaller than the box, so crop the largest part of it with the aspect ratio of the box
+                    let crop_width = ((box_width as f64 / ratio).round() as u32).clamp(1, width);
+                    let crop_height = ((box_height as f64 / ratio).round() as u32).clamp(1, height);
+                    return self.crop_at_focus(&img, crop_width, crop_height);
+                }
+                let scaled = img.resize_exact(
+                    ((width as f64 * ratio).round() as u32).max(box_width),
+                    ((height as f64 * ratio).round() as u32).max(box_height),
+                    FilterType::Lanczos3,
+                );
+                self.crop_at_focus(&scaled, box_width, box_height)
+            }
+            Fit::Crop => self.crop_at_focus(&img, box_width.min(width), box_height.min(height)),
+        }
+    }
+
+    /// Cuts a `width` by `height` part out of `img`, with the focal point as close to its center as possible.
+    fn crop_at_focus(&self, img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let start = |size: u32, crop: u32, focus: f32| {
+            let center = (size as f32 * focus).round() as u32;
+            center.saturating_sub(crop / 2).min(size - crop)
+        };
+        let (img_width, img_height) = img.dimensions();
+        img.crop_imm(
+            start(img_width, width, self.focus.0),
+            start(img_height, height, self.focus.1),
+            width,
+            height,
+        )
+    }
+
+    fn encode(&self, img: &DynamicImage, output: &Path) -> AtomicServerResult<()> {
+        let quality = self.quality.unwrap_or(DEFAULT_QUALITY);
+        let mut file = std::io::BufWriter::new(std::fs::File::create(output)?);
+        match self.format {
+            Format::Webp => {
+                // The WebP encoder only supports 8 bit RGB and RGBA
+                let img = if img.color().has_alpha() {
+                    DynamicImage::ImageRgba8(img.to_rgba8())
+                } else {
+                    DynamicImage::ImageRgb8(img.to_rgb8())
+                };
+                let encoder = webp::Encoder::from_image(&img)?;
+                file.write_all(&encoder.encode(quality))?;
+                Ok(())
+            }
+            Format::Avif => img.write_with_encoder(AvifEncoder::new_with_speed_quality(
+                &mut file,
+                8,
+                quality as u8,
+            )),
+            // JPEG has no alpha channel
+            Format::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(
+                JpegEncoder::new_with_quality(&mut file, quality.max(1.0) as u8),
+            ),
+            Format::Png => img.write_with_encoder(PngEncoder::new(&mut file)),
+        }
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Turns the image upright, according to its EXIF `orientation` (1 to 8).
+fn apply_orientation(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Reads the EXIF orientation of a JPEG, PNG or WebP image.
+fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    let tiff = find_exif(bytes)?;
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| {
+        let bytes: [u8; 2] = tiff.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |pos: usize| {
+        let bytes: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    // The orientation is in the first IFD, which consists of 12 byte entries
+    let ifd = u32_at(4)? as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|entry| u16_at(*entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Finds the TIFF structure that contains the EXIF metadata.
+fn find_exif(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // JPEG: the metadata is in an APP1 segment, before the image data
+        let mut pos = 2;
+        loop {
+            let marker = *bytes.get(pos + 1)?;
+            if bytes[pos] != 0xFF || marker == 0xDA || marker == 0xD9 {
+                return None;
+            }
+            let length = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+            let segment = bytes.get(pos + 4..pos + 2 + length)?;
+            if marker == 0xE1 {
+                if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                    return Some(tiff);
+                }
+            }
+            pos += 2 + length;
+        }
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // PNG: the metadata is in an eXIf chunk
+        let mut pos = 8;
+        loop {
+            let length = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+            let data = bytes.get(pos + 8..pos + 8 + length)?;
+            if bytes.get(pos + 4..pos + 8)? == b"eXIf" {
+                return Some(data);
+            }
+            // Length, type, data and CRC
+            pos += 12 + length;
+        }
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12)? == b"WEBP" {
+        // WebP: the metadata is in an EXIF chunk
+        let mut pos = 12;
+        loop {
+            let length = u32::from_le_bytes(bytes.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+            let data = bytes.get(pos + 8..pos + 8 + length)?;
+            if bytes.get(pos..pos + 4)? == b"EXIF" {
+                return Some(data.strip_prefix(b"Exif\0\0").unwrap_or(data));
+            }
+            // Chunks are padded to an even length
+            pos += 8 + length + length % 2;
+        }
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transform(query: &str) -> ImageTransform {
+        let params = actix_web::web::Query::<DownloadParams>::from_query(query).unwrap();
+        ImageTransform::from_params(&params).unwrap().unwrap()
+    }
+
+    /// A JPEG of `width` by `height` pixels, with an EXIF orientation.
+    fn jpeg_with_orientation(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        // Little endian TIFF header, followed by an IFD with a single entry
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&ORIENTATION_TAG.to_le_bytes());
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend_from_slice(&tiff);
+        jpeg.splice(2..2, segment);
+        jpeg
+    }
+
+    #[test]
+    fn reads_exif_orientation() {
+        assert_eq!(exif_orientation(&jpeg_with_orientation(4, 2, 6)), Some(6));
+        assert_eq!(exif_orientation(&jpeg_with_orientation(4, 2, 9)), None);
+        assert_eq!(exif_orientation(b"not an image"), None);
+    }
+
+    #[test]
+    fn applies_exif_orientation_and_strips_metadata() {
+        let folder = std::env::temp_dir().join(atomic_lib::utils::random_string(10));
+        std::fs::create_dir_all(&folder).unwrap();
+        let input = folder.join("rotated.jpg");
+        let output = folder.join("upright.jpg");
+        std::fs::write(&input, jpeg_with_orientation(40, 20, 6)).unwrap();
+        transform("f=jpeg").apply(&input, &output).unwrap();
+        let processed = std::fs::read(&output).unwrap();
+        assert_eq!(exif_orientation(&processed), None);
+        let img = image::load_from_memory(&processed).unwrap();
+        assert_eq!(img.dimensions(), (20, 40));
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn fits_images() {
+        let img = || DynamicImage::new_rgba8(400, 200);
+        let dimensions = |query: &str| transform(query).resize(img()).dimensions();
+        assert_eq!(dimensions("w=100"), (100, 50));
+        assert_eq!(dimensions("h=100"), (200, 100));
+        assert_eq!(dimensions("w=100&h=100"), (100, 50));
+        assert_eq!(dimensions("w=100&dpr=2"), (200, 100));
+        assert_eq!(dimensions("w=100&h=100&fit=cover"), (100, 100));
+        assert_eq!(dimensions("w=100&h=100&fit=crop&fx=0"), (100, 100));
+        // Images are never enlarged
+        assert_eq!(dimensions("w=1000"), (400, 200));
+        assert_eq!(dimensions("w=1000&h=1000&fit=cover"), (200, 200));
+
+        // The focal point stays in view
+        let mut img = img();
+        img.as_mut_rgba8()
+            .unwrap()
+            .put_pixel(390, 10, image::Rgba([255, 0, 0, 255]));
+        let cropped = transform("w=50&h=50&fit=crop&fx=1&fy=0").resize(img);
+        assert_eq!(cropped.get_pixel(40, 10), image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        for query in ["f=gif", "w=0", "fit=stretch", "fx=2", "dpr=5", "rot=45"] {
+            let params = actix_web::web::Query::<DownloadParams>::from_query(query).unwrap();
+            assert!(ImageTransform::from_params(&params).is_err(), "{}", query);
+        }
+        let params = actix_web::web::Query::<DownloadParams>::from_query("").unwrap();
+        assert!(ImageTransform::from_params(&params).unwrap().is_none());
+    }
+
+    #[test]
+    fn processed_keys_start_with_the_blob() {
+        let blob = "ab12";
+        assert_eq!(
+            transform("f=avif&q=60&w=500").processed_key(blob),
+            "processed/ab12-q60-w500.avif"
+        );
+        assert_eq!(
+            transform("w=100&h=100&fit=cover&fy=0.25&dpr=2&rot=90&f=png").processed_key(blob),
+            "processed/ab12-w100-h100-cover-fx0.5-fy0.25-dpr2-r90.png"
+        );
+        assert_eq!(
+            transform("w=500").processed_key("1668879942069-funny-meme.jpg"),
+            "processed/1668879942069-w500-funny-meme.webp"
+        );
+    }
+}
@@ -9,11 +9,15 @@ mod commit_monitor;
 pub mod config;
 mod content_types;
 mod errors;
+mod files;
 mod handlers;
 mod helpers;
 #[cfg(feature = "https")]
 mod https;
+mod images;
 mod jsonerrors;
+mod oidc;
+mod replication;
 mod routes;
 pub mod serve;
 // #[cfg(feature = "search")]
@@ -0,0 +1,389 @@
+//! OpenID Connect login bridge.
+//! Atomic-Server acts as a Relying Party: users sign in at a configured issuer (e.g. a company SSO),
+//! after which they are linked to an Agent and receive an `atomic_session` cookie.
+//! See https://docs.atomicdata.dev/authentication.html#openid-connect
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::cookie::{time::Duration, Cookie, SameSite};
+use atomic_lib::{
+    agents::{add_session_key, generate_keypair, Agent},
+    authentication::AuthValues,
+    storelike::Query,
+    urls, Resource, Storelike, Value,
+};
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    errors::{AtomicServerError, AtomicServerResult},
+};
+
+/// Label of the [AgentKey]s that are created for OpenID Connect sessions.
+pub const SESSION_KEY_LABEL: &str = "OpenID Connect session";
+
+/// How long (in ms) a user can take to log in at the provider.
+const LOGIN_TIMEOUT: i64 = 10 * 60 * 1000;
+
+/// Cookie that ties a [PendingLogin] to the browser that started it.
+/// Prevents others from completing a login (or linking their Agent) in someone else's browser.
+pub const LOGIN_COOKIE: &str = "atomic_oidc_login";
+
+/// The OpenID Connect options from the [Config].
+#[derive(Clone, Debug)]
+pub struct OidcSettings {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where the provider sends the user after logging in: `{server_url}/oidc/callback`
+    pub redirect_uri: String,
+    pub session_hours: i64,
+}
+
+impl OidcSettings {
+    pub fn from_config(config: &Config) -> AtomicServerResult<OidcSettings> {
+        let opts = &config.opts;
+        let issuer = opts
+            .oidc_issuer
+            .clone()
+            .ok_or("OpenID Connect is not enabled on this server. Set `--oidc-issuer`.")?;
+        Ok(OidcSettings {
+            issuer,
+            client_id: opts
+                .oidc_client_id
+                .clone()
+                .ok_or("Missing `--oidc-client-id`")?,
+            client_secret: opts
+                .oidc_client_secret
+                .clone()
+                .ok_or("Missing `--oidc-client-secret`")?,
+            redirect_uri: format!("{}/oidc/callback", config.server_url),
+            session_hours: opts.oidc_session_hours,
+        })
+    }
+}
+
+/// A login that has been started at `/oidc/login`, but has not yet returned at `/oidc/callback`.
+#[derive(Clone, Debug)]
+pub struct PendingLogin {
+    /// Has to match the `nonce` in the ID Token, which prevents replaying tokens.
+    pub nonce: String,
+    /// Existing Agent that will be linked to the account, if the user was already signed in.
+    pub link_agent: Option<String>,
+    /// URL to send the user to after logging in.
+    pub redirect: String,
+    /// Hash of the random value in the [LOGIN_COOKIE] of the browser that started the login.
+    pub browser_hash: String,
+    pub created_at: i64,
+}
+
+/// The [PendingLogin]s, by their `state` parameter.
+/// Kept in memory, so the `state` can not be tampered with by the client.
+#[derive(Clone, Default)]
+pub struct PendingLogins(Arc<Mutex<HashMap<String, PendingLogin>>>);
+
+impl PendingLogins {
+    pub fn insert(&self, state: String, login: PendingLogin) -> AtomicServerResult<()> {
+        let mut logins = self
+            .0
+            .lock()
+            .map_err(|_e| "Could not lock pending logins")?;
+        let now = atomic_lib::utils::now();
+        logins.retain(|_, l| now - l.created_at < LOGIN_TIMEOUT);
+        logins.insert(state, login);
+        Ok(())
+    }
+
+    /// Removes and returns the login. Every `state` can only be used once.
+    /// Fails if `browser` is not the value of the [LOGIN_COOKIE] that was set when the login started.
+    pub fn take(&self, state: &str, browser: Option<&str>) -> AtomicServerResult<PendingLogin> {
+        let login = self
+            .0
+            .lock()
+            .map_err(|_e| "Could not lock pending logins")?
+            .remove(state)
+            .ok_or_else(|| unauthorized("Unknown or already used `state`. Please log in again."))?;
+        if atomic_lib::utils::now() - login.created_at >= LOGIN_TIMEOUT {
+            return Err(unauthorized("Login has expired. Please log in again."));
+        }
+        if browser.map(hash_browser_value) != Some(login.browser_hash.clone()) {
+            return Err(unauthorized(
+                "Login was started in another browser. Please log in again.",
+            ));
+        }
+        Ok(login)
+    }
+}
+
+pub fn hash_browser_value(value: &str) -> String {
+    let hash = ring::digest::digest(&ring::digest::SHA256, value.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hash)
+}
+
+/// The short-lived [LOGIN_COOKIE], which is only sent to `/oidc`.
+/// `SameSite=Lax`, so it is still sent when the provider redirects back to the callback.
+pub fn login_cookie(value: &str, server_url: &str) -> Cookie<'static> {
+    Cookie::build(LOGIN_COOKIE, value.to_string())
+        .path("/oidc")
+        .http_only(true)
+        .secure(server_url.starts_with("https"))
+        .same_site(SameSite::Lax)
+        .max_age(Duration::milliseconds(LOGIN_TIMEOUT))
+        .finish()
+}
+
+/// The parts of the `/.well-known/openid-configuration` that we use.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+}
+
+/// Fetches the [ProviderMetadata] of the issuer. Blocking.
+pub fn discover(issuer: &str) -> AtomicServerResult<ProviderMetadata> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let body = ureq::get(&url)
+        .call()
+        .map_err(|e| {
+            format!(
+                "Could not fetch OpenID Connect configuration {}. {}",
+                url, e
+            )
+        })?
+        .into_string()?;
+    let metadata: ProviderMetadata = serde_json::from_str(&body)
+        .map_err(|e| format!("Invalid OpenID Connect configuration at {}. {}", url, e))?;
+    if metadata.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(format!(
+            "OpenID Connect configuration is for issuer {}, expected {}",
+            metadata.issuer, issuer
+        )
+        .into());
+    }
+    Ok(metadata)
+}
+
+/// Where the user logs in at the provider, using the Authorization Code flow.
+pub fn authorization_url(
+    metadata: &ProviderMetadata,
+    settings: &OidcSettings,
+    state: &str,
+    nonce: &str,
+) -> String {
+    let separator = if metadata.authorization_endpoint.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    format!(
+        "{}{}response_type=code&scope={}&client_id={}&redirect_uri={}&state={}&nonce={}",
+        metadata.authorization_endpoint,
+        separator,
+        urlencoding::encode("openid email profile"),
+        urlencoding::encode(&settings.client_id),
+        urlencoding::encode(&settings.redirect_uri),
+        urlencoding::encode(state),
+        urlencoding::encode(nonce),
+    )
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// `aud` can be a single string or an array.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// The claims of the ID Token that we use.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    /// Expiration time, in seconds since unix epoch
+    pub exp: i64,
+    pub nonce: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// The ID Token is received directly from the token endpoint, so we rely on TLS instead of checking its signature.
+    /// See https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation
+    pub fn validate(
+        &self,
+        metadata: &ProviderMetadata,
+        settings: &OidcSettings,
+        nonce: &str,
+    ) -> AtomicServerResult<()> {
+        if self.iss != metadata.issuer {
+            return Err(unauthorized(format!(
+                "ID Token is issued by {}, expected {}",
+                self.iss, metadata.issuer
+            )));
+        }
+        if !self.aud.contains(&settings.client_id) {
+            return Err(unauthorized("ID Token is not meant for this server"));
+        }
+        if self.exp * 1000 <= atomic_lib::utils::now() {
+            return Err(unauthorized("ID Token has expired"));
+        }
+        if self.nonce.as_deref() != Some(nonce) {
+            return Err(unauthorized("ID Token has an invalid nonce"));
+        }
+        Ok(())
+    }
+}
+
+/// Exchanges the authorization code for an ID Token at the token endpoint. Blocking.
+pub fn exchange_code(
+    metadata: &ProviderMetadata,
+    settings: &OidcSettings,
+    code: &str,
+) -> AtomicServerResult<IdTokenClaims> {
+    let body = ureq::post(&metadata.token_endpoint)
+        .send_form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &settings.redirect_uri),
+            ("client_id", &settings.client_id),
+            ("client_secret", &settings.client_secret),
+        ])
+        .map_err(|e| format!("Could not exchange OpenID Connect code for a token. {}", e))?
+        .into_string()?;
+    let token: TokenResponse = serde_json::from_str(&body)
+        .map_err(|e| format!("Invalid token response from OpenID Connect provider. {}", e))?;
+    decode_id_token(&token.id_token)
+}
+
+fn decode_id_token(id_token: &str) -> AtomicServerResult<IdTokenClaims> {
+    let payload = id_token.split('.').nth(1).ok_or("ID Token is not a JWT")?;
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| format!("ID Token payload is not valid base64. {}", e))?;
+    let claims = serde_json::from_slice(&json)
+        .map_err(|e| format!("ID Token payload has invalid claims. {}", e))?;
+    Ok(claims)
+}
+
+/// Returns the subject of the Agent that is linked to the account.
+/// If there is none yet, links `link_agent` or a newly created Agent.
+pub fn find_or_create_agent(
+    store: &impl Storelike,
+    claims: &IdTokenClaims,
+    link_agent: Option<&str>,
+) -> AtomicServerResult<String> {
+    let results = store.query(&Query::new_prop_val(urls::OIDC_SUBJECT, &claims.sub))?;
+    for subject in results.subjects {
+        let identity = store.get_resource(&subject)?;
+        if identity.get(urls::OIDC_ISSUER)?.to_string() == claims.iss {
+            return Ok(identity.get(urls::OIDC_AGENT)?.to_string());
+        }
+    }
+
+    let agent_subject = match link_agent {
+        Some(agent) => agent.to_string(),
+        None => {
+            let name = claims.name.as_deref().or(claims.email.as_deref());
+            let agent = Agent::new(name, store)?;
+            agent.to_resource()?.save_locally(store)?;
+            agent.subject
+        }
+    };
+    let mut identity = Resource::new_instance(urls::OIDC_IDENTITY, store)?;
+    identity.set(
+        urls::OIDC_ISSUER.into(),
+        Value::String(claims.iss.clone()),
+        store,
+    )?;
+    identity.set(
+        urls::OIDC_SUBJECT.into(),
+        Value::String(claims.sub.clone()),
+        store,
+    )?;
+    identity.set(
+        urls::OIDC_AGENT.into(),
+        Value::AtomicUrl(agent_subject.clone()),
+        store,
+    )?;
+    identity.set(urls::READ.into(), vec![agent_subject.clone()].into(), store)?;
+    identity.save_locally(store)?;
+    tracing::info!(
+        "Linked OpenID Connect account {} to {}",
+        claims.sub,
+        agent_subject
+    );
+    Ok(agent_subject)
+}
+
+/// Adds a new key to the Agent that is valid for `hours`, and returns an [Agent] that signs with it.
+/// Removes expired session keys that never signed a Commit, see [add_session_key].
+pub fn create_session(
+    store: &impl Storelike,
+    agent_subject: &str,
+    hours: i64,
+) -> AtomicServerResult<Agent> {
+    let mut agent_resource = store.get_resource(agent_subject)?;
+    let pair = generate_keypair()?;
+    add_session_key(
+        &mut agent_resource,
+        &pair.public,
+        SESSION_KEY_LABEL,
+        atomic_lib::utils::now() + hours * 60 * 60 * 1000,
+        store,
+    )?;
+    agent_resource.save_locally(store)?;
+    Ok(Agent::from_private_key_and_subject(
+        &pair.private,
+        agent_subject,
+    )?)
+}
+
+/// The `atomic_session` cookie, signed by the session key.
+/// See [crate::helpers::get_auth_from_cookie].
+pub fn session_cookie(
+    session: &Agent,
+    server_url: &str,
+    hours: i64,
+) -> AtomicServerResult<Cookie<'static>> {
+    let auth_values = AuthValues::new_signed(session, server_url)?;
+    let json = serde_json::to_string(&auth_values)
+        .map_err(|e| format!("Could not serialize session. {}", e))?;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(json);
+    Ok(
+        Cookie::build("atomic_session", urlencoding::encode(&encoded).into_owned())
+            .path("/")
+            .http_only(true)
+            .secure(server_url.starts_with("https"))
+            .same_site(SameSite::Lax)
+            .max_age(Duration::hours(hours))
+            .finish(),
+    )
+}
+
+fn unauthorized(message: impl Into<String>) -> AtomicServerError {
+    atomic_lib::AtomicError::unauthorized(message.into()).into()
+}
@@ -0,0 +1,43 @@
+//! Keeps the mirrored Drives up to date, see [atomic_lib::replication].
+
+use atomic_lib::{db::DEFAULT_REPLICATION_BATCH, Db};
+
+use crate::{appstate::AppState, errors::AtomicServerResult};
+
+/// How long the leader is asked to wait for new Commits.
+const POLL_WAIT: std::time::Duration = std::time::Duration::from_secs(30);
+/// How long to wait before retrying when the leader can't be reached.
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Starts replicating the Drives that are passed with `--mirror`, each in its own thread.
+pub fn start_followers(appstate: &AppState) -> AtomicServerResult<()> {
+    for drive in &appstate.config.opts.mirrors {
+        let status = appstate.store.add_mirror(drive)?;
+        tracing::info!("Mirroring {} from {}", drive, status.leader);
+        let store = appstate.store.clone();
+        let drive = drive.clone();
+        std::thread::spawn(move || follow(&store, &drive));
+    }
+    Ok(())
+}
+
+fn follow(store: &Db, drive: &str) {
+    loop {
+        match store.sync_replication(drive, DEFAULT_REPLICATION_BATCH, POLL_WAIT) {
+            Ok(status) => {
+                if let Some(lag) = status.lag_ms {
+                    tracing::debug!(
+                        "Replicated {} up to {:?}, lag {}ms",
+                        drive,
+                        status.cursor,
+                        lag
+                    );
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to replicate {}: {}", drive, e);
+                std::thread::sleep(RETRY_DELAY);
+            }
+        }
+    }
+}
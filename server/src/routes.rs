@@ -21,6 +21,31 @@ pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
     app.service(web::resource("/ws").to(handlers::web_sockets::web_socket_handler))
         .service(web::resource("/download/{path:[^{}]+}").to(handlers::download::handle_download))
         .service(web::resource("/export").to(handlers::export::handle_export))
+        .service(
+            web::resource("/audit-log")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::audit_log::handle_audit_log),
+        )
+        .service(
+            web::resource("/changes")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::changes::handle_changes),
+        )
+        .service(
+            web::resource("/replication")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::replication::handle_replication),
+        )
+        .service(
+            web::resource("/oidc/login")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::oidc::login),
+        )
+        .service(
+            web::resource("/oidc/callback")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::oidc::callback),
+        )
         // This `generate` imports the static files from the `app_assets` folder
         .service(
             ResourceFiles::new("/", generate())
@@ -39,8 +64,19 @@ pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
         )
         .service(
             web::resource("/upload")
-                .guard(guard::Method(Method::POST))
-                .to(handlers::upload::upload_handler),
+                .route(
+                    web::post()
+                        .guard(guard::Header("Tus-Resumable", "1.0.0"))
+                        .to(handlers::resumable_upload::create_upload_session),
+                )
+                .route(web::post().to(handlers::upload::upload_handler))
+                .route(web::method(Method::OPTIONS).to(handlers::resumable_upload::upload_options)),
+        )
+        .service(
+            web::resource("/upload/{id}")
+                .route(web::head().to(handlers::resumable_upload::upload_session_offset))
+                .route(web::patch().to(handlers::resumable_upload::upload_chunk))
+                .route(web::delete().to(handlers::resumable_upload::delete_upload_session)),
         )
         .service(
             web::resource("/commit")
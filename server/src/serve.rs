@@ -44,6 +44,7 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
     if config.opts.rebuild_indexes {
         rebuild_indexes(&appstate)?;
     }
+    crate::replication::start_followers(&appstate)?;
 
     let server = HttpServer::new(move || {
         let cors = Cors::permissive();
@@ -163,3 +163,915 @@ fn get_body(resp: ServiceResponse) -> String {
     let bytes = boxbody.try_into_bytes().unwrap();
     String::from_utf8(bytes.as_ref().into()).unwrap()
 }
+
+/// A minimal OpenID Connect provider, which signs in `user-1` for any code.
+/// The code is used as the `nonce`, so the test can pass the nonce from the authorization URL.
+fn start_mock_oidc_issuer() -> String {
+    use actix_web::{web, HttpRequest, HttpResponse, HttpServer};
+    use base64::Engine;
+
+    fn issuer(req: &HttpRequest) -> String {
+        format!("http://{}", req.connection_info().host())
+    }
+
+    let server = HttpServer::new(|| {
+        App::new()
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(|req: HttpRequest| async move {
+                    let issuer = issuer(&req);
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "issuer": issuer,
+                        "authorization_endpoint": format!("{}/authorize", issuer),
+                        "token_endpoint": format!("{}/token", issuer),
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                web::post().to(
+                    |req: HttpRequest, form: web::Form<std::collections::HashMap<String, String>>| async move {
+                        let claims = serde_json::json!({
+                            "iss": issuer(&req),
+                            "sub": "user-1",
+                            "aud": "atomic-test",
+                            "exp": atomic_lib::utils::now() / 1000 + 60,
+                            "nonce": form.get("code"),
+                            "name": "SSO User",
+                        });
+                        let encode = |v: serde_json::Value| {
+                            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(v.to_string())
+                        };
+                        let id_token = format!(
+                            "{}.{}.signature",
+                            encode(serde_json::json!({"alg": "RS256"})),
+                            encode(claims)
+                        );
+                        HttpResponse::Ok().json(serde_json::json!({
+                            "access_token": "access",
+                            "token_type": "Bearer",
+                            "id_token": id_token,
+                        }))
+                    },
+                ),
+            )
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .expect("could not bind mock issuer");
+    let issuer = format!("http://127.0.0.1:{}", server.addrs()[0].port());
+    actix_rt::spawn(server.run());
+    issuer
+}
+
+#[actix_rt::test]
+async fn oidc_login() {
+    use actix_web::http::header;
+    use clap::Parser;
+
+    let issuer = start_mock_oidc_issuer();
+    let unique_string = atomic_lib::utils::random_string(10);
+    let opts = Opts::parse_from([
+        "atomic-server",
+        "--initialize",
+        "--data-dir",
+        &format!("./.temp/{}/db", unique_string),
+        "--config-dir",
+        &format!("./.temp/{}/config", unique_string),
+        "--oidc-issuer",
+        &issuer,
+        "--oidc-client-id",
+        "atomic-test",
+        "--oidc-client-secret",
+        "secret",
+    ]);
+    let mut config = config::build_config(opts).expect("failed init config");
+    config.search_index_path = format!("./.temp/{}/search_index", unique_string).into();
+    let appstate = crate::appstate::AppState::init(config.clone()).expect("failed init appstate");
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let server_url = appstate.config.server_url.clone();
+
+    // Starts a login, the server redirects to the provider.
+    // Returns the callback URL that the provider sends the user back to, and the login cookie.
+    let start_login = || async {
+        let req = test::TestRequest::with_uri("/oidc/login?redirect=/welcome");
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status().as_u16(), 302);
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(location.starts_with(&format!("{}/authorize?", issuer)));
+        let param = |name: &str| {
+            location
+                .split(['?', '&'])
+                .find_map(|p| p.strip_prefix(&format!("{}=", name)))
+                .unwrap()
+                .to_string()
+        };
+        let login_cookie = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == crate::oidc::LOGIN_COOKIE)
+            .unwrap()
+            .into_owned();
+        assert!(login_cookie.http_only().unwrap());
+        let callback = format!(
+            "/oidc/callback?state={}&code={}",
+            param("state"),
+            param("nonce")
+        );
+        (callback, login_cookie)
+    };
+
+    // A callback in a browser that did not start the login is rejected,
+    // so a login can't be completed in someone else's browser
+    let (callback, _login_cookie) = start_login().await;
+    let resp = test::call_service(&app, test::TestRequest::with_uri(&callback).to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let (callback, _login_cookie) = start_login().await;
+    let (_other_callback, other_cookie) = start_login().await;
+    let req = test::TestRequest::with_uri(&callback).cookie(other_cookie);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let mut logged_in_agents = Vec::new();
+    for _ in 0..2 {
+        // The provider sends the user back with a code
+        let (callback, login_cookie) = start_login().await;
+        let req = test::TestRequest::with_uri(&callback).cookie(login_cookie.clone());
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status().as_u16(), 302, "{:?}", resp.response().error());
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            &format!("{}/welcome", server_url)
+        );
+        let session = resp
+            .response()
+            .cookies()
+            .find(|c| c.name() == "atomic_session")
+            .unwrap();
+
+        // The cookie authenticates as the linked Agent
+        let mut headers = actix_web::http::header::HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            session.stripped().to_string().parse().unwrap(),
+        );
+        let auth = crate::helpers::get_auth_from_cookie(&headers, &server_url)
+            .unwrap()
+            .unwrap();
+        let for_agent =
+            atomic_lib::authentication::get_agent_from_auth_values_and_check(Some(auth), store)
+                .unwrap();
+        logged_in_agents.push(for_agent.to_string());
+
+        // A `state` can only be used once
+        let req = test::TestRequest::with_uri(&callback).cookie(login_cookie);
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+    assert_eq!(logged_in_agents[0], logged_in_agents[1]);
+    let agent = store.get_resource(&logged_in_agents[0]).unwrap();
+    assert_eq!(agent.get(urls::NAME).unwrap().to_string(), "SSO User");
+    let identities = store
+        .query(&atomic_lib::storelike::Query::new_prop_val(
+            urls::OIDC_SUBJECT,
+            "user-1",
+        ))
+        .unwrap();
+    assert_eq!(identities.subjects.len(), 1);
+}
+
+/// Builds the AppState for a server that stores its data in `./.temp/{unique_string}`.
+fn init_appstate(unique_string: &str, args: &[&str]) -> AppState {
+    use clap::Parser;
+    let data_dir = format!("./.temp/{}/db", unique_string);
+    let config_dir = format!("./.temp/{}/config", unique_string);
+    let mut all_args = vec![
+        "atomic-server",
+        "--initialize",
+        "--data-dir",
+        &data_dir,
+        "--config-dir",
+        &config_dir,
+    ];
+    all_args.extend_from_slice(args);
+    let mut config = config::build_config(Opts::parse_from(all_args)).expect("failed init config");
+    config.search_index_path = format!("./.temp/{}/search_index", unique_string).into();
+    crate::appstate::AppState::init(config).expect("failed init appstate")
+}
+
+#[actix_rt::test]
+async fn replication() {
+    use actix_web::HttpServer;
+
+    // The leader is served over HTTP, so the follower can read its changefeed
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let leader_url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let leader = init_appstate(
+        &atomic_lib::utils::random_string(10),
+        &["--server-url", &leader_url],
+    );
+    let leader_data = Data::new(leader.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(leader_data.clone())
+            .configure(crate::routes::config_routes)
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap();
+    actix_rt::spawn(server.run());
+
+    let follower = init_appstate(&atomic_lib::utils::random_string(10), &[]);
+    // The leader can't reach the follower, so it can't fetch the Agent that reads the changefeed
+    leader
+        .store
+        .add_resource(
+            &follower
+                .store
+                .get_resource(&follower.store.get_default_agent().unwrap().subject)
+                .unwrap(),
+        )
+        .unwrap();
+    follower.store.add_mirror(&leader_url).unwrap();
+
+    let mut resource = atomic_lib::Resource::new_generate_subject(&leader.store);
+    resource
+        .set(
+            urls::NAME.into(),
+            atomic_lib::Value::String("Mirrored".into()),
+            &leader.store,
+        )
+        .unwrap();
+    resource
+        .set(
+            urls::PARENT.into(),
+            atomic_lib::Value::AtomicUrl(leader_url.clone()),
+            &leader.store,
+        )
+        .unwrap();
+    resource.save_locally(&leader.store).unwrap();
+    let subject = resource.get_subject().clone();
+
+    let sync = |follower: &AppState| {
+        let store = follower.store.clone();
+        let drive = leader_url.clone();
+        actix_web::web::block(move || {
+            store.sync_replication(&drive, 1000, std::time::Duration::from_secs(0))
+        })
+    };
+    let status = sync(&follower).await.unwrap().unwrap();
+    assert!(status.caught_up, "{:?}", status.last_error);
+    assert!(status.applied > 0);
+    let mirrored = follower.store.get_resource(&subject).unwrap();
+    assert_eq!(mirrored.get(urls::NAME).unwrap().to_string(), "Mirrored");
+
+    // Changes are replicated incrementally
+    resource.destroy(&leader.store).unwrap();
+    let next = sync(&follower).await.unwrap().unwrap();
+    assert_eq!(next.applied, status.applied + 1);
+    assert!(next.cursor > status.cursor);
+    follower.store.get_resource(&subject).unwrap_err();
+
+    // The follower doesn't accept Commits for mirrored Resources
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(follower.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let mut builder = atomic_lib::commit::CommitBuilder::new(subject.clone());
+    builder.set(
+        urls::NAME.into(),
+        atomic_lib::Value::String("Changed on the follower".into()),
+    );
+    let agent = follower.store.get_default_agent().unwrap();
+    let commit = builder
+        .sign(&agent, &follower.store, &atomic_lib::Resource::new(subject))
+        .unwrap();
+    let req = test::TestRequest::post().uri("/commit").set_payload(
+        commit
+            .into_resource(&follower.store)
+            .unwrap()
+            .to_json_ad()
+            .unwrap(),
+    );
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(!resp.status().is_success());
+
+    // The status of the mirrors is available to the owner of the follower
+    let req = build_request_authenticated("/replication", &follower);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let body = get_body(resp);
+    let listed: atomic_lib::replication::ReplicationStatus =
+        serde_json::from_str(body.lines().next().unwrap()).unwrap();
+    assert_eq!(listed.cursor, next.cursor);
+}
+
+/// Uploads a file to the root of the server as `multipart/form-data`.
+fn build_upload_request(
+    appstate: &AppState,
+    filename: &str,
+    contents: impl AsRef<[u8]>,
+) -> TestRequest {
+    let mut body = format!(
+        "--BOUNDARY\r\nContent-Disposition: form-data; name=\"assets\"; filename=\"{}\"\r\nContent-Type: text/plain\r\n\r\n",
+        filename
+    )
+    .into_bytes();
+    body.extend_from_slice(contents.as_ref());
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+    build_request_authenticated(
+        &format!(
+            "/upload?parent={}",
+            urlencoding::encode(&appstate.config.server_url)
+        ),
+        appstate,
+    )
+    .method(actix_web::http::Method::POST)
+    .insert_header((
+        actix_web::http::header::CONTENT_TYPE,
+        "multipart/form-data; boundary=BOUNDARY",
+    ))
+    .set_payload(body)
+}
+
+#[actix_rt::test]
+async fn uploads_are_stored_by_hash() {
+    use actix_web::http::header;
+
+    let appstate = init_appstate(&atomic_lib::utils::random_string(10), &[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let server_url = appstate.config.server_url.clone();
+    let uploads_path = appstate.config.uploads_path.clone();
+
+    let mut files = Vec::new();
+    for filename in ["first.txt", "second.txt"] {
+        let req = build_upload_request(&appstate, filename, "hello world").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let created: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+        files.push(created[0]["@id"].as_str().unwrap().to_string());
+    }
+    let mut first = store.get_resource(&files[0]).unwrap();
+    let mut second = store.get_resource(&files[1]).unwrap();
+    let blob = first.get(urls::INTERNAL_ID).unwrap().to_string();
+    // Files with the same contents share one blob
+    assert_eq!(second.get(urls::INTERNAL_ID).unwrap().to_string(), blob);
+    assert_eq!(
+        first.get(urls::CHECKSUM).unwrap().to_string(),
+        "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+    );
+    assert!(uploads_path.join(&blob).exists());
+
+    // The hash is used as ETag. Downloads are authenticated for the subject of the File.
+    let download = || {
+        let headers = atomic_lib::client::get_authentication_headers(
+            &files[0],
+            &store.get_default_agent().unwrap(),
+        )
+        .unwrap();
+        let mut req = test::TestRequest::with_uri(&format!(
+            "/download{}",
+            files[0].trim_start_matches(&server_url)
+        ));
+        for header in headers {
+            req = req.insert_header(header);
+        }
+        req
+    };
+    let resp = test::call_service(&app, download().to_request()).await;
+    assert!(resp.status().is_success());
+    let etag = resp.headers().get(header::ETAG).unwrap().clone();
+    assert_eq!(etag.to_str().unwrap(), format!("\"{}\"", blob));
+    assert_eq!(test::read_body(resp).await, "hello world");
+    let req = download()
+        .insert_header((header::IF_NONE_MATCH, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 304);
+
+    // Corrupted files are verified while they are streamed, and the response is aborted
+    std::fs::write(uploads_path.join(&blob), "tampered").unwrap();
+    let resp = test::call_service(&app, download().to_request()).await;
+    assert!(actix_web::body::to_bytes(resp.into_body()).await.is_err());
+
+    // Blobs are removed when no File uses them anymore, including the ones in the trash
+    let no_grace = std::time::Duration::ZERO;
+    first.destroy(store).unwrap();
+    assert_eq!(
+        crate::files::collect_garbage(store, appstate.file_storage.as_ref(), no_grace).unwrap(),
+        0
+    );
+    store.purge_from_trash(&files[0]).unwrap();
+    assert_eq!(
+        crate::files::collect_garbage(store, appstate.file_storage.as_ref(), no_grace).unwrap(),
+        0
+    );
+    second.destroy(store).unwrap();
+    store.purge_from_trash(&files[1]).unwrap();
+    assert_eq!(
+        crate::files::collect_garbage(store, appstate.file_storage.as_ref(), no_grace).unwrap(),
+        1
+    );
+    assert!(!uploads_path.join(&blob).exists());
+}
+
+/// Objects and unfinished multipart uploads of a bucket in [s3_stand_in].
+#[derive(Default)]
+struct S3StandIn {
+    objects: std::collections::HashMap<String, (Vec<u8>, std::time::SystemTime)>,
+    uploads: std::collections::HashMap<String, std::collections::BTreeMap<u32, Vec<u8>>>,
+    /// Amount of parts that were sent in multipart uploads
+    parts: usize,
+    /// Amount of parts that were copied from other objects
+    copied_parts: usize,
+    /// Objects larger than this can not be copied at once, like S3 rejects copies above 5 GiB
+    copy_limit: Option<usize>,
+}
+
+/// Handles the S3 requests that the server sends, like a MinIO server with a single bucket would.
+async fn s3_stand_in(
+    req: actix_web::HttpRequest,
+    body: actix_web::web::Bytes,
+    bucket: Data<std::sync::Mutex<S3StandIn>>,
+) -> actix_web::HttpResponse {
+    use actix_web::HttpResponse;
+    use chrono::{DateTime, Utc};
+
+    let authorization = req
+        .headers()
+        .get("Authorization")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=test-key/"));
+    let path = urlencoding::decode(req.path()).unwrap().to_string();
+    let key = path.trim_start_matches("/uploads").trim_start_matches('/');
+    let query = actix_web::web::Query::<std::collections::HashMap<String, String>>::from_query(
+        req.query_string(),
+    )
+    .unwrap()
+    .into_inner();
+    let mut bucket = bucket.lock().unwrap();
+    let now = std::time::SystemTime::now();
+    match (req.method().as_str(), key) {
+        ("GET", "") => {
+            let prefix = query.get("prefix").cloned().unwrap_or_default();
+            let contents: String = bucket
+                .objects
+                .iter()
+                .filter(|(key, _)| {
+                    key.strip_prefix(&prefix)
+                        .is_some_and(|rest| !rest.contains('/'))
+                })
+                .map(|(key, (data, modified))| {
+                    format!(
+                        "<Contents><Key>{}</Key><LastModified>{}</LastModified><Size>{}</Size></Contents>",
+                        key,
+                        DateTime::<Utc>::from(*modified).to_rfc3339(),
+                        data.len()
+                    )
+                })
+                .collect();
+            HttpResponse::Ok().body(format!(
+                "<ListBucketResult><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                contents
+            ))
+        }
+        ("HEAD" | "GET", key) => {
+            let Some((data, modified)) = bucket.objects.get(key) else {
+                return HttpResponse::NotFound().finish();
+            };
+            let last_modified = (
+                "Last-Modified",
+                DateTime::<Utc>::from(*modified).to_rfc2822(),
+            );
+            match req.headers().get("Range") {
+                Some(range) => {
+                    let range = range.to_str().unwrap().trim_start_matches("bytes=");
+                    let (start, end) = range.split_once('-').unwrap();
+                    let (start, end): (usize, usize) =
+                        (start.parse().unwrap(), end.parse().unwrap());
+                    HttpResponse::PartialContent()
+                        .insert_header(last_modified)
+                        .body(data[start..=end].to_vec())
+                }
+                None => HttpResponse::Ok()
+                    .insert_header(last_modified)
+                    .body(data.clone()),
+            }
+        }
+        ("PUT", key) if query.contains_key("partNumber") => {
+            let part_number = query["partNumber"].parse().unwrap();
+            let etag = format!("\"{}-{}\"", key, part_number);
+            let (part, response) = match req.headers().get("x-amz-copy-source") {
+                Some(source) => {
+                    let source = urlencoding::decode(source.to_str().unwrap()).unwrap();
+                    let source = source.trim_start_matches("/uploads/");
+                    let range = req
+                        .headers()
+                        .get("x-amz-copy-source-range")
+                        .unwrap()
+                        .to_str()
+                        .unwrap();
+                    let (start, end) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+                    let (start, end): (usize, usize) =
+                        (start.parse().unwrap(), end.parse().unwrap());
+                    bucket.copied_parts += 1;
+                    (
+                        bucket.objects[source].0[start..=end].to_vec(),
+                        HttpResponse::Ok().body(format!(
+                            "<CopyPartResult><ETag>{}</ETag></CopyPartResult>",
+                            etag.replace('"', "&quot;")
+                        )),
+                    )
+                }
+                None => {
+                    bucket.parts += 1;
+                    (
+                        body.to_vec(),
+                        HttpResponse::Ok().insert_header(("ETag", etag)).finish(),
+                    )
+                }
+            };
+            bucket
+                .uploads
+                .get_mut(&query["uploadId"])
+                .unwrap()
+                .insert(part_number, part);
+            response
+        }
+        ("PUT", key) => {
+            let data = match req.headers().get("x-amz-copy-source") {
+                Some(source) => {
+                    let source = urlencoding::decode(source.to_str().unwrap()).unwrap();
+                    let source = source.trim_start_matches("/uploads/");
+                    let data = bucket.objects.get(source).unwrap().0.clone();
+                    if bucket.copy_limit.is_some_and(|limit| data.len() > limit) {
+                        return HttpResponse::BadRequest()
+                            .body("<Error><Code>InvalidRequest</Code></Error>");
+                    }
+                    data
+                }
+                None => body.to_vec(),
+            };
+            bucket.objects.insert(key.to_string(), (data, now));
+            HttpResponse::Ok().body("<CopyObjectResult></CopyObjectResult>")
+        }
+        ("POST", _) if query.contains_key("uploads") => {
+            let upload_id = atomic_lib::utils::random_string(10);
+            bucket.uploads.insert(upload_id.clone(), Default::default());
+            HttpResponse::Ok().body(format!(
+                "<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                upload_id
+            ))
+        }
+        ("POST", key) => {
+            let parts = bucket.uploads.remove(&query["uploadId"]).unwrap();
+            let data = parts.into_values().flatten().collect();
+            bucket.objects.insert(key.to_string(), (data, now));
+            HttpResponse::Ok()
+                .body("<CompleteMultipartUploadResult></CompleteMultipartUploadResult>")
+        }
+        ("DELETE", _) if query.contains_key("uploadId") => {
+            bucket.uploads.remove(&query["uploadId"]);
+            HttpResponse::NoContent().finish()
+        }
+        ("DELETE", key) => {
+            bucket.objects.remove(key);
+            HttpResponse::NoContent().finish()
+        }
+        _ => HttpResponse::MethodNotAllowed().finish(),
+    }
+}
+
+#[actix_rt::test]
+async fn uploads_in_s3_storage() {
+    use crate::files::{collect_garbage, BlobWriter, FileStorage, S3Storage};
+    use actix_web::{http::header, HttpServer};
+
+    let bucket = Data::new(std::sync::Mutex::new(S3StandIn::default()));
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let endpoint = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let stand_in_bucket = bucket.clone();
+    let stand_in = HttpServer::new(move || {
+        App::new()
+            .app_data(stand_in_bucket.clone())
+            .default_service(actix_web::web::to(s3_stand_in))
+    })
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(stand_in);
+
+    let appstate = init_appstate(
+        &atomic_lib::utils::random_string(10),
+        &[
+            "--s3-bucket",
+            "uploads",
+            "--s3-endpoint",
+            &endpoint,
+            "--s3-access-key-id",
+            "test-key",
+            "--s3-secret-access-key",
+            "test-secret",
+        ],
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+
+    let req = build_upload_request(&appstate, "hello.txt", "hello world").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let created: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let subject = created[0]["@id"].as_str().unwrap().to_string();
+    let blob = store
+        .get_resource(&subject)
+        .unwrap()
+        .get(urls::INTERNAL_ID)
+        .unwrap()
+        .to_string();
+    {
+        let bucket = bucket.lock().unwrap();
+        assert_eq!(bucket.objects.len(), 1, "Only the blob should be stored");
+        assert_eq!(bucket.objects[&blob].0, b"hello world");
+    }
+
+    // Ranged reads
+    let headers = atomic_lib::client::get_authentication_headers(
+        &subject,
+        &store.get_default_agent().unwrap(),
+    )
+    .unwrap();
+    let mut req = test::TestRequest::with_uri(&format!(
+        "/download{}",
+        subject.trim_start_matches(&appstate.config.server_url)
+    ))
+    .insert_header((header::RANGE, "bytes=6-10"));
+    for header in headers {
+        req = req.insert_header(header);
+    }
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 206);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes 6-10/11"
+    );
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/plain"
+    );
+    assert_eq!(test::read_body(resp).await, "world");
+
+    // Large files are streamed in parts, and copied in parts when they are larger than the copy limit
+    bucket.lock().unwrap().copy_limit = Some(10);
+    let mut storage =
+        S3Storage::new(&endpoint, "uploads", "us-east-1", "test-key", "test-secret").unwrap();
+    storage.set_part_size(4);
+    storage.set_copy_limit(10);
+    let contents = "a file that is sent in parts";
+    let large_blob = actix_web::web::block(move || {
+        let upload = || {
+            let mut writer = BlobWriter::new(&storage).unwrap();
+            for chunk in contents.as_bytes().chunks(3) {
+                writer.write(chunk).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let blob = upload();
+        // Uploading the same file again touches the existing large object
+        assert_eq!(upload().id, blob.id);
+        let mut read = String::new();
+        storage
+            .read(&blob.id, Some(2..6))
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, "file");
+        blob
+    })
+    .await
+    .unwrap();
+    {
+        let bucket = bucket.lock().unwrap();
+        assert_eq!(bucket.parts, 14);
+        // Both the first copy to the final key and the touch are sent as three parts of at most 10 bytes
+        assert_eq!(bucket.copied_parts, 6);
+        assert!(bucket.uploads.is_empty());
+        assert_eq!(bucket.objects[&large_blob.id].0, contents.as_bytes());
+        assert!(
+            !bucket.objects.keys().any(|key| key.starts_with("tmp/")),
+            "Temporary objects should be removed"
+        );
+    }
+
+    // Only the blob that is not used by a File is removed
+    let gc_store = store.clone();
+    let gc_storage = appstate.file_storage.clone();
+    let removed = actix_web::web::block(move || {
+        collect_garbage(&gc_store, gc_storage.as_ref(), std::time::Duration::ZERO)
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(removed, 1);
+    let bucket = bucket.lock().unwrap();
+    assert!(bucket.objects.contains_key(&blob));
+    assert!(!bucket.objects.contains_key(&large_blob.id));
+}
+
+#[actix_rt::test]
+async fn resumable_uploads() {
+    use actix_web::http::{header, Method};
+    use base64::Engine;
+
+    let appstate = init_appstate(&atomic_lib::utils::random_string(10), &[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let server_url = appstate.config.server_url.clone();
+
+    let req = test::TestRequest::with_uri("/upload")
+        .method(Method::OPTIONS)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("Tus-Version").unwrap(), "1.0.0");
+
+    let filename = base64::engine::general_purpose::STANDARD.encode("hello.txt");
+    let req = build_request_authenticated(
+        &format!("/upload?parent={}", urlencoding::encode(&server_url)),
+        &appstate,
+    )
+    .method(Method::POST)
+    .insert_header(("Tus-Resumable", "1.0.0"))
+    .insert_header(("Upload-Length", "11"))
+    .insert_header(("Upload-Metadata", format!("filename {}", filename)))
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 201, "{}", get_body(resp));
+    let location = resp
+        .headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let session_path = location.trim_start_matches(&server_url).to_string();
+
+    let patch = |offset: &str, body: &'static str| {
+        build_request_authenticated(&session_path, &appstate)
+            .method(Method::PATCH)
+            .insert_header(("Tus-Resumable", "1.0.0"))
+            .insert_header(("Upload-Offset", offset))
+            .insert_header((header::CONTENT_TYPE, "application/offset+octet-stream"))
+            .set_payload(body)
+            .to_request()
+    };
+    let head = || {
+        build_request_authenticated(&session_path, &appstate)
+            .method(Method::HEAD)
+            .insert_header(("Tus-Resumable", "1.0.0"))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, patch("0", "hello")).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "5");
+
+    // The client lost track of the offset, so it asks the server where to continue
+    let resp = test::call_service(&app, patch("0", "hello world")).await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let resp = test::call_service(&app, head()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "5");
+    assert_eq!(resp.headers().get("Upload-Length").unwrap(), "11");
+
+    // The last chunk creates the File
+    let resp = test::call_service(&app, patch("5", " world")).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "11");
+    let subject = resp
+        .headers()
+        .get("Atomic-File")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let file = store.get_resource(&subject).unwrap();
+    assert_eq!(file.get(urls::FILENAME).unwrap().to_string(), "hello.txt");
+    assert_eq!(file.get(urls::MIMETYPE).unwrap().to_string(), "text/plain");
+    assert_eq!(file.get(urls::PARENT).unwrap().to_string(), server_url);
+    assert_eq!(
+        file.get(urls::CHECKSUM).unwrap().to_string(),
+        "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+    );
+    let resp = test::call_service(&app, head()).await;
+    assert_eq!(resp.headers().get("Atomic-File").unwrap(), subject.as_str());
+
+    let req = build_request_authenticated(&session_path, &appstate)
+        .method(Method::DELETE)
+        .insert_header(("Tus-Resumable", "1.0.0"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    let resp = test::call_service(&app, head()).await;
+    assert_eq!(resp.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn processed_images() {
+    use actix_web::http::header;
+    use image::GenericImageView;
+
+    let appstate = init_appstate(&atomic_lib::utils::random_string(10), &[]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let server_url = appstate.config.server_url.clone();
+    let processed_folder = appstate.config.uploads_path.join("processed");
+
+    let mut png = Vec::new();
+    image::DynamicImage::new_rgb8(64, 32)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let req = build_upload_request(&appstate, "photo.png", &png).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let created: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let file = created[0]["@id"].as_str().unwrap().to_string();
+
+    let download = |query: &str| {
+        let headers = atomic_lib::client::get_authentication_headers(
+            &file,
+            &store.get_default_agent().unwrap(),
+        )
+        .unwrap();
+        let mut req = test::TestRequest::with_uri(&format!(
+            "/download{}?{}",
+            file.trim_start_matches(&server_url),
+            query
+        ));
+        for header in headers {
+            req = req.insert_header(header);
+        }
+        req.to_request()
+    };
+
+    let resp = test::call_service(&app, download("w=16&h=16&fit=cover&dpr=2&f=jpeg")).await;
+    assert!(resp.status().is_success());
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "image/jpeg"
+    );
+    let img = image::load_from_memory(&test::read_body(resp).await).unwrap();
+    assert_eq!(img.dimensions(), (32, 32));
+
+    let resp = test::call_service(&app, download("h=8&rot=90&f=png")).await;
+    assert!(resp.status().is_success());
+    let img = image::load_from_memory(&test::read_body(resp).await).unwrap();
+    assert_eq!(img.dimensions(), (4, 8));
+    assert_eq!(std::fs::read_dir(&processed_folder).unwrap().count(), 2);
+
+    let resp = test::call_service(&app, download("f=gif")).await;
+    assert!(!resp.status().is_success());
+
+    // Processed images are removed when their File is destroyed
+    store.get_resource(&file).unwrap().destroy(store).unwrap();
+    for _ in 0..50 {
+        if std::fs::read_dir(&processed_folder).unwrap().count() == 0 {
+            break;
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(std::fs::read_dir(&processed_folder).unwrap().count(), 0);
+}